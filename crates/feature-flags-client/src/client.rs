@@ -0,0 +1,353 @@
+use reqwest::{header::{AUTHORIZATION, ETAG, IF_NONE_MATCH}, StatusCode};
+use std::{collections::HashMap, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, Weak}, time::{Duration, Instant}};
+use tokio::sync::RwLock;
+
+use crate::Context;
+use feature_flags_core::{eval_flag, Bundle, EvalResponse, Expected, Flag, SignatureError, SignatureVerifier, Signed};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("server returned {0}")]
+    Status(StatusCode),
+    #[error("stream closed")]
+    StreamClosed,
+    #[error("cannot read bootstrap file: {0}")]
+    Bootstrap(#[from] std::io::Error),
+    #[error("malformed bootstrap file: {0}")]
+    BootstrapFormat(#[from] serde_json::Error),
+    #[error("flag data failed verification: {0}")]
+    Signature(#[from] SignatureError),
+}
+
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(45);
+const STREAM_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+pub struct ClientBuilder {
+    base_url: String,
+    sdk_key: String,
+    poll_interval: Duration,
+    stale_after: Duration,
+    idle_timeout: Duration,
+    request_timeout: Duration,
+    streaming: bool,
+    local_evaluation: bool,
+    bootstrap: Option<PathBuf>,
+    verifier: Option<SignatureVerifier>,
+    environment: Option<String>,
+    max_signature_age: Duration,
+}
+
+impl ClientBuilder {
+    pub fn poll_interval(mut self, d: Duration) -> Self { self.poll_interval = d; self }
+
+    pub fn stale_after(mut self, d: Duration) -> Self { self.stale_after = d; self }
+
+    pub fn idle_timeout(mut self, d: Duration) -> Self { self.idle_timeout = d; self }
+
+    pub fn request_timeout(mut self, d: Duration) -> Self { self.request_timeout = d; self }
+
+    pub fn streaming(mut self, enabled: bool) -> Self { self.streaming = enabled; self }
+
+    pub fn local_evaluation(mut self, enabled: bool) -> Self { self.local_evaluation = enabled; self }
+
+    pub fn bootstrap_file(mut self, path: impl Into<PathBuf>) -> Self { self.bootstrap = Some(path.into()); self }
+
+    // requires signed snapshots, rules and bootstrap files, and rejects any that don't verify against one of the keys
+    pub fn verify_signatures(mut self, verifier: SignatureVerifier) -> Self { self.verifier = Some(verifier); self }
+
+    // the environment signed data has to be for; unset, the first one verified is kept to
+    pub fn environment(mut self, environment: impl Into<String>) -> Self { self.environment = Some(environment.into()); self }
+
+    // signed snapshots and rules issued longer ago than this are rejected, so an old copy can't be replayed
+    pub fn max_signature_age(mut self, d: Duration) -> Self { self.max_signature_age = d; self }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let http = reqwest::Client::builder().timeout(self.request_timeout).build()?;
+        let stream_http = reqwest::Client::builder().connect_timeout(self.request_timeout).build()?;
+        let mut environment = self.environment;
+        let rules = match &self.bootstrap {
+            Some(path) => {
+                let bytes = std::fs::read(path)?;
+                // a bootstrap file is meant to be old, so only its environment is checked
+                let bundle: Bundle = match &self.verifier {
+                    Some(v) => {
+                        let envelope = v.verify_for::<Bundle>(&serde_json::from_slice::<Signed>(&bytes)?, Expected { environment: environment.as_deref(), ..Default::default() })?;
+                        environment.get_or_insert(envelope.environment);
+                        envelope.data
+                    }
+                    None => serde_json::from_slice(&bytes)?,
+                };
+                tracing::info!(environment = %bundle.environment, generated_at = %bundle.generated_at, flags = bundle.flags.len(), "loaded flag bootstrap");
+                Some(Rules { flags: bundle.flags.into_iter().map(|f| (f.key.clone(), f)).collect(), etag: None, fetched_at: None, revalidating: false })
+            }
+            None => None,
+        };
+        let inner = Arc::new(Inner {
+            base_url: self.base_url.trim_end_matches('/').to_string(),
+            sdk_key: self.sdk_key,
+            stale_after: self.stale_after,
+            idle_timeout: self.idle_timeout,
+            http,
+            cache: RwLock::new(HashMap::new()),
+            local: self.local_evaluation,
+            rules: RwLock::new(rules),
+            stream_connected: AtomicBool::new(false),
+            verifier: self.verifier,
+            environment: Mutex::new(environment),
+            max_signature_age: self.max_signature_age,
+        });
+        tokio::spawn(poll(Arc::downgrade(&inner), self.poll_interval));
+        let stream_task = self.streaming.then(|| tokio::spawn(stream(Arc::downgrade(&inner), stream_http)).abort_handle());
+        Ok(Client { inner, _stream: Arc::new(StreamTask(stream_task)) })
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+    _stream: Arc<StreamTask>,
+}
+
+struct StreamTask(Option<tokio::task::AbortHandle>);
+
+impl Drop for StreamTask {
+    fn drop(&mut self) {
+        if let Some(task) = &self.0 { task.abort(); }
+    }
+}
+
+struct Inner {
+    base_url: String,
+    sdk_key: String,
+    stale_after: Duration,
+    idle_timeout: Duration,
+    http: reqwest::Client,
+    cache: RwLock<HashMap<Context, Entry>>,
+    local: bool,
+    rules: RwLock<Option<Rules>>,
+    stream_connected: AtomicBool,
+    verifier: Option<SignatureVerifier>,
+    environment: Mutex<Option<String>>,
+    max_signature_age: Duration,
+}
+
+struct Rules {
+    flags: HashMap<String, Flag>,
+    etag: Option<String>,
+    fetched_at: Option<Instant>,
+    revalidating: bool,
+}
+
+struct Entry {
+    flags: HashMap<String, EvalResponse>,
+    etag: Option<String>,
+    fetched_at: Instant,
+    used_at: Instant,
+    revalidating: bool,
+}
+
+impl Client {
+    pub fn builder(base_url: impl Into<String>, sdk_key: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.into(),
+            sdk_key: sdk_key.into(),
+            poll_interval: Duration::from_secs(30),
+            stale_after: Duration::from_secs(120),
+            idle_timeout: Duration::from_secs(600),
+            request_timeout: Duration::from_secs(5),
+            streaming: false,
+            local_evaluation: false,
+            bootstrap: None,
+            verifier: None,
+            environment: None,
+            max_signature_age: Duration::from_secs(3600),
+        }
+    }
+
+    pub async fn is_enabled(&self, key: impl AsRef<str>, ctx: &Context) -> bool {
+        self.evaluate(key, ctx).await.is_some_and(|r| r.matched)
+    }
+
+    pub async fn variant(&self, key: impl AsRef<str>, ctx: &Context) -> Option<String> {
+        self.evaluate(key, ctx).await.and_then(|r| if r.matched { r.variant } else { None })
+    }
+
+    pub async fn evaluate(&self, key: impl AsRef<str>, ctx: &Context) -> Option<EvalResponse> {
+        let key = key.as_ref();
+        if self.inner.local { return self.evaluate_local(key, ctx).await; }
+        {
+            let mut cache = self.inner.cache.write().await;
+            if let Some(entry) = cache.get_mut(ctx) {
+                entry.used_at = Instant::now();
+                if entry.fetched_at.elapsed() > self.inner.stale_after && !entry.revalidating {
+                    entry.revalidating = true;
+                    let inner = self.inner.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = inner.refresh(&ctx).await { tracing::warn!(error = %e, "flag revalidation failed, serving cached values"); }
+                        if let Some(entry) = inner.cache.write().await.get_mut(&ctx) { entry.revalidating = false; }
+                    });
+                }
+                return entry.flags.get(key).cloned();
+            }
+        }
+        if let Err(e) = self.inner.refresh(ctx).await {
+            tracing::warn!(error = %e, "flag fetch failed");
+            return self.inner.rules.read().await.as_ref().and_then(|r| r.flags.get(key)).map(|f| eval_flag(f, ctx.bucketing_id()));
+        }
+        self.inner.cache.read().await.get(ctx).and_then(|e| e.flags.get(key).cloned())
+    }
+
+    async fn evaluate_local(&self, key: &str, ctx: &Context) -> Option<EvalResponse> {
+        {
+            let mut rules = self.inner.rules.write().await;
+            if let Some(r) = rules.as_mut() {
+                if r.fetched_at.is_none_or(|t| t.elapsed() > self.inner.stale_after) && !r.revalidating {
+                    r.revalidating = true;
+                    let inner = self.inner.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = inner.refresh_rules().await { tracing::warn!(error = %e, "flag rules revalidation failed, serving cached rules"); }
+                        if let Some(r) = inner.rules.write().await.as_mut() { r.revalidating = false; }
+                    });
+                }
+                return r.flags.get(key).map(|f| eval_flag(f, ctx.bucketing_id()));
+            }
+        }
+        if let Err(e) = self.inner.refresh_rules().await { tracing::warn!(error = %e, "flag rules fetch failed"); }
+        self.inner.rules.read().await.as_ref().and_then(|r| r.flags.get(key)).map(|f| eval_flag(f, ctx.bucketing_id()))
+    }
+
+    pub async fn refresh(&self, ctx: &Context) -> Result<(), Error> {
+        if self.inner.local { self.inner.refresh_rules().await } else { self.inner.refresh(ctx).await }
+    }
+
+    // links a signed-in user to the anonymous id they used before, so they keep its rollout buckets and variants
+    pub async fn identify(&self, anonymous_id: impl AsRef<str>, user_id: impl AsRef<str>) -> Result<(), Error> {
+        let user_id = user_id.as_ref();
+        let res = self.inner.http.post(format!("{}/identify", self.inner.base_url))
+            .header(AUTHORIZATION, format!("Bearer {}", self.inner.sdk_key))
+            .json(&serde_json::json!({ "anonymous_id": anonymous_id.as_ref(), "user_id": user_id }))
+            .send()
+            .await?;
+        if !res.status().is_success() { return Err(Error::Status(res.status())); }
+        self.inner.cache.write().await.retain(|ctx, _| ctx.user_id.as_deref() != Some(user_id));
+        Ok(())
+    }
+
+    pub fn is_streaming(&self) -> bool { self.inner.stream_connected.load(Ordering::Relaxed) }
+}
+
+impl Inner {
+    // a body that fails verification is an error, so the cache keeps serving the last data that did verify.
+    // `subject` is who a snapshot was asked for, None for rules
+    async fn decode<T: serde::de::DeserializeOwned>(&self, res: reqwest::Response, subject: Option<&str>) -> Result<T, Error> {
+        let Some(v) = &self.verifier else { return Ok(res.json().await?) };
+        let signed = res.json::<Signed>().await?;
+        let environment = self.environment.lock().unwrap().clone();
+        let envelope = v.verify_for::<T>(&signed, Expected { environment: environment.as_deref(), subject, max_age: Some(self.max_signature_age) })?;
+        self.environment.lock().unwrap().get_or_insert(envelope.environment);
+        Ok(envelope.data)
+    }
+
+    async fn refresh(&self, ctx: &Context) -> Result<(), Error> {
+        let etag = self.cache.read().await.get(ctx).and_then(|e| e.etag.clone());
+        let mut req = self.http.get(format!("{}/snapshot", self.base_url)).header(AUTHORIZATION, format!("Bearer {}", self.sdk_key));
+        if let Some(user_id) = &ctx.user_id { req = req.query(&[("user_id", user_id)]); }
+        if let Some(anonymous_id) = &ctx.anonymous_id { req = req.query(&[("anonymous_id", anonymous_id)]); }
+        if let Some(etag) = &etag { req = req.header(IF_NONE_MATCH, etag); }
+        if self.verifier.is_some() { req = req.query(&[("signed", "true")]); }
+        let res = req.send().await?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            if let Some(entry) = self.cache.write().await.get_mut(ctx) { entry.fetched_at = Instant::now(); }
+            return Ok(());
+        }
+        if !res.status().is_success() { return Err(Error::Status(res.status())); }
+        let etag = res.headers().get(ETAG).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let flags: Vec<EvalResponse> = self.decode(res, ctx.user_id.as_deref().or(ctx.anonymous_id.as_deref())).await?;
+        let flags = flags.into_iter().map(|f| (f.key.clone(), f)).collect();
+        let now = Instant::now();
+        let mut cache = self.cache.write().await;
+        let used_at = cache.get(ctx).map_or(now, |e| e.used_at);
+        cache.insert(ctx.clone(), Entry { flags, etag, fetched_at: now, used_at, revalidating: false });
+        Ok(())
+    }
+
+    async fn refresh_rules(&self) -> Result<(), Error> {
+        let etag = self.rules.read().await.as_ref().and_then(|r| r.etag.clone());
+        let mut req = self.http.get(format!("{}/rules", self.base_url)).header(AUTHORIZATION, format!("Bearer {}", self.sdk_key));
+        if let Some(etag) = &etag { req = req.header(IF_NONE_MATCH, etag); }
+        if self.verifier.is_some() { req = req.query(&[("signed", "true")]); }
+        let res = req.send().await?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            if let Some(r) = self.rules.write().await.as_mut() { r.fetched_at = Some(Instant::now()); }
+            return Ok(());
+        }
+        if !res.status().is_success() { return Err(Error::Status(res.status())); }
+        let etag = res.headers().get(ETAG).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let flags: Vec<Flag> = self.decode(res, None).await?;
+        let flags = flags.into_iter().map(|f| (f.key.clone(), f)).collect();
+        *self.rules.write().await = Some(Rules { flags, etag, fetched_at: Some(Instant::now()), revalidating: false });
+        Ok(())
+    }
+
+    async fn refresh_all(&self) {
+        if self.local {
+            if let Err(e) = self.refresh_rules().await { tracing::warn!(error = %e, "flag rules refresh failed, serving cached rules"); }
+            return;
+        }
+        let contexts: Vec<Context> = {
+            let mut cache = self.cache.write().await;
+            cache.retain(|_, e| e.used_at.elapsed() < self.idle_timeout);
+            cache.keys().cloned().collect()
+        };
+        for ctx in contexts {
+            if let Err(e) = self.refresh(&ctx).await { tracing::warn!(error = %e, "flag refresh failed, serving cached values"); }
+        }
+    }
+
+    async fn follow_stream(&self, http: &reqwest::Client) -> Result<(), Error> {
+        let mut res = http.get(format!("{}/stream", self.base_url)).header(AUTHORIZATION, format!("Bearer {}", self.sdk_key)).send().await?;
+        if !res.status().is_success() { return Err(Error::Status(res.status())); }
+        let mut buf = String::new();
+        loop {
+            let chunk = tokio::time::timeout(STREAM_IDLE_TIMEOUT, res.chunk()).await.map_err(|_| Error::StreamClosed)??.ok_or(Error::StreamClosed)?;
+            buf.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(end) = buf.find("\n\n") {
+                let block: String = buf.drain(..end + 2).collect();
+                let event = block.lines().find_map(|l| l.strip_prefix("event:")).map(str::trim);
+                match event {
+                    Some("ready") => { self.stream_connected.store(true, Ordering::Relaxed); tracing::debug!("flag stream connected"); self.refresh_all().await; }
+                    Some("flag") | Some("resync") => self.refresh_all().await,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+async fn poll(inner: Weak<Inner>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(inner) = inner.upgrade() else { return };
+        if inner.stream_connected.load(Ordering::Relaxed) { continue; }
+        inner.refresh_all().await;
+    }
+}
+
+async fn stream(inner: Weak<Inner>, http: reqwest::Client) {
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        let Some(strong) = inner.upgrade() else { return };
+        let err = strong.follow_stream(&http).await.err();
+        let was_connected = strong.stream_connected.swap(false, Ordering::Relaxed);
+        drop(strong);
+        if was_connected { backoff = Duration::from_secs(1); }
+        if let Some(e) = err { tracing::warn!(error = %e, retry_in = ?backoff, "flag stream unavailable, falling back to polling"); }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(STREAM_MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,19 @@
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Context {
+    pub user_id: Option<String>,
+    pub anonymous_id: Option<String>,
+}
+
+impl Context {
+    pub fn anonymous() -> Self { Context::default() }
+
+    pub fn user(user_id: impl Into<String>) -> Self { Context { user_id: Some(user_id.into()), anonymous_id: None } }
+
+    // a stable id generated on the client for a visitor who hasn't signed in yet
+    pub fn visitor(anonymous_id: impl Into<String>) -> Self { Context { user_id: None, anonymous_id: Some(anonymous_id.into()) } }
+
+    pub fn with_anonymous_id(mut self, anonymous_id: impl Into<String>) -> Self { self.anonymous_id = Some(anonymous_id.into()); self }
+
+    // local evaluation can't see server-side aliases, so it hashes on whichever id it has
+    pub(crate) fn bucketing_id(&self) -> Option<&str> { self.user_id.as_deref().or(self.anonymous_id.as_deref()) }
+}
@@ -0,0 +1,131 @@
+use axum::{async_trait, body::Body, extract::{FromRef, FromRequestParts}, http::{request::Parts, Request, StatusCode}, response::{IntoResponse, Response}};
+use std::{convert::Infallible, future::Future, marker::PhantomData, pin::Pin, sync::Arc, task::{Context as TaskContext, Poll}};
+use tower::{util::BoxCloneService, Layer, Service, ServiceExt};
+
+use crate::{Client, Context};
+
+pub trait FlagKey: Send + Sync + 'static {
+    const KEY: &'static str;
+}
+
+#[macro_export]
+macro_rules! flag_key {
+    ($($name:ident = $key:literal),+ $(,)?) => {
+        $(
+            pub struct $name;
+            impl $crate::FlagKey for $name { const KEY: &'static str = $key; }
+        )+
+    };
+}
+
+fn request_context(parts: &Parts) -> Context {
+    if let Some(ctx) = parts.extensions.get::<Context>() { return ctx.clone(); }
+    let header = |name: &str| parts.headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    Context { user_id: header("x-user-id"), anonymous_id: header("x-anonymous-id") }
+}
+
+pub struct Flag<K: FlagKey> {
+    pub enabled: bool,
+    pub variant: Option<String>,
+    _key: PhantomData<K>,
+}
+
+impl<K: FlagKey> Flag<K> {
+    pub fn key(&self) -> &'static str { K::KEY }
+}
+
+#[async_trait]
+impl<K: FlagKey, S: Send + Sync> FromRequestParts<S> for Flag<K> where Client: FromRef<S> {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let client = Client::from_ref(state);
+        let res = client.evaluate(K::KEY, &request_context(parts)).await;
+        let enabled = res.as_ref().is_some_and(|r| r.matched);
+        let variant = res.and_then(|r| if r.matched { r.variant } else { None });
+        Ok(Flag { enabled, variant, _key: PhantomData })
+    }
+}
+
+pub struct Enabled<K: FlagKey>(pub Flag<K>);
+
+#[async_trait]
+impl<K: FlagKey, S: Send + Sync> FromRequestParts<S> for Enabled<K> where Client: FromRef<S> {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Ok(flag) = Flag::<K>::from_request_parts(parts, state).await;
+        if flag.enabled { Ok(Enabled(flag)) } else { Err(StatusCode::NOT_FOUND) }
+    }
+}
+
+type Resolver = Arc<dyn Fn(&Parts) -> Context + Send + Sync>;
+
+#[derive(Clone)]
+enum Fallback {
+    Reject(StatusCode),
+    Reroute(BoxCloneService<Request<Body>, Response, Infallible>),
+}
+
+#[derive(Clone)]
+pub struct FeatureGate {
+    client: Client,
+    key: Arc<str>,
+    resolve: Resolver,
+    fallback: Fallback,
+}
+
+impl FeatureGate {
+    pub fn new(client: Client, key: impl Into<Arc<str>>) -> Self {
+        FeatureGate { client, key: key.into(), resolve: Arc::new(request_context), fallback: Fallback::Reject(StatusCode::NOT_FOUND) }
+    }
+
+    pub fn context(mut self, resolve: impl Fn(&Parts) -> Context + Send + Sync + 'static) -> Self { self.resolve = Arc::new(resolve); self }
+
+    pub fn reject_with(mut self, status: StatusCode) -> Self { self.fallback = Fallback::Reject(status); self }
+
+    pub fn reroute<T>(mut self, service: T) -> Self
+    where T: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static, T::Future: Send + 'static {
+        self.fallback = Fallback::Reroute(BoxCloneService::new(service));
+        self
+    }
+}
+
+impl<S> Layer<S> for FeatureGate {
+    type Service = FeatureGateService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service { FeatureGateService { gate: self.clone(), inner } }
+}
+
+#[derive(Clone)]
+pub struct FeatureGateService<S> {
+    gate: FeatureGate,
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for FeatureGateService<S>
+where S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static, S::Future: Send + 'static {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> { self.inner.poll_ready(cx) }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let gate = self.gate.clone();
+        let inner = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, inner);
+        Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            let ctx = (gate.resolve)(&parts);
+            let enabled = gate.client.is_enabled(&gate.key, &ctx).await;
+            parts.extensions.insert(ctx);
+            let req = Request::from_parts(parts, body);
+            if enabled { return inner.call(req).await; }
+            match gate.fallback {
+                Fallback::Reject(status) => Ok(status.into_response()),
+                Fallback::Reroute(service) => service.oneshot(req).await,
+            }
+        })
+    }
+}
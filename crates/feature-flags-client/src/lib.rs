@@ -0,0 +1,12 @@
+mod client;
+mod context;
+#[cfg(feature = "axum")]
+mod gate;
+
+pub use client::{Client, ClientBuilder, Error};
+pub use context::Context;
+pub use feature_flags_core::{EvalResponse, SignatureError, SignatureVerifier, Signed};
+#[cfg(feature = "macros")]
+pub use feature_flags_macros::flag_keys;
+#[cfg(feature = "axum")]
+pub use gate::{Enabled, FeatureGate, FeatureGateService, Flag, FlagKey};
@@ -0,0 +1,50 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use feature_flags_core::{eval_flag, Bundle, Flag};
+use std::collections::HashMap;
+
+fn flag(key: &str, rollout: Option<u8>, variants: usize) -> Flag {
+    let variants = (variants > 0).then(|| (0..variants).map(|i| (format!("v{i}"), 1)).collect::<HashMap<_, _>>());
+    Flag { id: 1, uid: String::new(), key: key.into(), project: "default".into(), enabled: true, protected: false, variants, rollout, lifecycle: Default::default(), updated_at: "2024-01-01 00:00:00".into(), pins: Default::default() }
+}
+
+fn eval(c: &mut Criterion) {
+    let users: Vec<String> = (0..1024).map(|i| format!("user-{i}")).collect();
+    let mut group = c.benchmark_group("eval_flag");
+    group.throughput(Throughput::Elements(1));
+    for (name, f) in [
+        ("boolean", flag("checkout", None, 0)),
+        ("rollout", flag("checkout", Some(50), 0)),
+        ("variants_2", flag("checkout", Some(100), 2)),
+        ("variants_16", flag("checkout", Some(100), 16)),
+    ] {
+        let mut i = 0;
+        group.bench_function(name, |b| b.iter(|| {
+            i = (i + 1) % users.len();
+            eval_flag(black_box(&f), Some(black_box(users[i].as_str())))
+        }));
+    }
+    group.bench_function("anonymous", |b| { let f = flag("checkout", Some(50), 2); b.iter(|| eval_flag(black_box(&f), None)) });
+    group.finish();
+}
+
+fn snapshot(c: &mut Criterion) {
+    let mut group = c.benchmark_group("snapshot");
+    for size in [10, 100, 1000] {
+        let flags: Vec<Flag> = (0..size).map(|i| flag(&format!("flag_{i}"), Some(50), 3)).collect();
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("eval_and_serialize", size), &flags, |b, flags| b.iter(|| {
+            let out: Vec<_> = flags.iter().map(|f| eval_flag(f, Some("user-42"))).collect();
+            serde_json::to_vec(&out).unwrap()
+        }));
+        let bundle = Bundle { environment: "prod".into(), generated_at: "2024-01-01T00:00:00Z".into(), flags: flags.clone() };
+        let raw = serde_json::to_vec(&bundle).unwrap();
+        group.bench_with_input(BenchmarkId::new("bundle_roundtrip", size), &raw, |b, raw| b.iter(|| {
+            let bundle: Bundle = serde_json::from_slice(black_box(raw)).unwrap();
+            serde_json::to_vec(&bundle).unwrap()
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, eval, snapshot);
+criterion_main!(benches);
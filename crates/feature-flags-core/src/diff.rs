@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Flag;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: serde_json::Value,
+    pub to: serde_json::Value,
+}
+
+pub fn diff_flags(before: Option<&Flag>, after: &Flag) -> Vec<FieldChange> {
+    let from = before.map(|b| serde_json::to_value(b).unwrap()).unwrap_or(serde_json::Value::Null);
+    let to = serde_json::to_value(after).unwrap();
+    let mut out = Vec::new();
+    if let serde_json::Value::Object(fields) = &to {
+        for (field, new) in fields {
+            if field == "id" || field == "updated_at" { continue; }
+            let old = from.get(field).cloned().unwrap_or(serde_json::Value::Null);
+            if &old != new { out.push(FieldChange { field: field.clone(), from: old, to: new.clone() }); }
+        }
+    }
+    out
+}
@@ -0,0 +1,163 @@
+use crate::{EvalResponse, Flag};
+
+pub fn eval_flag(flag: &Flag, user_id: Option<&str>) -> EvalResponse {
+    if let Some(pin) = user_id.and_then(|uid| flag.pins.get(uid)) {
+        return EvalResponse { key: flag.key.clone(), matched: pin.enabled, variant: pin.variant.clone().filter(|_| pin.enabled) };
+    }
+    let gate = match flag.rollout {
+        None => true,
+        Some(p) => match user_id { None => false, Some(uid) => rollout_bucket(&flag.key, uid) < p },
+    };
+    if !flag.enabled || !gate { return EvalResponse { key: flag.key.clone(), matched: false, variant: None }; }
+    if let Some(vs) = &flag.variants {
+        // summed as u64 so weights from an unchecked bundle can't overflow; any sum that fits a u32 picks as before
+        let total: u64 = vs.values().map(|w| u64::from(*w)).sum();
+        if total == 0 { return EvalResponse { key: flag.key.clone(), matched: true, variant: None }; }
+        let pick = match user_id { None => 0, Some(uid) => u64::from(variant_hash(&flag.key, uid)) % total };
+        let mut acc = 0u64;
+        let mut ordered: Vec<_> = vs.iter().collect();
+        ordered.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, weight) in ordered { acc += u64::from(*weight); if pick < acc { return EvalResponse { key: flag.key.clone(), matched: true, variant: Some(name.clone()) }; } }
+        return EvalResponse { key: flag.key.clone(), matched: true, variant: None };
+    }
+    EvalResponse { key: flag.key.clone(), matched: true, variant: None }
+}
+
+// pins are stored by the id the user was pinned as, which for an identified user isn't the anonymous id they're bucketed by
+pub fn eval_pinned(flag: &Flag, user_id: Option<&str>, bucketing_id: Option<&str>) -> EvalResponse {
+    if let Some(pin) = user_id.and_then(|uid| flag.pins.get(uid)) {
+        return EvalResponse { key: flag.key.clone(), matched: pin.enabled, variant: pin.variant.clone().filter(|_| pin.enabled) };
+    }
+    eval_flag(flag, bucketing_id)
+}
+
+// 0..100; a user is in a rollout of p percent when their bucket is below p
+pub fn rollout_bucket(key: &str, user_id: &str) -> u8 {
+    let mut hasher = blake3::Hasher::new(); hasher.update(key.as_bytes()); hasher.update(b":"); hasher.update(user_id.as_bytes()); let h = hasher.finalize(); h.as_bytes()[0] % 100
+}
+
+// the variant is the first, by name, whose running weight total is above this modulo the weights' sum
+pub fn variant_hash(key: &str, user_id: &str) -> u32 {
+    let mut hasher = blake3::Hasher::new(); hasher.update(key.as_bytes()); hasher.update(b"/"); hasher.update(user_id.as_bytes()); let hh = hasher.finalize(); u32::from_le_bytes(hk(hh.as_bytes()))
+}
+
+fn hk(b: &[u8]) -> [u8; 4] { [b[0], b[1], b[2], b[3]] }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pin;
+
+    fn flag(key: &str, rollout: Option<u8>, variants: &[(&str, u32)]) -> Flag {
+        let variants = (!variants.is_empty()).then(|| variants.iter().map(|(n, w)| (n.to_string(), *w)).collect());
+        Flag { id: 0, uid: String::new(), key: key.into(), project: String::new(), enabled: true, protected: false, variants, rollout, lifecycle: Default::default(), updated_at: String::new(), pins: Default::default() }
+    }
+
+    // the configurations /bucketing/test-vectors serves for its six keys, in the same order
+    fn vector_flag(key: &str) -> Flag {
+        match key {
+            "new_checkout" => flag(key, None, &[]),
+            "dark_mode" => flag(key, Some(50), &[]),
+            "search.ranking-v2" => flag(key, Some(10), &[("a", 50), ("b", 50)]),
+            "ümlaut_flag" => flag(key, None, &[("control", 34), ("treatment_a", 33), ("treatment_b", 33)]),
+            "k" => flag(key, Some(100), &[("off", 3), ("on", 1)]),
+            _ => flag(key, Some(75), &[("a", 0), ("b", 7), ("c", 3)]),
+        }
+    }
+
+    // key, user id, bucket, variant hash, matched, variant
+    type Vector = (&'static str, &'static str, u8, u32, bool, Option<&'static str>);
+
+    // copied from /bucketing/test-vectors; SDK ports check against the same output, so a change here breaks every one of them
+    const VECTORS: &[Vector] = &[
+        ("new_checkout", "user-1", 23, 1905354592, true, None),
+        ("new_checkout", "alice@example.com", 15, 613222949, true, None),
+        ("new_checkout", "42", 45, 3507001183, true, None),
+        ("new_checkout", "ユーザー", 64, 1424233609, true, None),
+        ("new_checkout", "", 59, 2213665148, true, None),
+        ("new_checkout", "user with spaces", 87, 3997059739, true, None),
+        ("dark_mode", "user-1", 89, 1721042460, false, None),
+        ("dark_mode", "alice@example.com", 92, 245747508, false, None),
+        ("dark_mode", "42", 48, 1319415366, true, None),
+        ("dark_mode", "ユーザー", 11, 4152219762, true, None),
+        ("dark_mode", "", 4, 3432847666, true, None),
+        ("dark_mode", "user with spaces", 47, 323641923, true, None),
+        ("search.ranking-v2", "user-1", 46, 4002431344, false, None),
+        ("search.ranking-v2", "alice@example.com", 99, 2168928651, false, None),
+        ("search.ranking-v2", "42", 59, 330810388, false, None),
+        ("search.ranking-v2", "ユーザー", 9, 1962873081, true, Some("b")),
+        ("search.ranking-v2", "", 94, 132626390, false, None),
+        ("search.ranking-v2", "user with spaces", 24, 719752010, false, None),
+        ("ümlaut_flag", "user-1", 2, 3955098458, true, Some("treatment_a")),
+        ("ümlaut_flag", "alice@example.com", 38, 2496578514, true, Some("control")),
+        ("ümlaut_flag", "42", 70, 4130817120, true, Some("control")),
+        ("ümlaut_flag", "ユーザー", 31, 1825721695, true, Some("treatment_b")),
+        ("ümlaut_flag", "", 76, 3725872747, true, Some("treatment_a")),
+        ("ümlaut_flag", "user with spaces", 24, 3790694108, true, Some("control")),
+        ("k", "user-1", 93, 2434170104, true, Some("off")),
+        ("k", "alice@example.com", 0, 1749791332, true, Some("off")),
+        ("k", "42", 65, 2923887862, true, Some("off")),
+        ("k", "ユーザー", 56, 2328943118, true, Some("off")),
+        ("k", "", 26, 4267035731, true, Some("on")),
+        ("k", "user with spaces", 58, 877006722, true, Some("off")),
+        ("checkout/v2 💳", "user-1", 52, 2235369558, true, Some("c")),
+        ("checkout/v2 💳", "alice@example.com", 69, 2477244950, true, Some("b")),
+        ("checkout/v2 💳", "42", 9, 2244717554, true, Some("b")),
+        ("checkout/v2 💳", "ユーザー", 39, 4279680008, true, Some("c")),
+        ("checkout/v2 💳", "", 91, 4269807500, false, None),
+        ("checkout/v2 💳", "user with spaces", 38, 1036878978, true, Some("c")),
+    ];
+
+    #[test]
+    fn matches_the_published_test_vectors() {
+        for &(key, user, bucket, hash, matched, variant) in VECTORS {
+            assert_eq!(rollout_bucket(key, user), bucket, "bucket of {key:?} for {user:?}");
+            assert_eq!(variant_hash(key, user), hash, "variant hash of {key:?} for {user:?}");
+            let res = eval_flag(&vector_flag(key), Some(user));
+            assert_eq!((res.matched, res.variant.as_deref()), (matched, variant), "{key:?} for {user:?}");
+        }
+    }
+
+    #[test]
+    fn rollout_needs_a_user() {
+        assert!(eval_flag(&flag("f", None, &[]), None).matched);
+        assert!(!eval_flag(&flag("f", Some(100), &[]), None).matched);
+        assert!(!eval_flag(&flag("f", Some(0), &[]), Some("user-1")).matched);
+    }
+
+    #[test]
+    fn disabled_flags_never_match() {
+        let mut f = flag("f", None, &[("a", 1)]);
+        f.enabled = false;
+        let res = eval_flag(&f, Some("user-1"));
+        assert!(!res.matched);
+        assert_eq!(res.variant, None);
+    }
+
+    #[test]
+    fn zero_weights_match_without_a_variant() {
+        let res = eval_flag(&flag("f", None, &[("a", 0), ("b", 0)]), Some("user-1"));
+        assert!(res.matched);
+        assert_eq!(res.variant, None);
+    }
+
+    #[test]
+    fn weights_above_u32_do_not_overflow() {
+        let res = eval_flag(&flag("f", None, &[("a", u32::MAX), ("b", u32::MAX)]), Some("user-1"));
+        assert!(res.matched);
+        assert!(res.variant.is_some());
+    }
+
+    #[test]
+    fn pins_win_over_the_rollout() {
+        let mut f = flag("f", Some(0), &[("a", 1), ("b", 0)]);
+        f.pins.insert("qa".into(), Pin { enabled: true, variant: Some("b".into()), expires_at: None });
+        let res = eval_flag(&f, Some("qa"));
+        assert!(res.matched);
+        assert_eq!(res.variant.as_deref(), Some("b"));
+        // a linked user keeps their pin while being bucketed by their anonymous id
+        assert_eq!(eval_pinned(&f, Some("qa"), Some("anon-1")).variant.as_deref(), Some("b"));
+        f.pins.insert("qa".into(), Pin { enabled: false, variant: Some("b".into()), expires_at: None });
+        assert_eq!(eval_flag(&f, Some("qa")).variant, None);
+    }
+}
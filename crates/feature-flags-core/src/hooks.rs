@@ -0,0 +1,105 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, sync::Arc};
+
+use crate::{eval_pinned, EvalResponse, Flag};
+
+// what the caller knows about the user beyond their id, e.g. country or os; evaluation itself only buckets on the id
+pub type Attributes = BTreeMap<String, serde_json::Value>;
+
+static NO_ATTRIBUTES: Attributes = BTreeMap::new();
+
+pub struct EvalContext<'a> {
+    pub flag: &'a Flag,
+    pub user_id: Option<&'a str>,
+    pub attributes: &'a Attributes,
+}
+
+// before hooks run in order and the first to return a result replaces evaluation; after hooks all run and may change the result
+pub trait EvalHook: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn before(&self, _ctx: &EvalContext) -> Option<EvalResponse> { None }
+
+    fn after(&self, _ctx: &EvalContext, _res: &mut EvalResponse) {}
+}
+
+#[derive(Clone, Default)]
+pub struct Hooks {
+    hooks: Vec<Arc<dyn EvalHook>>,
+}
+
+impl Hooks {
+    pub fn new() -> Hooks { Hooks::default() }
+
+    pub fn push(&mut self, hook: Arc<dyn EvalHook>) { self.hooks.push(hook); }
+
+    pub fn is_empty(&self) -> bool { self.hooks.is_empty() }
+
+    pub fn names(&self) -> Vec<&str> { self.hooks.iter().map(|h| h.name()).collect() }
+
+    pub fn evaluate(&self, flag: &Flag, user_id: Option<&str>) -> EvalResponse { self.evaluate_with(flag, user_id, &NO_ATTRIBUTES) }
+
+    pub fn evaluate_with(&self, flag: &Flag, user_id: Option<&str>, attributes: &Attributes) -> EvalResponse { self.evaluate_for(flag, user_id, user_id, attributes) }
+
+    // for callers that bucket a user by another id (an identified user's anonymous id): pins still match the user id
+    pub fn evaluate_for(&self, flag: &Flag, user_id: Option<&str>, bucketing_id: Option<&str>, attributes: &Attributes) -> EvalResponse {
+        if self.hooks.is_empty() { return eval_pinned(flag, user_id, bucketing_id); }
+        let ctx = EvalContext { flag, user_id: bucketing_id, attributes };
+        let mut res = self.hooks.iter().find_map(|h| h.before(&ctx)).unwrap_or_else(|| eval_pinned(flag, user_id, bucketing_id));
+        for h in &self.hooks { h.after(&ctx, &mut res); }
+        res
+    }
+}
+
+pub type HookFactory = fn(&str) -> Result<Arc<dyn EvalHook>, String>;
+
+// named hook constructors, so deployments pick hooks by configuration: `log; denylist=u-1,u-2`
+pub struct HookRegistry {
+    factories: HashMap<String, HookFactory>,
+}
+
+impl Default for HookRegistry {
+    fn default() -> Self {
+        let mut registry = HookRegistry { factories: HashMap::new() };
+        registry.register("denylist", |arg| Ok(Arc::new(Denylist::parse(arg)?)));
+        registry
+    }
+}
+
+impl HookRegistry {
+    pub fn new() -> HookRegistry { HookRegistry::default() }
+
+    pub fn register(&mut self, name: &str, factory: HookFactory) { self.factories.insert(name.to_string(), factory); }
+
+    pub fn build(&self, spec: &str) -> Result<Hooks, String> {
+        let mut hooks = Hooks::new();
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, arg) = entry.split_once('=').map_or((entry, ""), |(n, a)| (n.trim(), a.trim()));
+            let factory = self.factories.get(name).ok_or_else(|| format!("unknown evaluation hook {name}"))?;
+            hooks.push(factory(arg).map_err(|e| format!("{name}: {e}"))?);
+        }
+        Ok(hooks)
+    }
+}
+
+// users that never get any flag, e.g. abusive or internal test accounts
+pub struct Denylist {
+    users: HashSet<String>,
+}
+
+impl Denylist {
+    pub fn new(users: impl IntoIterator<Item = String>) -> Denylist { Denylist { users: users.into_iter().collect() } }
+
+    fn parse(arg: &str) -> Result<Denylist, String> {
+        let users: HashSet<String> = arg.split(',').map(str::trim).filter(|u| !u.is_empty()).map(String::from).collect();
+        if users.is_empty() { return Err("expects a comma-separated list of user ids".into()); }
+        Ok(Denylist { users })
+    }
+}
+
+impl EvalHook for Denylist {
+    fn name(&self) -> &str { "denylist" }
+
+    fn before(&self, ctx: &EvalContext) -> Option<EvalResponse> {
+        ctx.user_id.filter(|u| self.users.contains(*u)).map(|_| EvalResponse { key: ctx.flag.key.clone(), matched: false, variant: None })
+    }
+}
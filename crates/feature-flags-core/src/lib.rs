@@ -0,0 +1,28 @@
+mod diff;
+mod eval;
+mod hooks;
+mod lint;
+mod memory;
+mod model;
+mod signing;
+mod store;
+
+#[cfg(feature = "sqlite")]
+pub mod migrations;
+#[cfg(feature = "sqlite")]
+mod service;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use diff::{diff_flags, FieldChange};
+pub use eval::{eval_flag, eval_pinned, rollout_bucket, variant_hash};
+pub use hooks::{Attributes, Denylist, EvalContext, EvalHook, HookFactory, HookRegistry, Hooks};
+pub use lint::{lint_flag, Issue, Lint};
+pub use memory::MemoryStore;
+pub use model::{default_project, is_uuid, Bundle, CreateFlag, EvalResponse, Flag, Lifecycle, Pin, UpdateFlag};
+pub use signing::{BundleSigner, Envelope, Expected, SignatureError, SignatureVerifier, Signed};
+pub use store::{FlagStore, StoreError};
+#[cfg(feature = "sqlite")]
+pub use service::{FlagService, FlagServiceBuilder};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Flag, Lifecycle};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Issue {
+    pub code: String,
+    pub message: String,
+}
+
+// errors make a flag misbehave and block saving it; warnings are legal but probably not what was meant
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Lint {
+    pub errors: Vec<Issue>,
+    pub warnings: Vec<Issue>,
+}
+
+impl Lint {
+    pub fn is_valid(&self) -> bool { self.errors.is_empty() }
+
+    fn error(&mut self, code: &str, message: String) { self.errors.push(Issue { code: code.into(), message }); }
+
+    fn warn(&mut self, code: &str, message: String) { self.warnings.push(Issue { code: code.into(), message }); }
+}
+
+pub fn lint_flag(flag: &Flag) -> Lint {
+    let mut lint = Lint::default();
+    if let Some(r) = flag.rollout.filter(|r| *r > 100) { lint.error("rollout_out_of_range", format!("rollout is {r}; it must be between 0 and 100")); }
+    if flag.enabled && flag.rollout == Some(0) { lint.warn("serves_nobody", "enabled with a rollout of 0, so no user gets the flag".into()); }
+
+    if let Some(variants) = &flag.variants {
+        let mut names: Vec<&String> = variants.keys().collect();
+        names.sort();
+        let total: u64 = variants.values().map(|w| u64::from(*w)).sum();
+        if names.iter().any(|n| n.trim().is_empty()) { lint.error("empty_variant_name", "variant names must not be empty".into()); }
+        if total > u64::from(u32::MAX) { lint.error("variant_weights_overflow", format!("variant weights sum to {total}, more than {}", u32::MAX)); }
+        if total == 0 {
+            lint.warn("no_variant_served", "variants are set but none has a weight, so matched users get no variant".into());
+        } else {
+            for name in names.iter().filter(|n| variants[**n] == 0) { lint.warn("unreachable_variant", format!("variant {name} has weight 0 and is never served")); }
+            if total != 100 { lint.warn("variant_weights_not_100", format!("variant weights sum to {total}, not 100; each variant gets its weight out of {total}")); }
+        }
+        if variants.len() == 1 { lint.warn("single_variant", format!("{} is the only variant, so every matched user gets it", names[0])); }
+    }
+
+    let mut pinned: Vec<(&String, &String)> = flag.pins.iter().filter_map(|(user, p)| p.variant.as_ref().map(|v| (user, v))).filter(|(_, v)| !flag.variants.as_ref().is_some_and(|vs| vs.contains_key(*v))).collect();
+    pinned.sort();
+    for (user, variant) in pinned { lint.warn("pinned_unknown_variant", format!("{user} is pinned to {variant}, which is not one of the flag's variants")); }
+
+    match flag.lifecycle {
+        Lifecycle::Launched if !flag.enabled || flag.rollout.is_some_and(|r| r < 100) => lint.warn("launched_not_fully_on", "marked launched but not on for everyone".into()),
+        Lifecycle::Archived if flag.enabled => lint.warn("archived_enabled", "archived but still enabled".into()),
+        _ => {}
+    }
+    lint
+}
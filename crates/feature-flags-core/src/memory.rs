@@ -0,0 +1,96 @@
+use async_trait::async_trait;
+use std::{collections::BTreeMap, hash::{BuildHasher, RandomState}, sync::{atomic::{AtomicI64, Ordering}, RwLock}};
+
+use crate::{CreateFlag, Flag, FlagStore, Lifecycle, StoreError, UpdateFlag};
+
+#[derive(Default)]
+pub struct MemoryStore {
+    flags: RwLock<BTreeMap<String, Flag>>,
+    next_id: AtomicI64,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self { MemoryStore::default() }
+
+    pub fn with_flags(flags: impl IntoIterator<Item = Flag>) -> Self {
+        let store = MemoryStore::default();
+        for mut f in flags {
+            f.id = store.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+            store.flags.write().unwrap().insert(f.key.clone(), f);
+        }
+        store
+    }
+
+    pub fn put(&self, mut flag: Flag) -> Flag {
+        let mut flags = self.flags.write().unwrap();
+        flag.id = flags.get(&flag.key).map_or_else(|| self.next_id.fetch_add(1, Ordering::Relaxed) + 1, |f| f.id);
+        flag.updated_at = now();
+        flags.insert(flag.key.clone(), flag.clone());
+        flag
+    }
+}
+
+#[async_trait]
+impl FlagStore for MemoryStore {
+    async fn list(&self) -> Result<Vec<Flag>, StoreError> {
+        Ok(self.flags.read().unwrap().values().cloned().collect())
+    }
+
+    async fn get(&self, key: &str) -> Result<Flag, StoreError> {
+        self.flags.read().unwrap().get(key).cloned().ok_or(StoreError::NotFound)
+    }
+
+    async fn create(&self, input: &CreateFlag) -> Result<Flag, StoreError> {
+        input.validate()?;
+        let mut flags = self.flags.write().unwrap();
+        if flags.contains_key(&input.key) { return Err(StoreError::Conflict); }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let f = Flag {
+            id,
+            uid: input.uid.clone().unwrap_or_else(|| new_uid(&input.key)),
+            key: input.key.clone(),
+            project: input.project.clone(),
+            enabled: input.enabled,
+            protected: input.protected,
+            variants: input.variants.clone(),
+            rollout: input.rollout,
+            lifecycle: Lifecycle::default(),
+            updated_at: now(),
+            pins: Default::default(),
+        };
+        flags.insert(f.key.clone(), f.clone());
+        Ok(f)
+    }
+
+    async fn update(&self, key: &str, input: &UpdateFlag) -> Result<Flag, StoreError> {
+        input.validate()?;
+        let mut flags = self.flags.write().unwrap();
+        let f = flags.get_mut(key).ok_or(StoreError::NotFound)?;
+        if let Some(enabled) = input.enabled { f.enabled = enabled; }
+        if let Some(protected) = input.protected { f.protected = protected; }
+        if let Some(variants) = &input.variants { f.variants = Some(variants.clone()); }
+        if let Some(rollout) = input.rollout { f.rollout = Some(rollout); }
+        if let Some(lifecycle) = input.lifecycle {
+            if !f.lifecycle.can_become(lifecycle) { return Err(StoreError::Invalid("lifecycle transition not allowed")); }
+            f.lifecycle = lifecycle;
+        }
+        f.updated_at = now();
+        Ok(f.clone())
+    }
+
+    async fn delete(&self, key: &str) -> Result<Flag, StoreError> {
+        self.flags.write().unwrap().remove(key).ok_or(StoreError::NotFound)
+    }
+}
+
+// a random v4 UUID; std seeds every RandomState from the OS, so this needs no RNG dependency
+fn new_uid(key: &str) -> String {
+    let seed = (RandomState::new().hash_one(key), RandomState::new().hash_one(key), chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default());
+    let mut b: [u8; 16] = blake3::hash(format!("{seed:?}{key}").as_bytes()).as_bytes()[..16].try_into().unwrap();
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let hex: String = b.iter().map(|x| format!("{x:02x}")).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+fn now() -> String { chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string() }
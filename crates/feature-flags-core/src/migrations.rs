@@ -0,0 +1,392 @@
+use sqlx::{Pool, Row, Sqlite};
+
+// SQL expression for a random v4 UUID
+macro_rules! new_uuid {
+    () => { "lower(hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-' || substr('89ab', 1 + (abs(random()) % 4), 1) || substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)))" };
+}
+pub(crate) use new_uuid;
+
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS flags (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        key TEXT UNIQUE NOT NULL,
+        enabled INTEGER NOT NULL,
+        variants TEXT NULL,
+        rollout INTEGER NULL,
+        updated_at TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS flag_revisions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        flag_key TEXT NOT NULL,
+        rev INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        UNIQUE (flag_key, rev)
+    )",
+    "CREATE TABLE IF NOT EXISTS api_keys (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        key_hash TEXT UNIQUE NOT NULL,
+        created_at TEXT NOT NULL
+    )",
+    "ALTER TABLE api_keys ADD COLUMN kind TEXT NOT NULL DEFAULT 'server'",
+    "ALTER TABLE flags ADD COLUMN project TEXT NOT NULL DEFAULT 'default'",
+    "CREATE TABLE IF NOT EXISTS key_roles (
+        key_id INTEGER NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+        project TEXT NOT NULL,
+        role TEXT NOT NULL,
+        PRIMARY KEY (key_id, project)
+    )",
+    "INSERT OR IGNORE INTO key_roles (key_id, project, role) SELECT id, '*', 'admin' FROM api_keys WHERE kind = 'server'",
+    "CREATE TABLE key_roles_new (
+        key_id INTEGER NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+        project TEXT NOT NULL,
+        environment TEXT NOT NULL DEFAULT '*',
+        role TEXT NOT NULL,
+        PRIMARY KEY (key_id, project, environment)
+    )",
+    "INSERT INTO key_roles_new (key_id, project, environment, role) SELECT key_id, project, '*', role FROM key_roles",
+    "DROP TABLE key_roles",
+    "ALTER TABLE key_roles_new RENAME TO key_roles",
+    "ALTER TABLE api_keys ADD COLUMN expires_at TEXT NULL",
+    "ALTER TABLE api_keys ADD COLUMN last_used_at TEXT NULL",
+    "ALTER TABLE api_keys ADD COLUMN revoked_at TEXT NULL",
+    "CREATE TABLE IF NOT EXISTS change_requests (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        flag_key TEXT NOT NULL,
+        project TEXT NOT NULL,
+        change TEXT NOT NULL,
+        status TEXT NOT NULL,
+        comment TEXT NULL,
+        requested_by TEXT NOT NULL,
+        reviewed_by TEXT NULL,
+        review_comment TEXT NULL,
+        created_at TEXT NOT NULL,
+        reviewed_at TEXT NULL
+    )",
+    "ALTER TABLE flags ADD COLUMN protected INTEGER NOT NULL DEFAULT 0",
+    "CREATE TABLE IF NOT EXISTS audit_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        at TEXT NOT NULL,
+        actor TEXT NULL,
+        action TEXT NOT NULL,
+        target TEXT NULL,
+        outcome TEXT NOT NULL,
+        ip TEXT NULL,
+        detail TEXT NULL
+    )",
+    "ALTER TABLE api_keys ADD COLUMN rate_limit INTEGER NULL",
+    "CREATE TABLE IF NOT EXISTS token_usage (
+        key_id INTEGER NOT NULL,
+        day TEXT NOT NULL,
+        route TEXT NOT NULL,
+        count INTEGER NOT NULL,
+        PRIMARY KEY (key_id, day, route)
+    )",
+    "ALTER TABLE api_keys ADD COLUMN previous_key_hash TEXT NULL",
+    "ALTER TABLE api_keys ADD COLUMN previous_expires_at TEXT NULL",
+    "CREATE INDEX IF NOT EXISTS api_keys_previous_key_hash ON api_keys (previous_key_hash)",
+    "ALTER TABLE audit_log ADD COLUMN prev_hash TEXT NULL",
+    "ALTER TABLE audit_log ADD COLUMN hash TEXT NULL",
+    "CREATE TABLE IF NOT EXISTS exposures (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        flag_key TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        variant TEXT NOT NULL,
+        at TEXT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS exposures_flag_user ON exposures (flag_key, user_id)",
+    "CREATE TABLE IF NOT EXISTS conversions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        event TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        value REAL NULL,
+        experiment TEXT NULL,
+        properties TEXT NULL,
+        at TEXT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS conversions_event_user ON conversions (event, user_id)",
+    "CREATE TABLE IF NOT EXISTS experiments (
+        flag_key TEXT PRIMARY KEY,
+        status TEXT NOT NULL,
+        description TEXT NULL,
+        winner TEXT NULL,
+        created_at TEXT NOT NULL,
+        started_at TEXT NULL,
+        paused_at TEXT NULL,
+        concluded_at TEXT NULL
+    )",
+    "ALTER TABLE experiments ADD COLUMN srm_detected INTEGER NULL",
+    "ALTER TABLE experiments ADD COLUMN srm_p_value REAL NULL",
+    "ALTER TABLE experiments ADD COLUMN srm_checked_at TEXT NULL",
+    "CREATE TABLE IF NOT EXISTS metric_definitions (
+        name TEXT PRIMARY KEY,
+        event TEXT NOT NULL,
+        aggregation TEXT NOT NULL,
+        direction TEXT NOT NULL,
+        description TEXT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    )",
+    "ALTER TABLE experiments ADD COLUMN metric TEXT NULL",
+    "CREATE TABLE IF NOT EXISTS export_runs (
+        dataset TEXT PRIMARY KEY,
+        until TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS flag_eval_stats (
+        flag_key TEXT NOT NULL,
+        hour TEXT NOT NULL,
+        variant TEXT NOT NULL,
+        evaluations INTEGER NOT NULL,
+        matched INTEGER NOT NULL,
+        PRIMARY KEY (flag_key, hour, variant)
+    )",
+    "ALTER TABLE flags ADD COLUMN uid TEXT NULL",
+    concat!("UPDATE flags SET uid = ", new_uuid!(), " WHERE uid IS NULL"),
+    "CREATE UNIQUE INDEX IF NOT EXISTS idx_flags_uid ON flags (uid)",
+    "CREATE TABLE IF NOT EXISTS cluster_instances (
+        id TEXT PRIMARY KEY,
+        address TEXT NOT NULL,
+        environment TEXT NOT NULL,
+        started_at TEXT NOT NULL,
+        last_seen_at TEXT NOT NULL,
+        applied_seq INTEGER NOT NULL DEFAULT 0,
+        flags_cached INTEGER NOT NULL DEFAULT 0,
+        cache_age_secs INTEGER NOT NULL DEFAULT 0
+    )",
+    "CREATE TABLE IF NOT EXISTS cluster_invalidations (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        origin TEXT NOT NULL,
+        flag_key TEXT NOT NULL,
+        action TEXT NOT NULL,
+        at TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS scheduled_actions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        flag_key TEXT NOT NULL,
+        changes TEXT NOT NULL,
+        run_at TEXT NOT NULL,
+        status TEXT NOT NULL,
+        error TEXT NULL,
+        protected_ok INTEGER NOT NULL,
+        created_by TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        executed_at TEXT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS scheduled_actions_due ON scheduled_actions (status, run_at)",
+    "CREATE TABLE IF NOT EXISTS flag_guards (
+        flag_key TEXT PRIMARY KEY,
+        safe_rev INTEGER NOT NULL,
+        query TEXT NULL,
+        threshold REAL NULL,
+        status TEXT NOT NULL,
+        reason TEXT NULL,
+        armed_by TEXT NOT NULL,
+        armed_at TEXT NOT NULL,
+        tripped_at TEXT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS flag_templates (
+        name TEXT PRIMARY KEY,
+        description TEXT NULL,
+        enabled INTEGER NOT NULL,
+        variants TEXT NULL,
+        rollout INTEGER NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS context_schemas (
+        project TEXT PRIMARY KEY,
+        mode TEXT NOT NULL,
+        attributes TEXT NOT NULL,
+        updated_by TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    )",
+    "ALTER TABLE flags ADD COLUMN lifecycle TEXT NOT NULL DEFAULT 'development'",
+    "ALTER TABLE flags ADD COLUMN lifecycle_changed_at TEXT NULL",
+    "ALTER TABLE flags ADD COLUMN cleanup_nudged_at TEXT NULL",
+    "UPDATE flags SET lifecycle_changed_at = updated_at",
+    "CREATE TABLE IF NOT EXISTS teams (
+        name TEXT PRIMARY KEY,
+        description TEXT NULL,
+        webhook_url TEXT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS team_members (
+        team TEXT NOT NULL,
+        member TEXT NOT NULL,
+        added_at TEXT NOT NULL,
+        PRIMARY KEY (team, member)
+    )",
+    "CREATE TABLE IF NOT EXISTS flag_owners (
+        flag_key TEXT PRIMARY KEY,
+        team TEXT NOT NULL,
+        assigned_by TEXT NOT NULL,
+        assigned_at TEXT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS flag_owners_team ON flag_owners (team)",
+    "CREATE TABLE IF NOT EXISTS flag_comments (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        flag_key TEXT NOT NULL,
+        rev INTEGER NULL,
+        author TEXT NOT NULL,
+        body TEXT NOT NULL,
+        reply_to INTEGER NULL,
+        created_at TEXT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS flag_comments_flag ON flag_comments (flag_key, id)",
+    "CREATE TABLE IF NOT EXISTS identity_aliases (
+        user_id TEXT PRIMARY KEY,
+        anonymous_id TEXT NOT NULL,
+        linked_at TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS replication_log (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        flag_key TEXT NOT NULL,
+        at TEXT NOT NULL
+    )",
+    "CREATE TRIGGER IF NOT EXISTS replication_flag_insert AFTER INSERT ON flags BEGIN
+        INSERT INTO replication_log (flag_key, at) VALUES (NEW.key, datetime('now'));
+    END",
+    "CREATE TRIGGER IF NOT EXISTS replication_flag_update AFTER UPDATE OF uid, project, enabled, protected, variants, rollout, lifecycle, updated_at ON flags BEGIN
+        INSERT INTO replication_log (flag_key, at) VALUES (NEW.key, datetime('now'));
+    END",
+    "CREATE TRIGGER IF NOT EXISTS replication_flag_delete AFTER DELETE ON flags BEGIN
+        INSERT INTO replication_log (flag_key, at) VALUES (OLD.key, datetime('now'));
+    END",
+    "CREATE TABLE IF NOT EXISTS flag_shadows (
+        flag_key TEXT PRIMARY KEY,
+        enabled INTEGER NOT NULL,
+        variants TEXT NULL,
+        rollout INTEGER NULL,
+        set_by TEXT NOT NULL,
+        set_at TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS shadow_results (
+        flag_key TEXT NOT NULL,
+        live TEXT NOT NULL,
+        shadow TEXT NOT NULL,
+        evaluations INTEGER NOT NULL,
+        PRIMARY KEY (flag_key, live, shadow)
+    )",
+    "CREATE TABLE IF NOT EXISTS flag_pins (
+        flag_key TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        enabled INTEGER NOT NULL,
+        variant TEXT NULL,
+        expires_at TEXT NULL,
+        pinned_by TEXT NOT NULL,
+        pinned_at TEXT NOT NULL,
+        PRIMARY KEY (flag_key, user_id)
+    )",
+    "CREATE INDEX IF NOT EXISTS flag_pins_expires_at ON flag_pins (expires_at)",
+    "CREATE TRIGGER IF NOT EXISTS flag_pins_cleanup AFTER DELETE ON flags BEGIN
+        DELETE FROM flag_pins WHERE flag_key = OLD.key;
+    END",
+    "ALTER TABLE api_keys ADD COLUMN flag_prefixes TEXT NULL",
+    "CREATE TABLE IF NOT EXISTS release_groups (
+        name TEXT PRIMARY KEY,
+        description TEXT NULL,
+        created_by TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS release_group_flags (
+        group_name TEXT NOT NULL,
+        flag_key TEXT NOT NULL,
+        PRIMARY KEY (group_name, flag_key)
+    )",
+    "CREATE TRIGGER IF NOT EXISTS release_group_flags_cleanup AFTER DELETE ON flags BEGIN
+        DELETE FROM release_group_flags WHERE flag_key = OLD.key;
+    END",
+    "CREATE TABLE IF NOT EXISTS break_glass (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        subject TEXT NOT NULL,
+        project TEXT NOT NULL,
+        reason TEXT NOT NULL,
+        started_at TEXT NOT NULL,
+        expires_at TEXT NOT NULL,
+        ended_at TEXT NULL,
+        ended_by TEXT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS break_glass_subject ON break_glass (subject, ended_at)",
+    "CREATE TABLE IF NOT EXISTS code_ref_scans (
+        repository TEXT PRIMARY KEY,
+        branch TEXT NULL,
+        commit_sha TEXT NULL,
+        uploaded_by TEXT NOT NULL,
+        uploaded_at TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS code_refs (
+        repository TEXT NOT NULL,
+        flag_key TEXT NOT NULL,
+        path TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        snippet TEXT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS code_refs_flag ON code_refs (flag_key, repository)",
+    "CREATE TABLE IF NOT EXISTS project_quotas (
+        project TEXT PRIMARY KEY,
+        max_flags INTEGER NULL,
+        max_variants INTEGER NULL,
+        max_pins INTEGER NULL,
+        updated_by TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS jobs (
+        name TEXT PRIMARY KEY,
+        interval_secs INTEGER NOT NULL,
+        next_run_at TEXT NOT NULL,
+        lease_until TEXT NULL,
+        failures INTEGER NOT NULL DEFAULT 0
+    )",
+    "CREATE TABLE IF NOT EXISTS job_runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        job TEXT NOT NULL,
+        attempt INTEGER NOT NULL,
+        instance TEXT NOT NULL,
+        status TEXT NOT NULL,
+        error TEXT NULL,
+        started_at TEXT NOT NULL,
+        finished_at TEXT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS job_runs_job ON job_runs (job, id)",
+    "ALTER TABLE break_glass ADD COLUMN principal TEXT NULL",
+    "CREATE INDEX IF NOT EXISTS break_glass_principal ON break_glass (principal, ended_at)",
+    "ALTER TABLE change_requests ADD COLUMN requested_by_id TEXT NULL",
+    "CREATE TRIGGER IF NOT EXISTS replication_flag_rename AFTER UPDATE OF key ON flags WHEN OLD.key != NEW.key BEGIN
+        INSERT INTO replication_log (flag_key, at) VALUES (OLD.key, datetime('now'));
+    END",
+    "ALTER TABLE jobs ADD COLUMN rerun INTEGER NOT NULL DEFAULT 0",
+];
+
+// a binary older than the schema would otherwise set the version back, and a newer one re-run migrations that can't run twice
+fn check_not_newer(version: usize) -> Result<(), sqlx::Error> {
+    if version > MIGRATIONS.len() { return Err(sqlx::Error::Protocol(format!("database schema version {version} is newer than this binary's {}", MIGRATIONS.len()))); }
+    Ok(())
+}
+
+pub async fn run(db: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let version = sqlx::query("PRAGMA user_version").fetch_one(&mut *tx).await?.get::<i64,_>(0) as usize;
+    check_not_newer(version)?;
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        tracing::info!(version = i + 1, "applying migration");
+        sqlx::query(sql).execute(&mut *tx).await?;
+    }
+    sqlx::query(&format!("PRAGMA user_version = {}", MIGRATIONS.len())).execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// applies whatever is pending in a transaction that is then rolled back (SQLite DDL is transactional), returning how many would run
+pub async fn dry_run(db: &Pool<Sqlite>) -> Result<usize, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let version = sqlx::query("PRAGMA user_version").fetch_one(&mut *tx).await?.get::<i64,_>(0) as usize;
+    check_not_newer(version)?;
+    for sql in MIGRATIONS.iter().skip(version) {
+        sqlx::query(sql).execute(&mut *tx).await?;
+    }
+    tx.rollback().await?;
+    Ok(MIGRATIONS.len() - version)
+}
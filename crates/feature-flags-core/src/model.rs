@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::StoreError;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Flag {
+    pub id: i64,
+    #[serde(default)]
+    pub uid: String,
+    pub key: String,
+    #[serde(default = "default_project")]
+    pub project: String,
+    pub enabled: bool,
+    #[serde(default)]
+    pub protected: bool,
+    pub variants: Option<HashMap<String, u32>>,
+    pub rollout: Option<u8>,
+    #[serde(default)]
+    pub lifecycle: Lifecycle,
+    pub updated_at: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub pins: HashMap<String, Pin>,
+}
+
+// one user forced into a state of the flag, ahead of enabled, rollout and variant weights; stores only load pins that haven't expired
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Pin {
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Lifecycle {
+    #[default]
+    Development,
+    Rollout,
+    Launched,
+    Deprecated,
+    Archived,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateFlag {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<String>,
+    pub key: String,
+    #[serde(default = "default_project")]
+    pub project: String,
+    pub enabled: bool,
+    #[serde(default)]
+    pub protected: bool,
+    pub variants: Option<HashMap<String, u32>>,
+    pub rollout: Option<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UpdateFlag {
+    pub enabled: Option<bool>,
+    pub protected: Option<bool>,
+    pub variants: Option<HashMap<String, u32>>,
+    pub rollout: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lifecycle: Option<Lifecycle>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Bundle {
+    pub environment: String,
+    pub generated_at: String,
+    pub flags: Vec<Flag>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EvalResponse {
+    pub key: String,
+    pub matched: bool,
+    pub variant: Option<String>,
+}
+
+impl CreateFlag {
+    pub fn validate(&self) -> Result<(), StoreError> {
+        if self.uid.as_deref().is_some_and(|u| !is_uuid(u)) { return Err(StoreError::Invalid("uid must be a lowercase hyphenated UUID")); }
+        validate_rollout(self.rollout)
+    }
+}
+
+impl UpdateFlag {
+    pub fn validate(&self) -> Result<(), StoreError> { validate_rollout(self.rollout) }
+}
+
+impl Lifecycle {
+    pub const ALL: [Lifecycle; 5] = [Lifecycle::Development, Lifecycle::Rollout, Lifecycle::Launched, Lifecycle::Deprecated, Lifecycle::Archived];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Lifecycle::Development => "development",
+            Lifecycle::Rollout => "rollout",
+            Lifecycle::Launched => "launched",
+            Lifecycle::Deprecated => "deprecated",
+            Lifecycle::Archived => "archived",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Lifecycle> {
+        Lifecycle::ALL.into_iter().find(|l| l.as_str() == s)
+    }
+
+    // forward through the life of a flag, with a step back where that's a real-world move (pausing a rollout, un-deprecating)
+    pub fn can_become(self, next: Lifecycle) -> bool {
+        use Lifecycle::*;
+        self == next || matches!((self, next),
+            (Development, Rollout | Launched | Archived)
+            | (Rollout, Development | Launched | Archived)
+            | (Launched, Rollout | Deprecated)
+            | (Deprecated, Launched | Archived)
+            | (Archived, Deprecated))
+    }
+}
+
+fn validate_rollout(rollout: Option<u8>) -> Result<(), StoreError> {
+    if rollout.is_some_and(|r| r > 100) { return Err(StoreError::Invalid("rollout must be between 0 and 100")); }
+    Ok(())
+}
+
+pub fn is_uuid(s: &str) -> bool {
+    s.len() == 36 && s.char_indices().all(|(i, c)| if matches!(i, 8 | 13 | 18 | 23) { c == '-' } else { c.is_ascii_digit() || ('a'..='f').contains(&c) })
+}
+
+pub fn default_project() -> String { "default".into() }
@@ -0,0 +1,140 @@
+use std::{collections::HashMap, path::Path, sync::{Arc, RwLock, Weak}, time::Duration};
+
+use crate::{CreateFlag, EvalResponse, Flag, FlagStore, Hooks, MemoryStore, SqliteStore, StoreError, UpdateFlag};
+
+enum Backend {
+    Sqlite(String),
+    Store(Arc<dyn FlagStore>),
+}
+
+pub struct FlagServiceBuilder {
+    backend: Backend,
+    refresh_interval: Option<Duration>,
+    hooks: Hooks,
+}
+
+// storage, cache and evaluation in-process, for a monolith that wants the server's flag semantics without running it.
+// evaluation is synchronous and served from memory; writes go through the store and update the cache right away
+pub struct FlagService {
+    store: Arc<dyn FlagStore>,
+    flags: RwLock<Arc<HashMap<String, Flag>>>,
+    hooks: Hooks,
+}
+
+impl FlagServiceBuilder {
+    // a file path, or a full sqlite:// URL; the schema is migrated on build, so the file can be shared with a server
+    pub fn sqlite(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_string_lossy();
+        self.backend = Backend::Sqlite(if path.starts_with("sqlite:") { path.into_owned() } else { format!("sqlite://{path}?mode=rwc") });
+        self
+    }
+
+    pub fn store(mut self, store: impl FlagStore + 'static) -> Self {
+        self.backend = Backend::Store(Arc::new(store));
+        self
+    }
+
+    // re-reads every flag on this interval to pick up writes from other processes; needs a tokio runtime
+    pub fn refresh_interval(mut self, every: Duration) -> Self {
+        self.refresh_interval = Some(every);
+        self
+    }
+
+    // run around every evaluation, e.g. an entitlement check that turns flags off for accounts without the plan
+    pub fn hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    pub async fn build(self) -> Result<Arc<FlagService>, StoreError> {
+        let store: Arc<dyn FlagStore> = match self.backend {
+            Backend::Sqlite(url) => Arc::new(SqliteStore::connect(&url).await?),
+            Backend::Store(store) => store,
+        };
+        let service = Arc::new(FlagService { store, flags: RwLock::new(Arc::new(HashMap::new())), hooks: self.hooks });
+        service.reload().await?;
+        if let Some(every) = self.refresh_interval { tokio::spawn(refresh(Arc::downgrade(&service), every)); }
+        Ok(service)
+    }
+}
+
+async fn refresh(service: Weak<FlagService>, every: Duration) {
+    let mut tick = tokio::time::interval(every);
+    tick.tick().await;
+    loop {
+        tick.tick().await;
+        let Some(service) = service.upgrade() else { return };
+        if let Err(e) = service.reload().await { tracing::warn!(error = %e, "failed to refresh embedded flags, serving the previous copy"); }
+    }
+}
+
+impl FlagService {
+    // in memory until sqlite() or store() picks something else
+    pub fn builder() -> FlagServiceBuilder {
+        FlagServiceBuilder { backend: Backend::Store(Arc::new(MemoryStore::new())), refresh_interval: None, hooks: Hooks::new() }
+    }
+
+    pub async fn reload(&self) -> Result<usize, StoreError> {
+        let flags: HashMap<String, Flag> = self.store.list().await?.into_iter().map(|f| (f.key.clone(), f)).collect();
+        let count = flags.len();
+        *self.flags.write().unwrap() = Arc::new(flags);
+        Ok(count)
+    }
+
+    // an unknown flag is off, as it would be for an SDK with nothing cached
+    pub fn evaluate(&self, key: &str, user_id: Option<&str>) -> EvalResponse {
+        match self.flags.read().unwrap().get(key) {
+            Some(flag) => self.hooks.evaluate(flag, user_id),
+            None => EvalResponse { key: key.to_string(), matched: false, variant: None },
+        }
+    }
+
+    pub fn evaluate_all(&self, user_id: Option<&str>) -> Vec<EvalResponse> {
+        let flags = self.flags.read().unwrap().clone();
+        let mut out: Vec<EvalResponse> = flags.values().map(|f| self.hooks.evaluate(f, user_id)).collect();
+        out.sort_by(|a, b| a.key.cmp(&b.key));
+        out
+    }
+
+    pub fn is_enabled(&self, key: &str, user_id: Option<&str>) -> bool { self.evaluate(key, user_id).matched }
+
+    pub fn variant(&self, key: &str, user_id: Option<&str>) -> Option<String> { self.evaluate(key, user_id).variant }
+
+    pub fn flag(&self, key: &str) -> Option<Flag> { self.flags.read().unwrap().get(key).cloned() }
+
+    pub fn flags(&self) -> Vec<Flag> {
+        let mut flags: Vec<Flag> = self.flags.read().unwrap().values().cloned().collect();
+        flags.sort_by(|a, b| a.key.cmp(&b.key));
+        flags
+    }
+
+    pub async fn create(&self, input: &CreateFlag) -> Result<Flag, StoreError> {
+        let flag = self.store.create(input).await?;
+        self.cache(Some(flag.clone()), &flag.key);
+        Ok(flag)
+    }
+
+    pub async fn update(&self, key: &str, input: &UpdateFlag) -> Result<Flag, StoreError> {
+        let flag = self.store.update(key, input).await?;
+        self.cache(Some(flag.clone()), key);
+        Ok(flag)
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<Flag, StoreError> {
+        let flag = self.store.delete(key).await?;
+        self.cache(None, key);
+        Ok(flag)
+    }
+
+    pub fn store(&self) -> &dyn FlagStore { self.store.as_ref() }
+
+    fn cache(&self, flag: Option<Flag>, key: &str) {
+        let mut current = self.flags.write().unwrap();
+        let mut flags = (**current).clone();
+        match flag {
+            Some(f) => { flags.insert(key.to_string(), f); }
+            None => { flags.remove(key); }
+        }
+        *current = Arc::new(flags);
+    }
+}
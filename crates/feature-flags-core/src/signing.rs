@@ -0,0 +1,190 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::time::Duration;
+
+// the payload is the exact JSON that was signed, kept as a string so verifying never depends on re-serializing it the same way
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Signed {
+    pub algorithm: String,
+    pub key_id: String,
+    pub signature: String,
+    pub payload: String,
+}
+
+// what's actually signed: the body plus what it was issued for, so a verified copy can't be replayed to another
+// environment or user, or long after it was current. `subject` is the user or anonymous id a snapshot was evaluated for
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Envelope<T> {
+    pub environment: String,
+    pub issued_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    pub data: T,
+}
+
+// what a verifier holds an envelope to; `environment` and `max_age` are skipped when None, `subject` must match as is
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Expected<'a> {
+    pub environment: Option<&'a str>,
+    pub subject: Option<&'a str>,
+    pub max_age: Option<Duration>,
+}
+
+// how far ahead of the verifier's clock issued_at may be
+const MAX_CLOCK_SKEW: i64 = 300;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignatureError {
+    #[error("malformed key: {0}")]
+    Key(String),
+    #[error("unsupported signature algorithm {0}")]
+    Algorithm(String),
+    #[error("signed by unknown key {0}")]
+    UnknownKey(String),
+    #[error("signature doesn't match the payload")]
+    Invalid,
+    #[error("signed for {field} {found:?}, expected {expected:?}")]
+    Mismatch { field: &'static str, found: String, expected: String },
+    #[error("signed {0}s ago, longer than allowed")]
+    Expired(i64),
+    #[error("malformed signed payload: {0}")]
+    Payload(#[from] serde_json::Error),
+}
+
+// the first 8 bytes of the public key's blake3 hash, so a verifier holding several keys knows which one to use
+fn key_id(key: &VerifyingKey) -> String {
+    blake3::hash(key.as_bytes()).as_bytes()[..8].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_key(encoded: &str) -> Result<[u8; 32], SignatureError> {
+    let bytes = STANDARD.decode(encoded.trim()).map_err(|e| SignatureError::Key(e.to_string()))?;
+    bytes.try_into().map_err(|b: Vec<u8>| SignatureError::Key(format!("expected 32 bytes, got {}", b.len())))
+}
+
+pub struct BundleSigner {
+    key: SigningKey,
+    key_id: String,
+}
+
+impl BundleSigner {
+    // a base64 32-byte Ed25519 seed, e.g. from `head -c 32 /dev/urandom | base64`
+    pub fn from_base64(seed: &str) -> Result<BundleSigner, SignatureError> {
+        let key = SigningKey::from_bytes(&decode_key(seed)?);
+        let key_id = key_id(&key.verifying_key());
+        Ok(BundleSigner { key, key_id })
+    }
+
+    pub fn key_id(&self) -> &str { &self.key_id }
+
+    pub fn public_key(&self) -> String { STANDARD.encode(self.key.verifying_key().as_bytes()) }
+
+    pub fn sign_for<T: Serialize>(&self, environment: &str, subject: Option<&str>, value: &T) -> Result<Signed, serde_json::Error> {
+        self.sign(&Envelope { environment: environment.to_string(), issued_at: chrono::Utc::now().timestamp(), subject: subject.map(str::to_string), data: value })
+    }
+
+    pub fn sign<T: Serialize>(&self, value: &T) -> Result<Signed, serde_json::Error> {
+        let payload = serde_json::to_string(value)?;
+        let signature = self.key.sign(payload.as_bytes());
+        Ok(Signed { algorithm: "ed25519".into(), key_id: self.key_id.clone(), signature: STANDARD.encode(signature.to_bytes()), payload })
+    }
+}
+
+// holds every public key still trusted, so a new signing key can roll out before the old one is dropped
+pub struct SignatureVerifier {
+    keys: Vec<(String, VerifyingKey)>,
+}
+
+impl SignatureVerifier {
+    pub fn new<S: AsRef<str>>(public_keys: impl IntoIterator<Item = S>) -> Result<SignatureVerifier, SignatureError> {
+        let mut keys = Vec::new();
+        for k in public_keys {
+            let key = VerifyingKey::from_bytes(&decode_key(k.as_ref())?).map_err(|e| SignatureError::Key(e.to_string()))?;
+            keys.push((key_id(&key), key));
+        }
+        if keys.is_empty() { return Err(SignatureError::Key("no public keys".into())); }
+        Ok(SignatureVerifier { keys })
+    }
+
+    pub fn verify<T: DeserializeOwned>(&self, signed: &Signed) -> Result<T, SignatureError> {
+        if signed.algorithm != "ed25519" { return Err(SignatureError::Algorithm(signed.algorithm.clone())); }
+        let key = self.keys.iter().find(|(id, _)| *id == signed.key_id).map(|(_, k)| k).ok_or_else(|| SignatureError::UnknownKey(signed.key_id.clone()))?;
+        let bytes: [u8; 64] = STANDARD.decode(&signed.signature).ok().and_then(|b| b.try_into().ok()).ok_or(SignatureError::Invalid)?;
+        key.verify(signed.payload.as_bytes(), &Signature::from_bytes(&bytes)).map_err(|_| SignatureError::Invalid)?;
+        Ok(serde_json::from_str(&signed.payload)?)
+    }
+
+    pub fn verify_for<T: DeserializeOwned>(&self, signed: &Signed, expected: Expected) -> Result<Envelope<T>, SignatureError> {
+        let envelope: Envelope<T> = self.verify(signed)?;
+        if let Some(environment) = expected.environment.filter(|e| *e != envelope.environment) {
+            return Err(SignatureError::Mismatch { field: "environment", found: envelope.environment, expected: environment.to_string() });
+        }
+        if envelope.subject.as_deref() != expected.subject {
+            return Err(SignatureError::Mismatch { field: "subject", found: envelope.subject.unwrap_or_default(), expected: expected.subject.unwrap_or_default().to_string() });
+        }
+        let age = chrono::Utc::now().timestamp() - envelope.issued_at;
+        if age < -MAX_CLOCK_SKEW { return Err(SignatureError::Mismatch { field: "issued_at", found: envelope.issued_at.to_string(), expected: "a time in the past".into() }); }
+        if expected.max_age.is_some_and(|max| age > max.as_secs() as i64) { return Err(SignatureError::Expired(age)); }
+        Ok(envelope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(byte: u8) -> BundleSigner { BundleSigner::from_base64(&STANDARD.encode([byte; 32])).unwrap() }
+
+    fn verifier(signers: &[&BundleSigner]) -> SignatureVerifier { SignatureVerifier::new(signers.iter().map(|s| s.public_key())).unwrap() }
+
+    fn expect<'a>(environment: &'a str, subject: Option<&'a str>) -> Expected<'a> {
+        Expected { environment: Some(environment), subject, max_age: Some(Duration::from_secs(60)) }
+    }
+
+    #[test]
+    fn accepts_what_it_was_signed_for() {
+        let s = signer(1);
+        let signed = s.sign_for("prod", Some("user-1"), &vec![1, 2, 3]).unwrap();
+        let envelope: Envelope<Vec<i32>> = verifier(&[&s]).verify_for(&signed, expect("prod", Some("user-1"))).unwrap();
+        assert_eq!(envelope.data, vec![1, 2, 3]);
+        // no expected environment accepts any
+        let any = Expected { environment: None, ..expect("", Some("user-1")) };
+        assert!(verifier(&[&s]).verify_for::<Vec<i32>>(&signed, any).is_ok());
+    }
+
+    #[test]
+    fn accepts_any_trusted_key() {
+        let (old, new) = (signer(1), signer(2));
+        let signed = old.sign_for("prod", None, &"x").unwrap();
+        assert!(verifier(&[&new, &old]).verify_for::<String>(&signed, expect("prod", None)).is_ok());
+    }
+
+    #[test]
+    fn rejects_another_environment_or_subject() {
+        let s = signer(1);
+        let signed = s.sign_for("staging", Some("user-1"), &"x").unwrap();
+        let v = verifier(&[&s]);
+        assert!(matches!(v.verify_for::<String>(&signed, expect("prod", Some("user-1"))), Err(SignatureError::Mismatch { field: "environment", .. })));
+        assert!(matches!(v.verify_for::<String>(&signed, expect("staging", Some("user-2"))), Err(SignatureError::Mismatch { field: "subject", .. })));
+        assert!(matches!(v.verify_for::<String>(&signed, expect("staging", None)), Err(SignatureError::Mismatch { field: "subject", .. })));
+    }
+
+    #[test]
+    fn rejects_a_tampered_payload_or_unknown_key() {
+        let s = signer(1);
+        let mut signed = s.sign_for("prod", None, &"x").unwrap();
+        assert!(matches!(verifier(&[&signer(2)]).verify_for::<String>(&signed, expect("prod", None)), Err(SignatureError::UnknownKey(_))));
+        signed.payload = signed.payload.replace("prod", "prid");
+        assert!(matches!(verifier(&[&s]).verify_for::<String>(&signed, expect("prid", None)), Err(SignatureError::Invalid)));
+    }
+
+    #[test]
+    fn rejects_old_or_future_envelopes() {
+        let s = signer(1);
+        let v = verifier(&[&s]);
+        let at = |offset: i64| s.sign(&Envelope { environment: "prod".into(), issued_at: chrono::Utc::now().timestamp() + offset, subject: None, data: "x" }).unwrap();
+        assert!(matches!(v.verify_for::<String>(&at(-120), expect("prod", None)), Err(SignatureError::Expired(_))));
+        assert!(v.verify_for::<String>(&at(-120), Expected { max_age: None, ..expect("prod", None) }).is_ok());
+        assert!(matches!(v.verify_for::<String>(&at(MAX_CLOCK_SKEW + 60), expect("prod", None)), Err(SignatureError::Mismatch { field: "issued_at", .. })));
+    }
+}
@@ -0,0 +1,240 @@
+use async_trait::async_trait;
+use sqlx::{sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteRow}, Pool, Row, Sqlite, SqliteConnection};
+use std::{collections::HashMap, str::FromStr};
+
+use crate::{migrations::new_uuid, CreateFlag, Flag, FlagStore, Lifecycle, Pin, StoreError, UpdateFlag};
+
+// static SQL so every call hits the per-connection prepared statement cache
+macro_rules! select_flag {
+    ($tail:literal) => { concat!("SELECT id, uid, key, project, enabled, protected, variants, rollout, lifecycle, updated_at, ", select_pins!(), " AS pins FROM flags ", $tail) };
+}
+
+// unexpired pins as a JSON object by user id, NULL when there are none
+macro_rules! select_pins {
+    () => { "(SELECT json_group_object(p.user_id, json_object('enabled', json(CASE WHEN p.enabled THEN 'true' ELSE 'false' END), 'variant', p.variant, 'expires_at', p.expires_at)) FROM flag_pins p WHERE p.flag_key = flags.key AND (p.expires_at IS NULL OR p.expires_at > datetime('now')) HAVING COUNT(*) > 0)" };
+}
+
+const STATEMENT_CACHE: usize = 256;
+
+pub const LIST_FLAGS: &str = select_flag!("ORDER BY key");
+
+impl From<sqlx::Error> for StoreError {
+    fn from(e: sqlx::Error) -> Self { StoreError::Backend(Box::new(e)) }
+}
+
+#[derive(Clone)]
+pub struct SqliteStore {
+    db: Pool<Sqlite>,
+}
+
+impl SqliteStore {
+    pub fn new(db: Pool<Sqlite>) -> Self { SqliteStore { db } }
+
+    pub async fn connect(url: &str) -> Result<Self, StoreError> {
+        let db = sqlx::sqlite::SqlitePoolOptions::new().max_connections(5).connect_with(connect_options(url)?).await?;
+        crate::migrations::run(&db).await?;
+        Ok(SqliteStore { db })
+    }
+
+    pub fn pool(&self) -> &Pool<Sqlite> { &self.db }
+}
+
+#[async_trait]
+impl FlagStore for SqliteStore {
+    async fn list(&self) -> Result<Vec<Flag>, StoreError> {
+        list_flags(&mut *self.db.acquire().await?).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Flag, StoreError> {
+        fetch_flag(&mut *self.db.acquire().await?, key).await
+    }
+
+    async fn create(&self, input: &CreateFlag) -> Result<Flag, StoreError> {
+        let mut tx = self.db.begin().await?;
+        let f = insert_flag(&mut tx, input).await?;
+        tx.commit().await?;
+        Ok(f)
+    }
+
+    async fn update(&self, key: &str, input: &UpdateFlag) -> Result<Flag, StoreError> {
+        let mut tx = self.db.begin().await?;
+        let (_, f) = apply_update(&mut tx, key, input).await?;
+        tx.commit().await?;
+        Ok(f)
+    }
+
+    async fn delete(&self, key: &str) -> Result<Flag, StoreError> {
+        let mut tx = self.db.begin().await?;
+        let f = remove_flag(&mut tx, key).await?;
+        tx.commit().await?;
+        Ok(f)
+    }
+}
+
+pub fn connect_options(url: &str) -> Result<SqliteConnectOptions, sqlx::Error> {
+    Ok(SqliteConnectOptions::from_str(url)?.statement_cache_capacity(STATEMENT_CACHE).journal_mode(SqliteJournalMode::Wal))
+}
+
+pub async fn list_flags(conn: &mut SqliteConnection) -> Result<Vec<Flag>, StoreError> {
+    let rows = sqlx::query(LIST_FLAGS).fetch_all(&mut *conn).await?;
+    rows.into_iter().map(row_to_flag).collect()
+}
+
+pub async fn fetch_flag(conn: &mut SqliteConnection, key: &str) -> Result<Flag, StoreError> {
+    let r = sqlx::query(select_flag!("WHERE key = ?"))
+        .bind(key)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(StoreError::NotFound)?;
+    row_to_flag(r)
+}
+
+pub async fn resolve_flag(conn: &mut SqliteConnection, key_or_uid: &str) -> Result<Flag, StoreError> {
+    let r = sqlx::query(select_flag!("WHERE key = ?1 OR uid = ?1 ORDER BY key = ?1 DESC LIMIT 1"))
+        .bind(key_or_uid)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(StoreError::NotFound)?;
+    row_to_flag(r)
+}
+
+pub async fn insert_flag(conn: &mut SqliteConnection, input: &CreateFlag) -> Result<Flag, StoreError> {
+    input.validate()?;
+    let variants_str = input.variants.as_ref().map(serde_json::to_string).transpose()?;
+    sqlx::query(concat!("INSERT INTO flags (uid, key, project, enabled, protected, variants, rollout, lifecycle_changed_at, updated_at) VALUES (COALESCE(?, ", new_uuid!(), "), ?, ?, ?, ?, ?, ?, datetime('now'), datetime('now'))"))
+        .bind(&input.uid)
+        .bind(&input.key)
+        .bind(&input.project)
+        .bind(if input.enabled { 1 } else { 0 })
+        .bind(if input.protected { 1 } else { 0 })
+        .bind(variants_str)
+        .bind(input.rollout.map(|x| x as i64))
+        .execute(&mut *conn)
+        .await
+        .map_err(|_| StoreError::Conflict)?;
+    let f = fetch_flag(conn, &input.key).await?;
+    record_revision(conn, &f).await?;
+    Ok(f)
+}
+
+pub async fn apply_update(conn: &mut SqliteConnection, key: &str, input: &UpdateFlag) -> Result<(Flag, Flag), StoreError> {
+    input.validate()?;
+    let existing = fetch_flag(conn, key).await?;
+    let enabled = input.enabled.unwrap_or(existing.enabled);
+    let protected = input.protected.unwrap_or(existing.protected);
+    let variants = input.variants.as_ref().or(existing.variants.as_ref()).map(serde_json::to_string).transpose()?;
+    let rollout = input.rollout.or(existing.rollout).map(|x| x as i64);
+    let lifecycle = input.lifecycle.unwrap_or(existing.lifecycle);
+    if !existing.lifecycle.can_become(lifecycle) { return Err(StoreError::Invalid("lifecycle transition not allowed")); }
+    sqlx::query("UPDATE flags SET enabled = ?, protected = ?, variants = ?, rollout = ?, lifecycle = ?, lifecycle_changed_at = CASE WHEN lifecycle = ? THEN lifecycle_changed_at ELSE datetime('now') END, updated_at = datetime('now') WHERE key = ?")
+        .bind(if enabled { 1 } else { 0 })
+        .bind(if protected { 1 } else { 0 })
+        .bind(variants)
+        .bind(rollout)
+        .bind(lifecycle.as_str())
+        .bind(lifecycle.as_str())
+        .bind(&existing.key)
+        .execute(&mut *conn)
+        .await?;
+    let f = fetch_flag(conn, &existing.key).await?;
+    record_revision(conn, &f).await?;
+    Ok((existing, f))
+}
+
+// unlike apply_update this can also clear variants or rollout, so an earlier revision comes back exactly
+pub async fn restore_flag(conn: &mut SqliteConnection, key: &str, from: &Flag) -> Result<(Flag, Flag), StoreError> {
+    let existing = fetch_flag(conn, key).await?;
+    sqlx::query("UPDATE flags SET enabled = ?, variants = ?, rollout = ?, updated_at = datetime('now') WHERE key = ?")
+        .bind(if from.enabled { 1 } else { 0 })
+        .bind(from.variants.as_ref().map(serde_json::to_string).transpose()?)
+        .bind(from.rollout.map(|x| x as i64))
+        .bind(&existing.key)
+        .execute(&mut *conn)
+        .await?;
+    let f = fetch_flag(conn, &existing.key).await?;
+    record_revision(conn, &f).await?;
+    Ok((existing, f))
+}
+
+// sets a flag to what a manifest declares: like restore_flag, variants or rollout it leaves out are cleared.
+// key, project, uid and lifecycle stay as stored
+pub async fn replace_flag(conn: &mut SqliteConnection, key: &str, want: &CreateFlag) -> Result<(Flag, Flag), StoreError> {
+    want.validate()?;
+    let existing = fetch_flag(conn, key).await?;
+    sqlx::query("UPDATE flags SET enabled = ?, protected = ?, variants = ?, rollout = ?, updated_at = datetime('now') WHERE key = ?")
+        .bind(if want.enabled { 1 } else { 0 })
+        .bind(if want.protected { 1 } else { 0 })
+        .bind(want.variants.as_ref().map(serde_json::to_string).transpose()?)
+        .bind(want.rollout.map(|x| x as i64))
+        .bind(&existing.key)
+        .execute(&mut *conn)
+        .await?;
+    let f = fetch_flag(conn, &existing.key).await?;
+    record_revision(conn, &f).await?;
+    Ok((existing, f))
+}
+
+pub async fn fetch_flag_by_uid(conn: &mut SqliteConnection, uid: &str) -> Result<Flag, StoreError> {
+    let r = sqlx::query(select_flag!("WHERE uid = ?"))
+        .bind(uid)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(StoreError::NotFound)?;
+    row_to_flag(r)
+}
+
+// tables whose rows belong to a flag and follow it to its new key; audit entries, change requests and code references keep the key they were recorded under
+const FLAG_TABLES: &[&str] = &["flag_revisions", "experiments", "exposures", "flag_eval_stats", "scheduled_actions", "flag_guards", "flag_owners", "flag_comments", "flag_shadows", "shadow_results", "flag_pins", "release_group_flags"];
+
+pub async fn rename_flag(conn: &mut SqliteConnection, from: &str, to: &str) -> Result<Flag, StoreError> {
+    fetch_flag(conn, from).await?;
+    sqlx::query("UPDATE flags SET key = ?, updated_at = datetime('now') WHERE key = ?")
+        .bind(to)
+        .bind(from)
+        .execute(&mut *conn)
+        .await
+        .map_err(|_| StoreError::Conflict)?;
+    for table in FLAG_TABLES {
+        // rows left behind by a deleted flag that had the new key would collide with the moved ones
+        sqlx::query(&format!("DELETE FROM {table} WHERE flag_key = ?")).bind(to).execute(&mut *conn).await?;
+        sqlx::query(&format!("UPDATE {table} SET flag_key = ? WHERE flag_key = ?")).bind(to).bind(from).execute(&mut *conn).await?;
+    }
+    let f = fetch_flag(conn, to).await?;
+    record_revision(conn, &f).await?;
+    Ok(f)
+}
+
+pub async fn remove_flag(conn: &mut SqliteConnection, key: &str) -> Result<Flag, StoreError> {
+    let existing = fetch_flag(conn, key).await?;
+    sqlx::query("DELETE FROM flags WHERE key = ?")
+        .bind(key)
+        .execute(&mut *conn)
+        .await?;
+    Ok(existing)
+}
+
+pub async fn record_revision(conn: &mut SqliteConnection, flag: &Flag) -> Result<(), StoreError> {
+    sqlx::query("INSERT INTO flag_revisions (flag_key, rev, data, created_at) SELECT ?, COALESCE(MAX(rev), 0) + 1, ?, datetime('now') FROM flag_revisions WHERE flag_key = ?")
+        .bind(&flag.key)
+        .bind(serde_json::to_string(&Flag { pins: HashMap::new(), ..flag.clone() })?)
+        .bind(&flag.key)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+pub fn row_to_flag(r: SqliteRow) -> Result<Flag, StoreError> {
+    let id = r.get::<i64,_>("id");
+    let uid = r.get::<Option<String>,_>("uid").unwrap_or_default();
+    let key = r.get::<String,_>("key");
+    let project = r.get::<String,_>("project");
+    let enabled = r.get::<i64,_>("enabled") != 0;
+    let protected = r.get::<i64,_>("protected") != 0;
+    let variants_str = r.get::<Option<String>,_>("variants");
+    let variants = match variants_str { Some(s) => Some(serde_json::from_str::<HashMap<String, u32>>(&s)?), None => None };
+    let rollout = r.get::<Option<i64>,_>("rollout").map(|x| x as u8);
+    let lifecycle = Lifecycle::parse(&r.get::<String,_>("lifecycle")).unwrap_or_default();
+    let updated_at = r.get::<String,_>("updated_at");
+    let pins = match r.get::<Option<String>,_>("pins") { Some(s) => serde_json::from_str::<HashMap<String, Pin>>(&s)?, None => HashMap::new() };
+    Ok(Flag { id, uid, key, project, enabled, protected, variants, rollout, lifecycle, updated_at, pins })
+}
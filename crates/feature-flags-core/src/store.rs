@@ -0,0 +1,26 @@
+use async_trait::async_trait;
+
+use crate::{CreateFlag, Flag, UpdateFlag};
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("flag not found")]
+    NotFound,
+    #[error("flag already exists")]
+    Conflict,
+    #[error("invalid flag: {0}")]
+    Invalid(&'static str),
+    #[error("malformed flag data: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("storage backend error: {0}")]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+#[async_trait]
+pub trait FlagStore: Send + Sync {
+    async fn list(&self) -> Result<Vec<Flag>, StoreError>;
+    async fn get(&self, key: &str) -> Result<Flag, StoreError>;
+    async fn create(&self, input: &CreateFlag) -> Result<Flag, StoreError>;
+    async fn update(&self, key: &str, input: &UpdateFlag) -> Result<Flag, StoreError>;
+    async fn delete(&self, key: &str) -> Result<Flag, StoreError>;
+}
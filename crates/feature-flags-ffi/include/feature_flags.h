@@ -0,0 +1,36 @@
+#ifndef FEATURE_FLAGS_H
+#define FEATURE_FLAGS_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque evaluator holding the flags of a bundle exported from GET /bootstrap. */
+typedef struct FfEvaluator FfEvaluator;
+
+/* Parses a bundle. Returns NULL on error (see ff_last_error), including flags the server would reject on save.
+   Release with ff_evaluator_free. */
+FfEvaluator *ff_evaluator_new(const char *bundle_json);
+void ff_evaluator_free(FfEvaluator *evaluator);
+
+/* Evaluates one flag. user_id may be NULL. Returns a JSON object {"key","matched","variant"}
+   owned by the caller (release with ff_string_free), or NULL for unknown flags and errors. */
+char *ff_evaluate(const FfEvaluator *evaluator, const char *key, const char *user_id);
+
+/* Returns 1 if the flag matched, 0 if not or unknown, -1 on invalid arguments. */
+int ff_is_enabled(const FfEvaluator *evaluator, const char *key, const char *user_id);
+
+/* One-shot evaluation from a bundle and a context such as {"user_id":"42"}; context_json may be NULL.
+   Same result and ownership as ff_evaluate. Prefer an FfEvaluator when evaluating repeatedly. */
+char *ff_evaluate_json(const char *bundle_json, const char *key, const char *context_json);
+
+void ff_string_free(char *s);
+
+/* Message for the last failed call on this thread, or NULL. Valid until the next failing call. */
+const char *ff_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
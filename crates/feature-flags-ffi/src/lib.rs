@@ -0,0 +1,154 @@
+use feature_flags_core::{eval_flag, lint_flag, Bundle, EvalResponse, Flag};
+use serde::Deserialize;
+use std::{cell::RefCell, collections::HashMap, ffi::{c_char, c_int, CStr, CString}, panic::{self, UnwindSafe}, ptr};
+
+pub struct FfEvaluator {
+    flags: HashMap<String, Flag>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Context {
+    user_id: Option<String>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(msg: impl ToString) {
+    let msg = CString::new(msg.to_string().replace('\0', " ")).unwrap_or_default();
+    let _ = LAST_ERROR.try_with(|e| *e.borrow_mut() = Some(msg));
+}
+
+// a panic must not unwind into the host, which would abort it, so it's reported like any other error
+fn guarded<T>(failed: T, f: impl FnOnce() -> T + UnwindSafe) -> T {
+    panic::catch_unwind(f).unwrap_or_else(|_| { set_error("internal error"); failed })
+}
+
+unsafe fn read_str<'a>(s: *const c_char, what: &str) -> Option<&'a str> {
+    if s.is_null() { set_error(format!("{what} is null")); return None; }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(_) => { set_error(format!("{what} is not valid UTF-8")); None }
+    }
+}
+
+unsafe fn read_opt_str<'a>(s: *const c_char, what: &str) -> Result<Option<&'a str>, ()> {
+    if s.is_null() { return Ok(None); }
+    read_str(s, what).map(Some).ok_or(())
+}
+
+fn to_c_json(res: &EvalResponse) -> *mut c_char {
+    match serde_json::to_string(res).map(CString::new) {
+        Ok(Ok(s)) => s.into_raw(),
+        _ => { set_error("cannot encode result"); ptr::null_mut() }
+    }
+}
+
+fn parse_bundle(json: &str) -> Option<HashMap<String, Flag>> {
+    match serde_json::from_str::<Bundle>(json) {
+        Ok(bundle) => {
+            // the same checks as the server's on save, for bundles that didn't come from one
+            for f in &bundle.flags {
+                if let Some(e) = lint_flag(f).errors.first() { set_error(format!("invalid bundle: flag {}: {}", f.key, e.message)); return None; }
+            }
+            Some(bundle.flags.into_iter().map(|f| (f.key.clone(), f)).collect())
+        }
+        Err(e) => { set_error(format!("invalid bundle: {e}")); None }
+    }
+}
+
+/// Parses a bundle exported from `GET /bootstrap`; null on error.
+///
+/// # Safety
+/// `bundle_json` must be null or a valid NUL-terminated string. The result must be released with
+/// `ff_evaluator_free` and nothing else.
+#[no_mangle]
+pub unsafe extern "C" fn ff_evaluator_new(bundle_json: *const c_char) -> *mut FfEvaluator {
+    guarded(ptr::null_mut(), || {
+        let Some(json) = read_str(bundle_json, "bundle_json") else { return ptr::null_mut() };
+        let Some(flags) = parse_bundle(json) else { return ptr::null_mut() };
+        Box::into_raw(Box::new(FfEvaluator { flags }))
+    })
+}
+
+/// # Safety
+/// `evaluator` must be null or a pointer returned by `ff_evaluator_new` that hasn't been freed yet,
+/// and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ff_evaluator_free(evaluator: *mut FfEvaluator) {
+    guarded((), || {
+        if !evaluator.is_null() { drop(Box::from_raw(evaluator)); }
+    })
+}
+
+/// Evaluates one flag as JSON; null on error.
+///
+/// # Safety
+/// `evaluator` must be null or a live pointer from `ff_evaluator_new`; `key` and `user_id` must be null
+/// or valid NUL-terminated strings. The result must be released with `ff_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn ff_evaluate(evaluator: *const FfEvaluator, key: *const c_char, user_id: *const c_char) -> *mut c_char {
+    guarded(ptr::null_mut(), || {
+        let Some(evaluator) = evaluator.as_ref() else { set_error("evaluator is null"); return ptr::null_mut() };
+        let Some(key) = read_str(key, "key") else { return ptr::null_mut() };
+        let Ok(user_id) = read_opt_str(user_id, "user_id") else { return ptr::null_mut() };
+        match evaluator.flags.get(key) {
+            Some(flag) => to_c_json(&eval_flag(flag, user_id)),
+            None => { set_error(format!("unknown flag: {key}")); ptr::null_mut() }
+        }
+    })
+}
+
+/// 1 if the flag matched, 0 if not or unknown, -1 on invalid arguments.
+///
+/// # Safety
+/// `evaluator` must be null or a live pointer from `ff_evaluator_new`; `key` and `user_id` must be null
+/// or valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn ff_is_enabled(evaluator: *const FfEvaluator, key: *const c_char, user_id: *const c_char) -> c_int {
+    guarded(-1, || {
+        let Some(evaluator) = evaluator.as_ref() else { set_error("evaluator is null"); return -1 };
+        let Some(key) = read_str(key, "key") else { return -1 };
+        let Ok(user_id) = read_opt_str(user_id, "user_id") else { return -1 };
+        evaluator.flags.get(key).is_some_and(|f| eval_flag(f, user_id).matched) as c_int
+    })
+}
+
+/// One-shot evaluation from a bundle and a context as JSON; null on error.
+///
+/// # Safety
+/// Each argument must be null or a valid NUL-terminated string. The result must be released with
+/// `ff_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn ff_evaluate_json(bundle_json: *const c_char, key: *const c_char, context_json: *const c_char) -> *mut c_char {
+    guarded(ptr::null_mut(), || {
+        let Some(json) = read_str(bundle_json, "bundle_json") else { return ptr::null_mut() };
+        let Some(key) = read_str(key, "key") else { return ptr::null_mut() };
+        let ctx = match read_opt_str(context_json, "context_json") {
+            Ok(None) => Context::default(),
+            Ok(Some(c)) => match serde_json::from_str::<Context>(c) { Ok(c) => c, Err(e) => { set_error(format!("invalid context: {e}")); return ptr::null_mut() } },
+            Err(()) => return ptr::null_mut(),
+        };
+        let Some(flags) = parse_bundle(json) else { return ptr::null_mut() };
+        match flags.get(key) {
+            Some(flag) => to_c_json(&eval_flag(flag, ctx.user_id.as_deref())),
+            None => { set_error(format!("unknown flag: {key}")); ptr::null_mut() }
+        }
+    })
+}
+
+/// # Safety
+/// `s` must be null or a string returned by this library that hasn't been freed yet, and must not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ff_string_free(s: *mut c_char) {
+    guarded((), || {
+        if !s.is_null() { drop(CString::from_raw(s)); }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ff_last_error() -> *const c_char {
+    guarded(ptr::null(), || LAST_ERROR.try_with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr())).unwrap_or(ptr::null()))
+}
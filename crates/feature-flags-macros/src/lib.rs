@@ -0,0 +1,120 @@
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use std::path::PathBuf;
+use syn::{braced, parse::{Parse, ParseStream}, parse_macro_input, punctuated::Punctuated, Attribute, Ident, LitStr, Token, Visibility};
+
+struct FlagKeys {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    name: Ident,
+    source: Source,
+}
+
+enum Source {
+    Inline(Vec<LitStr>),
+    File(LitStr),
+}
+
+impl Parse for FlagKeys {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        input.parse::<Token![enum]>()?;
+        let name = input.parse()?;
+        let source = if input.peek(syn::token::Brace) {
+            let content;
+            braced!(content in input);
+            Source::Inline(Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?.into_iter().collect())
+        } else {
+            let from: Ident = input.parse()?;
+            if from != "from" { return Err(syn::Error::new(from.span(), "expected `{ \"key\", ... }` or `from \"path\"`")); }
+            Source::File(input.parse()?)
+        };
+        input.parse::<Option<Token![;]>>()?;
+        Ok(FlagKeys { attrs, vis, name, source })
+    }
+}
+
+#[proc_macro]
+pub fn flag_keys(input: TokenStream) -> TokenStream {
+    let FlagKeys { attrs, vis, name, source } = parse_macro_input!(input as FlagKeys);
+    let (keys, tracked) = match source {
+        Source::Inline(keys) => (keys.iter().map(|k| (k.value(), k.span())).collect::<Vec<_>>(), None),
+        Source::File(path) => match read_schema(&path) {
+            Ok((keys, file)) => (keys.into_iter().map(|k| (k, path.span())).collect(), Some(file)),
+            Err(e) => return e.to_compile_error().into(),
+        },
+    };
+    let mut variants: Vec<Ident> = Vec::new();
+    for (key, span) in &keys {
+        let Some(ident) = variant_name(key) else {
+            return syn::Error::new(*span, format!("flag key `{key}` cannot be turned into an identifier")).to_compile_error().into();
+        };
+        if variants.iter().any(|v| *v == ident) {
+            return syn::Error::new(*span, format!("flag key `{key}` collides with another key as `{ident}`")).to_compile_error().into();
+        }
+        variants.push(format_ident!("{}", ident, span = *span));
+    }
+    let key_strs = keys.iter().map(|(k, _)| k);
+    let key_strs2 = key_strs.clone();
+    let track = tracked.map(|f| { let f = f.to_string_lossy().into_owned(); quote! { const _: &[u8] = include_bytes!(#f); } });
+    quote! {
+        #(#attrs)*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        #vis enum #name { #(#variants),* }
+
+        impl #name {
+            pub const ALL: &'static [#name] = &[#(#name::#variants),*];
+
+            pub const fn as_str(self) -> &'static str {
+                match self { #(#name::#variants => #key_strs),* }
+            }
+
+            pub fn from_key(key: &str) -> Option<#name> {
+                match key { #(#key_strs2 => Some(#name::#variants),)* _ => None }
+            }
+        }
+
+        impl AsRef<str> for #name {
+            fn as_ref(&self) -> &str { self.as_str() }
+        }
+
+        impl std::fmt::Display for #name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { f.write_str(self.as_str()) }
+        }
+
+        #track
+    }
+    .into()
+}
+
+fn variant_name(key: &str) -> Option<String> {
+    let mut out = String::new();
+    for part in key.split(|c: char| !c.is_ascii_alphanumeric()).filter(|p| !p.is_empty()) {
+        let mut chars = part.chars();
+        out.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+        out.push_str(chars.as_str());
+    }
+    if out.is_empty() { return None; }
+    if out.starts_with(|c: char| c.is_ascii_digit()) { out.insert_str(0, "Flag"); }
+    Some(out)
+}
+
+fn read_schema(path: &LitStr) -> syn::Result<(Vec<String>, PathBuf)> {
+    let err = |msg: String| syn::Error::new(path.span(), msg);
+    let dir = std::env::var("CARGO_MANIFEST_DIR").map_err(|_| err("CARGO_MANIFEST_DIR is not set".into()))?;
+    let file = PathBuf::from(dir).join(path.value());
+    let text = std::fs::read_to_string(&file).map_err(|e| err(format!("cannot read {}: {e}", file.display())))?;
+    let json: serde_json::Value = serde_json::from_str(&text).map_err(|e| err(format!("invalid JSON in {}: {e}", file.display())))?;
+    let items = match &json {
+        serde_json::Value::Array(items) => items,
+        serde_json::Value::Object(o) => o.get("flags").and_then(|f| f.as_array()).ok_or_else(|| err("expected a `flags` array".into()))?,
+        _ => return Err(err("expected a bundle, a list of flags or a list of keys".into())),
+    };
+    let mut keys: Vec<String> = items.iter()
+        .map(|i| i.as_str().or_else(|| i.get("key").and_then(|k| k.as_str())).map(str::to_string).ok_or_else(|| err("every entry needs a flag key".into())))
+        .collect::<syn::Result<_>>()?;
+    keys.sort();
+    keys.dedup();
+    Ok((keys, file))
+}
@@ -0,0 +1,154 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::sse::{Event, KeepAlive, Sse}, routing::{get, post}, Json, Router};
+use feature_flags_client::{Client, ClientBuilder};
+use feature_flags_core::{eval_flag, Bundle, EvalResponse, Flag, FlagStore, MemoryStore};
+use futures_util::{Stream, StreamExt};
+use serde::Deserialize;
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+#[derive(Default, Clone)]
+pub struct TestFlags {
+    flags: Vec<Flag>,
+}
+
+impl TestFlags {
+    pub fn new() -> Self { TestFlags::default() }
+
+    pub fn enabled(self, key: &str) -> Self { self.set(key, |f| f.enabled = true) }
+
+    pub fn disabled(self, key: &str) -> Self { self.set(key, |f| f.enabled = false) }
+
+    pub fn variant(self, key: &str, variant: &str) -> Self {
+        self.set(key, |f| { f.enabled = true; f.variants = Some(HashMap::from([(variant.to_string(), 1)])); })
+    }
+
+    pub fn variants(self, key: &str, weights: &[(&str, u32)]) -> Self {
+        self.set(key, |f| { f.enabled = true; f.variants = Some(weights.iter().map(|(n, w)| (n.to_string(), *w)).collect()); })
+    }
+
+    pub fn rollout(self, key: &str, percent: u8) -> Self { self.set(key, |f| { f.enabled = true; f.rollout = Some(percent.min(100)); }) }
+
+    pub fn flag(mut self, flag: Flag) -> Self {
+        self.flags.retain(|f| f.key != flag.key);
+        self.flags.push(flag);
+        self
+    }
+
+    pub fn bundle(&self) -> Bundle {
+        Bundle { environment: "test".into(), generated_at: "1970-01-01T00:00:00Z".into(), flags: self.flags.clone() }
+    }
+
+    pub fn store(&self) -> MemoryStore { MemoryStore::with_flags(self.flags.clone()) }
+
+    pub async fn start(self) -> TestServer { TestServer::start(self).await }
+
+    fn set(mut self, key: &str, apply: impl FnOnce(&mut Flag)) -> Self {
+        let pos = self.flags.iter().position(|f| f.key == key).unwrap_or_else(|| { self.flags.push(blank(key)); self.flags.len() - 1 });
+        apply(&mut self.flags[pos]);
+        self
+    }
+}
+
+fn blank(key: &str) -> Flag {
+    Flag { id: 0, uid: String::new(), key: key.to_string(), project: "default".into(), enabled: false, protected: false, variants: None, rollout: None, lifecycle: Default::default(), updated_at: "1970-01-01 00:00:00".into(), pins: Default::default() }
+}
+
+#[derive(Clone)]
+struct ServerState {
+    store: Arc<MemoryStore>,
+    changes: broadcast::Sender<String>,
+}
+
+pub struct TestServer {
+    addr: SocketAddr,
+    state: ServerState,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Deserialize)]
+struct EvalRequest {
+    key: String,
+    user_id: Option<String>,
+    anonymous_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotParams {
+    user_id: Option<String>,
+    anonymous_id: Option<String>,
+}
+
+impl TestServer {
+    async fn start(flags: TestFlags) -> TestServer {
+        let (changes, _) = broadcast::channel(64);
+        let state = ServerState { store: Arc::new(flags.store()), changes };
+        let app = Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .route("/evaluate", post(evaluate))
+            .route("/snapshot", get(snapshot))
+            .route("/rules", get(rules))
+            .route("/bootstrap", get(bootstrap))
+            .route("/stream", get(stream))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind test server");
+        let addr = listener.local_addr().expect("test server address");
+        let task = tokio::spawn(async move { let _ = axum::serve(listener, app).await; });
+        TestServer { addr, state, task }
+    }
+
+    pub fn url(&self) -> String { format!("http://{}", self.addr) }
+
+    pub fn client_builder(&self) -> ClientBuilder { Client::builder(self.url(), "test") }
+
+    pub fn client(&self) -> Client { self.client_builder().build().expect("test client") }
+
+    pub fn set(&self, flag: Flag) {
+        let key = flag.key.clone();
+        self.state.store.put(flag);
+        let _ = self.state.changes.send(key);
+    }
+
+    pub async fn set_enabled(&self, key: &str, enabled: bool) {
+        let mut flag = self.state.store.get(key).await.unwrap_or_else(|_| blank(key));
+        flag.enabled = enabled;
+        self.set(flag);
+    }
+
+    pub async fn remove(&self, key: &str) {
+        if self.state.store.delete(key).await.is_ok() { let _ = self.state.changes.send(key.to_string()); }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) { self.task.abort(); }
+}
+
+async fn evaluate(State(state): State<ServerState>, Json(req): Json<EvalRequest>) -> Result<Json<EvalResponse>, StatusCode> {
+    let flag = state.store.get(&req.key).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    Ok(Json(eval_flag(&flag, req.user_id.as_deref().or(req.anonymous_id.as_deref()))))
+}
+
+async fn snapshot(State(state): State<ServerState>, Query(params): Query<SnapshotParams>) -> Json<Vec<EvalResponse>> {
+    let flags = state.store.list().await.unwrap_or_default();
+    Json(flags.iter().map(|f| eval_flag(f, params.user_id.as_deref().or(params.anonymous_id.as_deref()))).collect())
+}
+
+async fn rules(State(state): State<ServerState>) -> Json<Vec<Flag>> {
+    Json(state.store.list().await.unwrap_or_default())
+}
+
+async fn bootstrap(State(state): State<ServerState>) -> Json<Bundle> {
+    let flags = state.store.list().await.unwrap_or_default();
+    Json(Bundle { environment: "test".into(), generated_at: "1970-01-01T00:00:00Z".into(), flags })
+}
+
+async fn stream(State(state): State<ServerState>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let ready = futures_util::stream::once(async { Ok(Event::default().event("ready").data("{}")) });
+    let events = BroadcastStream::new(state.changes.subscribe())
+        .map(|key| Ok(match key {
+            Ok(key) => Event::default().event("flag").data(serde_json::json!({ "key": key, "action": "updated" }).to_string()),
+            Err(_) => Event::default().event("resync").data("{}"),
+        }));
+    Sse::new(ready.chain(events)).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,46 @@
+use feature_flags_core::{eval_flag, Bundle, Flag};
+use std::collections::HashMap;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct Evaluator {
+    environment: String,
+    flags: HashMap<String, Flag>,
+}
+
+#[wasm_bindgen]
+impl Evaluator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(bundle_json: &str) -> Result<Evaluator, JsError> {
+        let bundle: Bundle = serde_json::from_str(bundle_json)?;
+        Ok(Evaluator { environment: bundle.environment, flags: bundle.flags.into_iter().map(|f| (f.key.clone(), f)).collect() })
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn environment(&self) -> String { self.environment.clone() }
+
+    pub fn evaluate(&self, key: &str, user_id: Option<String>) -> Result<JsValue, JsError> {
+        let res = self.flags.get(key).map(|f| eval_flag(f, user_id.as_deref()));
+        to_js(&res)
+    }
+
+    #[wasm_bindgen(js_name = isEnabled)]
+    pub fn is_enabled(&self, key: &str, user_id: Option<String>) -> bool {
+        self.flags.get(key).is_some_and(|f| eval_flag(f, user_id.as_deref()).matched)
+    }
+
+    pub fn variant(&self, key: &str, user_id: Option<String>) -> Option<String> {
+        self.flags.get(key).map(|f| eval_flag(f, user_id.as_deref())).and_then(|r| if r.matched { r.variant } else { None })
+    }
+
+    pub fn snapshot(&self, user_id: Option<String>) -> Result<JsValue, JsError> {
+        let mut out: Vec<_> = self.flags.values().map(|f| eval_flag(f, user_id.as_deref())).collect();
+        out.sort_by(|a, b| a.key.cmp(&b.key));
+        to_js(&out)
+    }
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsError> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
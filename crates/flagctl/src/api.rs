@@ -0,0 +1,127 @@
+use anyhow::anyhow;
+use feature_flags_core::{CreateFlag, Flag, UpdateFlag};
+use reqwest::{blocking::{Client, RequestBuilder}, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::config::Target;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuditEntry {
+    pub at: String,
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub outcome: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Token {
+    pub id: i64,
+    pub revoked_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageRow {
+    pub day: String,
+    pub route: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Usage {
+    pub daily: Vec<UsageRow>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AppliedChange {
+    pub key: String,
+    pub action: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApplyResult {
+    pub changes: Vec<AppliedChange>,
+}
+
+#[derive(Serialize)]
+struct DesiredFlags<'a> {
+    flags: &'a [CreateFlag],
+}
+
+#[derive(Clone)]
+pub struct Api {
+    http: Client,
+    base: String,
+    token: String,
+}
+
+impl Api {
+    pub fn new(target: &Target) -> anyhow::Result<Api> {
+        let http = Client::builder().timeout(std::time::Duration::from_secs(15)).build()?;
+        Ok(Api { http, base: target.url.clone(), token: target.token.clone() })
+    }
+
+    fn send<T: DeserializeOwned>(&self, req: RequestBuilder) -> anyhow::Result<Option<T>> {
+        let res = req.bearer_auth(&self.token).send()?;
+        match res.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            s if s.is_success() => Ok(Some(res.json()?)),
+            StatusCode::UNAUTHORIZED => Err(anyhow!("401 Unauthorized: check the API token")),
+            StatusCode::FORBIDDEN => Err(anyhow!("403 Forbidden: the token lacks the required role, or direct changes are disabled (REQUIRE_APPROVAL)")),
+            StatusCode::PRECONDITION_REQUIRED => Err(anyhow!("428 Precondition Required: the flag is protected, pass --confirm-protected")),
+            s => Err(anyhow!("server returned {s}: {}", res.text().unwrap_or_default())),
+        }
+    }
+
+    fn send_admin<T: DeserializeOwned>(&self, req: RequestBuilder) -> anyhow::Result<Option<T>> {
+        let res = req.bearer_auth(&self.token).send()?;
+        match res.status() {
+            StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => Ok(None),
+            s if s.is_success() => Ok(Some(res.json()?)),
+            s => Err(anyhow!("server returned {s}")),
+        }
+    }
+
+    pub fn audit(&self, limit: u32) -> anyhow::Result<Option<Vec<AuditEntry>>> {
+        self.send_admin(self.http.get(format!("{}/audit", self.base)).query(&[("limit", limit)]))
+    }
+
+    pub fn tokens(&self) -> anyhow::Result<Option<Vec<Token>>> {
+        self.send_admin(self.http.get(format!("{}/tokens", self.base)))
+    }
+
+    pub fn usage(&self, token_id: i64) -> anyhow::Result<Option<Usage>> {
+        self.send_admin(self.http.get(format!("{}/tokens/{token_id}/usage", self.base)))
+    }
+
+    pub fn list(&self) -> anyhow::Result<Vec<Flag>> {
+        Ok(self.send(self.http.get(format!("{}/flags", self.base)))?.unwrap_or_default())
+    }
+
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<Flag>> {
+        self.send(self.http.get(format!("{}/flags/{key}", self.base)))
+    }
+
+    pub fn create(&self, input: &CreateFlag, dry_run: bool) -> anyhow::Result<()> {
+        self.mutate(self.http.post(format!("{}/flags", self.base)).query(&[("dry_run", dry_run)]).json(input))
+    }
+
+    pub fn update(&self, key: &str, input: &UpdateFlag, dry_run: bool, confirm: bool) -> anyhow::Result<()> {
+        let mut req = self.http.patch(format!("{}/flags/{key}", self.base)).query(&[("dry_run", dry_run)]).json(input);
+        if confirm { req = req.query(&[("confirm", key)]); }
+        self.mutate(req)
+    }
+
+    pub fn apply(&self, flags: &[CreateFlag], project: &str, prune: bool, confirm_protected: bool) -> anyhow::Result<ApplyResult> {
+        let req = self.http.post(format!("{}/apply", self.base))
+            .query(&[("project", project)])
+            .query(&[("prune", prune), ("confirm_protected", confirm_protected)])
+            .json(&DesiredFlags { flags });
+        self.send(req)?.ok_or_else(|| anyhow!("the server does not support /apply"))
+    }
+
+    fn mutate(&self, req: RequestBuilder) -> anyhow::Result<()> {
+        self.send::<serde_json::Value>(req)?.ok_or_else(|| anyhow!("flag not found"))?;
+        Ok(())
+    }
+}
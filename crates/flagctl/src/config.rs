@@ -0,0 +1,50 @@
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf};
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    default_env: Option<String>,
+    #[serde(default)]
+    environments: HashMap<String, Profile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Profile {
+    url: String,
+    token: Option<String>,
+    token_env: Option<String>,
+}
+
+pub struct Target {
+    pub env: Option<String>,
+    pub url: String,
+    pub token: String,
+}
+
+fn config_path() -> Option<PathBuf> {
+    if let Ok(p) = std::env::var("FLAGCTL_CONFIG") { return Some(p.into()); }
+    let base = std::env::var("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|_| std::env::var("HOME").map(|h| PathBuf::from(h).join(".config"))).ok()?;
+    Some(base.join("flagctl").join("config.yaml"))
+}
+
+pub fn resolve(env: Option<&str>, url: Option<&str>, token: Option<&str>) -> anyhow::Result<Target> {
+    let file = match config_path() {
+        Some(p) if p.exists() => serde_yaml::from_str::<ConfigFile>(&std::fs::read_to_string(&p)?).with_context(|| format!("reading {}", p.display()))?,
+        _ => ConfigFile::default(),
+    };
+    let env = env.map(str::to_string).or(file.default_env);
+    let profile = match &env {
+        Some(name) if url.is_none() => Some(file.environments.get(name).ok_or_else(|| anyhow!("unknown environment `{name}` (not in the flagctl config)"))?),
+        Some(name) => file.environments.get(name),
+        None => None,
+    };
+    let url = url.map(str::to_string).or_else(|| profile.map(|p| p.url.clone())).ok_or_else(|| anyhow!("no server URL: pass --url, set FLAGCTL_URL or pick an --env from the config"))?;
+    let token = match (token, profile) {
+        (Some(t), _) => t.to_string(),
+        (None, Some(Profile { token: Some(t), .. })) => t.clone(),
+        (None, Some(Profile { token_env: Some(var), .. })) => std::env::var(var).with_context(|| format!("{var} is not set"))?,
+        _ => return Err(anyhow!("no API token: pass --token, set FLAGCTL_TOKEN or configure one for the environment")),
+    };
+    Ok(Target { env, url: url.trim_end_matches('/').to_string(), token })
+}
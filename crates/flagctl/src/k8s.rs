@@ -0,0 +1,195 @@
+use anyhow::{anyhow, bail, Context};
+use feature_flags_core::CreateFlag;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::{BTreeMap, HashMap}, io::{BufRead, BufReader}, time::Duration};
+
+use crate::api::Api;
+
+const GROUP: &str = "flags.toggler.dev";
+const VERSION: &str = "v1alpha1";
+const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+    name: String,
+    namespace: String,
+    #[serde(default)]
+    generation: i64,
+    resource_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Spec {
+    key: Option<String>,
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    protected: bool,
+    variants: Option<HashMap<String, u32>>,
+    rollout: Option<u8>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FeatureFlag {
+    metadata: Metadata,
+    spec: Spec,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListMeta {
+    #[serde(rename = "resourceVersion")]
+    resource_version: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeatureFlagList {
+    metadata: ListMeta,
+    items: Vec<FeatureFlag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WatchEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    object: serde_json::Value,
+}
+
+struct Kube {
+    http: Client,
+    base: String,
+    token: Option<String>,
+}
+
+impl Kube {
+    fn connect(api_url: Option<&str>) -> anyhow::Result<Kube> {
+        if let Some(url) = api_url {
+            let http = Client::builder().timeout(None).build()?;
+            return Ok(Kube { http, base: url.trim_end_matches('/').to_string(), token: std::env::var("KUBE_TOKEN").ok() });
+        }
+        let host = std::env::var("KUBERNETES_SERVICE_HOST").map_err(|_| anyhow!("not running in a cluster: pass --kube-api (e.g. the address of `kubectl proxy`)"))?;
+        let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".into());
+        let token = std::fs::read_to_string(format!("{SERVICE_ACCOUNT}/token")).context("reading the service account token")?;
+        let ca = reqwest::Certificate::from_pem(&std::fs::read(format!("{SERVICE_ACCOUNT}/ca.crt")).context("reading the cluster CA")?)?;
+        let http = Client::builder().timeout(None).add_root_certificate(ca).build()?;
+        Ok(Kube { http, base: format!("https://{host}:{port}"), token: Some(token.trim().to_string()) })
+    }
+
+    fn auth(&self, req: RequestBuilder) -> RequestBuilder {
+        match &self.token { Some(t) => req.bearer_auth(t), None => req }
+    }
+
+    fn collection(&self, namespace: Option<&str>) -> String {
+        match namespace {
+            Some(ns) => format!("{}/apis/{GROUP}/{VERSION}/namespaces/{ns}/featureflags", self.base),
+            None => format!("{}/apis/{GROUP}/{VERSION}/featureflags", self.base),
+        }
+    }
+
+    fn list(&self, namespace: Option<&str>) -> anyhow::Result<FeatureFlagList> {
+        let res = self.auth(self.http.get(self.collection(namespace)).timeout(Duration::from_secs(30))).send()?;
+        if !res.status().is_success() { bail!("listing FeatureFlags failed with {}: {}", res.status(), res.text().unwrap_or_default()); }
+        Ok(res.json()?)
+    }
+
+    fn set_status(&self, flag: &FeatureFlag, synced: bool, message: &str) -> anyhow::Result<()> {
+        let url = format!("{}/apis/{GROUP}/{VERSION}/namespaces/{}/featureflags/{}/status", self.base, flag.metadata.namespace, flag.metadata.name);
+        let body = json!({ "status": { "synced": synced, "message": message, "observedGeneration": flag.metadata.generation } });
+        let res = self.auth(self.http.patch(url).timeout(Duration::from_secs(30))).header("content-type", "application/merge-patch+json").body(body.to_string()).send()?;
+        if !res.status().is_success() { bail!("updating status of {}/{} failed with {}", flag.metadata.namespace, flag.metadata.name, res.status()); }
+        Ok(())
+    }
+}
+
+pub struct Controller<'a> {
+    pub api: &'a Api,
+    pub kube_api: Option<String>,
+    pub namespace: Option<String>,
+    pub project: String,
+    pub prune: bool,
+    pub confirm_protected: bool,
+    pub resync: Duration,
+}
+
+type Resources = BTreeMap<(String, String), FeatureFlag>;
+
+impl Controller<'_> {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let kube = Kube::connect(self.kube_api.as_deref())?;
+        let mut reported: HashMap<(String, String), (i64, bool, String)> = HashMap::new();
+        eprintln!("watching FeatureFlags in {} for project `{}`", self.namespace.as_deref().unwrap_or("all namespaces"), self.project);
+        loop {
+            let list = match kube.list(self.namespace.as_deref()) {
+                Ok(list) => list,
+                Err(e) => { eprintln!("{e:#}"); std::thread::sleep(Duration::from_secs(5)); continue; }
+            };
+            let mut resources: Resources = list.items.into_iter().map(|f| ((f.metadata.namespace.clone(), f.metadata.name.clone()), f)).collect();
+            self.reconcile(&kube, &resources, &mut reported);
+            if let Err(e) = self.watch(&kube, list.metadata.resource_version, &mut resources, &mut reported) { eprintln!("watch ended: {e:#}"); }
+        }
+    }
+
+    fn watch(&self, kube: &Kube, mut resource_version: String, resources: &mut Resources, reported: &mut HashMap<(String, String), (i64, bool, String)>) -> anyhow::Result<()> {
+        let req = kube.http.get(kube.collection(self.namespace.as_deref()))
+            .query(&[("watch", "true"), ("allowWatchBookmarks", "true"), ("resourceVersion", resource_version.as_str())])
+            .query(&[("timeoutSeconds", self.resync.as_secs())]);
+        let res = kube.auth(req).send()?;
+        if !res.status().is_success() { bail!("watch failed with {}", res.status()); }
+        for line in BufReader::new(res).lines() {
+            let event: WatchEvent = serde_json::from_str(&line?)?;
+            match event.kind.as_str() {
+                "ADDED" | "MODIFIED" | "DELETED" => {
+                    let flag: FeatureFlag = match serde_json::from_value(event.object) {
+                        Ok(f) => f,
+                        Err(e) => { eprintln!("skipping malformed FeatureFlag: {e}"); continue; }
+                    };
+                    if let Some(rv) = &flag.metadata.resource_version { resource_version.clone_from(rv); }
+                    let id = (flag.metadata.namespace.clone(), flag.metadata.name.clone());
+                    if event.kind == "DELETED" { resources.remove(&id); reported.remove(&id); } else { resources.insert(id, flag); }
+                    self.reconcile(kube, resources, reported);
+                }
+                "BOOKMARK" => {
+                    if let Some(rv) = event.object.pointer("/metadata/resourceVersion").and_then(|v| v.as_str()) { resource_version = rv.to_string(); }
+                }
+                _ => bail!("{}", event.object.get("message").and_then(|m| m.as_str()).unwrap_or("watch error")),
+            }
+        }
+        Ok(())
+    }
+
+    fn reconcile(&self, kube: &Kube, resources: &Resources, reported: &mut HashMap<(String, String), (i64, bool, String)>) {
+        let mut outcome: HashMap<(String, String), (bool, String)> = HashMap::new();
+        let mut owners: HashMap<String, (String, String)> = HashMap::new();
+        let mut flags = Vec::new();
+        for (id, f) in resources {
+            let key = f.spec.key.clone().unwrap_or_else(|| f.metadata.name.clone());
+            if let Some(owner) = owners.get(&key) {
+                outcome.insert(id.clone(), (false, format!("flag `{key}` is already defined by {}/{}", owner.0, owner.1)));
+                continue;
+            }
+            owners.insert(key.clone(), id.clone());
+            flags.push(CreateFlag { uid: None, key, project: self.project.clone(), enabled: f.spec.enabled, protected: f.spec.protected, variants: f.spec.variants.clone(), rollout: f.spec.rollout });
+        }
+        match self.api.apply(&flags, &self.project, self.prune, self.confirm_protected) {
+            Ok(result) => {
+                for c in &result.changes { eprintln!("{}: {}", c.key, c.action); }
+                for id in owners.values() { outcome.insert(id.clone(), (true, "applied".into())); }
+            }
+            Err(e) => {
+                eprintln!("apply failed: {e:#}");
+                for id in owners.values() { outcome.insert(id.clone(), (false, format!("{e:#}"))); }
+            }
+        }
+        for (id, (synced, message)) in outcome {
+            let Some(flag) = resources.get(&id) else { continue };
+            let state = (flag.metadata.generation, synced, message.clone());
+            if reported.get(&id) == Some(&state) { continue; }
+            match kube.set_status(flag, synced, &message) {
+                Ok(()) => { reported.insert(id, state); }
+                Err(e) => eprintln!("{e:#}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::{anyhow, bail};
+use clap::{Parser, Subcommand, ValueEnum};
+use feature_flags_core::{diff_flags, CreateFlag, Flag, UpdateFlag};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+mod api;
+mod config;
+mod k8s;
+mod tui;
+
+#[derive(Parser)]
+#[command(name = "flagctl", about = "Manage flags on a rust-feature-flags-toggler server", version)]
+struct Cli {
+    #[arg(long, global = true, env = "FLAGCTL_ENV")]
+    env: Option<String>,
+    #[arg(long, global = true, env = "FLAGCTL_URL")]
+    url: Option<String>,
+    #[arg(long, global = true, env = "FLAGCTL_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List flags
+    List {
+        #[arg(long)]
+        project: Option<String>,
+    },
+    /// Show one flag
+    Get { key: String },
+    /// Flip a flag, or force it with --on / --off
+    Toggle {
+        key: String,
+        #[arg(long, conflicts_with = "off")]
+        on: bool,
+        #[arg(long)]
+        off: bool,
+        #[arg(long)]
+        confirm_protected: bool,
+    },
+    /// Print all flags in the format `apply` reads
+    Export {
+        #[arg(long, value_enum, default_value = "yaml")]
+        format: Format,
+        #[arg(long)]
+        project: Option<String>,
+    },
+    /// Compare flags with another environment from the config, e.g. before promoting
+    Diff {
+        key: Option<String>,
+        #[arg(long)]
+        against: String,
+        #[arg(long)]
+        project: Option<String>,
+    },
+    /// Create or update flags to match a file
+    Apply {
+        #[arg(short = 'f', long = "file")]
+        file: PathBuf,
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(long)]
+        confirm_protected: bool,
+    },
+    /// Reconcile FeatureFlag custom resources from a Kubernetes cluster into the server
+    K8sController {
+        #[arg(long, env = "KUBE_API_URL")]
+        kube_api: Option<String>,
+        #[arg(long)]
+        namespace: Option<String>,
+        #[arg(long, default_value = "default")]
+        project: String,
+        #[arg(long)]
+        prune: bool,
+        #[arg(long)]
+        confirm_protected: bool,
+        #[arg(long, default_value_t = 300)]
+        resync_secs: u64,
+    },
+    /// Live dashboard: flag states, recent changes and evaluation rate
+    Tui {
+        #[arg(long)]
+        confirm_protected: bool,
+        #[arg(long, default_value_t = 2)]
+        refresh_secs: u64,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Yaml,
+    Json,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FlagFile {
+    flags: Vec<CreateFlag>,
+}
+
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("error: {e:#}");
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> anyhow::Result<()> {
+    let target = config::resolve(cli.env.as_deref(), cli.url.as_deref(), cli.token.as_deref())?;
+    let api = api::Api::new(&target)?;
+    match cli.command {
+        Command::List { project } => {
+            let flags: Vec<Flag> = api.list()?.into_iter().filter(|f| project.as_ref().is_none_or(|p| &f.project == p)).collect();
+            let width = flags.iter().map(|f| f.key.len()).max().unwrap_or(3).max(3);
+            println!("{:<width$}  {:<12}  {:<7}  {:<7}  VARIANTS", "KEY", "PROJECT", "ENABLED", "ROLLOUT");
+            for f in flags {
+                let rollout = f.rollout.map(|r| format!("{r}%")).unwrap_or_else(|| "-".into());
+                let mut variants: Vec<String> = f.variants.iter().flatten().map(|(n, w)| format!("{n}={w}")).collect();
+                variants.sort();
+                let key = if f.protected { format!("{} (protected)", f.key) } else { f.key };
+                println!("{:<width$}  {:<12}  {:<7}  {:<7}  {}", key, f.project, if f.enabled { "on" } else { "off" }, rollout, variants.join(","));
+            }
+        }
+        Command::Get { key } => {
+            let flag = api.get(&key)?.ok_or_else(|| anyhow!("flag `{key}` not found"))?;
+            println!("{}", serde_json::to_string_pretty(&flag)?);
+        }
+        Command::Toggle { key, on, off, confirm_protected } => {
+            let flag = api.get(&key)?.ok_or_else(|| anyhow!("flag `{key}` not found"))?;
+            let enabled = if on { true } else if off { false } else { !flag.enabled };
+            api.update(&key, &UpdateFlag { enabled: Some(enabled), ..Default::default() }, false, confirm_protected)?;
+            println!("{key}: {} -> {}{}", onoff(flag.enabled), onoff(enabled), target.env.map(|e| format!(" ({e})")).unwrap_or_default());
+        }
+        Command::Export { format, project } => {
+            let flags = api.list()?.into_iter().filter(|f| project.as_ref().is_none_or(|p| &f.project == p)).map(to_input).collect();
+            let file = FlagFile { flags };
+            match format {
+                Format::Yaml => print!("{}", serde_yaml::to_string(&file)?),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&file)?),
+            }
+        }
+        Command::Diff { key, against, project } => {
+            let other = api::Api::new(&config::resolve(Some(&against), None, None)?)?;
+            let here = target.env.clone().unwrap_or_else(|| target.url.clone());
+            let wanted = |f: &Flag| key.as_ref().is_none_or(|k| &f.key == k) && project.as_ref().is_none_or(|p| &f.project == p);
+            let ours: BTreeMap<String, Flag> = api.list()?.into_iter().filter(wanted).map(|f| (f.key.clone(), f)).collect();
+            let theirs: BTreeMap<String, Flag> = other.list()?.into_iter().filter(wanted).map(|f| (f.key.clone(), f)).collect();
+            if let Some(k) = &key { if ours.is_empty() && theirs.is_empty() { bail!("flag `{k}` not found in either environment"); } }
+            let mut differ = 0;
+            for k in ours.keys().chain(theirs.keys().filter(|k| !ours.contains_key(*k))) {
+                let line = match (ours.get(k), theirs.get(k)) {
+                    (Some(_), None) => format!("only in {here}"),
+                    (None, Some(_)) => format!("only in {against}"),
+                    (Some(a), Some(b)) => {
+                        let changes = diff_flags(Some(&comparable(a)), &comparable(b));
+                        if changes.is_empty() { continue; }
+                        changes.iter().map(|c| format!("{}: {} -> {}", c.field, c.from, c.to)).collect::<Vec<_>>().join(", ")
+                    }
+                    (None, None) => continue,
+                };
+                println!("{k}: {line}");
+                differ += 1;
+            }
+            if differ == 0 { println!("no differences between {here} and {against}"); }
+        }
+        Command::Apply { file, dry_run, confirm_protected } => {
+            let text = std::fs::read_to_string(&file)?;
+            let desired: FlagFile = serde_yaml::from_str(&text)?;
+            let mut failed = 0;
+            for input in desired.flags {
+                if let Err(e) = apply_one(&api, &input, dry_run, confirm_protected) {
+                    eprintln!("{}: {e:#}", input.key);
+                    failed += 1;
+                }
+            }
+            if failed > 0 { bail!("{failed} flag(s) failed to apply"); }
+        }
+        Command::K8sController { kube_api, namespace, project, prune, confirm_protected, resync_secs } => {
+            k8s::Controller { api: &api, kube_api, namespace, project, prune, confirm_protected, resync: std::time::Duration::from_secs(resync_secs.max(10)) }.run()?
+        }
+        Command::Tui { confirm_protected, refresh_secs } => tui::run(&api, target.env, confirm_protected, std::time::Duration::from_secs(refresh_secs.max(1)))?,
+    }
+    Ok(())
+}
+
+fn apply_one(api: &api::Api, input: &CreateFlag, dry_run: bool, confirm_protected: bool) -> anyhow::Result<()> {
+    let prefix = if dry_run { "would " } else { "" };
+    let Some(current) = api.get(&input.key)? else {
+        api.create(input, dry_run)?;
+        println!("{}: {prefix}create", input.key);
+        return Ok(());
+    };
+    if current.project != input.project { bail!("exists in project `{}`, not `{}`", current.project, input.project); }
+    if input.uid.as_ref().is_some_and(|u| *u != current.uid) { bail!("exists with uid `{}`, not `{}`", current.uid, input.uid.as_deref().unwrap_or_default()); }
+    let desired = Flag { id: current.id, uid: current.uid.clone(), key: current.key.clone(), project: current.project.clone(), enabled: input.enabled, protected: input.protected, variants: input.variants.clone(), rollout: input.rollout, lifecycle: current.lifecycle, updated_at: current.updated_at.clone(), pins: current.pins.clone() };
+    let changes = diff_flags(Some(&current), &desired);
+    if changes.is_empty() {
+        println!("{}: unchanged", input.key);
+        return Ok(());
+    }
+    if input.variants.is_none() && current.variants.is_some() { bail!("removing variants is not supported by PATCH; set them to an empty map instead"); }
+    if input.rollout.is_none() && current.rollout.is_some() { bail!("removing a rollout is not supported by PATCH; set it to 100 instead"); }
+    let update = UpdateFlag { enabled: Some(input.enabled), protected: Some(input.protected), variants: input.variants.clone(), rollout: input.rollout, lifecycle: None };
+    api.update(&input.key, &update, dry_run, confirm_protected)?;
+    let summary: Vec<String> = changes.iter().map(|c| format!("{}: {} -> {}", c.field, c.from, c.to)).collect();
+    println!("{}: {prefix}update ({})", input.key, summary.join(", "));
+    Ok(())
+}
+
+fn to_input(f: Flag) -> CreateFlag {
+    CreateFlag { uid: Some(f.uid).filter(|u| !u.is_empty()), key: f.key, project: f.project, enabled: f.enabled, protected: f.protected, variants: f.variants, rollout: f.rollout }
+}
+
+// uids and pins are each environment's own, so only the configuration is compared
+fn comparable(f: &Flag) -> Flag {
+    Flag { uid: String::new(), pins: Default::default(), ..f.clone() }
+}
+
+fn onoff(enabled: bool) -> &'static str { if enabled { "on" } else { "off" } }
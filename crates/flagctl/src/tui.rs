@@ -0,0 +1,193 @@
+use feature_flags_core::{Flag, UpdateFlag};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Cell, List, ListItem, Paragraph, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+use std::{sync::mpsc, thread, time::{Duration, Instant}};
+
+use crate::api::{Api, AuditEntry};
+
+const SDK_ROUTES: &[&str] = &["/evaluate", "/snapshot", "/rules", "/bootstrap"];
+
+struct App<'a> {
+    api: &'a Api,
+    env: String,
+    confirm_protected: bool,
+    flags: Vec<Flag>,
+    changes: Option<Vec<AuditEntry>>,
+    table: TableState,
+    evaluations: Option<(Instant, String, i64)>,
+    rate: Option<f64>,
+    // usage is one request per token, so it's counted on another thread and picked up when done
+    counting: Option<mpsc::Receiver<Option<(Instant, String, i64)>>>,
+    message: String,
+    refreshed_at: Option<Instant>,
+}
+
+pub fn run(api: &Api, env: Option<String>, confirm_protected: bool, every: Duration) -> anyhow::Result<()> {
+    let mut app = App {
+        api,
+        env: env.unwrap_or_else(|| "-".into()),
+        confirm_protected,
+        flags: Vec::new(),
+        changes: None,
+        table: TableState::default().with_selected(0),
+        evaluations: None,
+        rate: None,
+        counting: None,
+        message: String::new(),
+        refreshed_at: None,
+    };
+    let mut terminal = ratatui::init();
+    let res = app.event_loop(&mut terminal, every);
+    ratatui::restore();
+    res
+}
+
+impl App<'_> {
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal, every: Duration) -> anyhow::Result<()> {
+        loop {
+            if self.refreshed_at.is_none_or(|t| t.elapsed() >= every) { self.refresh(); }
+            self.update_rate();
+            terminal.draw(|f| self.draw(f))?;
+            if !event::poll(Duration::from_millis(250))? { continue; }
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press { continue; }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::Char(' ') | KeyCode::Char('t') => self.toggle(),
+                KeyCode::Char('+') | KeyCode::Char('=') => self.adjust_rollout(10),
+                KeyCode::Char('-') => self.adjust_rollout(-10),
+                KeyCode::Char(']') => self.adjust_rollout(1),
+                KeyCode::Char('[') => self.adjust_rollout(-1),
+                KeyCode::Char('r') => self.refresh(),
+                _ => {}
+            }
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.refreshed_at = Some(Instant::now());
+        match self.api.list() {
+            Ok(mut flags) => { flags.sort_by(|a, b| a.key.cmp(&b.key)); self.flags = flags; }
+            Err(e) => { self.message = format!("refresh failed: {e:#}"); return; }
+        }
+        if let Some(i) = self.table.selected() { if i >= self.flags.len() { self.table.select(self.flags.len().checked_sub(1)); } }
+        self.changes = self.api.audit(50).ok().flatten();
+        if self.counting.is_none() {
+            let (tx, rx) = mpsc::channel();
+            let (api, day) = (self.api.clone(), self.evaluations.as_ref().map(|(_, day, _)| day.clone()).unwrap_or_default());
+            thread::spawn(move || { let _ = tx.send(count_sdk_requests(&api, day)); });
+            self.counting = Some(rx);
+        }
+    }
+
+    fn update_rate(&mut self) {
+        let Some(rx) = &self.counting else { return };
+        let counted = match rx.try_recv() {
+            Ok(counted) => counted,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => None,
+        };
+        self.counting = None;
+        let Some((now, day, total)) = counted else { self.rate = None; return };
+        if let Some((at, prev_day, prev)) = &self.evaluations {
+            if *prev_day == day { self.rate = Some((total - prev).max(0) as f64 / now.duration_since(*at).as_secs_f64()); }
+        }
+        self.evaluations = Some((now, day, total));
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let last = self.flags.len().saturating_sub(1);
+        self.table.select(Some(self.table.selected().unwrap_or(0).saturating_add_signed(delta).min(last)));
+    }
+
+    fn selected(&self) -> Option<&Flag> { self.table.selected().and_then(|i| self.flags.get(i)) }
+
+    fn patch(&mut self, update: UpdateFlag, done: String) {
+        let Some(flag) = self.selected() else { return };
+        let key = flag.key.clone();
+        if flag.protected && !self.confirm_protected {
+            self.message = format!("{key} is protected; restart with --confirm-protected to change it");
+            return;
+        }
+        self.message = match self.api.update(&key, &update, false, flag.protected) {
+            Ok(()) => format!("{key}: {done}"),
+            Err(e) => format!("{key}: {e:#}"),
+        };
+        self.refresh();
+    }
+
+    fn toggle(&mut self) {
+        let Some(flag) = self.selected() else { return };
+        let enabled = !flag.enabled;
+        self.patch(UpdateFlag { enabled: Some(enabled), ..Default::default() }, format!("turned {}", if enabled { "on" } else { "off" }));
+    }
+
+    fn adjust_rollout(&mut self, delta: i16) {
+        let Some(flag) = self.selected() else { return };
+        let rollout = (flag.rollout.unwrap_or(100) as i16 + delta).clamp(0, 100) as u8;
+        self.patch(UpdateFlag { rollout: Some(rollout), ..Default::default() }, format!("rollout {rollout}%"));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(5), Constraint::Length(3)]).areas(frame.area());
+        let [flags_area, changes_area] = Layout::horizontal([Constraint::Percentage(62), Constraint::Percentage(38)]).areas(main);
+
+        let rows = self.flags.iter().map(|f| {
+            let state = if f.enabled { Cell::from("on").style(Style::default().fg(Color::Green)) } else { Cell::from("off").style(Style::default().fg(Color::Red)) };
+            let mut variants: Vec<String> = f.variants.iter().flatten().map(|(n, w)| format!("{n}={w}")).collect();
+            variants.sort();
+            let key = if f.protected { format!("{} 🔒", f.key) } else { f.key.clone() };
+            Row::new(vec![Cell::from(key), Cell::from(f.project.clone()), state, Cell::from(f.rollout.map(|r| format!("{r}%")).unwrap_or_else(|| "-".into())), Cell::from(variants.join(","))])
+        });
+        let table = Table::new(rows, [Constraint::Percentage(34), Constraint::Percentage(16), Constraint::Length(4), Constraint::Length(8), Constraint::Fill(1)])
+            .header(Row::new(["KEY", "PROJECT", "", "ROLLOUT", "VARIANTS"]).style(Style::default().add_modifier(Modifier::BOLD)))
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .block(Block::default().borders(Borders::ALL).title(format!(" flags · {} ", self.env)));
+        frame.render_stateful_widget(table, flags_area, &mut self.table);
+
+        let items: Vec<ListItem> = match &self.changes {
+            Some(entries) => entries.iter().take(changes_area.height as usize).map(|e| {
+                let color = if e.outcome == "ok" { Color::Reset } else { Color::Yellow };
+                ListItem::new(Line::from(vec![
+                    Span::styled(e.at.get(11..19).unwrap_or(&e.at).to_string(), Style::default().fg(Color::DarkGray)),
+                    Span::raw(" "),
+                    Span::styled(format!("{} {}", e.action.split(' ').next().unwrap_or_default(), e.target.as_deref().unwrap_or("")), Style::default().fg(color)),
+                    Span::styled(format!(" {}", e.actor.as_deref().unwrap_or("?")), Style::default().fg(Color::DarkGray)),
+                ]))
+            }).collect(),
+            None => {
+                let mut recent: Vec<&Flag> = self.flags.iter().collect();
+                recent.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+                recent.into_iter().map(|f| ListItem::new(format!("{} {} updated", f.updated_at, f.key))).collect()
+            }
+        };
+        let title = if self.changes.is_some() { " recent changes " } else { " recently updated (audit log needs an admin token) " };
+        frame.render_widget(List::new(items).block(Block::default().borders(Borders::ALL).title(title)), changes_area);
+
+        let rate = match self.rate { Some(r) => format!("{r:.1} SDK req/s"), None if self.evaluations.is_some() => "measuring…".into(), None => "rate n/a".into() };
+        let help = "↑↓ select · space toggle · +/- rollout ±10 · [/] ±1 · r refresh · q quit";
+        let text = vec![Line::from(vec![Span::styled(rate, Style::default().fg(Color::Cyan)), Span::raw(format!(" · {} flags · ", self.flags.len())), Span::raw(self.message.clone())]), Line::from(Span::styled(help, Style::default().fg(Color::DarkGray)))];
+        frame.render_widget(Paragraph::new(text).block(Block::default().borders(Borders::TOP)), status);
+    }
+}
+
+// today's SDK requests over all live tokens, on the latest day any token has seen (never earlier than `day`)
+fn count_sdk_requests(api: &Api, mut day: String) -> Option<(Instant, String, i64)> {
+    let Ok(Some(tokens)) = api.tokens() else { return None };
+    let mut total = 0;
+    for t in tokens.iter().filter(|t| t.revoked_at.is_none()) {
+        let Ok(Some(usage)) = api.usage(t.id) else { continue };
+        let Some(latest) = usage.daily.iter().map(|r| r.day.clone()).max() else { continue };
+        if latest > day { day = latest; }
+        total += usage.daily.iter().filter(|r| r.day == day && SDK_ROUTES.contains(&r.route.as_str())).map(|r| r.count).sum::<i64>();
+    }
+    Some((Instant::now(), day, total))
+}
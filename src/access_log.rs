@@ -0,0 +1,106 @@
+use axum::{extract::{MatchedPath, Request, State}, http::header::USER_AGENT, middleware::Next, response::Response};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{sync::{Arc, OnceLock}, time::Instant};
+
+use crate::config::AccessLogConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Redaction {
+    Hash,
+    Mask,
+    None,
+}
+
+pub struct AccessLog {
+    redaction: Redaction,
+    params: Vec<String>,
+    hash_key: Vec<u8>,
+}
+
+// filled in by the auth middleware so the outer access log can report which token was used
+#[derive(Clone, Default)]
+pub struct TokenSlot(Arc<OnceLock<i64>>);
+
+pub fn note_token(req: &Request, token_id: i64) {
+    if let Some(slot) = req.extensions().get::<TokenSlot>() { let _ = slot.0.set(token_id); }
+}
+
+impl AccessLog {
+    pub fn from_config(config: &AccessLogConfig) -> anyhow::Result<Option<Arc<AccessLog>>> {
+        if !config.enabled { return Ok(None); }
+        let redaction = match config.redaction.as_deref() {
+            None | Some("hash") => Redaction::Hash,
+            Some("mask") => Redaction::Mask,
+            Some("none") => Redaction::None,
+            Some(other) => anyhow::bail!("unknown ACCESS_LOG_REDACTION {other:?}, expected hash, mask or none"),
+        };
+        let params = config.redact_params.clone().unwrap_or_else(|| ["user_id", "anonymous_id", "email"].map(String::from).to_vec()).into_iter().filter(|p| !p.is_empty()).collect();
+        // without a configured key, hashes only correlate requests within one process
+        let hash_key = match config.hash_key.as_deref() {
+            Some(key) if !key.is_empty() => key.as_bytes().to_vec(),
+            _ if redaction == Redaction::Hash => {
+                tracing::warn!("ACCESS_LOG_HASH_KEY is not set, using a random key for this run");
+                uuid::Uuid::new_v4().as_bytes().to_vec()
+            }
+            _ => Vec::new(),
+        };
+        Ok(Some(Arc::new(AccessLog { redaction, params, hash_key })))
+    }
+
+    fn redact(&self, value: &str) -> String {
+        match self.redaction {
+            Redaction::Hash => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(&self.hash_key).expect("hmac accepts any key length");
+                mac.update(value.as_bytes());
+                format!("h:{}", mac.finalize().into_bytes()[..8].iter().map(|b| format!("{b:02x}")).collect::<String>())
+            }
+            Redaction::Mask => "***".into(),
+            Redaction::None => value.to_string(),
+        }
+    }
+
+    fn sensitive(&self, name: &str) -> bool {
+        self.redaction != Redaction::None && self.params.iter().any(|p| p == name)
+    }
+
+    fn path(&self, req: &Request) -> String {
+        let uri = req.uri();
+        let mut path = match req.extensions().get::<MatchedPath>() {
+            Some(matched) if matched.as_str().contains(':') => matched.as_str().split('/').zip(uri.path().split('/'))
+                .map(|(pattern, actual)| match pattern.strip_prefix(':') { Some(name) if self.sensitive(name) => self.redact(actual), _ => actual.to_string() })
+                .collect::<Vec<_>>()
+                .join("/"),
+            _ => uri.path().to_string(),
+        };
+        if let Some(query) = uri.query() {
+            path.push('?');
+            path.push_str(&query.split('&').map(|pair| match pair.split_once('=') {
+                Some((name, value)) if self.sensitive(name) => format!("{name}={}", self.redact(value)),
+                _ => pair.to_string(),
+            }).collect::<Vec<_>>().join("&"));
+        }
+        path
+    }
+}
+
+pub async fn log(State(log): State<Arc<AccessLog>>, mut req: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let path = log.path(&req);
+    let user_agent = req.headers().get(USER_AGENT).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+    let slot = TokenSlot::default();
+    req.extensions_mut().insert(slot.clone());
+    let res = next.run(req).await;
+    tracing::info!(
+        target: "access_log",
+        method = %method,
+        path,
+        status = res.status().as_u16(),
+        latency_ms = started.elapsed().as_secs_f64() * 1000.0,
+        token_id = slot.0.get().copied(),
+        user_agent,
+        "request"
+    );
+    res
+}
@@ -0,0 +1,106 @@
+use axum::{extract::{ConnectInfo, Request, State}, http::{HeaderMap, Method, StatusCode}, middleware::Next, response::Response};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::{audit, config::AuthConfig, AppState};
+
+pub struct Allowlist {
+    nets: Vec<IpNet>,
+    trust_forwarded_for: bool,
+    trusted_proxies: usize,
+}
+
+impl Allowlist {
+    pub fn from_config(config: &AuthConfig) -> anyhow::Result<Allowlist> {
+        let nets = config.management_allowlist.iter()
+            .map(|n| n.trim())
+            .filter(|n| !n.is_empty())
+            .map(|n| n.parse::<IpNet>().or_else(|_| n.parse::<IpAddr>().map(IpNet::from)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("invalid MANAGEMENT_ALLOWLIST entry: {e}"))?;
+        let trust_forwarded_for = config.trust_forwarded_for;
+        let trusted_proxies = config.trusted_proxies.unwrap_or(1);
+        if trusted_proxies == 0 { anyhow::bail!("TRUSTED_PROXIES must be at least 1"); }
+        if !nets.is_empty() { tracing::info!(ranges = nets.len(), "management allowlist enabled"); }
+        Ok(Allowlist { nets, trust_forwarded_for, trusted_proxies })
+    }
+
+    // each proxy appends the address it was called from, so only the last TRUSTED_PROXIES entries can be believed;
+    // anything left of them is whatever the client chose to send
+    pub fn client_ip(&self, peer: SocketAddr, headers: &HeaderMap) -> IpAddr {
+        if self.trust_forwarded_for {
+            let hops: Vec<&str> = headers.get_all("x-forwarded-for").iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .map(str::trim)
+                .collect();
+            let forwarded = hops.get(hops.len().saturating_sub(self.trusted_proxies)).and_then(|v| v.parse::<IpAddr>().ok());
+            if let Some(ip) = forwarded { return ip; }
+        }
+        peer.ip()
+    }
+
+    fn allows(&self, ip: IpAddr) -> bool {
+        self.nets.is_empty() || self.nets.iter().any(|n| n.contains(&ip))
+    }
+}
+
+pub async fn enforce(State(state): State<AppState>, ConnectInfo(peer): ConnectInfo<SocketAddr>, req: Request, next: Next) -> Result<Response, StatusCode> {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) { return Ok(next.run(req).await); }
+    let ip = state.allowlist.client_ip(peer, req.headers());
+    if !state.allowlist.allows(ip) {
+        tracing::warn!(%ip, method = %req.method(), path = %req.uri().path(), "rejected management request from outside allowlist");
+        state.audit.record(audit::NewEntry {
+            action: "allowlist.rejected",
+            target: Some(req.uri().path()),
+            outcome: "denied",
+            ip: Some(ip.to_string()),
+            detail: Some(serde_json::json!({ "method": req.method().as_str() })),
+            ..Default::default()
+        }).await;
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist(nets: &[&str], trusted_proxies: Option<usize>) -> anyhow::Result<Allowlist> {
+        Allowlist::from_config(&AuthConfig { management_allowlist: nets.iter().map(|n| n.to_string()).collect(), trust_forwarded_for: true, trusted_proxies, ..Default::default() })
+    }
+
+    fn ip(s: &str) -> IpAddr { s.parse().unwrap() }
+
+    #[test]
+    fn matches_ranges_and_single_addresses() {
+        let list = allowlist(&["10.0.0.0/8", " 192.168.1.7 ", "", "fd00::/8"], None).unwrap();
+        for allowed in ["10.0.0.1", "10.255.255.255", "192.168.1.7", "fd00::1"] { assert!(list.allows(ip(allowed)), "{allowed}"); }
+        for denied in ["11.0.0.1", "192.168.1.8", "9.255.255.255", "fe80::1", "::1"] { assert!(!list.allows(ip(denied)), "{denied}"); }
+    }
+
+    #[test]
+    fn empty_allows_everyone() {
+        assert!(allowlist(&[], None).unwrap().allows(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        assert!(allowlist(&["10.0.0.0/33"], None).is_err());
+        assert!(allowlist(&["not-an-ip"], None).is_err());
+        assert!(allowlist(&[], Some(0)).is_err());
+    }
+
+    #[test]
+    fn believes_only_the_trusted_hops() {
+        let peer: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "10.0.0.1, 203.0.113.9, 172.16.0.2".parse().unwrap());
+        assert_eq!(allowlist(&[], None).unwrap().client_ip(peer, &headers), ip("172.16.0.2"));
+        assert_eq!(allowlist(&[], Some(2)).unwrap().client_ip(peer, &headers), ip("203.0.113.9"));
+        // more trusted proxies than hops falls back to the leftmost rather than past it
+        assert_eq!(allowlist(&[], Some(5)).unwrap().client_ip(peer, &headers), ip("10.0.0.1"));
+        assert_eq!(allowlist(&[], None).unwrap().client_ip(peer, &HeaderMap::new()), ip("127.0.0.1"));
+    }
+}
@@ -0,0 +1,78 @@
+use axum::{extract::{Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+
+use feature_flags_core::{diff_flags, Flag};
+
+use crate::{auth::{Principal, Role}, exports::normalize_time, AppState};
+
+const MAX_ANNOTATIONS: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct AnnotationParams {
+    from: Option<String>,
+    to: Option<String>,
+    flag: Option<String>,
+    project: Option<String>,
+}
+
+// the shape Grafana's JSON and Infinity data sources map onto annotations without any field mapping
+#[derive(Debug, Serialize)]
+pub struct Annotation {
+    time: i64,
+    #[serde(rename = "timeEnd")]
+    time_end: i64,
+    title: String,
+    text: String,
+    tags: Vec<String>,
+}
+
+// grafana passes ${__from} and ${__to} as epoch milliseconds; anything the exports accept works too
+fn parse_time(s: &str) -> Option<String> {
+    match s.parse::<i64>() {
+        Ok(ms) => chrono::DateTime::from_timestamp_millis(ms).map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string()),
+        Err(_) => normalize_time(s),
+    }
+}
+
+fn epoch_millis(at: &str) -> i64 {
+    chrono::NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M:%S").map(|t| t.and_utc().timestamp_millis()).unwrap_or_default()
+}
+
+fn describe(before: Option<&Flag>, after: &Flag) -> String {
+    diff_flags(before, after).iter().filter(|c| c.field != "uid").map(|c| match before {
+        Some(_) => format!("{}: {} → {}", c.field, c.from, c.to),
+        None => format!("{}: {}", c.field, c.to),
+    }).collect::<Vec<_>>().join(", ")
+}
+
+pub async fn annotations(State(state): State<AppState>, Extension(principal): Extension<Principal>, Query(params): Query<AnnotationParams>) -> Result<Json<Vec<Annotation>>, StatusCode> {
+    let now = chrono::Utc::now();
+    let from = match params.from { Some(f) => parse_time(&f).ok_or(StatusCode::BAD_REQUEST)?, None => (now - chrono::Duration::hours(24)).format("%Y-%m-%d %H:%M:%S").to_string() };
+    let to = match params.to { Some(t) => parse_time(&t).ok_or(StatusCode::BAD_REQUEST)?, None => now.format("%Y-%m-%d %H:%M:%S").to_string() };
+    let rows = sqlx::query("SELECT r.flag_key, r.rev, r.data, r.created_at, p.data AS previous FROM flag_revisions r LEFT JOIN flag_revisions p ON p.flag_key = r.flag_key AND p.rev = r.rev - 1 WHERE r.created_at >= ? AND r.created_at <= ? AND (? IS NULL OR r.flag_key = ?) ORDER BY r.id LIMIT ?")
+        .bind(&from)
+        .bind(&to)
+        .bind(&params.flag)
+        .bind(&params.flag)
+        .bind(MAX_ANNOTATIONS)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut out = Vec::with_capacity(rows.len());
+    for r in rows {
+        let Ok(flag) = serde_json::from_str::<Flag>(&r.get::<String,_>("data")) else { continue };
+        if !principal.has_role(&flag.project, Role::Viewer) || params.project.as_ref().is_some_and(|p| p != &flag.project) { continue; }
+        let previous = r.get::<Option<String>,_>("previous").and_then(|p| serde_json::from_str::<Flag>(&p).ok());
+        let action = if previous.is_some() { "updated" } else { "created" };
+        let time = epoch_millis(&r.get::<String,_>("created_at"));
+        out.push(Annotation {
+            time,
+            time_end: time,
+            title: format!("{} {action} (rev {})", flag.key, r.get::<i64,_>("rev")),
+            text: describe(previous.as_ref(), &flag),
+            tags: vec!["feature-flag".into(), flag.key.clone(), flag.project.clone(), action.into()],
+        });
+    }
+    Ok(Json(out))
+}
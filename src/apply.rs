@@ -0,0 +1,148 @@
+use axum::{extract::{Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use feature_flags_core::{diff_flags, lint_flag, sqlite, CreateFlag, FieldChange, Flag, FlagStore, StoreError};
+
+use crate::{auth::{Principal, Role}, experiments, flags_file, quotas, store_status, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct ApplyInput {
+    flags: Vec<CreateFlag>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApplyParams {
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub prune: bool,
+    pub project: Option<String>,
+    #[serde(default)]
+    pub confirm_protected: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedChange {
+    key: String,
+    project: String,
+    action: &'static str,
+    diff: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApplyResponse {
+    dry_run: bool,
+    changes: Vec<PlannedChange>,
+    unchanged: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DriftParams {
+    project: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Drifted {
+    key: String,
+    diff: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DriftReport {
+    in_sync: bool,
+    missing: Vec<String>,
+    extra: Vec<String>,
+    changed: Vec<Drifted>,
+}
+
+fn guard_protected(principal: &Principal, project: &str, confirmed: bool) -> Result<(), StatusCode> {
+    principal.require(project, Role::Admin)?;
+    if !confirmed { return Err(StatusCode::PRECONDITION_REQUIRED); }
+    Ok(())
+}
+
+pub async fn apply(State(state): State<AppState>, Extension(principal): Extension<Principal>, Query(params): Query<ApplyParams>, Json(input): Json<ApplyInput>) -> Result<Json<ApplyResponse>, StatusCode> {
+    apply_flags(&state, &principal, &params, &input.flags).await.map(Json)
+}
+
+// shared with the importers, which translate another tool's export into the same desired state
+pub async fn apply_flags(state: &AppState, principal: &Principal, params: &ApplyParams, flags: &[CreateFlag]) -> Result<ApplyResponse, StatusCode> {
+    if crate::break_glass::approval_required(state, principal) && !params.dry_run { return Err(StatusCode::FORBIDDEN); }
+    let mut keys = HashSet::new();
+    for f in flags {
+        if !keys.insert(f.key.as_str()) || f.validate().is_err() { return Err(StatusCode::BAD_REQUEST); }
+        if params.project.as_ref().is_some_and(|p| *p != f.project) { return Err(StatusCode::BAD_REQUEST); }
+    }
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut changes = Vec::new();
+    let mut unchanged = 0;
+    let mut renamed = Vec::new();
+    for want in flags {
+        // a uid that already belongs to another key renames that flag, so tools tracking it by uid can change its key
+        let found = match (sqlite::fetch_flag(&mut tx, &want.key).await, &want.uid) {
+            (Err(StoreError::NotFound), Some(uid)) => sqlite::fetch_flag_by_uid(&mut tx, uid).await,
+            (found, _) => found,
+        };
+        let current = match found {
+            Err(StoreError::NotFound) => {
+                principal.require(&want.project, if want.protected { Role::Admin } else { Role::Editor })?;
+                let after = sqlite::insert_flag(&mut tx, want).await.map_err(store_status)?;
+                if !lint_flag(&after).is_valid() { return Err(StatusCode::UNPROCESSABLE_ENTITY); }
+                quotas::enforce(&mut tx, &after, true, false).await?;
+                changes.push(PlannedChange { key: want.key.clone(), project: want.project.clone(), action: "created", diff: diff_flags(None, &after) });
+                continue;
+            }
+            other => other.map_err(store_status)?,
+        };
+        if current.project != want.project || want.uid.as_ref().is_some_and(|u| *u != current.uid) { return Err(StatusCode::UNPROCESSABLE_ENTITY); }
+        let drift = flags_file::drift(&current, want);
+        if drift.is_noop() && current.key == want.key { unchanged += 1; continue; }
+        principal.require(&current.project, Role::Editor)?;
+        if current.protected || drift.protected { guard_protected(principal, &current.project, params.confirm_protected)?; }
+        if current.key != want.key {
+            sqlite::rename_flag(&mut tx, &current.key, &want.key).await.map_err(store_status)?;
+            renamed.push(current.key.clone());
+        }
+        if drift.variants { experiments::check_unlocked(&mut tx, &want.key, None).await?; }
+        let (_, after) = sqlite::replace_flag(&mut tx, &want.key, want).await.map_err(store_status)?;
+        if !lint_flag(&after).is_valid() { return Err(StatusCode::UNPROCESSABLE_ENTITY); }
+        quotas::enforce(&mut tx, &after, false, drift.variants).await?;
+        changes.push(PlannedChange { key: want.key.clone(), diff: diff_flags(Some(&current), &after), project: current.project, action: "updated" });
+    }
+    if params.prune {
+        for extra in sqlite::list_flags(&mut tx).await.map_err(store_status)? {
+            if keys.contains(extra.key.as_str()) || params.project.as_ref().is_some_and(|p| *p != extra.project) { continue; }
+            principal.require(&extra.project, Role::Editor)?;
+            if extra.protected { guard_protected(principal, &extra.project, params.confirm_protected)?; }
+            experiments::check_unlocked(&mut tx, &extra.key, None).await?;
+            sqlite::remove_flag(&mut tx, &extra.key).await.map_err(store_status)?;
+            changes.push(PlannedChange { key: extra.key, project: extra.project, action: "deleted", diff: Vec::new() });
+        }
+    }
+    if params.dry_run {
+        tx.rollback().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    } else {
+        tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        for key in &renamed { state.flag_changes.publish(key, "deleted"); }
+        for c in &changes { state.flag_changes.publish(&c.key, c.action); }
+    }
+    Ok(ApplyResponse { dry_run: params.dry_run, changes, unchanged })
+}
+
+pub async fn drift_check(State(state): State<AppState>, Extension(principal): Extension<Principal>, Query(params): Query<DriftParams>, Json(input): Json<ApplyInput>) -> Result<Json<DriftReport>, StatusCode> {
+    principal.require(params.project.as_deref().unwrap_or("*"), Role::Viewer)?;
+    let mut keys = HashSet::new();
+    if input.flags.iter().any(|f| !keys.insert(f.key.as_str())) { return Err(StatusCode::BAD_REQUEST); }
+    let stored: Vec<Flag> = state.store.list().await.map_err(store_status)?.into_iter().filter(|f| params.project.as_ref().is_none_or(|p| *p == f.project)).collect();
+    let mut missing = Vec::new();
+    let mut changed = Vec::new();
+    for want in &input.flags {
+        let Some(current) = stored.iter().find(|f| f.key == want.key) else { missing.push(want.key.clone()); continue };
+        let expected = Flag { id: current.id, uid: want.uid.clone().unwrap_or_else(|| current.uid.clone()), key: want.key.clone(), project: want.project.clone(), enabled: want.enabled, protected: want.protected, variants: want.variants.clone(), rollout: want.rollout, lifecycle: current.lifecycle, updated_at: current.updated_at.clone(), pins: current.pins.clone() };
+        let diff = diff_flags(Some(current), &expected);
+        if !diff.is_empty() { changed.push(Drifted { key: want.key.clone(), diff }); }
+    }
+    let extra: Vec<String> = stored.into_iter().filter(|f| !keys.contains(f.key.as_str())).map(|f| f.key).collect();
+    Ok(Json(DriftReport { in_sync: missing.is_empty() && extra.is_empty() && changed.is_empty(), missing, extra, changed }))
+}
@@ -0,0 +1,193 @@
+use axum::{extract::{ConnectInfo, MatchedPath, Query, Request, State}, http::{Method, StatusCode}, middleware::Next, response::Response, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::{net::SocketAddr, sync::{Arc, OnceLock}};
+use tokio::sync::mpsc;
+
+use crate::{audit_sink, auth::Principal, config::AuditSinkConfig, AppState};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub at: String,
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub outcome: String,
+    pub ip: Option<String>,
+    pub detail: Option<serde_json::Value>,
+    pub prev_hash: Option<String>,
+    pub hash: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct NewEntry<'a> {
+    pub actor: Option<&'a str>,
+    pub action: &'a str,
+    pub target: Option<&'a str>,
+    pub outcome: &'a str,
+    pub ip: Option<String>,
+    pub detail: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditParams {
+    limit: Option<i64>,
+    action: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    checked: usize,
+    valid: bool,
+    first_invalid_id: Option<i64>,
+}
+
+pub struct Audit {
+    db: Pool<Sqlite>,
+    sink: Option<mpsc::Sender<AuditEntry>>,
+}
+
+impl Audit {
+    pub async fn from_config(config: &AuditSinkConfig, db: Pool<Sqlite>) -> anyhow::Result<Audit> {
+        let sink = audit_sink::Sink::from_config(config).await?.map(audit_sink::spawn);
+        Ok(Audit { db, sink })
+    }
+
+    pub async fn record(&self, entry: NewEntry<'_>) {
+        match self.append(entry).await {
+            Ok(saved) => {
+                if let Some(sink) = &self.sink {
+                    if sink.try_send(saved).is_err() { tracing::warn!("audit sink queue is full, entry kept only in the database"); }
+                }
+            }
+            Err(e) => tracing::warn!(error = %e, "failed to write audit entry"),
+        }
+    }
+
+    // the row is written first, so the transaction holds the database's write lock before the previous hash is read:
+    // instances sharing the database queue up behind it instead of chaining onto the same entry
+    async fn append(&self, entry: NewEntry<'_>) -> Result<AuditEntry, sqlx::Error> {
+        let mut tx = self.db.begin().await?;
+        let mut saved = AuditEntry {
+            id: 0,
+            at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            actor: entry.actor.map(str::to_string),
+            action: entry.action.to_string(),
+            target: entry.target.map(str::to_string),
+            outcome: entry.outcome.to_string(),
+            ip: entry.ip,
+            detail: entry.detail,
+            prev_hash: None,
+            hash: None,
+        };
+        saved.id = sqlx::query("INSERT INTO audit_log (at, actor, action, target, outcome, ip, detail) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(&saved.at)
+            .bind(&saved.actor)
+            .bind(&saved.action)
+            .bind(&saved.target)
+            .bind(&saved.outcome)
+            .bind(&saved.ip)
+            .bind(saved.detail.as_ref().map(|d| d.to_string()))
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+        let prev_hash = sqlx::query("SELECT hash FROM audit_log WHERE hash IS NOT NULL AND id < ? ORDER BY id DESC LIMIT 1")
+            .bind(saved.id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .map(|r| r.get::<String,_>("hash"))
+            .unwrap_or_default();
+        saved.prev_hash = Some(prev_hash);
+        saved.hash = Some(chain_hash(&saved));
+        sqlx::query("UPDATE audit_log SET prev_hash = ?, hash = ? WHERE id = ?")
+            .bind(&saved.prev_hash)
+            .bind(&saved.hash)
+            .bind(saved.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(saved)
+    }
+}
+
+fn chain_hash(e: &AuditEntry) -> String {
+    let canonical = serde_json::json!([e.prev_hash, e.at, e.actor, e.action, e.target, e.outcome, e.ip, e.detail.as_ref().map(|d| d.to_string())]);
+    blake3::hash(canonical.to_string().as_bytes()).to_hex().to_string()
+}
+
+// filled in by the auth middleware, which runs inside audit_management so requests it turns away are audited too
+#[derive(Clone, Default)]
+pub struct ActorSlot(Arc<OnceLock<(String, bool)>>);
+
+pub fn note_actor(req: &Request, principal: &Principal) {
+    if let Some(slot) = req.extensions().get::<ActorSlot>() { let _ = slot.0.set((principal.subject.clone(), principal.break_glass)); }
+}
+
+pub async fn audit_management(State(state): State<AppState>, ConnectInfo(peer): ConnectInfo<SocketAddr>, mut req: Request, next: Next) -> Response {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) { return next.run(req).await; }
+    let action = format!("{} {}", req.method(), req.extensions().get::<MatchedPath>().map(|p| p.as_str()).unwrap_or_default());
+    let target = req.uri().path().to_string();
+    let query = req.uri().query().map(str::to_string);
+    let ip = state.allowlist.client_ip(peer, req.headers());
+    let slot = ActorSlot::default();
+    req.extensions_mut().insert(slot.clone());
+    let res = next.run(req).await;
+    let (actor, break_glass) = slot.0.get().cloned().map_or((None, false), |(actor, break_glass)| (Some(actor), break_glass));
+    let status = res.status();
+    let mut detail = serde_json::json!({ "status": status.as_u16(), "query": query });
+    if break_glass { detail["break_glass"] = true.into(); }
+    let outcome = if status.is_success() { "ok" } else if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::PRECONDITION_REQUIRED) { "denied" } else { "failed" };
+    state.audit.record(NewEntry {
+        actor: actor.as_deref(),
+        action: &action,
+        target: Some(&target),
+        outcome,
+        ip: Some(ip.to_string()),
+        detail: Some(detail),
+    }).await;
+    res
+}
+
+pub async fn list_audit(State(state): State<AppState>, Query(params): Query<AuditParams>) -> Result<Json<Vec<AuditEntry>>, StatusCode> {
+    let rows = sqlx::query("SELECT * FROM audit_log WHERE ? IS NULL OR action = ? ORDER BY id DESC LIMIT ?")
+        .bind(&params.action)
+        .bind(&params.action)
+        .bind(params.limit.unwrap_or(100).clamp(1, 1000))
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(rows.into_iter().map(row_to_entry).collect()))
+}
+
+pub async fn verify_audit(State(state): State<AppState>) -> Result<Json<VerifyReport>, StatusCode> {
+    let rows = sqlx::query("SELECT * FROM audit_log WHERE hash IS NOT NULL ORDER BY id")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut prev = String::new();
+    let mut checked = 0;
+    for e in rows.into_iter().map(row_to_entry) {
+        checked += 1;
+        if e.prev_hash.as_deref() != Some(prev.as_str()) || e.hash.as_deref() != Some(chain_hash(&e).as_str()) {
+            return Ok(Json(VerifyReport { checked, valid: false, first_invalid_id: Some(e.id) }));
+        }
+        prev = e.hash.unwrap_or_default();
+    }
+    Ok(Json(VerifyReport { checked, valid: true, first_invalid_id: None }))
+}
+
+fn row_to_entry(r: sqlx::sqlite::SqliteRow) -> AuditEntry {
+    AuditEntry {
+        id: r.get("id"),
+        at: r.get("at"),
+        actor: r.get("actor"),
+        action: r.get("action"),
+        target: r.get("target"),
+        outcome: r.get("outcome"),
+        ip: r.get("ip"),
+        detail: r.get::<Option<String>,_>("detail").and_then(|d| serde_json::from_str(&d).ok()),
+        prev_hash: r.get("prev_hash"),
+        hash: r.get("hash"),
+    }
+}
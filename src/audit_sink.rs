@@ -0,0 +1,90 @@
+use rskafka::{client::{partition::{Compression, PartitionClient, UnknownTopicHandling}, ClientBuilder}, record::Record};
+use std::{collections::BTreeMap, time::Duration};
+use tokio::{net::UdpSocket, sync::mpsc};
+
+use crate::{audit::AuditEntry, config::AuditSinkConfig};
+
+const QUEUE_SIZE: usize = 10_000;
+const MAX_ATTEMPTS: u32 = 5;
+
+pub enum Sink {
+    Syslog { socket: UdpSocket, addr: String, hostname: String },
+    Http { http: reqwest::Client, url: String, token: Option<String> },
+    Kafka { client: PartitionClient },
+}
+
+impl Sink {
+    pub async fn from_config(config: &AuditSinkConfig) -> anyhow::Result<Option<Sink>> {
+        let Some(kind) = &config.kind else { return Ok(None) };
+        let required = |value: &Option<String>, name: &str| value.clone().ok_or_else(|| anyhow::anyhow!("{name} is required when AUDIT_SINK={kind}"));
+        let sink = match kind.as_str() {
+            "syslog" => {
+                let addr = required(&config.syslog_addr, "AUDIT_SYSLOG_ADDR")?;
+                let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                let hostname = std::env::var("HOSTNAME").unwrap_or_else(|_| "-".into());
+                Sink::Syslog { socket, addr, hostname }
+            }
+            "http" => {
+                let url = required(&config.http_url, "AUDIT_HTTP_URL")?;
+                let token = config.http_token.clone();
+                let http = reqwest::Client::builder().timeout(Duration::from_secs(5)).build()?;
+                Sink::Http { http, url, token }
+            }
+            "kafka" => {
+                let brokers = config.kafka_brokers.clone().filter(|b| !b.is_empty()).ok_or_else(|| anyhow::anyhow!("AUDIT_KAFKA_BROKERS is required when AUDIT_SINK={kind}"))?;
+                let topic = required(&config.kafka_topic, "AUDIT_KAFKA_TOPIC")?;
+                let client = ClientBuilder::new(brokers).build().await?
+                    .partition_client(topic, 0, UnknownTopicHandling::Retry).await?;
+                Sink::Kafka { client }
+            }
+            other => anyhow::bail!("unknown AUDIT_SINK {other:?}, expected syslog, http or kafka"),
+        };
+        tracing::info!(sink = %kind, "audit streaming enabled");
+        Ok(Some(sink))
+    }
+
+    async fn send(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        let payload = serde_json::to_vec(entry)?;
+        match self {
+            Sink::Syslog { socket, addr, hostname } => {
+                // RFC 5424, facility log audit (13), severity notice (5)
+                let line = format!("<109>1 {} {} rust-feature-flags-toggler - audit - {}", chrono::Utc::now().to_rfc3339(), hostname, String::from_utf8_lossy(&payload));
+                socket.send_to(line.as_bytes(), addr).await?;
+            }
+            Sink::Http { http, url, token } => {
+                let mut req = http.post(url).header("content-type", "application/json").body(payload);
+                if let Some(t) = token { req = req.bearer_auth(t); }
+                req.send().await?.error_for_status()?;
+            }
+            Sink::Kafka { client } => {
+                let record = Record { key: Some(entry.id.to_string().into_bytes()), value: Some(payload), headers: BTreeMap::new(), timestamp: chrono::Utc::now() };
+                client.produce(vec![record], Compression::NoCompression).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn spawn(sink: Sink) -> mpsc::Sender<AuditEntry> {
+    let (tx, mut rx) = mpsc::channel::<AuditEntry>(QUEUE_SIZE);
+    tokio::spawn(async move {
+        while let Some(entry) = rx.recv().await {
+            let mut attempt = 0;
+            loop {
+                attempt += 1;
+                match sink.send(&entry).await {
+                    Ok(()) => break,
+                    Err(e) if attempt < MAX_ATTEMPTS => {
+                        tracing::debug!(error = %e, attempt, id = entry.id, "audit sink delivery failed, retrying");
+                        tokio::time::sleep(Duration::from_millis(200 * 2u64.pow(attempt))).await;
+                    }
+                    Err(e) => {
+                        tracing::error!(error = %e, id = entry.id, "giving up forwarding audit entry");
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    tx
+}
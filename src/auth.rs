@@ -0,0 +1,440 @@
+use axum::{extract::{MatchedPath, Path, Request, State}, http::{header::{AUTHORIZATION, RETRY_AFTER}, HeaderMap, Method, StatusCode}, middleware::Next, response::{IntoResponse, Response}, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::collections::HashMap;
+
+use feature_flags_core::{sqlite, StoreError};
+
+use crate::{oidc::Oidc, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyKind {
+    Server,
+    Client,
+}
+
+impl KeyKind {
+    fn as_str(self) -> &'static str {
+        match self { KeyKind::Server => "server", KeyKind::Client => "client" }
+    }
+
+    fn parse(s: &str) -> KeyKind {
+        if s == "client" { KeyKind::Client } else { KeyKind::Server }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self { Role::Viewer => "viewer", Role::Editor => "editor", Role::Admin => "admin" }
+    }
+
+    pub fn parse(s: &str) -> Option<Role> {
+        match s { "viewer" => Some(Role::Viewer), "editor" => Some(Role::Editor), "admin" => Some(Role::Admin), _ => None }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub kind: KeyKind,
+    pub rate_limit: Option<u32>,
+    pub flag_prefixes: Vec<String>,
+}
+
+impl ApiKey {
+    // a key scoped to prefixes only ever sees flags whose key starts with one of them
+    pub fn sees(&self, flag_key: &str) -> bool {
+        self.flag_prefixes.is_empty() || self.flag_prefixes.iter().any(|p| flag_key.starts_with(p.as_str()))
+    }
+}
+
+// `id` is unique (`key:<id>` or `oidc:<sub>`); `subject` is the display name, and several tokens can share one
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub id: String,
+    pub subject: String,
+    pub roles: HashMap<String, Role>,
+    pub break_glass: bool,
+}
+
+impl Principal {
+    pub fn role_for(&self, project: &str) -> Option<Role> {
+        self.roles.get(project).copied().max(self.roles.get("*").copied())
+    }
+
+    pub fn has_role(&self, project: &str, role: Role) -> bool {
+        self.role_for(project).is_some_and(|r| r >= role)
+    }
+
+    pub fn require(&self, project: &str, role: Role) -> Result<(), StatusCode> {
+        if self.has_role(project, role) { Ok(()) } else { Err(StatusCode::FORBIDDEN) }
+    }
+
+    // for shared definitions that don't belong to a project: editor anywhere is enough
+    pub fn require_editor(&self) -> Result<(), StatusCode> {
+        if self.roles.values().any(|r| *r >= Role::Editor) { Ok(()) } else { Err(StatusCode::FORBIDDEN) }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RoleAssignment {
+    project: String,
+    #[serde(default = "any_environment")]
+    environment: String,
+    role: Role,
+}
+
+fn any_environment() -> String { "*".into() }
+
+#[derive(Debug, Deserialize)]
+pub struct CreateToken {
+    name: String,
+    kind: KeyKind,
+    #[serde(default)]
+    roles: Vec<RoleAssignment>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    rate_limit: Option<u32>,
+    #[serde(default)]
+    flag_prefixes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct RotateToken {
+    grace_period_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateToken {
+    rate_limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenInfo {
+    id: i64,
+    name: String,
+    kind: KeyKind,
+    roles: Vec<RoleAssignment>,
+    rate_limit: Option<u32>,
+    flag_prefixes: Vec<String>,
+    created_at: String,
+    expires_at: Option<String>,
+    last_used_at: Option<String>,
+    revoked_at: Option<String>,
+    previous_secret_expires_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    token: TokenInfo,
+    secret: String,
+}
+
+pub async fn init(db: &Pool<Sqlite>, admin_api_key: Option<&str>) -> anyhow::Result<()> {
+    match admin_api_key {
+        Some(secret) if !secret.is_empty() => {
+            sqlx::query("INSERT OR IGNORE INTO api_keys (name, key_hash, kind, created_at) VALUES ('bootstrap-admin', ?, 'server', datetime('now'))")
+                .bind(hash_secret(secret))
+                .execute(db)
+                .await?;
+            sqlx::query("INSERT OR IGNORE INTO key_roles (key_id, project, role) SELECT id, '*', 'admin' FROM api_keys WHERE key_hash = ?")
+                .bind(hash_secret(secret))
+                .execute(db)
+                .await?;
+        }
+        _ => tracing::warn!("ADMIN_API_KEY is not set; management routes only accept keys already stored in api_keys"),
+    }
+    Ok(())
+}
+
+fn parse_prefixes(stored: Option<String>) -> Vec<String> {
+    stored.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default()
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    blake3::hash(secret.as_bytes()).to_hex().to_string()
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Result<&str, StatusCode> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+async fn authenticate(db: &Pool<Sqlite>, headers: &HeaderMap) -> Result<ApiKey, StatusCode> {
+    let secret = bearer_token(headers)?;
+    let hash = hash_secret(secret);
+    let r = sqlx::query("SELECT id, name, kind, rate_limit, flag_prefixes FROM api_keys WHERE (key_hash = ? OR (previous_key_hash = ? AND previous_expires_at > datetime('now'))) AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > datetime('now'))")
+        .bind(&hash)
+        .bind(&hash)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let key = ApiKey {
+        id: r.get::<i64,_>("id"),
+        name: r.get::<String,_>("name"),
+        kind: KeyKind::parse(&r.get::<String,_>("kind")),
+        rate_limit: r.get::<Option<i64>,_>("rate_limit").map(|l| l as u32),
+        flag_prefixes: parse_prefixes(r.get("flag_prefixes")),
+    };
+    crate::telemetry::record_actor(&key.name);
+    let db = db.clone();
+    tokio::spawn(async move {
+        let _ = sqlx::query("UPDATE api_keys SET last_used_at = datetime('now') WHERE id = ? AND (last_used_at IS NULL OR last_used_at < datetime('now', '-1 minute'))")
+            .bind(key.id)
+            .execute(&db)
+            .await;
+    });
+    Ok(key)
+}
+
+fn meter(state: &AppState, key: &ApiKey, req: &Request) -> Option<Response> {
+    if let Err(retry_after) = state.usage.check(key.id, key.rate_limit) {
+        return Some((StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, retry_after.to_string())]).into_response());
+    }
+    let route = req.extensions().get::<MatchedPath>().map(|p| p.as_str()).unwrap_or_else(|| req.uri().path());
+    state.usage.record(key.id, route);
+    None
+}
+
+pub async fn require_sdk_key(State(state): State<AppState>, mut req: Request, next: Next) -> Result<Response, StatusCode> {
+    let key = authenticate(&state.db, req.headers()).await?;
+    crate::access_log::note_token(&req, key.id);
+    if let Some(limited) = meter(&state, &key, &req) { return Ok(limited); }
+    req.extensions_mut().insert(key);
+    Ok(next.run(req).await)
+}
+
+// lets a relay learn what a key it was handed may see without guessing from status codes
+pub async fn sdk_key(Extension(key): Extension<ApiKey>) -> Json<ApiKey> {
+    Json(key)
+}
+
+pub async fn require_server_key(State(state): State<AppState>, mut req: Request, next: Next) -> Result<Response, StatusCode> {
+    if let Some(oidc) = &state.oidc {
+        let token = bearer_token(req.headers())?;
+        if Oidc::looks_like_jwt(token) {
+            let identity = oidc.verify(token, &state.environment).await?;
+            crate::telemetry::record_actor(&identity.subject);
+            let mut principal = Principal { id: format!("oidc:{}", identity.subject), subject: identity.subject, roles: identity.roles, break_glass: false };
+            let elevated = if crate::break_glass::applies(&req) { crate::break_glass::elevate(&state.db, &mut principal).await } else { Ok(()) };
+            crate::audit::note_actor(&req, &principal);
+            elevated?;
+            req.extensions_mut().insert(principal);
+            return Ok(next.run(req).await);
+        }
+    }
+    let key = authenticate(&state.db, req.headers()).await?;
+    crate::access_log::note_token(&req, key.id);
+    if key.kind != KeyKind::Server { return Err(StatusCode::FORBIDDEN); }
+    if let Some(limited) = meter(&state, &key, &req) { return Ok(limited); }
+    let rows = sqlx::query("SELECT project, role FROM key_roles WHERE key_id = ? AND environment IN ('*', ?)")
+        .bind(key.id)
+        .bind(&*state.environment)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut roles: HashMap<String, Role> = HashMap::new();
+    for r in &rows {
+        let Some(role) = Role::parse(&r.get::<String,_>("role")) else { continue };
+        let entry = roles.entry(r.get::<String,_>("project")).or_insert(role);
+        *entry = (*entry).max(role);
+    }
+    let mut principal = Principal { id: format!("key:{}", key.id), subject: key.name, roles, break_glass: false };
+    let elevated = if crate::break_glass::applies(&req) { crate::break_glass::elevate(&state.db, &mut principal).await } else { Ok(()) };
+    crate::audit::note_actor(&req, &principal);
+    elevated?;
+    req.extensions_mut().insert(principal);
+    Ok(next.run(req).await)
+}
+
+pub async fn authorize_flag(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(params): Path<HashMap<String, String>>, req: Request, next: Next) -> Result<Response, StatusCode> {
+    let required = if req.method() == Method::GET || req.method() == Method::HEAD { Role::Viewer } else { Role::Editor };
+    let key = params.get("key").ok_or(StatusCode::BAD_REQUEST)?;
+    crate::telemetry::record_flag(key);
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let project = match sqlite::resolve_flag(&mut conn, key).await {
+        Ok(f) => Some(f.project),
+        Err(StoreError::NotFound) => None,
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+    drop(conn);
+    match project {
+        Some(p) => principal.require(&p, required)?,
+        None => if principal.roles.is_empty() { return Err(StatusCode::FORBIDDEN); },
+    }
+    tracing::debug!(actor = %principal.subject, flag = %key, method = %req.method(), "authorized flag route");
+    Ok(next.run(req).await)
+}
+
+// tokens, roles and server settings outlast any session, so break-glass never reaches them
+pub async fn require_admin(Extension(principal): Extension<Principal>, req: Request, next: Next) -> Result<Response, StatusCode> {
+    if principal.break_glass { return Err(StatusCode::FORBIDDEN); }
+    principal.require("*", Role::Admin)?;
+    Ok(next.run(req).await)
+}
+
+pub async fn create_token(State(state): State<AppState>, Json(input): Json<CreateToken>) -> Result<Json<CreatedToken>, StatusCode> {
+    if input.flag_prefixes.iter().any(|p| p.is_empty()) { return Err(StatusCode::BAD_REQUEST); }
+    let flag_prefixes = (!input.flag_prefixes.is_empty()).then(|| serde_json::to_string(&input.flag_prefixes).unwrap());
+    let secret = format!("{}-{}", input.kind.as_str(), uuid::Uuid::new_v4().simple());
+    let expires_at = input.expires_at.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let id = sqlx::query("INSERT INTO api_keys (name, key_hash, kind, created_at, expires_at, rate_limit, flag_prefixes) VALUES (?, ?, ?, datetime('now'), ?, ?, ?)")
+        .bind(&input.name)
+        .bind(hash_secret(&secret))
+        .bind(input.kind.as_str())
+        .bind(expires_at)
+        .bind(input.rate_limit.filter(|l| *l > 0))
+        .bind(flag_prefixes)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .last_insert_rowid();
+    for a in &input.roles {
+        upsert_role(&mut tx, id, a).await?;
+    }
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let token = fetch_token(&state.db, id).await?;
+    Ok(Json(CreatedToken { token, secret }))
+}
+
+pub async fn list_tokens(State(state): State<AppState>) -> Result<Json<Vec<TokenInfo>>, StatusCode> {
+    let rows = sqlx::query("SELECT id, name, kind, rate_limit, flag_prefixes, created_at, expires_at, last_used_at, revoked_at, previous_expires_at FROM api_keys ORDER BY id")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut roles = load_role_assignments(&state.db, None).await?;
+    let out = rows.into_iter().map(|r| {
+        let id = r.get::<i64,_>("id");
+        row_to_token(r, roles.remove(&id).unwrap_or_default())
+    }).collect();
+    Ok(Json(out))
+}
+
+pub async fn get_token(State(state): State<AppState>, Path(id): Path<i64>) -> Result<Json<TokenInfo>, StatusCode> {
+    Ok(Json(fetch_token(&state.db, id).await?))
+}
+
+pub async fn update_token(State(state): State<AppState>, Path(id): Path<i64>, Json(input): Json<UpdateToken>) -> Result<Json<TokenInfo>, StatusCode> {
+    let rows = sqlx::query("UPDATE api_keys SET rate_limit = ? WHERE id = ?")
+        .bind(input.rate_limit.filter(|l| *l > 0))
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    Ok(Json(fetch_token(&state.db, id).await?))
+}
+
+// longer grace periods are cut to this; the old secret is meant to bridge a deploy, not live on
+const MAX_GRACE_SECS: u64 = 30 * 86_400;
+
+pub async fn rotate_token(State(state): State<AppState>, Path(id): Path<i64>, input: Option<Json<RotateToken>>) -> Result<Json<CreatedToken>, StatusCode> {
+    let grace = input.and_then(|Json(i)| i.grace_period_secs).unwrap_or(86_400).min(MAX_GRACE_SECS);
+    let current = fetch_token(&state.db, id).await?;
+    if current.revoked_at.is_some() { return Err(StatusCode::CONFLICT); }
+    let secret = format!("{}-{}", current.kind.as_str(), uuid::Uuid::new_v4().simple());
+    sqlx::query("UPDATE api_keys SET previous_key_hash = key_hash, previous_expires_at = datetime('now', ?), key_hash = ? WHERE id = ?")
+        .bind(format!("+{grace} seconds"))
+        .bind(hash_secret(&secret))
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let token = fetch_token(&state.db, id).await?;
+    Ok(Json(CreatedToken { token, secret }))
+}
+
+pub async fn revoke_token(State(state): State<AppState>, Path(id): Path<i64>) -> Result<Json<TokenInfo>, StatusCode> {
+    let rows = sqlx::query("UPDATE api_keys SET revoked_at = datetime('now') WHERE id = ? AND revoked_at IS NULL")
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    let token = fetch_token(&state.db, id).await?;
+    if rows == 0 { return Err(StatusCode::CONFLICT); }
+    Ok(Json(token))
+}
+
+pub async fn assign_role(State(state): State<AppState>, Path(id): Path<i64>, Json(input): Json<RoleAssignment>) -> Result<Json<RoleAssignment>, StatusCode> {
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("SELECT id FROM api_keys WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    upsert_role(&mut tx, id, &input).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(input))
+}
+
+async fn fetch_token(db: &Pool<Sqlite>, id: i64) -> Result<TokenInfo, StatusCode> {
+    let r = sqlx::query("SELECT id, name, kind, rate_limit, flag_prefixes, created_at, expires_at, last_used_at, revoked_at, previous_expires_at FROM api_keys WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let roles = load_role_assignments(db, Some(id)).await?.remove(&id).unwrap_or_default();
+    Ok(row_to_token(r, roles))
+}
+
+async fn load_role_assignments(db: &Pool<Sqlite>, key_id: Option<i64>) -> Result<HashMap<i64, Vec<RoleAssignment>>, StatusCode> {
+    let rows = sqlx::query("SELECT key_id, project, environment, role FROM key_roles WHERE ? IS NULL OR key_id = ? ORDER BY project, environment")
+        .bind(key_id)
+        .bind(key_id)
+        .fetch_all(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut out: HashMap<i64, Vec<RoleAssignment>> = HashMap::new();
+    for r in rows {
+        let Some(role) = Role::parse(&r.get::<String,_>("role")) else { continue };
+        out.entry(r.get::<i64,_>("key_id")).or_default().push(RoleAssignment { project: r.get("project"), environment: r.get("environment"), role });
+    }
+    Ok(out)
+}
+
+fn row_to_token(r: sqlx::sqlite::SqliteRow, roles: Vec<RoleAssignment>) -> TokenInfo {
+    TokenInfo {
+        id: r.get("id"),
+        name: r.get("name"),
+        kind: KeyKind::parse(&r.get::<String,_>("kind")),
+        roles,
+        rate_limit: r.get::<Option<i64>,_>("rate_limit").map(|l| l as u32),
+        flag_prefixes: parse_prefixes(r.get("flag_prefixes")),
+        created_at: r.get("created_at"),
+        expires_at: r.get("expires_at"),
+        last_used_at: r.get("last_used_at"),
+        revoked_at: r.get("revoked_at"),
+        previous_secret_expires_at: r.get("previous_expires_at"),
+    }
+}
+async fn upsert_role(tx: &mut sqlx::Transaction<'_, Sqlite>, key_id: i64, a: &RoleAssignment) -> Result<(), StatusCode> {
+    sqlx::query("INSERT INTO key_roles (key_id, project, environment, role) VALUES (?, ?, ?, ?) ON CONFLICT (key_id, project, environment) DO UPDATE SET role = excluded.role")
+        .bind(key_id)
+        .bind(&a.project)
+        .bind(&a.environment)
+        .bind(a.role.as_str())
+        .execute(&mut **tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(())
+}
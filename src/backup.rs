@@ -0,0 +1,52 @@
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{Pool, Sqlite};
+use std::{path::{Path, PathBuf}, time::Duration};
+
+use feature_flags_core::sqlite;
+
+use crate::{config::BackupConfig, jobs::Job};
+
+const STAMP: &str = "%Y%m%dT%H%M%SZ";
+
+// copies are named after the database file, so tenants sharing BACKUP_DIR don't prune each other's
+pub fn job(config: &BackupConfig, database_url: &str) -> anyhow::Result<Option<Job>> {
+    let Some(dir) = &config.dir else { return Ok(None) };
+    let every = config.interval_secs.filter(|s| *s > 0).unwrap_or(86_400);
+    let keep = config.keep.filter(|k| *k > 0).unwrap_or(7);
+    let options = sqlite::connect_options(database_url)?;
+    let stem = options.get_filename().file_stem().and_then(|s| s.to_str()).filter(|s| !s.is_empty() && *s != ":memory:")
+        .ok_or_else(|| anyhow::anyhow!("BACKUP_DIR needs a database file, not {database_url}"))?
+        .to_string();
+    let dir = PathBuf::from(dir);
+    Ok(Some(Job::new("backup", Duration::from_secs(every), move |state| {
+        let (dir, stem) = (dir.clone(), stem.clone());
+        async move { backup(&state.db, &dir, &stem, keep).await }
+    }).timeout(Duration::from_secs(3600))))
+}
+
+async fn backup(db: &Pool<Sqlite>, dir: &Path, stem: &str, keep: usize) -> anyhow::Result<()> {
+    let name = format!("{stem}-{}.db", Utc::now().format(STAMP));
+    tokio::fs::create_dir_all(dir).await.map_err(|e| anyhow::anyhow!("BACKUP_DIR {}: {e}", dir.display()))?;
+    // VACUUM INTO won't overwrite, and whatever an interrupted run left behind is no use
+    let partial = dir.join(format!("{name}.partial"));
+    let _ = tokio::fs::remove_file(&partial).await;
+    sqlx::query("VACUUM INTO ?").bind(partial.to_string_lossy()).execute(db).await?;
+    // renamed only once complete, so a half-written copy is never mistaken for a backup
+    tokio::fs::rename(&partial, dir.join(&name)).await?;
+    let size = tokio::fs::metadata(dir.join(&name)).await?.len();
+    tracing::info!(file = %dir.join(&name).display(), bytes = size, "database backed up");
+    prune(dir, stem, keep).await
+}
+
+async fn prune(dir: &Path, stem: &str, keep: usize) -> anyhow::Result<()> {
+    let mut backups = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let stamp = name.strip_prefix(stem).and_then(|n| n.strip_prefix('-')).and_then(|n| n.strip_suffix(".db"));
+        if stamp.is_some_and(|s| NaiveDateTime::parse_from_str(s, STAMP).is_ok()) { backups.push(name); }
+    }
+    backups.sort();
+    for name in &backups[..backups.len().saturating_sub(keep)] { tokio::fs::remove_file(dir.join(name)).await?; }
+    Ok(())
+}
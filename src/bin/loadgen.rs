@@ -0,0 +1,92 @@
+use std::{sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
+
+const USAGE: &str = "usage: loadgen --token <sdk key> [--url http://127.0.0.1:8080] [--keys a,b,c] [--concurrency 32] [--duration 10] [--users 10000]";
+
+struct Args {
+    url: String,
+    token: String,
+    keys: Vec<String>,
+    concurrency: usize,
+    duration: Duration,
+    users: u64,
+}
+
+fn parse_args() -> anyhow::Result<Args> {
+    let mut args = Args { url: "http://127.0.0.1:8080".into(), token: std::env::var("LOADGEN_TOKEN").unwrap_or_default(), keys: Vec::new(), concurrency: 32, duration: Duration::from_secs(10), users: 10_000 };
+    let mut it = std::env::args().skip(1);
+    while let Some(flag) = it.next() {
+        let mut value = || it.next().ok_or_else(|| anyhow::anyhow!("{flag} needs a value\n{USAGE}"));
+        match flag.as_str() {
+            "--url" => args.url = value()?.trim_end_matches('/').to_string(),
+            "--token" => args.token = value()?,
+            "--keys" => args.keys = value()?.split(',').map(str::trim).filter(|k| !k.is_empty()).map(str::to_string).collect(),
+            "--concurrency" => args.concurrency = value()?.parse()?,
+            "--duration" => args.duration = Duration::from_secs(value()?.parse()?),
+            "--users" => args.users = value()?.parse()?,
+            "-h" | "--help" => { println!("{USAGE}"); std::process::exit(0); }
+            other => anyhow::bail!("unknown argument {other}\n{USAGE}"),
+        }
+    }
+    if args.token.is_empty() { anyhow::bail!("an SDK key is required\n{USAGE}"); }
+    Ok(args)
+}
+
+#[derive(serde::Deserialize)]
+struct Flag {
+    key: String,
+}
+
+fn percentile(sorted: &[u32], p: f64) -> f64 {
+    if sorted.is_empty() { return 0.0; }
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize] as f64 / 1000.0
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = parse_args()?;
+    let http = reqwest::Client::builder().pool_max_idle_per_host(args.concurrency).timeout(Duration::from_secs(10)).build()?;
+    if args.keys.is_empty() {
+        let flags: Vec<Flag> = http.get(format!("{}/rules", args.url)).bearer_auth(&args.token).send().await?.error_for_status()
+            .map_err(|e| anyhow::anyhow!("{e}: pass --keys when using a client key"))?.json().await?;
+        args.keys = flags.into_iter().map(|f| f.key).collect();
+        if args.keys.is_empty() { anyhow::bail!("the server has no flags to evaluate"); }
+    }
+    println!("{} workers for {:?} against {}/evaluate over {} flags", args.concurrency, args.duration, args.url, args.keys.len());
+
+    let args = Arc::new(args);
+    let stop = Arc::new(AtomicBool::new(false));
+    let errors = Arc::new(AtomicU64::new(0));
+    let latencies = Arc::new(Mutex::new(Vec::<u32>::new()));
+    let started = Instant::now();
+    let workers: Vec<_> = (0..args.concurrency).map(|w| {
+        let (http, args, stop, errors, latencies) = (http.clone(), args.clone(), stop.clone(), errors.clone(), latencies.clone());
+        tokio::spawn(async move {
+            let mut local = Vec::new();
+            let mut n = w as u64;
+            while !stop.load(Ordering::Relaxed) {
+                n = n.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                let key = &args.keys[(n >> 33) as usize % args.keys.len()];
+                let body = serde_json::json!({ "key": key, "user_id": format!("user-{}", (n >> 17) % args.users.max(1)) });
+                let t = Instant::now();
+                match http.post(format!("{}/evaluate", args.url)).bearer_auth(&args.token).json(&body).send().await.and_then(|r| r.error_for_status()) {
+                    Ok(r) => { let _ = r.bytes().await; local.push(t.elapsed().as_micros().min(u32::MAX as u128) as u32); }
+                    Err(_) => { errors.fetch_add(1, Ordering::Relaxed); }
+                }
+            }
+            latencies.lock().unwrap().extend(local);
+        })
+    }).collect();
+    tokio::time::sleep(args.duration).await;
+    stop.store(true, Ordering::Relaxed);
+    for w in workers { w.await?; }
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let mut sorted = std::mem::take(&mut *latencies.lock().unwrap());
+    sorted.sort_unstable();
+    let errors = errors.load(Ordering::Relaxed);
+    println!("requests  {} ok, {errors} failed", sorted.len());
+    println!("rate      {:.0} req/s", sorted.len() as f64 / elapsed);
+    println!("latency   p50 {:.2}ms  p90 {:.2}ms  p99 {:.2}ms  max {:.2}ms", percentile(&sorted, 0.5), percentile(&sorted, 0.9), percentile(&sorted, 0.99), percentile(&sorted, 1.0));
+    if errors > 0 { std::process::exit(1); }
+    Ok(())
+}
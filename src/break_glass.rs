@@ -0,0 +1,141 @@
+use axum::{extract::{ConnectInfo, MatchedPath, Path, Request, State}, http::{Method, StatusCode}, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::net::SocketAddr;
+
+use crate::{audit::NewEntry, auth::{Principal, Role}, config::BreakGlassConfig, jobs::Job, AppState};
+
+const MIN_REASON: usize = 10;
+
+// the only routes a session elevates on: changing flags, alone or in bulk
+const FLAG_WRITES: &[&str] = &["/apply", "/release-groups/:name/enable", "/release-groups/:name/disable"];
+
+#[derive(Debug, Deserialize)]
+pub struct Activate {
+    reason: String,
+    project: Option<String>,
+    minutes: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    id: i64,
+    #[serde(skip)]
+    principal: Option<String>,
+    subject: String,
+    project: String,
+    reason: String,
+    started_at: String,
+    expires_at: String,
+    ended_at: Option<String>,
+    ended_by: Option<String>,
+}
+
+fn max_minutes(config: &BreakGlassConfig) -> u32 {
+    config.max_minutes.filter(|m| *m > 0).unwrap_or(120)
+}
+
+fn row_to_session(r: sqlx::sqlite::SqliteRow) -> Session {
+    Session { id: r.get("id"), principal: r.get("principal"), subject: r.get("subject"), project: r.get("project"), reason: r.get("reason"), started_at: r.get("started_at"), expires_at: r.get("expires_at"), ended_at: r.get("ended_at"), ended_by: r.get("ended_by") }
+}
+
+pub fn applies(req: &Request) -> bool {
+    let Some(path) = req.extensions().get::<MatchedPath>().map(|p| p.as_str()) else { return false };
+    req.method() != Method::GET && req.method() != Method::HEAD && (path == "/flags" || path.starts_with("/flags/") || FLAG_WRITES.contains(&path))
+}
+
+// an open session makes its holder admin on the session's project for flag writes and lets them change flags directly while REQUIRE_APPROVAL is on
+pub async fn elevate(db: &Pool<Sqlite>, principal: &mut Principal) -> Result<(), StatusCode> {
+    let rows = sqlx::query("SELECT project FROM break_glass WHERE principal = ? AND ended_at IS NULL AND expires_at > datetime('now')")
+        .bind(&principal.id)
+        .fetch_all(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for r in rows {
+        principal.roles.insert(r.get("project"), Role::Admin);
+        principal.break_glass = true;
+    }
+    Ok(())
+}
+
+pub fn approval_required(state: &AppState, principal: &Principal) -> bool {
+    state.require_approval && !principal.break_glass
+}
+
+async fn fetch_session(db: &Pool<Sqlite>, id: i64) -> Result<Session, StatusCode> {
+    sqlx::query("SELECT * FROM break_glass WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(row_to_session)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+// everything about a session is meant to be noticed: an error-level log, a webhook, and an audit entry carrying the reason
+async fn announce(state: &AppState, event: &str, session: &Session, actor: &str, ip: Option<String>) {
+    tracing::error!(event, id = session.id, subject = %session.subject, project = %session.project, reason = %session.reason, expires_at = %session.expires_at, actor, "break-glass access");
+    state.webhooks.notify(event, session);
+    state.audit.record(NewEntry {
+        actor: Some(actor),
+        action: event,
+        target: Some(&format!("/break-glass/{}", session.id)),
+        outcome: "ok",
+        ip,
+        detail: Some(serde_json::json!({ "subject": session.subject, "project": session.project, "reason": session.reason, "expires_at": session.expires_at })),
+    }).await;
+}
+
+// an editor on the project can open a session for themselves; it needs a real reason and ends by itself
+pub async fn activate(State(state): State<AppState>, Extension(principal): Extension<Principal>, ConnectInfo(peer): ConnectInfo<SocketAddr>, Json(input): Json<Activate>) -> Result<(StatusCode, Json<Session>), StatusCode> {
+    let project = input.project.unwrap_or_else(|| "*".into());
+    let minutes = input.minutes.unwrap_or(60);
+    let reason = input.reason.trim();
+    if reason.chars().count() < MIN_REASON || minutes == 0 || minutes > max_minutes(&state.config.current().break_glass) { return Err(StatusCode::BAD_REQUEST); }
+    principal.require(&project, Role::Editor)?;
+    let id = sqlx::query("INSERT INTO break_glass (principal, subject, project, reason, started_at, expires_at) VALUES (?, ?, ?, ?, datetime('now'), datetime('now', ?))")
+        .bind(&principal.id)
+        .bind(&principal.subject)
+        .bind(&project)
+        .bind(reason)
+        .bind(format!("+{minutes} minutes"))
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .last_insert_rowid();
+    let session = fetch_session(&state.db, id).await?;
+    announce(&state, "break_glass.activated", &session, &principal.subject, Some(peer.ip().to_string())).await;
+    Ok((StatusCode::CREATED, Json(session)))
+}
+
+pub async fn list_sessions(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Result<Json<Vec<Session>>, StatusCode> {
+    principal.require("*", Role::Viewer)?;
+    let rows = sqlx::query("SELECT * FROM break_glass ORDER BY id DESC LIMIT 100").fetch_all(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(rows.into_iter().map(row_to_session).collect()))
+}
+
+// the holder or an admin can end a session early
+pub async fn end_session(State(state): State<AppState>, Extension(principal): Extension<Principal>, ConnectInfo(peer): ConnectInfo<SocketAddr>, Path(id): Path<i64>) -> Result<Json<Session>, StatusCode> {
+    let session = fetch_session(&state.db, id).await?;
+    if session.principal.as_deref() != Some(principal.id.as_str()) { principal.require("*", Role::Admin)?; }
+    let rows = sqlx::query("UPDATE break_glass SET ended_at = datetime('now'), ended_by = ? WHERE id = ? AND ended_at IS NULL AND expires_at > datetime('now')")
+        .bind(&principal.subject)
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::CONFLICT); }
+    let session = fetch_session(&state.db, id).await?;
+    announce(&state, "break_glass.ended", &session, &principal.subject, Some(peer.ip().to_string())).await;
+    Ok(Json(session))
+}
+
+// sessions stop granting anything at expires_at regardless; this only closes them so the expiry is announced too
+pub fn job() -> Job {
+    Job::new("break-glass-expiry", std::time::Duration::from_secs(30), |state| async move {
+        let expired = sqlx::query("UPDATE break_glass SET ended_at = expires_at, ended_by = 'expiry' WHERE ended_at IS NULL AND expires_at <= datetime('now') RETURNING *").fetch_all(&state.db).await?;
+        for session in expired.into_iter().map(row_to_session) { announce(&state, "break_glass.expired", &session, "expiry", None).await; }
+        Ok(())
+    })
+}
@@ -0,0 +1,96 @@
+use axum::{extract::Query, http::{header, StatusCode}, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use feature_flags_core::{eval_flag, rollout_bucket, variant_hash, Flag};
+
+const MAX_GENERATED: usize = 1000;
+
+type Weights = &'static [(&'static str, u32)];
+
+// unicode, punctuation and an empty user id are the cases ports usually get wrong
+const KEYS: [&str; 6] = ["new_checkout", "dark_mode", "search.ranking-v2", "ümlaut_flag", "k", "checkout/v2 💳"];
+const USERS: [&str; 6] = ["user-1", "alice@example.com", "42", "ユーザー", "", "user with spaces"];
+
+#[derive(Debug, Deserialize)]
+pub struct VectorParams {
+    count: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Algorithm {
+    rollout_bucket: &'static str,
+    variant_hash: &'static str,
+    variant: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VectorFlag {
+    enabled: bool,
+    rollout: Option<u8>,
+    variants: Option<BTreeMap<String, u32>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Vector {
+    key: String,
+    user_id: String,
+    bucket: u8,
+    variant_hash: u32,
+    flag: VectorFlag,
+    matched: bool,
+    variant: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestVectors {
+    algorithm: Algorithm,
+    vectors: Vec<Vector>,
+}
+
+// one configuration per key, covering plain on, a partial rollout, uneven and zero weights, and weights not summing to 100
+fn config(i: usize) -> (Option<u8>, Option<Weights>) {
+    match i % 6 {
+        0 => (None, None),
+        1 => (Some(50), None),
+        2 => (Some(10), Some(&[("a", 50), ("b", 50)])),
+        3 => (None, Some(&[("control", 34), ("treatment_a", 33), ("treatment_b", 33)])),
+        4 => (Some(100), Some(&[("off", 3), ("on", 1)])),
+        _ => (Some(75), Some(&[("a", 0), ("b", 7), ("c", 3)])),
+    }
+}
+
+fn vectors(generated: usize) -> Vec<Vector> {
+    let users: Vec<String> = USERS.iter().map(|u| u.to_string()).chain((0..generated).map(|n| format!("user-{n}"))).collect();
+    let mut out = Vec::with_capacity(KEYS.len() * users.len());
+    for (i, key) in KEYS.iter().enumerate() {
+        let (rollout, variants) = config(i);
+        let variants: Option<HashMap<String, u32>> = variants.map(|vs| vs.iter().map(|(n, w)| (n.to_string(), *w)).collect());
+        let flag = Flag { id: 0, uid: String::new(), key: key.to_string(), project: String::new(), enabled: true, protected: false, variants, rollout, lifecycle: Default::default(), updated_at: String::new(), pins: Default::default() };
+        for user in &users {
+            let res = eval_flag(&flag, Some(user));
+            out.push(Vector {
+                key: flag.key.clone(),
+                user_id: user.clone(),
+                bucket: rollout_bucket(&flag.key, user),
+                variant_hash: variant_hash(&flag.key, user),
+                flag: VectorFlag { enabled: flag.enabled, rollout, variants: flag.variants.as_ref().map(|v| v.iter().map(|(k, w)| (k.clone(), *w)).collect()) },
+                matched: res.matched,
+                variant: res.variant,
+            });
+        }
+    }
+    out
+}
+
+// canonical inputs and outputs of this server's bucketing, for SDKs in other languages to check their port against
+pub async fn test_vectors(Query(params): Query<VectorParams>) -> Result<impl IntoResponse, StatusCode> {
+    let generated = params.count.unwrap_or(20);
+    if generated > MAX_GENERATED { return Err(StatusCode::BAD_REQUEST); }
+    let algorithm = Algorithm {
+        rollout_bucket: "first byte of blake3(utf8(key) ++ \":\" ++ utf8(user_id)) mod 100; in the rollout when below the rollout percentage",
+        variant_hash: "first four bytes of blake3(utf8(key) ++ \"/\" ++ utf8(user_id)) as a little-endian u32",
+        variant: "variant_hash mod the sum of the weights, then the first variant by byte-wise name order whose running weight total is above it",
+    };
+    Ok(([(header::CACHE_CONTROL, "public, max-age=86400")], Json(TestVectors { algorithm, vectors: vectors(generated) })))
+}
@@ -0,0 +1,98 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::{IntoResponse, Response}, Extension, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use feature_flags_core::{diff_flags, sqlite, FieldChange, UpdateFlag};
+
+use crate::{auth::{Principal, Role}, config::BulkConfig, experiments, store_status, teams, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct BulkRollout {
+    rollout: u8,
+    keys: Option<Vec<String>>,
+    project: Option<String>,
+    team: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkParams {
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    confirm_protected: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RolloutChange {
+    key: String,
+    project: String,
+    from: Option<u8>,
+    to: u8,
+    diff: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TooLarge {
+    key: String,
+    from: Option<u8>,
+    to: u8,
+    step: u8,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkResponse {
+    dry_run: bool,
+    changes: Vec<RolloutChange>,
+    unchanged: usize,
+}
+
+fn max_step(config: &BulkConfig) -> u8 {
+    config.rollout_max_step.filter(|s| *s > 0 && *s <= 100).unwrap_or(25)
+}
+
+// every selector given must match, so a team's flags can be narrowed to one project
+pub async fn bulk_rollout(State(state): State<AppState>, Extension(principal): Extension<Principal>, Query(params): Query<BulkParams>, Json(input): Json<BulkRollout>) -> Result<Response, StatusCode> {
+    if crate::break_glass::approval_required(&state, &principal) && !params.dry_run { return Err(StatusCode::FORBIDDEN); }
+    if input.rollout > 100 || (input.keys.is_none() && input.project.is_none() && input.team.is_none()) { return Err(StatusCode::BAD_REQUEST); }
+    let keys: Option<HashSet<String>> = input.keys.map(|k| k.into_iter().collect());
+    let owned: Option<HashSet<String>> = match &input.team { Some(team) => Some(teams::owned_flags(&state.db, team).await?.into_iter().collect()), None => None };
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let selected: Vec<_> = sqlite::list_flags(&mut tx).await.map_err(store_status)?.into_iter()
+        .filter(|f| keys.as_ref().is_none_or(|k| k.contains(&f.key)) && owned.as_ref().is_none_or(|o| o.contains(&f.key)) && input.project.as_ref().is_none_or(|p| *p == f.project))
+        .collect();
+    if let Some(keys) = &keys {
+        if keys.len() != selected.iter().filter(|f| keys.contains(&f.key)).count() { return Err(StatusCode::NOT_FOUND); }
+    }
+
+    // access is checked up front so the step errors can't reveal flags the caller can't edit
+    for f in selected.iter().filter(|f| f.rollout != Some(input.rollout)) { principal.require(&f.project, Role::Editor)?; }
+
+    // a flag without a rollout is on for everyone, so only explicit increases count against the step
+    let step = max_step(&state.config.current().bulk);
+    let too_large: Vec<TooLarge> = selected.iter()
+        .filter(|f| input.rollout > f.rollout.unwrap_or(100).saturating_add(step))
+        .map(|f| TooLarge { key: f.key.clone(), from: f.rollout, to: input.rollout, step })
+        .collect();
+    if !too_large.is_empty() { return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({ "errors": too_large }))).into_response()); }
+
+    let mut changes = Vec::new();
+    let mut unchanged = 0;
+    for current in selected {
+        if current.rollout == Some(input.rollout) { unchanged += 1; continue; }
+        if current.protected {
+            principal.require(&current.project, Role::Admin)?;
+            if !params.confirm_protected { return Err(StatusCode::PRECONDITION_REQUIRED); }
+        }
+        let update = UpdateFlag { rollout: Some(input.rollout), ..Default::default() };
+        experiments::check_unlocked(&mut tx, &current.key, Some(&update)).await?;
+        let (before, after) = sqlite::apply_update(&mut tx, &current.key, &update).await.map_err(store_status)?;
+        changes.push(RolloutChange { key: current.key, project: current.project, from: before.rollout, to: input.rollout, diff: diff_flags(Some(&before), &after) });
+    }
+    if params.dry_run {
+        tx.rollback().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    } else {
+        tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        for c in &changes { state.flag_changes.publish(&c.key, "updated"); }
+    }
+    Ok(Json(BulkResponse { dry_run: params.dry_run, changes, unchanged }).into_response())
+}
@@ -0,0 +1,316 @@
+use arc_swap::ArcSwap;
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use lru::LruCache;
+use serde::Serialize;
+use std::{collections::HashMap, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
+use tokio::sync::{broadcast::error::RecvError, OnceCell};
+
+use feature_flags_core::{Flag, FlagStore, StoreError};
+
+use crate::{auth::{Principal, Role}, config::CacheConfig, AppState};
+
+pub struct Snapshot {
+    flags: Vec<Flag>,
+    index: HashMap<String, usize>,
+    loaded_at: Instant,
+}
+
+struct Entries {
+    flags: LruCache<String, (Flag, usize)>,
+    bytes: usize,
+}
+
+// for installations too large to hold every flag: only recently evaluated flags stay resident
+pub struct Lru {
+    entries: Mutex<Entries>,
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+    loaded_at: Mutex<Instant>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+type Fetch = Arc<OnceCell<Result<Flag, Arc<StoreError>>>>;
+
+enum Resident {
+    Snapshot(ArcSwap<Snapshot>),
+    Lru(Lru),
+}
+
+// one per state, so with TENANTS a miss only ever joins a read of its own tenant's store
+pub struct FlagCache {
+    resident: Resident,
+    // misses for the same key that overlap share one store read, so a cold start doesn't send every request for a hot flag to the database
+    inflight: Mutex<HashMap<String, Fetch>>,
+    coalesced: AtomicU64,
+    // keys the store didn't have, so evaluating an unknown flag doesn't read the database every time; bounded since callers pick the keys
+    missing: Mutex<LruCache<String, ()>>,
+    // bumped before every refresh, so a miss that read the store before a change doesn't cache what it read
+    generation: AtomicU64,
+}
+
+const MAX_MISSING: usize = 10_000;
+
+#[derive(Debug, Serialize)]
+pub struct CacheStats {
+    mode: &'static str,
+    entries: usize,
+    bytes: Option<usize>,
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+    hits: Option<u64>,
+    misses: Option<u64>,
+    evictions: Option<u64>,
+    coalesced: u64,
+    age_secs: u64,
+}
+
+impl Snapshot {
+    fn new(mut flags: Vec<Flag>) -> Snapshot {
+        flags.sort_by(|a, b| a.key.cmp(&b.key));
+        let index = flags.iter().enumerate().map(|(i, f)| (f.key.clone(), i)).collect();
+        Snapshot { flags, index, loaded_at: Instant::now() }
+    }
+
+    // single-key patches keep the age of the last full load, which is what bounds staleness
+    fn with(&self, flag: Flag) -> Snapshot {
+        let mut flags: Vec<Flag> = self.flags.iter().filter(|f| f.key != flag.key).cloned().collect();
+        flags.push(flag);
+        Snapshot { loaded_at: self.loaded_at, ..Snapshot::new(flags) }
+    }
+
+    fn without(&self, key: &str) -> Snapshot {
+        Snapshot { loaded_at: self.loaded_at, ..Snapshot::new(self.flags.iter().filter(|f| f.key != key).cloned().collect()) }
+    }
+
+    fn get(&self, key: &str) -> Option<&Flag> {
+        self.index.get(key).map(|i| &self.flags[*i])
+    }
+}
+
+fn weight(f: &Flag) -> usize {
+    let variants = f.variants.as_ref().map_or(0, |v| v.keys().map(|k| k.len() + 48).sum());
+    let pins: usize = f.pins.iter().map(|(user, p)| user.len() + p.variant.as_ref().map_or(0, String::len) + 96).sum();
+    std::mem::size_of::<Flag>() + f.key.len() * 2 + f.uid.len() + f.project.len() + f.updated_at.len() + variants + pins + 64
+}
+
+impl Lru {
+    fn get(&self, key: &str) -> Option<Flag> {
+        let found = self.entries.lock().unwrap().flags.get(key).map(|(f, _)| f.clone());
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    fn put(&self, flag: Flag) {
+        let size = weight(&flag);
+        let mut e = self.entries.lock().unwrap();
+        if let Some((_, old)) = e.flags.put(flag.key.clone(), (flag, size)) { e.bytes -= old; }
+        e.bytes += size;
+        while e.flags.len() > 1 && (self.max_entries.is_some_and(|m| e.flags.len() > m) || self.max_bytes.is_some_and(|m| e.bytes > m)) {
+            let Some((_, (_, size))) = e.flags.pop_lru() else { break };
+            e.bytes -= size;
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // refreshes keep their place in the eviction order
+    fn replace(&self, flag: Flag) {
+        let size = weight(&flag);
+        let mut e = self.entries.lock().unwrap();
+        let Some(slot) = e.flags.peek_mut(&flag.key) else { return };
+        let old = std::mem::replace(slot, (flag, size)).1;
+        e.bytes = e.bytes - old + size;
+    }
+
+    fn remove(&self, key: &str) {
+        let mut e = self.entries.lock().unwrap();
+        if let Some((_, size)) = e.flags.pop(key) { e.bytes -= size; }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.entries.lock().unwrap().flags.contains(key)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.entries.lock().unwrap().flags.iter().map(|(k, _)| k.clone()).collect()
+    }
+}
+
+pub fn from_config(config: &CacheConfig) -> anyhow::Result<FlagCache> {
+    let (max_entries, max_bytes) = (config.max_entries.filter(|n| *n > 0), config.max_bytes.filter(|n| *n > 0));
+    let cache = |resident| FlagCache { resident, inflight: Mutex::new(HashMap::new()), coalesced: AtomicU64::new(0), missing: Mutex::new(LruCache::new(std::num::NonZeroUsize::new(MAX_MISSING).unwrap())), generation: AtomicU64::new(0) };
+    if max_entries.is_none() && max_bytes.is_none() { return Ok(cache(Resident::Snapshot(ArcSwap::from_pointee(Snapshot::new(Vec::new()))))); }
+    if cache_only(config).is_some() { anyhow::bail!("CACHE_ONLY_EVAL needs every flag in memory and can't be combined with CACHE_MAX_ENTRIES or CACHE_MAX_BYTES"); }
+    tracing::info!(?max_entries, ?max_bytes, "flag cache is bounded, evicting least recently used flags");
+    Ok(cache(Resident::Lru(Lru {
+        entries: Mutex::new(Entries { flags: LruCache::unbounded(), bytes: 0 }),
+        max_entries,
+        max_bytes,
+        loaded_at: Mutex::new(Instant::now()),
+        hits: AtomicU64::new(0),
+        misses: AtomicU64::new(0),
+        evictions: AtomicU64::new(0),
+    })))
+}
+
+pub fn cache_only(config: &CacheConfig) -> Option<Duration> {
+    config.only_eval.then(|| Duration::from_secs(if config.max_staleness_secs > 0 { config.max_staleness_secs } else { 60 }))
+}
+
+pub fn age(state: &AppState) -> Duration {
+    match &state.cache.resident {
+        Resident::Snapshot(s) => s.load().loaded_at.elapsed(),
+        Resident::Lru(lru) => lru.loaded_at.lock().unwrap().elapsed(),
+    }
+}
+
+// a bounded cache isn't filled up front; a reload re-reads only the flags that are resident
+pub async fn warm(state: &AppState) -> Result<usize, StoreError> {
+    state.cache.generation.fetch_add(1, Ordering::SeqCst);
+    state.cache.missing.lock().unwrap().clear();
+    match &state.cache.resident {
+        Resident::Snapshot(s) => {
+            let snapshot = Snapshot::new(state.store.list().await?);
+            let count = snapshot.flags.len();
+            s.store(Arc::new(snapshot));
+            Ok(count)
+        }
+        Resident::Lru(lru) => {
+            let started = Instant::now();
+            for key in lru.keys() {
+                match state.store.get(&key).await {
+                    Ok(f) => lru.replace(f),
+                    Err(StoreError::NotFound) => lru.remove(&key),
+                    Err(e) => return Err(e),
+                }
+            }
+            *lru.loaded_at.lock().unwrap() = started;
+            Ok(len(state))
+        }
+    }
+}
+
+fn shared_error(e: &StoreError) -> StoreError {
+    match e {
+        StoreError::NotFound => StoreError::NotFound,
+        StoreError::Conflict => StoreError::Conflict,
+        StoreError::Invalid(m) => StoreError::Invalid(m),
+        e => StoreError::Backend(e.to_string().into()),
+    }
+}
+
+async fn fetch(state: &AppState, key: &str) -> Result<Flag, StoreError> {
+    let (cell, joined) = {
+        let mut inflight = state.cache.inflight.lock().unwrap();
+        match inflight.get(key) {
+            Some(cell) => (cell.clone(), true),
+            None => { let cell = Fetch::default(); inflight.insert(key.to_string(), cell.clone()); (cell, false) }
+        }
+    };
+    if joined { state.cache.coalesced.fetch_add(1, Ordering::Relaxed); }
+    // if the request doing the read is dropped, one of the waiters takes it over
+    let res = cell.get_or_init(|| async { state.store.get(key).await.map_err(Arc::new) }).await.clone();
+    let mut inflight = state.cache.inflight.lock().unwrap();
+    if inflight.get(key).is_some_and(|c| Arc::ptr_eq(c, &cell)) { inflight.remove(key); }
+    res.map_err(|e| shared_error(&e))
+}
+
+pub async fn get(state: &AppState, key: &str) -> Result<Flag, StoreError> {
+    let cached = match &state.cache.resident {
+        Resident::Snapshot(s) => s.load().get(key).cloned(),
+        Resident::Lru(lru) => lru.get(key),
+    };
+    if let Some(f) = cached { return Ok(f); }
+    if state.cache_only.is_some() || state.cache.missing.lock().unwrap().get(key).is_some() { return Err(StoreError::NotFound); }
+    let generation = state.cache.generation.load(Ordering::SeqCst);
+    let fetched = fetch(state, key).await;
+    // a change since the read started will refresh the key itself; caching what was read could bring back a deleted flag
+    if state.cache.generation.load(Ordering::SeqCst) != generation { return fetched; }
+    match (&fetched, &state.cache.resident) {
+        (Ok(f), Resident::Snapshot(s)) => { s.rcu(|s| s.with(f.clone())); }
+        (Ok(f), Resident::Lru(lru)) => lru.put(f.clone()),
+        (Err(StoreError::NotFound), _) => { state.cache.missing.lock().unwrap().put(key.to_string(), ()); }
+        (Err(_), _) => {}
+    }
+    fetched
+}
+
+pub fn len(state: &AppState) -> usize {
+    match &state.cache.resident {
+        Resident::Snapshot(s) => s.load().flags.len(),
+        Resident::Lru(lru) => lru.entries.lock().unwrap().flags.len(),
+    }
+}
+
+pub async fn all(state: &AppState) -> Result<Vec<Flag>, StoreError> {
+    match &state.cache.resident {
+        Resident::Snapshot(s) => Ok(s.load().flags.clone()),
+        Resident::Lru(_) => state.store.list().await,
+    }
+}
+
+async fn refresh_key(state: &AppState, key: &str) {
+    state.cache.generation.fetch_add(1, Ordering::SeqCst);
+    state.cache.missing.lock().unwrap().pop(key);
+    if let Resident::Lru(lru) = &state.cache.resident {
+        if !lru.contains(key) { return; }
+    }
+    match (state.store.get(key).await, &state.cache.resident) {
+        (Ok(f), Resident::Snapshot(s)) => { s.rcu(|s| s.with(f.clone())); }
+        (Ok(f), Resident::Lru(lru)) => lru.replace(f),
+        (Err(StoreError::NotFound), Resident::Snapshot(s)) => { s.rcu(|s| s.without(key)); }
+        (Err(StoreError::NotFound), Resident::Lru(lru)) => lru.remove(key),
+        (Err(e), _) => tracing::warn!(error = %e, key, "failed to refresh cached flag"),
+    }
+}
+
+// looked up after every reload, so a config reload takes effect from the next one
+fn refresh_interval(state: &AppState) -> Duration {
+    let every = state.config.refresh_interval();
+    match state.cache_only { Some(max) => every.min(max / 2), None => every }
+}
+
+pub fn spawn_refresher(state: AppState) {
+    let mut changes = state.flag_changes.subscribe();
+    tokio::spawn(async move {
+        let mut next = tokio::time::Instant::now() + refresh_interval(&state);
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(next) => {
+                    if let Err(e) = warm(&state).await { tracing::warn!(error = %e, "failed to refresh flag cache, serving the previous copy"); }
+                    next = tokio::time::Instant::now() + refresh_interval(&state);
+                }
+                change = changes.recv() => match change {
+                    Ok(change) => refresh_key(&state, &change.key).await,
+                    Err(RecvError::Lagged(_)) => { let _ = warm(&state).await; }
+                    Err(RecvError::Closed) => return,
+                },
+            }
+        }
+    });
+}
+
+pub async fn stats(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Result<Json<CacheStats>, StatusCode> {
+    principal.require("*", Role::Viewer)?;
+    let age_secs = age(&state).as_secs();
+    Ok(Json(match &state.cache.resident {
+        Resident::Snapshot(s) => CacheStats { mode: "snapshot", entries: s.load().flags.len(), bytes: None, max_entries: None, max_bytes: None, hits: None, misses: None, evictions: None, coalesced: state.cache.coalesced.load(Ordering::Relaxed), age_secs },
+        Resident::Lru(lru) => {
+            let (entries, bytes) = { let e = lru.entries.lock().unwrap(); (e.flags.len(), e.bytes) };
+            CacheStats {
+                mode: "lru",
+                entries,
+                bytes: Some(bytes),
+                max_entries: lru.max_entries,
+                max_bytes: lru.max_bytes,
+                hits: Some(lru.hits.load(Ordering::Relaxed)),
+                misses: Some(lru.misses.load(Ordering::Relaxed)),
+                evictions: Some(lru.evictions.load(Ordering::Relaxed)),
+                coalesced: state.cache.coalesced.load(Ordering::Relaxed),
+                age_secs,
+            }
+        }
+    }))
+}
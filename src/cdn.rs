@@ -0,0 +1,53 @@
+use axum::{http::{header, HeaderValue, StatusCode}, response::Response};
+use std::sync::OnceLock;
+
+use feature_flags_core::variant_hash;
+
+use crate::{config::SnapshotConfig, SnapshotParams};
+
+struct Policy {
+    buckets: Option<u32>,
+    max_age: u64,
+}
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+// set once at startup, before anything is served
+pub fn configure(config: &SnapshotConfig) {
+    let _ = POLICY.set(Policy { buckets: config.buckets.filter(|b| *b > 0), max_age: config.cache_max_age_secs.unwrap_or(30) });
+}
+
+fn policy() -> &'static Policy {
+    POLICY.get_or_init(|| Policy { buckets: None, max_age: 30 })
+}
+
+// a bucket stands in for every user hashed into it, so a CDN holds at most SNAPSHOT_BUCKETS variants instead of one per user
+pub fn bucket(params: &SnapshotParams) -> Result<Option<String>, StatusCode> {
+    match (params.bucket, policy().buckets) {
+        (None, _) => Ok(None),
+        (Some(_), _) if params.user_id.is_some() || params.anonymous_id.is_some() => Err(StatusCode::BAD_REQUEST),
+        (Some(bucket), Some(buckets)) if bucket < buckets => Ok(Some(format!("bucket:{bucket}"))),
+        _ => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+// with SNAPSHOT_BUCKETS set every user is evaluated as their bucket, so ?bucket=N answers exactly what each of its users
+// gets from /evaluate or /snapshot. exposures, pins and aliases still go by the user's own ids
+pub fn evaluated_as(bucketing_id: Option<&str>) -> Option<String> {
+    let id = bucketing_id?;
+    Some(match policy().buckets {
+        Some(buckets) => format!("bucket:{}", variant_hash(BUCKET_KEY, id) % buckets),
+        None => id.to_string(),
+    })
+}
+
+const BUCKET_KEY: &str = "snapshot-bucket";
+
+// per-user answers must never be shared; anonymous and bucketed ones are the same for every caller with the same token
+pub fn cache_headers(params: &SnapshotParams, mut res: Response) -> Response {
+    let control = if params.user_id.is_some() || params.anonymous_id.is_some() || policy().max_age == 0 { "private, no-cache".to_string() } else { format!("public, max-age={}", policy().max_age) };
+    let headers = res.headers_mut();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_str(&control).expect("ascii header value"));
+    headers.insert(header::VARY, HeaderValue::from_static("Authorization, Accept, Accept-Encoding"));
+    res
+}
@@ -0,0 +1,211 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, Sqlite};
+
+use feature_flags_core::{diff_flags, sqlite, CreateFlag, FieldChange, UpdateFlag};
+
+use crate::{auth::{Principal, Role}, store_status, AppState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum ProposedChange {
+    Create { flag: CreateFlag },
+    Update { key: String, changes: UpdateFlag },
+    Delete { key: String },
+}
+
+impl ProposedChange {
+    fn flag_key(&self) -> &str {
+        match self {
+            ProposedChange::Create { flag } => &flag.key,
+            ProposedChange::Update { key, .. } | ProposedChange::Delete { key } => key,
+        }
+    }
+
+    fn action(&self) -> &'static str {
+        match self {
+            ProposedChange::Create { .. } => "created",
+            ProposedChange::Update { .. } => "updated",
+            ProposedChange::Delete { .. } => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateChangeRequest {
+    #[serde(flatten)]
+    change: ProposedChange,
+    comment: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewInput {
+    comment: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListParams {
+    status: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangeRequest {
+    id: i64,
+    flag_key: String,
+    project: String,
+    change: ProposedChange,
+    status: String,
+    comment: Option<String>,
+    requested_by: String,
+    #[serde(skip)]
+    requested_by_id: Option<String>,
+    reviewed_by: Option<String>,
+    review_comment: Option<String>,
+    created_at: String,
+    reviewed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangeRequestPreview {
+    #[serde(flatten)]
+    request: ChangeRequest,
+    diff: Vec<FieldChange>,
+}
+
+struct Preview {
+    project: String,
+    protected: bool,
+    diff: Vec<FieldChange>,
+}
+
+async fn preview(tx: &mut sqlx::Transaction<'_, Sqlite>, change: &ProposedChange) -> Result<Preview, StatusCode> {
+    match change {
+        ProposedChange::Create { flag } => {
+            let after = sqlite::insert_flag(tx, flag).await.map_err(store_status)?;
+            crate::quotas::enforce(tx, &after, true, false).await?;
+            Ok(Preview { project: after.project.clone(), protected: after.protected, diff: diff_flags(None, &after) })
+        }
+        ProposedChange::Update { key, changes } => {
+            crate::experiments::check_unlocked(tx, key, Some(changes)).await?;
+            let (before, after) = sqlite::apply_update(tx, key, changes).await.map_err(store_status)?;
+            crate::quotas::enforce(tx, &after, false, changes.variants.is_some()).await?;
+            Ok(Preview { project: before.project.clone(), protected: before.protected || after.protected, diff: diff_flags(Some(&before), &after) })
+        }
+        ProposedChange::Delete { key } => {
+            crate::experiments::check_unlocked(tx, key, None).await?;
+            let before = sqlite::remove_flag(tx, key).await.map_err(store_status)?;
+            Ok(Preview { project: before.project, protected: before.protected, diff: Vec::new() })
+        }
+    }
+}
+
+pub async fn create_change_request(State(state): State<AppState>, Extension(principal): Extension<Principal>, Json(input): Json<CreateChangeRequest>) -> Result<Json<ChangeRequestPreview>, StatusCode> {
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Preview { project, diff, .. } = preview(&mut tx, &input.change).await?;
+    tx.rollback().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    principal.require(&project, Role::Editor)?;
+    let id = sqlx::query("INSERT INTO change_requests (flag_key, project, change, status, comment, requested_by, requested_by_id, created_at) VALUES (?, ?, ?, 'pending', ?, ?, ?, datetime('now'))")
+        .bind(input.change.flag_key())
+        .bind(&project)
+        .bind(serde_json::to_string(&input.change).unwrap())
+        .bind(&input.comment)
+        .bind(&principal.subject)
+        .bind(&principal.id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .last_insert_rowid();
+    let request = fetch_change_request(&state.db, id).await?;
+    state.webhooks.notify("change_request.created", &request);
+    Ok(Json(ChangeRequestPreview { request, diff }))
+}
+
+pub async fn list_change_requests(State(state): State<AppState>, Extension(principal): Extension<Principal>, Query(params): Query<ListParams>) -> Result<Json<Vec<ChangeRequest>>, StatusCode> {
+    let rows = sqlx::query("SELECT * FROM change_requests WHERE ? IS NULL OR status = ? ORDER BY id DESC")
+        .bind(&params.status)
+        .bind(&params.status)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut out = rows.into_iter().map(row_to_change_request).collect::<Result<Vec<_>, _>>()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    out.retain(|c| principal.has_role(&c.project, Role::Viewer));
+    Ok(Json(out))
+}
+
+pub async fn get_change_request(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(id): Path<i64>) -> Result<Json<ChangeRequest>, StatusCode> {
+    let request = fetch_change_request(&state.db, id).await?;
+    principal.require(&request.project, Role::Viewer)?;
+    Ok(Json(request))
+}
+
+pub async fn approve_change_request(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(id): Path<i64>, input: Option<Json<ReviewInput>>) -> Result<Json<ChangeRequest>, StatusCode> {
+    let request = fetch_change_request(&state.db, id).await?;
+    principal.require(&request.project, Role::Editor)?;
+    if request.status != "pending" { return Err(StatusCode::CONFLICT); }
+    // subjects are display names that two tokens can share; rows from before requested_by_id existed fall back to them
+    let own = match &request.requested_by_id { Some(id) => *id == principal.id, None => request.requested_by == principal.subject };
+    if own { return Err(StatusCode::FORBIDDEN); }
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let applied = preview(&mut tx, &request.change).await?;
+    if applied.protected { principal.require(&applied.project, Role::Admin)?; }
+    mark_reviewed(&mut tx, id, "approved", &principal, input.and_then(|Json(i)| i.comment)).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.flag_changes.publish(request.change.flag_key(), request.change.action());
+    let request = fetch_change_request(&state.db, id).await?;
+    state.webhooks.notify("change_request.approved", &request);
+    Ok(Json(request))
+}
+
+pub async fn reject_change_request(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(id): Path<i64>, input: Option<Json<ReviewInput>>) -> Result<Json<ChangeRequest>, StatusCode> {
+    let request = fetch_change_request(&state.db, id).await?;
+    principal.require(&request.project, Role::Editor)?;
+    if request.status != "pending" { return Err(StatusCode::CONFLICT); }
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    mark_reviewed(&mut tx, id, "rejected", &principal, input.and_then(|Json(i)| i.comment)).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let request = fetch_change_request(&state.db, id).await?;
+    state.webhooks.notify("change_request.rejected", &request);
+    Ok(Json(request))
+}
+
+async fn mark_reviewed(tx: &mut sqlx::Transaction<'_, Sqlite>, id: i64, status: &str, principal: &Principal, comment: Option<String>) -> Result<(), StatusCode> {
+    let rows = sqlx::query("UPDATE change_requests SET status = ?, reviewed_by = ?, review_comment = ?, reviewed_at = datetime('now') WHERE id = ? AND status = 'pending'")
+        .bind(status)
+        .bind(&principal.subject)
+        .bind(comment)
+        .bind(id)
+        .execute(&mut **tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::CONFLICT); }
+    Ok(())
+}
+
+async fn fetch_change_request(db: &sqlx::Pool<Sqlite>, id: i64) -> Result<ChangeRequest, StatusCode> {
+    let r = sqlx::query("SELECT * FROM change_requests WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    row_to_change_request(r).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn row_to_change_request(r: sqlx::sqlite::SqliteRow) -> Result<ChangeRequest, anyhow::Error> {
+    Ok(ChangeRequest {
+        id: r.get("id"),
+        flag_key: r.get("flag_key"),
+        project: r.get("project"),
+        change: serde_json::from_str(&r.get::<String,_>("change"))?,
+        status: r.get("status"),
+        comment: r.get("comment"),
+        requested_by: r.get("requested_by"),
+        requested_by_id: r.get("requested_by_id"),
+        reviewed_by: r.get("reviewed_by"),
+        review_comment: r.get("review_comment"),
+        created_at: r.get("created_at"),
+        reviewed_at: r.get("reviewed_at"),
+    })
+}
@@ -0,0 +1,104 @@
+use sqlx::{sqlite::SqlitePoolOptions, Row};
+use std::{fmt::Display, sync::Arc};
+
+use feature_flags_core::{migrations, sqlite};
+
+use crate::{access_log, allowlist, audit_sink, backup, cache, config::Config, eval_hooks, exports, flags_file, git_sync, guard, header_context, kafka, oidc, pubsub, relay, server, signing, telemetry, tenants, webhooks};
+
+pub fn requested() -> bool {
+    std::env::args().skip(1).any(|a| a == "--check")
+}
+
+#[derive(Default)]
+struct Report {
+    failed: usize,
+}
+
+impl Report {
+    fn check<T, E: Display>(&mut self, name: &str, res: Result<T, E>, detail: impl FnOnce(&T) -> String) -> Option<T> {
+        match res {
+            Ok(v) => {
+                let detail = detail(&v);
+                if detail.is_empty() { println!("ok    {name}"); } else { println!("ok    {name}: {detail}"); }
+                Some(v)
+            }
+            Err(e) => {
+                self.failed += 1;
+                println!("FAIL  {name}: {e:#}");
+                None
+            }
+        }
+    }
+
+    fn config<T>(&mut self, name: &str, res: anyhow::Result<T>) {
+        self.check(name, res.map_err(|e| format!("{e:#}")), |_| String::new());
+    }
+}
+
+// a pre-deploy gate: everything startup would trip over, without serving or changing anything; the exit status is the verdict
+pub async fn run() -> i32 {
+    let mut report = Report::default();
+    let Some(config) = report.check("configuration", Config::load(), |_| std::env::var("CONFIG_FILE").unwrap_or_else(|_| "environment only".into())) else { return verdict(report) };
+    report.config("server (BIND, TLS)", server::Server::from_config(&config.server));
+    report.config("CORS", config.cors.layer());
+    report.config("log level", telemetry::parse_filter(&config.log.level));
+
+    if let Some(relay) = report.check("relay", relay::Relay::from_config(&config), |r| if r.is_some() { "RELAY_UPSTREAM set, checking the upstream instead of a database".into() } else { String::new() }).flatten() {
+        let res = reqwest::Client::new().get(format!("{}/health", relay.upstream())).timeout(std::time::Duration::from_secs(5)).send().await.and_then(|r| r.error_for_status());
+        report.check("relay upstream", res, |_| relay.upstream().to_string());
+        return verdict(report);
+    }
+
+    report.config("flag cache", cache::from_config(&config.cache));
+    report.config("evaluation hooks", eval_hooks::from_config(&config.evaluation));
+    report.config("context headers", header_context::HeaderContext::from_config(&config.evaluation));
+    report.config("snapshot signing", signing::from_config(&config.signing));
+    report.config("allowlist", allowlist::Allowlist::from_config(&config.auth));
+    report.config("access log", access_log::AccessLog::from_config(&config.access_log));
+    report.config("flags file", flags_file::FlagsFile::from_config(&config.flags_file));
+    report.config("S3 export", exports::S3::from_config(&config.exports));
+    report.config("git sync", git_sync::GitSync::from_config(&config.git_sync));
+    report.config("prometheus guard", guard::Prometheus::from_config(&config.guard));
+    report.config("OIDC", oidc::Oidc::from_config(&config.auth.oidc).await);
+    report.config("kafka", kafka::Kafka::from_config(&config.kafka, Arc::from("check")).await);
+    report.config("pubsub", pubsub::Broadcaster::from_config(&config.pubsub).await);
+    report.config("audit sink", audit_sink::Sink::from_config(&config.audit_sink).await);
+
+    // with TENANTS every tenant's database is checked; team webhooks are looked up in the first
+    let databases = match report.check("tenants", tenants::Tenants::from_config(&config), |t| t.as_ref().map(|t| t.names().join(", ")).unwrap_or_default()) {
+        Some(Some(t)) => t.names().iter().map(|n| (format!(" ({n})"), t.database_url(n))).collect(),
+        _ => vec![(String::new(), config.database.url.clone())],
+    };
+    let mut pool = None;
+    for (tenant, database_url) in databases {
+        let res = match sqlite::connect_options(&database_url) {
+            Ok(options) => SqlitePoolOptions::new().max_connections(1).connect_with(options).await,
+            Err(e) => Err(e),
+        };
+        report.config(&format!("backups{tenant}"), backup::job(&config.backup, &database_url));
+        let Some(p) = report.check(&format!("database{tenant}"), res, |_| database_url.clone()) else { continue };
+        report.check(&format!("migrations (dry run){tenant}"), migrations::dry_run(&p).await, |pending| format!("{pending} pending"));
+        if pool.is_none() { pool = Some(p); } else { p.close().await; }
+    }
+
+    if let Some(hooks) = report.check("webhooks", webhooks::Webhooks::from_config(&config.webhooks), |_| String::new()) {
+        let mut urls = hooks.urls().to_vec();
+        // team webhooks live in the database, which may not have the table yet
+        if let Some(pool) = &pool {
+            if let Ok(rows) = sqlx::query("SELECT DISTINCT webhook_url FROM teams WHERE webhook_url IS NOT NULL").fetch_all(pool).await {
+                urls.extend(rows.into_iter().map(|r| r.get::<String, _>("webhook_url")));
+            }
+        }
+        urls.sort();
+        urls.dedup();
+        for url in urls {
+            report.check(&format!("webhook {url}"), hooks.probe(&url).await, |status| format!("reachable ({status})"));
+        }
+    }
+    if let Some(pool) = pool { pool.close().await; }
+    verdict(report)
+}
+
+fn verdict(report: Report) -> i32 {
+    if report.failed == 0 { println!("all checks passed"); 0 } else { println!("{} check(s) failed", report.failed); 1 }
+}
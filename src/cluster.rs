@@ -0,0 +1,197 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::Serialize;
+use sqlx::{Pool, Row, Sqlite};
+use std::{sync::{atomic::{AtomicI64, Ordering}, Arc}, time::Duration};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{auth::{Principal, Role}, cache, config::ClusterConfig, AppState};
+
+const RETENTION: &str = "-1 hour";
+const RESYNC: &str = "*";
+
+pub struct Cluster {
+    id: String,
+    address: String,
+    heartbeat: Duration,
+    poll: Duration,
+    applied: AtomicI64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Instance {
+    id: String,
+    address: String,
+    environment: String,
+    started_at: String,
+    last_seen_at: String,
+    alive: bool,
+    applied_seq: i64,
+    lag: i64,
+    flags_cached: i64,
+    cache_age_secs: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusResponse {
+    instance_id: String,
+    last_seq: i64,
+    consistent: bool,
+    instances: Vec<Instance>,
+}
+
+impl Cluster {
+    // peers reach this instance on CLUSTER_ADVERTISE_ADDR, or the bind address when that's unset
+    pub fn from_config(config: &ClusterConfig, bind: &str) -> Option<Cluster> {
+        if !config.enabled { return None; }
+        let id = config.instance_id.clone().unwrap_or_else(|| {
+            let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "instance".into());
+            format!("{host}-{}", &uuid::Uuid::new_v4().simple().to_string()[..8])
+        });
+        let address = config.advertise_addr.clone().unwrap_or_else(|| bind.to_string());
+        let heartbeat = Duration::from_secs(config.heartbeat_secs.filter(|s| *s > 0).unwrap_or(5));
+        let poll = Duration::from_millis(config.poll_ms.filter(|s| *s > 0).unwrap_or(500));
+        Some(Cluster { id, address, heartbeat, poll, applied: AtomicI64::new(0) })
+    }
+
+    // read before the cache is warmed, so anything logged while warming is replayed by the first poll rather than skipped
+    pub async fn mark(&self, db: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let last = sqlx::query("SELECT COALESCE(MAX(seq), 0) FROM cluster_invalidations").fetch_one(db).await?.get::<i64,_>(0);
+        self.applied.store(last, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn join(&self, db: &Pool<Sqlite>, environment: &str) -> Result<(), sqlx::Error> {
+        let last = self.applied.load(Ordering::Relaxed);
+        sqlx::query("INSERT INTO cluster_instances (id, address, environment, started_at, last_seen_at, applied_seq) VALUES (?, ?, ?, datetime('now'), datetime('now'), ?) ON CONFLICT (id) DO UPDATE SET address = excluded.address, environment = excluded.environment, started_at = excluded.started_at, last_seen_at = excluded.last_seen_at, applied_seq = excluded.applied_seq")
+            .bind(&self.id)
+            .bind(&self.address)
+            .bind(environment)
+            .bind(last)
+            .execute(db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn leave(&self, db: &Pool<Sqlite>) {
+        if let Err(e) = sqlx::query("DELETE FROM cluster_instances WHERE id = ?").bind(&self.id).execute(db).await {
+            tracing::warn!(error = %e, "failed to deregister cluster instance");
+        }
+    }
+
+    async fn announce(&self, db: &Pool<Sqlite>, key: &str, action: &str) {
+        let res = sqlx::query("INSERT INTO cluster_invalidations (origin, flag_key, action, at) VALUES (?, ?, ?, datetime('now'))")
+            .bind(&self.id)
+            .bind(key)
+            .bind(action)
+            .execute(db)
+            .await;
+        if let Err(e) = res { tracing::warn!(error = %e, key, "failed to announce flag change to the cluster"); }
+    }
+
+    async fn poll(&self, state: &AppState) -> Result<(), sqlx::Error> {
+        let rows = sqlx::query("SELECT seq, origin, flag_key, action FROM cluster_invalidations WHERE seq > ? ORDER BY seq")
+            .bind(self.applied.load(Ordering::Relaxed))
+            .fetch_all(&state.db)
+            .await?;
+        for r in &rows {
+            let origin = r.get::<String,_>("origin");
+            let key = r.get::<String,_>("flag_key");
+            if origin != self.id {
+                if key == RESYNC {
+                    if let Err(e) = cache::warm(state).await { tracing::warn!(error = %e, from = %origin, "failed to resync flag cache"); }
+                } else {
+                    let action = match r.get::<String,_>("action").as_str() { "created" => "created", "deleted" => "deleted", _ => "updated" };
+                    state.flag_changes.publish_remote(&key, action);
+                }
+            }
+            self.applied.store(r.get::<i64,_>("seq"), Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    async fn heartbeat(&self, state: &AppState) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE cluster_instances SET last_seen_at = datetime('now'), applied_seq = ?, flags_cached = ?, cache_age_secs = ? WHERE id = ?")
+            .bind(self.applied.load(Ordering::Relaxed))
+            .bind(cache::len(state) as i64)
+            .bind(cache::age(state).as_secs() as i64)
+            .bind(&self.id)
+            .execute(&state.db)
+            .await?;
+        sqlx::query("DELETE FROM cluster_invalidations WHERE at < datetime('now', ?)").bind(RETENTION).execute(&state.db).await?;
+        sqlx::query("DELETE FROM cluster_instances WHERE last_seen_at < datetime('now', '-1 day')").execute(&state.db).await?;
+        Ok(())
+    }
+
+    fn stale_after(&self) -> String { format!("-{} seconds", self.heartbeat.as_secs() * 3) }
+}
+
+pub async fn spawn(cluster: Arc<Cluster>, state: AppState) -> anyhow::Result<()> {
+    cluster.join(&state.db, &state.environment).await?;
+    tracing::info!(instance = %cluster.id, address = %cluster.address, "joined cluster");
+
+    let mut changes = state.flag_changes.subscribe();
+    let (c, s) = (cluster.clone(), state.clone());
+    tokio::spawn(async move {
+        loop {
+            match changes.recv().await {
+                Ok(change) if change.remote => {}
+                Ok(change) => c.announce(&s.db, &change.key, change.action).await,
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!(skipped = n, "cluster announcer fell behind, asking peers to resync");
+                    c.announce(&s.db, RESYNC, "updated").await;
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+
+    let (c, s) = (cluster.clone(), state.clone());
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(c.poll);
+        loop {
+            tick.tick().await;
+            if let Err(e) = c.poll(&s).await { tracing::warn!(error = %e, "failed to read cluster invalidations"); }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(cluster.heartbeat);
+        loop {
+            tick.tick().await;
+            if let Err(e) = cluster.heartbeat(&state).await { tracing::warn!(error = %e, "cluster heartbeat failed"); }
+        }
+    });
+    Ok(())
+}
+
+pub async fn status(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Result<Json<StatusResponse>, StatusCode> {
+    principal.require("*", Role::Viewer)?;
+    let cluster = state.cluster.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let last_seq = sqlx::query("SELECT COALESCE(MAX(seq), 0) FROM cluster_invalidations")
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .get::<i64,_>(0);
+    let rows = sqlx::query("SELECT id, address, environment, started_at, last_seen_at, last_seen_at >= datetime('now', ?) AS alive, applied_seq, flags_cached, cache_age_secs FROM cluster_instances ORDER BY started_at, id")
+        .bind(cluster.stale_after())
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let instances: Vec<Instance> = rows.iter().map(|r| {
+        let applied_seq = if r.get::<String,_>("id") == cluster.id { cluster.applied.load(Ordering::Relaxed) } else { r.get::<i64,_>("applied_seq") };
+        Instance {
+            id: r.get("id"),
+            address: r.get("address"),
+            environment: r.get("environment"),
+            started_at: r.get("started_at"),
+            last_seen_at: r.get("last_seen_at"),
+            alive: r.get::<i64,_>("alive") != 0,
+            applied_seq,
+            lag: (last_seq - applied_seq).max(0),
+            flags_cached: r.get("flags_cached"),
+            cache_age_secs: r.get("cache_age_secs"),
+        }
+    }).collect();
+    let consistent = instances.iter().filter(|i| i.alive).all(|i| i.lag == 0);
+    Ok(Json(StatusResponse { instance_id: cluster.id.clone(), last_seq, consistent, instances }))
+}
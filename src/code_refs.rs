@@ -0,0 +1,115 @@
+use axum::{extract::{Path, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, QueryBuilder, Row, Sqlite};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use feature_flags_core::FlagStore;
+
+use crate::{auth::Principal, events::INSERT_ROWS, store_status, AppState};
+
+const MAX_REFS: usize = 20_000;
+
+#[derive(Debug, Deserialize)]
+pub struct CodeRef {
+    key: String,
+    path: String,
+    line: i64,
+    snippet: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Upload {
+    repository: String,
+    branch: Option<String>,
+    commit: Option<String>,
+    refs: Vec<CodeRef>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadResponse {
+    repository: String,
+    refs: usize,
+    flags: usize,
+    unknown_keys: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Location {
+    path: String,
+    line: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippet: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepositoryRefs {
+    repository: String,
+    branch: Option<String>,
+    commit: Option<String>,
+    uploaded_at: String,
+    refs: Vec<Location>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlagRefs {
+    key: String,
+    total: usize,
+    repositories: Vec<RepositoryRefs>,
+}
+
+// each upload is a full scan of one repository and replaces what it sent last time
+pub async fn upload(State(state): State<AppState>, Extension(principal): Extension<Principal>, Json(input): Json<Upload>) -> Result<Json<UploadResponse>, StatusCode> {
+    principal.require_editor()?;
+    if input.repository.trim().is_empty() || input.refs.len() > MAX_REFS || input.refs.iter().any(|r| r.key.is_empty() || r.path.is_empty() || r.line < 1) { return Err(StatusCode::BAD_REQUEST); }
+    let known: HashSet<String> = state.store.list().await.map_err(store_status)?.into_iter().map(|f| f.key).collect();
+    let keys: HashSet<&str> = input.refs.iter().map(|r| r.key.as_str()).collect();
+    let mut unknown_keys: Vec<String> = keys.iter().filter(|k| !known.contains(**k)).map(|k| k.to_string()).collect();
+    unknown_keys.sort();
+
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("DELETE FROM code_refs WHERE repository = ?").bind(&input.repository).execute(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("INSERT INTO code_ref_scans (repository, branch, commit_sha, uploaded_by, uploaded_at) VALUES (?, ?, ?, ?, datetime('now')) ON CONFLICT (repository) DO UPDATE SET branch = excluded.branch, commit_sha = excluded.commit_sha, uploaded_by = excluded.uploaded_by, uploaded_at = excluded.uploaded_at")
+        .bind(&input.repository)
+        .bind(&input.branch)
+        .bind(&input.commit)
+        .bind(&principal.subject)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for chunk in input.refs.chunks(INSERT_ROWS) {
+        QueryBuilder::new("INSERT INTO code_refs (repository, flag_key, path, line, snippet) ")
+            .push_values(chunk, |mut b, r| { b.push_bind(&input.repository).push_bind(&r.key).push_bind(&r.path).push_bind(r.line).push_bind(&r.snippet); })
+            .build()
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(UploadResponse { repository: input.repository, refs: input.refs.len(), flags: keys.len(), unknown_keys }))
+}
+
+pub async fn flag_refs(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<FlagRefs>, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    let rows = sqlx::query("SELECT c.repository, s.branch, s.commit_sha, s.uploaded_at, c.path, c.line, c.snippet FROM code_refs c JOIN code_ref_scans s ON s.repository = c.repository WHERE c.flag_key = ? ORDER BY c.repository, c.path, c.line")
+        .bind(&flag.key)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let total = rows.len();
+    let mut repositories: BTreeMap<String, RepositoryRefs> = BTreeMap::new();
+    for r in rows {
+        let repository: String = r.get("repository");
+        repositories.entry(repository.clone())
+            .or_insert_with(|| RepositoryRefs { repository, branch: r.get("branch"), commit: r.get("commit_sha"), uploaded_at: r.get("uploaded_at"), refs: Vec::new() })
+            .refs
+            .push(Location { path: r.get("path"), line: r.get("line"), snippet: r.get("snippet") });
+    }
+    Ok(Json(FlagRefs { key: flag.key, total, repositories: repositories.into_values().collect() }))
+}
+
+// None until some repository has uploaded a scan, since before that every flag would look unreferenced
+pub async fn counts(db: &Pool<Sqlite>) -> Result<Option<HashMap<String, i64>>, sqlx::Error> {
+    if sqlx::query("SELECT 1 FROM code_ref_scans LIMIT 1").fetch_optional(db).await?.is_none() { return Ok(None); }
+    let rows = sqlx::query("SELECT flag_key, COUNT(*) AS refs FROM code_refs GROUP BY flag_key").fetch_all(db).await?;
+    Ok(Some(rows.iter().map(|r| (r.get("flag_key"), r.get("refs"))).collect()))
+}
@@ -0,0 +1,94 @@
+use axum::{extract::{Path, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::collections::HashMap;
+
+use feature_flags_core::FlagStore;
+
+use crate::{auth::{Principal, Role}, store_status, AppState};
+
+const MAX_COMMENT_LEN: usize = 4000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Comment {
+    id: i64,
+    flag_key: String,
+    rev: Option<i64>,
+    author: String,
+    body: String,
+    reply_to: Option<i64>,
+    created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewComment {
+    body: String,
+    reply_to: Option<i64>,
+}
+
+pub async fn list_comments(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<Vec<Comment>>, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    let rows = sqlx::query("SELECT * FROM flag_comments WHERE flag_key = ? ORDER BY id")
+        .bind(&flag.key)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(rows.into_iter().map(row_to_comment).collect()))
+}
+
+// a comment belongs to the revision that was current when it was written, so it shows up next to that change in the history
+pub async fn add_comment(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(key): Path<String>, Json(input): Json<NewComment>) -> Result<Json<Comment>, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    let body = input.body.trim();
+    if body.is_empty() || body.len() > MAX_COMMENT_LEN { return Err(StatusCode::BAD_REQUEST); }
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(parent) = input.reply_to {
+        let same_flag = sqlx::query("SELECT 1 FROM flag_comments WHERE id = ? AND flag_key = ?").bind(parent).bind(&flag.key).fetch_optional(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if same_flag.is_none() { return Err(StatusCode::BAD_REQUEST); }
+    }
+    let id = sqlx::query("INSERT INTO flag_comments (flag_key, rev, author, body, reply_to, created_at) SELECT ?, MAX(rev), ?, ?, ?, datetime('now') FROM flag_revisions WHERE flag_key = ?")
+        .bind(&flag.key)
+        .bind(&principal.subject)
+        .bind(body)
+        .bind(input.reply_to)
+        .bind(&flag.key)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .last_insert_rowid();
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    fetch_comment(&state.db, &flag.key, id).await.map(Json)
+}
+
+// authors can remove their own comments, project admins anyone's; replies stay, pointing at a removed comment
+pub async fn delete_comment(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path((key, id)): Path<(String, i64)>) -> Result<StatusCode, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    let comment = fetch_comment(&state.db, &flag.key, id).await?;
+    if comment.author != principal.subject { principal.require(&flag.project, Role::Admin)?; }
+    sqlx::query("DELETE FROM flag_comments WHERE id = ?").bind(id).execute(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn by_rev(db: &Pool<Sqlite>, key: &str) -> Result<HashMap<i64, Vec<Comment>>, sqlx::Error> {
+    let mut out: HashMap<i64, Vec<Comment>> = HashMap::new();
+    for r in sqlx::query("SELECT * FROM flag_comments WHERE flag_key = ? ORDER BY id").bind(key).fetch_all(db).await? {
+        let c = row_to_comment(r);
+        out.entry(c.rev.unwrap_or(0)).or_default().push(c);
+    }
+    Ok(out)
+}
+
+async fn fetch_comment(db: &Pool<Sqlite>, key: &str, id: i64) -> Result<Comment, StatusCode> {
+    sqlx::query("SELECT * FROM flag_comments WHERE id = ? AND flag_key = ?")
+        .bind(id)
+        .bind(key)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(row_to_comment)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn row_to_comment(r: sqlx::sqlite::SqliteRow) -> Comment {
+    Comment { id: r.get("id"), flag_key: r.get("flag_key"), rev: r.get("rev"), author: r.get("author"), body: r.get("body"), reply_to: r.get("reply_to"), created_at: r.get("created_at") }
+}
@@ -0,0 +1,665 @@
+use axum::{extract::State, http::StatusCode, response::{IntoResponse, Response}, Json};
+use figment::{providers::{Format, Serialized, Toml, Yaml}, value::Value, Figment};
+use serde::{Deserialize, Serialize};
+use std::{sync::{atomic::{AtomicU32, AtomicU64, Ordering}, Arc, Mutex}, time::Duration};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+
+// the knobs that used to be read straight from the environment, now typed and loadable from CONFIG_FILE;
+// the old variables still work and win over the file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub environment: String,
+    pub log: LogConfig,
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub cache: CacheConfig,
+    pub auth: AuthConfig,
+    pub cors: CorsConfig,
+    pub webhooks: WebhooksConfig,
+    pub metrics: MetricsConfig,
+    pub access_log: AccessLogConfig,
+    pub identity: IdentityConfig,
+    pub evaluation: EvaluationConfig,
+    pub snapshot: SnapshotConfig,
+    pub analytics: AnalyticsConfig,
+    pub audit_sink: AuditSinkConfig,
+    pub break_glass: BreakGlassConfig,
+    pub bulk: BulkConfig,
+    pub cleanup: CleanupConfig,
+    pub quotas: QuotasConfig,
+    pub schedule: ScheduleConfig,
+    pub srm: SrmConfig,
+    pub guard: GuardConfig,
+    pub signing: SigningConfig,
+    pub cluster: ClusterConfig,
+    pub replication: ReplicationConfig,
+    pub relay: RelayConfig,
+    pub tenants: TenantsConfig,
+    pub flags_file: FlagsFileConfig,
+    pub git_sync: GitSyncConfig,
+    pub kafka: KafkaConfig,
+    pub pubsub: PubsubConfig,
+    pub exports: ExportsConfig,
+    pub backup: BackupConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: String,
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub tls_cert_file: Option<String>,
+    pub tls_key_file: Option<String>,
+    pub http2: bool,
+    pub keep_alive: bool,
+    pub header_read_timeout_secs: u64,
+    pub http2_keepalive_interval_secs: Option<u64>,
+    pub http2_keepalive_timeout_secs: u64,
+    pub http2_max_concurrent_streams: u32,
+    pub shutdown_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub refresh_secs: u64,
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub only_eval: bool,
+    pub max_staleness_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub admin_api_key: Option<String>,
+    pub require_approval: bool,
+    pub token_rate_limit: Option<u32>,
+    pub management_allowlist: Vec<String>,
+    pub trust_forwarded_for: bool,
+    pub trusted_proxies: Option<usize>,
+    pub oidc: OidcConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OidcConfig {
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    pub scope: Option<String>,
+    pub roles_claim: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+    pub max_age_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    pub urls: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub max_flags: usize,
+}
+
+// the sections below are read by the module they're named after, which also keeps their defaults
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessLogConfig {
+    pub enabled: bool,
+    pub redaction: Option<String>,
+    pub redact_params: Option<Vec<String>>,
+    pub hash_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdentityConfig {
+    pub alias_cache_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvaluationConfig {
+    pub hooks: Option<String>,
+    pub context_headers: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub buckets: Option<u32>,
+    pub cache_max_age_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyticsConfig {
+    pub flush_secs: Option<u64>,
+    pub max_buffer: Option<usize>,
+    pub exposure_dedup_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditSinkConfig {
+    pub kind: Option<String>,
+    pub syslog_addr: Option<String>,
+    pub http_url: Option<String>,
+    pub http_token: Option<String>,
+    pub kafka_brokers: Option<Vec<String>>,
+    pub kafka_topic: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BreakGlassConfig {
+    pub max_minutes: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BulkConfig {
+    pub rollout_max_step: Option<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CleanupConfig {
+    pub after_days: Option<i64>,
+    pub check_interval_secs: Option<u64>,
+    pub stale_flag_days: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotasConfig {
+    pub max_flags: Option<i64>,
+    pub max_variants: Option<i64>,
+    pub max_pins: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub poll_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SrmConfig {
+    pub check_interval_secs: Option<u64>,
+    pub p_threshold: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GuardConfig {
+    pub prometheus_url: Option<String>,
+    pub poll_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+    pub key: Option<String>,
+    pub key_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClusterConfig {
+    pub enabled: bool,
+    pub instance_id: Option<String>,
+    pub advertise_addr: Option<String>,
+    pub heartbeat_secs: Option<u64>,
+    pub poll_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplicationConfig {
+    pub retention_days: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayConfig {
+    pub upstream: Option<String>,
+    pub token: Option<String>,
+    pub max_lag_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantsConfig {
+    pub names: Option<Vec<String>>,
+    pub database_url: Option<String>,
+    pub header: Option<String>,
+    pub domain: Option<String>,
+    pub operator_api_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlagsFileConfig {
+    pub path: Option<String>,
+    pub mode: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitSyncConfig {
+    pub repo: Option<String>,
+    pub branch: Option<String>,
+    pub path: Option<String>,
+    pub dir: Option<String>,
+    pub mode: Option<String>,
+    pub prune: bool,
+    pub interval_secs: Option<u64>,
+    pub webhook_secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KafkaConfig {
+    pub brokers: Option<Vec<String>>,
+    pub flag_changes_topic: Option<String>,
+    pub exposures_topic: Option<String>,
+    pub format: Option<String>,
+    pub schema_registry_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PubsubConfig {
+    pub url: Option<String>,
+    pub channel: Option<String>,
+}
+
+// AWS credentials stay in the usual AWS_* variables
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportsConfig {
+    pub s3_bucket: Option<String>,
+    pub s3_prefix: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub interval_secs: Option<u64>,
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    pub dir: Option<String>,
+    pub interval_secs: Option<u64>,
+    pub keep: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            environment: "default".into(),
+            log: LogConfig::default(),
+            server: ServerConfig::default(),
+            database: DatabaseConfig::default(),
+            cache: CacheConfig::default(),
+            auth: AuthConfig::default(),
+            cors: CorsConfig::default(),
+            webhooks: WebhooksConfig::default(),
+            metrics: MetricsConfig::default(),
+            access_log: AccessLogConfig::default(),
+            identity: IdentityConfig::default(),
+            evaluation: EvaluationConfig::default(),
+            snapshot: SnapshotConfig::default(),
+            analytics: AnalyticsConfig::default(),
+            audit_sink: AuditSinkConfig::default(),
+            break_glass: BreakGlassConfig::default(),
+            bulk: BulkConfig::default(),
+            cleanup: CleanupConfig::default(),
+            quotas: QuotasConfig::default(),
+            schedule: ScheduleConfig::default(),
+            srm: SrmConfig::default(),
+            guard: GuardConfig::default(),
+            signing: SigningConfig::default(),
+            cluster: ClusterConfig::default(),
+            replication: ReplicationConfig::default(),
+            relay: RelayConfig::default(),
+            tenants: TenantsConfig::default(),
+            flags_file: FlagsFileConfig::default(),
+            git_sync: GitSyncConfig::default(),
+            kafka: KafkaConfig::default(),
+            pubsub: PubsubConfig::default(),
+            exports: ExportsConfig::default(),
+            backup: BackupConfig::default(),
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> LogConfig { LogConfig { level: "info,tower_http=info".into(), format: None } }
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            bind: "0.0.0.0:8080".into(),
+            tls_cert_file: None,
+            tls_key_file: None,
+            http2: true,
+            keep_alive: true,
+            header_read_timeout_secs: 30,
+            http2_keepalive_interval_secs: None,
+            http2_keepalive_timeout_secs: 20,
+            http2_max_concurrent_streams: 256,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> DatabaseConfig { DatabaseConfig { url: "sqlite://flags.db".into() } }
+}
+
+impl Default for CacheConfig {
+    fn default() -> CacheConfig { CacheConfig { refresh_secs: 30, max_entries: None, max_bytes: None, only_eval: false, max_staleness_secs: 60 } }
+}
+
+impl Default for OidcConfig {
+    fn default() -> OidcConfig { OidcConfig { issuer: None, audience: None, scope: None, roles_claim: "roles".into() } }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> MetricsConfig { MetricsConfig { max_flags: 1000 } }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Text,
+    Number,
+    Decimal,
+    Flag,
+    List,
+}
+
+// every variable, and where it lands in the file. only CONFIG_FILE itself, FLAG_OVERRIDE_*, ADMIN_API_KEY_<TENANT>
+// (names that depend on the flag or tenant), the AWS_* credentials S3 exports use and HOSTNAME stay environment-only
+const ENV: &[(&str, &str, Kind)] = &[
+    ("ENVIRONMENT", "environment", Kind::Text),
+    ("RUST_LOG", "log.level", Kind::Text),
+    ("BIND", "server.bind", Kind::Text),
+    ("TLS_CERT_FILE", "server.tls_cert_file", Kind::Text),
+    ("TLS_KEY_FILE", "server.tls_key_file", Kind::Text),
+    ("HTTP2", "server.http2", Kind::Flag),
+    ("HTTP_KEEPALIVE", "server.keep_alive", Kind::Flag),
+    ("HTTP_HEADER_READ_TIMEOUT_SECS", "server.header_read_timeout_secs", Kind::Number),
+    ("HTTP2_KEEPALIVE_INTERVAL_SECS", "server.http2_keepalive_interval_secs", Kind::Number),
+    ("HTTP2_KEEPALIVE_TIMEOUT_SECS", "server.http2_keepalive_timeout_secs", Kind::Number),
+    ("HTTP2_MAX_CONCURRENT_STREAMS", "server.http2_max_concurrent_streams", Kind::Number),
+    ("HTTP_SHUTDOWN_TIMEOUT_SECS", "server.shutdown_timeout_secs", Kind::Number),
+    ("DATABASE_URL", "database.url", Kind::Text),
+    ("CACHE_REFRESH_SECS", "cache.refresh_secs", Kind::Number),
+    ("CACHE_MAX_ENTRIES", "cache.max_entries", Kind::Number),
+    ("CACHE_MAX_BYTES", "cache.max_bytes", Kind::Number),
+    ("CACHE_ONLY_EVAL", "cache.only_eval", Kind::Flag),
+    ("CACHE_MAX_STALENESS_SECS", "cache.max_staleness_secs", Kind::Number),
+    ("ADMIN_API_KEY", "auth.admin_api_key", Kind::Text),
+    ("REQUIRE_APPROVAL", "auth.require_approval", Kind::Flag),
+    ("TOKEN_RATE_LIMIT", "auth.token_rate_limit", Kind::Number),
+    ("MANAGEMENT_ALLOWLIST", "auth.management_allowlist", Kind::List),
+    ("TRUST_FORWARDED_FOR", "auth.trust_forwarded_for", Kind::Flag),
+    ("TRUSTED_PROXIES", "auth.trusted_proxies", Kind::Number),
+    ("OIDC_ISSUER", "auth.oidc.issuer", Kind::Text),
+    ("OIDC_AUDIENCE", "auth.oidc.audience", Kind::Text),
+    ("OIDC_SCOPE", "auth.oidc.scope", Kind::Text),
+    ("OIDC_ROLES_CLAIM", "auth.oidc.roles_claim", Kind::Text),
+    ("CORS_ALLOWED_ORIGINS", "cors.allowed_origins", Kind::List),
+    ("CORS_MAX_AGE_SECS", "cors.max_age_secs", Kind::Number),
+    ("WEBHOOK_URLS", "webhooks.urls", Kind::List),
+    ("METRICS_MAX_FLAGS", "metrics.max_flags", Kind::Number),
+    ("LOG_FORMAT", "log.format", Kind::Text),
+    ("ACCESS_LOG", "access_log.enabled", Kind::Flag),
+    ("ACCESS_LOG_REDACTION", "access_log.redaction", Kind::Text),
+    ("ACCESS_LOG_REDACT_PARAMS", "access_log.redact_params", Kind::List),
+    ("ACCESS_LOG_HASH_KEY", "access_log.hash_key", Kind::Text),
+    ("ALIAS_CACHE_SECS", "identity.alias_cache_secs", Kind::Number),
+    ("EVAL_HOOKS", "evaluation.hooks", Kind::Text),
+    ("CONTEXT_HEADERS", "evaluation.context_headers", Kind::Text),
+    ("SNAPSHOT_BUCKETS", "snapshot.buckets", Kind::Number),
+    ("SNAPSHOT_CACHE_MAX_AGE_SECS", "snapshot.cache_max_age_secs", Kind::Number),
+    ("ANALYTICS_FLUSH_SECS", "analytics.flush_secs", Kind::Number),
+    ("ANALYTICS_MAX_BUFFER", "analytics.max_buffer", Kind::Number),
+    ("EXPOSURE_DEDUP_SECS", "analytics.exposure_dedup_secs", Kind::Number),
+    ("AUDIT_SINK", "audit_sink.kind", Kind::Text),
+    ("AUDIT_SYSLOG_ADDR", "audit_sink.syslog_addr", Kind::Text),
+    ("AUDIT_HTTP_URL", "audit_sink.http_url", Kind::Text),
+    ("AUDIT_HTTP_TOKEN", "audit_sink.http_token", Kind::Text),
+    ("AUDIT_KAFKA_BROKERS", "audit_sink.kafka_brokers", Kind::List),
+    ("AUDIT_KAFKA_TOPIC", "audit_sink.kafka_topic", Kind::Text),
+    ("BREAK_GLASS_MAX_MINUTES", "break_glass.max_minutes", Kind::Number),
+    ("BULK_ROLLOUT_MAX_STEP", "bulk.rollout_max_step", Kind::Number),
+    ("CLEANUP_AFTER_DAYS", "cleanup.after_days", Kind::Number),
+    ("CLEANUP_CHECK_INTERVAL_SECS", "cleanup.check_interval_secs", Kind::Number),
+    ("STALE_FLAG_DAYS", "cleanup.stale_flag_days", Kind::Number),
+    ("QUOTA_MAX_FLAGS", "quotas.max_flags", Kind::Number),
+    ("QUOTA_MAX_VARIANTS", "quotas.max_variants", Kind::Number),
+    ("QUOTA_MAX_PINS", "quotas.max_pins", Kind::Number),
+    ("SCHEDULE_POLL_SECS", "schedule.poll_secs", Kind::Number),
+    ("SRM_CHECK_INTERVAL_SECS", "srm.check_interval_secs", Kind::Number),
+    ("SRM_P_THRESHOLD", "srm.p_threshold", Kind::Decimal),
+    ("GUARD_PROMETHEUS_URL", "guard.prometheus_url", Kind::Text),
+    ("GUARD_POLL_SECS", "guard.poll_secs", Kind::Number),
+    ("SIGNING_KEY", "signing.key", Kind::Text),
+    ("SIGNING_KEY_FILE", "signing.key_file", Kind::Text),
+    ("CLUSTER_ENABLED", "cluster.enabled", Kind::Flag),
+    ("CLUSTER_INSTANCE_ID", "cluster.instance_id", Kind::Text),
+    ("CLUSTER_ADVERTISE_ADDR", "cluster.advertise_addr", Kind::Text),
+    ("CLUSTER_HEARTBEAT_SECS", "cluster.heartbeat_secs", Kind::Number),
+    ("CLUSTER_POLL_MS", "cluster.poll_ms", Kind::Number),
+    ("REPLICATION_RETENTION_DAYS", "replication.retention_days", Kind::Number),
+    ("RELAY_UPSTREAM", "relay.upstream", Kind::Text),
+    ("RELAY_TOKEN", "relay.token", Kind::Text),
+    ("RELAY_MAX_LAG_SECS", "relay.max_lag_secs", Kind::Number),
+    ("TENANTS", "tenants.names", Kind::List),
+    ("TENANT_DATABASE_URL", "tenants.database_url", Kind::Text),
+    ("TENANT_HEADER", "tenants.header", Kind::Text),
+    ("TENANT_DOMAIN", "tenants.domain", Kind::Text),
+    ("OPERATOR_API_KEY", "tenants.operator_api_key", Kind::Text),
+    ("FLAGS_FILE", "flags_file.path", Kind::Text),
+    ("FLAGS_FILE_MODE", "flags_file.mode", Kind::Text),
+    ("GIT_SYNC_REPO", "git_sync.repo", Kind::Text),
+    ("GIT_SYNC_BRANCH", "git_sync.branch", Kind::Text),
+    ("GIT_SYNC_PATH", "git_sync.path", Kind::Text),
+    ("GIT_SYNC_DIR", "git_sync.dir", Kind::Text),
+    ("GIT_SYNC_MODE", "git_sync.mode", Kind::Text),
+    ("GIT_SYNC_PRUNE", "git_sync.prune", Kind::Flag),
+    ("GIT_SYNC_INTERVAL_SECS", "git_sync.interval_secs", Kind::Number),
+    ("GIT_SYNC_WEBHOOK_SECRET", "git_sync.webhook_secret", Kind::Text),
+    ("KAFKA_BROKERS", "kafka.brokers", Kind::List),
+    ("KAFKA_FLAG_CHANGES_TOPIC", "kafka.flag_changes_topic", Kind::Text),
+    ("KAFKA_EXPOSURES_TOPIC", "kafka.exposures_topic", Kind::Text),
+    ("KAFKA_FORMAT", "kafka.format", Kind::Text),
+    ("KAFKA_SCHEMA_REGISTRY_URL", "kafka.schema_registry_url", Kind::Text),
+    ("PUBSUB_URL", "pubsub.url", Kind::Text),
+    ("PUBSUB_CHANNEL", "pubsub.channel", Kind::Text),
+    ("EXPORT_S3_BUCKET", "exports.s3_bucket", Kind::Text),
+    ("EXPORT_S3_PREFIX", "exports.s3_prefix", Kind::Text),
+    ("EXPORT_S3_REGION", "exports.s3_region", Kind::Text),
+    ("EXPORT_S3_ENDPOINT", "exports.s3_endpoint", Kind::Text),
+    ("EXPORT_INTERVAL_SECS", "exports.interval_secs", Kind::Number),
+    ("EXPORT_FORMAT", "exports.format", Kind::Text),
+    ("BACKUP_DIR", "backup.dir", Kind::Text),
+    ("BACKUP_INTERVAL_SECS", "backup.interval_secs", Kind::Number),
+    ("BACKUP_KEEP", "backup.keep", Kind::Number),
+];
+
+// figment's own env provider guesses types from the value, so an all-digit ADMIN_API_KEY would stop being a string;
+// each variable is parsed the way it always was instead
+fn env_value(name: &str, raw: String, kind: Kind) -> anyhow::Result<Value> {
+    Ok(match kind {
+        Kind::Text => Value::from(raw),
+        Kind::Number => Value::from(raw.trim().parse::<u64>().map_err(|_| anyhow::anyhow!("{name} must be a whole number, got {raw:?}"))?),
+        Kind::Decimal => Value::from(raw.trim().parse::<f64>().map_err(|_| anyhow::anyhow!("{name} must be a number, got {raw:?}"))?),
+        Kind::Flag => Value::from(raw == "true" || raw == "1"),
+        Kind::List => Value::from(raw.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect::<Vec<_>>()),
+    })
+}
+
+impl Config {
+    // defaults, then CONFIG_FILE (TOML, or YAML by its extension), then the environment
+    pub fn load() -> anyhow::Result<Config> {
+        let mut figment = Figment::new();
+        if let Ok(path) = std::env::var("CONFIG_FILE") {
+            if !std::path::Path::new(&path).is_file() { anyhow::bail!("CONFIG_FILE {path} doesn't exist"); }
+            figment = match path.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).as_deref() {
+                Some("toml") => figment.merge(Toml::file_exact(&path)),
+                Some("yaml" | "yml") => figment.merge(Yaml::file_exact(&path)),
+                _ => anyhow::bail!("CONFIG_FILE {path} must end in .toml, .yaml or .yml"),
+            };
+        }
+        for &(name, path, kind) in ENV {
+            if let Ok(raw) = std::env::var(name) { figment = figment.merge(Serialized::default(path, env_value(name, raw, kind)?)); }
+        }
+        figment.extract().map_err(|e| anyhow::anyhow!("invalid configuration: {e}"))
+    }
+}
+
+impl CacheConfig {
+    // also how often context schemas are reloaded
+    pub fn refresh_interval(&self) -> Duration { Duration::from_secs(if self.refresh_secs > 0 { self.refresh_secs } else { 30 }) }
+}
+
+impl CorsConfig {
+    // no origins listed keeps the old behaviour of allowing any
+    pub fn layer(&self) -> anyhow::Result<CorsLayer> {
+        if self.allowed_origins.is_empty() { return Ok(CorsLayer::permissive()); }
+        let origins = self.allowed_origins.iter().map(|o| o.parse().map_err(|_| anyhow::anyhow!("invalid CORS origin {o:?}"))).collect::<anyhow::Result<Vec<_>>>()?;
+        let layer = CorsLayer::new().allow_origin(AllowOrigin::list(origins)).allow_methods(Any).allow_headers(Any).expose_headers(Any);
+        Ok(match self.max_age_secs { Some(secs) => layer.max_age(Duration::from_secs(secs)), None => layer })
+    }
+}
+
+// what POST /admin/config/reload can change in place. there's one per process, shared by every tenant
+pub struct Reloadable {
+    applied: Mutex<Config>,
+    refresh_secs: AtomicU64,
+    token_rate_limit: AtomicU32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Reloaded {
+    file: Option<String>,
+    applied: Vec<&'static str>,
+    restart_required: Vec<&'static str>,
+}
+
+impl Reloadable {
+    pub fn new(config: Config) -> Arc<Reloadable> {
+        Arc::new(Reloadable {
+            refresh_secs: AtomicU64::new(config.cache.refresh_interval().as_secs()),
+            token_rate_limit: AtomicU32::new(config.auth.token_rate_limit.unwrap_or(0)),
+            applied: Mutex::new(config),
+        })
+    }
+
+    pub fn current(&self) -> Config { self.applied.lock().unwrap().clone() }
+
+    pub fn refresh_interval(&self) -> Duration { Duration::from_secs(self.refresh_secs.load(Ordering::Relaxed)) }
+
+    pub fn token_rate_limit(&self) -> Option<u32> { Some(self.token_rate_limit.load(Ordering::Relaxed)).filter(|l| *l > 0) }
+
+    // a file that doesn't load changes nothing. settings that need a restart are only reported, and keep running as they were
+    pub fn reload(&self) -> anyhow::Result<Reloaded> {
+        let next = Config::load()?;
+        let mut current = self.applied.lock().unwrap();
+        let mut applied = Vec::new();
+        // first, as it's the only step that can still fail
+        if next.log.level != current.log.level { crate::telemetry::set_filter(&next.log.level)?; applied.push("log.level"); }
+        if next.cache.refresh_secs != current.cache.refresh_secs { self.refresh_secs.store(next.cache.refresh_interval().as_secs(), Ordering::Relaxed); applied.push("cache.refresh_secs"); }
+        if next.auth.token_rate_limit != current.auth.token_rate_limit { self.token_rate_limit.store(next.auth.token_rate_limit.unwrap_or(0), Ordering::Relaxed); applied.push("auth.token_rate_limit"); }
+        if next.metrics != current.metrics { crate::metrics::configure(&next.metrics); applied.push("metrics.max_flags"); }
+        if next.quotas != current.quotas { crate::quotas::configure(&next.quotas); applied.push("quotas"); }
+        // read by the handlers on each request
+        if next.break_glass != current.break_glass { applied.push("break_glass.max_minutes"); }
+        if next.bulk != current.bulk { applied.push("bulk.rollout_max_step"); }
+        if next.cleanup.after_days != current.cleanup.after_days { applied.push("cleanup.after_days"); }
+        if next.cleanup.stale_flag_days != current.cleanup.stale_flag_days { applied.push("cleanup.stale_flag_days"); }
+
+        let cold = |c: &Config| {
+            let mut c = c.clone();
+            (c.log.level, c.cache.refresh_secs, c.auth.token_rate_limit, c.metrics, c.quotas) = (String::new(), 0, None, MetricsConfig::default(), QuotasConfig::default());
+            (c.break_glass, c.bulk, c.cleanup.after_days, c.cleanup.stale_flag_days) = (BreakGlassConfig::default(), BulkConfig::default(), None, None);
+            c
+        };
+        let (was, now) = (cold(&current), cold(&next));
+        let restart_required = [
+            ("environment", was.environment != now.environment),
+            ("server", was.server != now.server),
+            ("database", was.database != now.database),
+            ("cache", was.cache != now.cache),
+            ("auth", was.auth != now.auth),
+            ("cors", was.cors != now.cors),
+            ("webhooks", was.webhooks != now.webhooks),
+            ("log", was.log != now.log),
+            ("access_log", was.access_log != now.access_log),
+            ("identity", was.identity != now.identity),
+            ("evaluation", was.evaluation != now.evaluation),
+            ("snapshot", was.snapshot != now.snapshot),
+            ("analytics", was.analytics != now.analytics),
+            ("audit_sink", was.audit_sink != now.audit_sink),
+            ("cleanup", was.cleanup != now.cleanup),
+            ("schedule", was.schedule != now.schedule),
+            ("srm", was.srm != now.srm),
+            ("guard", was.guard != now.guard),
+            ("signing", was.signing != now.signing),
+            ("cluster", was.cluster != now.cluster),
+            ("replication", was.replication != now.replication),
+            ("relay", was.relay != now.relay),
+            ("tenants", was.tenants != now.tenants),
+            ("flags_file", was.flags_file != now.flags_file),
+            ("git_sync", was.git_sync != now.git_sync),
+            ("kafka", was.kafka != now.kafka),
+            ("pubsub", was.pubsub != now.pubsub),
+            ("exports", was.exports != now.exports),
+            ("backup", was.backup != now.backup),
+        ].into_iter().filter(|(_, changed)| *changed).map(|(name, _)| name).collect();
+
+        (current.log.level, current.cache.refresh_secs, current.auth.token_rate_limit, current.metrics, current.quotas) = (next.log.level, next.cache.refresh_secs, next.auth.token_rate_limit, next.metrics, next.quotas);
+        (current.break_glass, current.bulk, current.cleanup.after_days, current.cleanup.stale_flag_days) = (next.break_glass, next.bulk, next.cleanup.after_days, next.cleanup.stale_flag_days);
+        Ok(Reloaded { file: std::env::var("CONFIG_FILE").ok(), applied, restart_required })
+    }
+}
+
+// re-reads CONFIG_FILE and the environment without dropping connections or streams
+pub async fn reload(State(config): State<Arc<Reloadable>>) -> Result<Json<Reloaded>, Response> {
+    match config.reload() {
+        Ok(reloaded) => {
+            tracing::info!(applied = ?reloaded.applied, restart_required = ?reloaded.restart_required, "configuration reloaded");
+            Ok(Json(reloaded))
+        }
+        Err(e) => Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({ "error": format!("{e:#}") }))).into_response()),
+    }
+}
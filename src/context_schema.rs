@@ -0,0 +1,190 @@
+use arc_swap::ArcSwap;
+use axum::{extract::{Path, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Pool, Row, Sqlite};
+use std::{collections::{BTreeMap, HashMap}, sync::{Arc, Mutex}};
+
+use crate::{auth::{Principal, Role}, config::Reloadable, valid_name, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributeType {
+    String,
+    Number,
+    Boolean,
+    List,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Warn,
+    Reject,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attribute {
+    name: String,
+    #[serde(rename = "type")]
+    kind: AttributeType,
+    #[serde(default)]
+    required: bool,
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    examples: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SchemaInput {
+    mode: Mode,
+    attributes: Vec<Attribute>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Schema {
+    project: String,
+    mode: Mode,
+    attributes: Vec<Attribute>,
+    updated_by: String,
+    updated_at: String,
+}
+
+pub enum Verdict {
+    Valid,
+    Warn(Vec<String>),
+    Reject(Vec<String>),
+}
+
+// every instance keeps the schemas in memory; evaluation never waits on the database for them
+pub struct ContextSchemas {
+    by_project: ArcSwap<HashMap<String, Schema>>,
+    // warn-mode mismatches per project since the last reload, with the latest problems as an example
+    mismatches: Mutex<HashMap<String, (u64, Vec<String>)>>,
+}
+
+impl AttributeType {
+    fn matches(self, v: &Value) -> bool {
+        match self {
+            AttributeType::String => v.is_string(),
+            AttributeType::Number => v.is_number(),
+            AttributeType::Boolean => v.is_boolean(),
+            AttributeType::List => v.as_array().is_some_and(|a| a.iter().all(|x| x.is_string() || x.is_number())),
+        }
+    }
+}
+
+impl Schema {
+    fn check(&self, context: &BTreeMap<String, Value>) -> Vec<String> {
+        let mut problems = Vec::new();
+        for a in &self.attributes {
+            match context.get(&a.name) {
+                None | Some(Value::Null) if a.required => problems.push(format!("missing required attribute {}", a.name)),
+                Some(v) if !v.is_null() && !a.kind.matches(v) => problems.push(format!("{} should be a {}", a.name, serde_json::to_value(a.kind).unwrap().as_str().unwrap())),
+                _ => {}
+            }
+        }
+        for name in context.keys() {
+            if name != "user_id" && name != "anonymous_id" && !self.attributes.iter().any(|a| &a.name == name) { problems.push(format!("unknown attribute {name}")); }
+        }
+        problems
+    }
+}
+
+impl ContextSchemas {
+    pub async fn load(db: &Pool<Sqlite>) -> Result<ContextSchemas, sqlx::Error> {
+        let schemas = ContextSchemas { by_project: ArcSwap::from_pointee(HashMap::new()), mismatches: Mutex::new(HashMap::new()) };
+        schemas.reload(db).await?;
+        Ok(schemas)
+    }
+
+    async fn reload(&self, db: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM context_schemas").fetch_all(db).await?;
+        let by_project = rows.into_iter().filter_map(|r| match row_to_schema(r) {
+            Ok(s) => Some((s.project.clone(), s)),
+            Err(e) => { tracing::warn!(error = %e, "skipping unreadable context schema"); None }
+        }).collect();
+        self.by_project.store(Arc::new(by_project));
+        Ok(())
+    }
+
+    pub fn validate(&self, project: &str, context: &BTreeMap<String, Value>) -> Verdict {
+        let by_project = self.by_project.load();
+        let Some(schema) = by_project.get(project) else { return Verdict::Valid };
+        let problems = schema.check(context);
+        match (problems.is_empty(), schema.mode) {
+            (true, _) => Verdict::Valid,
+            (false, Mode::Warn) => {
+                let mut mismatches = self.mismatches.lock().unwrap();
+                let entry = mismatches.entry(project.to_string()).or_default();
+                entry.0 += 1;
+                entry.1.clone_from(&problems);
+                Verdict::Warn(problems)
+            }
+            (false, Mode::Reject) => Verdict::Reject(problems),
+        }
+    }
+
+    // on the flag cache's interval, read again every round so a config reload applies
+    pub fn spawn_reloader(self: Arc<Self>, db: Pool<Sqlite>, config: Arc<Reloadable>) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(config.refresh_interval()).await;
+                let mismatches = std::mem::take(&mut *self.mismatches.lock().unwrap());
+                for (project, (count, problems)) in mismatches { tracing::warn!(%project, count, ?problems, "evaluation contexts didn't match the project's schema"); }
+                if let Err(e) = self.reload(&db).await { tracing::warn!(error = %e, "failed to reload context schemas"); }
+            }
+        });
+    }
+}
+
+pub async fn list_schemas(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Json<Vec<Schema>> {
+    let mut out: Vec<Schema> = state.context_schemas.by_project.load().values().filter(|s| principal.has_role(&s.project, Role::Viewer)).cloned().collect();
+    out.sort_by(|a, b| a.project.cmp(&b.project));
+    Json(out)
+}
+
+pub async fn get_schema(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(project): Path<String>) -> Result<Json<Schema>, StatusCode> {
+    principal.require(&project, Role::Viewer)?;
+    state.context_schemas.by_project.load().get(&project).cloned().map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn put_schema(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(project): Path<String>, Json(input): Json<SchemaInput>) -> Result<Json<Schema>, StatusCode> {
+    principal.require(&project, Role::Admin)?;
+    let mut names: Vec<&str> = input.attributes.iter().map(|a| a.name.as_str()).collect();
+    names.sort();
+    if names.iter().any(|n| !valid_name(n)) || names.windows(2).any(|w| w[0] == w[1]) { return Err(StatusCode::BAD_REQUEST); }
+    sqlx::query("INSERT INTO context_schemas (project, mode, attributes, updated_by, updated_at) VALUES (?, ?, ?, ?, datetime('now')) ON CONFLICT (project) DO UPDATE SET mode = excluded.mode, attributes = excluded.attributes, updated_by = excluded.updated_by, updated_at = excluded.updated_at")
+        .bind(&project)
+        .bind(serde_json::to_value(input.mode).unwrap().as_str())
+        .bind(serde_json::to_string(&input.attributes).unwrap())
+        .bind(&principal.subject)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.context_schemas.reload(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.context_schemas.by_project.load().get(&project).cloned().map(Json).ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+pub async fn delete_schema(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(project): Path<String>) -> Result<StatusCode, StatusCode> {
+    principal.require(&project, Role::Admin)?;
+    let rows = sqlx::query("DELETE FROM context_schemas WHERE project = ?")
+        .bind(&project)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    state.context_schemas.reload(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn row_to_schema(r: sqlx::sqlite::SqliteRow) -> Result<Schema, serde_json::Error> {
+    Ok(Schema {
+        project: r.get("project"),
+        mode: if r.get::<String,_>("mode") == "reject" { Mode::Reject } else { Mode::Warn },
+        attributes: serde_json::from_str(&r.get::<String,_>("attributes"))?,
+        updated_by: r.get("updated_by"),
+        updated_at: r.get("updated_at"),
+    })
+}
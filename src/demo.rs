@@ -0,0 +1,139 @@
+use sqlx::{Pool, Sqlite};
+use std::{collections::HashMap, net::SocketAddr, path::{Path, PathBuf}};
+
+use feature_flags_core::{sqlite, CreateFlag, UpdateFlag};
+
+use crate::auth::hash_secret;
+
+// (name, kind, project, role); role None for SDK tokens. secrets are made up per run and printed at startup
+const TOKENS: [(&str, &str, &str, Option<&str>); 4] = [
+    ("demo-admin", "server", "*", Some("admin")),
+    ("demo-checkout-editor", "server", "checkout", Some("editor")),
+    ("demo-viewer", "server", "*", Some("viewer")),
+    ("demo-web-sdk", "client", "*", None),
+];
+
+type Variants = &'static [(&'static str, u32)];
+
+// (key, project, enabled, protected, variants, rollout)
+type DemoFlag = (&'static str, &'static str, bool, bool, Variants, Option<u8>);
+
+const FLAGS: [DemoFlag; 9] = [
+    ("dark_mode", "default", true, false, &[], None),
+    ("new_onboarding", "default", true, false, &[], Some(1)),
+    ("maintenance_banner", "default", false, false, &[], None),
+    ("kill_switch_payments", "checkout", true, true, &[], None),
+    ("one_click_checkout", "checkout", true, false, &[], Some(5)),
+    ("checkout_button_color", "checkout", true, false, &[("control", 50), ("green", 25), ("orange", 25)], None),
+    ("free_shipping_threshold", "checkout", false, false, &[("control", 50), ("lower", 50)], None),
+    ("search_ranking_v2", "search", true, false, &[("bm25", 80), ("semantic", 20)], Some(50)),
+    ("search_autocomplete", "search", false, false, &[], None),
+];
+
+const TEMPLATES: [(&str, &str, bool, Variants, Option<u8>); 3] = [
+    ("ab-test", "Standard A/B test: control/treatment 50/50 on 10% of users", true, &[("control", 50), ("treatment", 50)], Some(10)),
+    ("kill-switch", "Operational switch, on for everyone", true, &[], None),
+    ("dark-launch", "Off until someone turns it on", false, &[], Some(0)),
+];
+
+pub fn requested() -> bool {
+    std::env::args().skip(1).any(|a| a == "--demo")
+}
+
+// a fresh database per run so the demo never touches real data; removed again on shutdown
+pub fn database_path() -> PathBuf {
+    std::env::temp_dir().join(format!("flags-demo-{}.db", std::process::id()))
+}
+
+pub fn database_url(path: &Path) -> String {
+    format!("sqlite://{}?mode=rwc", path.display())
+}
+
+pub fn remove_database(path: &Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+}
+
+// the demo hands out admin tokens to whoever reads the console, so it only listens on loopback
+pub fn loopback(bind: &str) -> String {
+    let port = bind.parse::<SocketAddr>().map_or(8080, |a| a.port());
+    SocketAddr::from(([127, 0, 0, 1], port)).to_string()
+}
+
+fn variants(v: &[(&str, u32)]) -> Option<HashMap<String, u32>> {
+    (!v.is_empty()).then(|| v.iter().map(|(k, w)| (k.to_string(), *w)).collect())
+}
+
+pub async fn seed(db: &Pool<Sqlite>) -> anyhow::Result<()> {
+    let mut tx = db.begin().await?;
+    for (key, project, enabled, protected, v, rollout) in FLAGS {
+        sqlite::insert_flag(&mut tx, &CreateFlag { uid: None, key: key.into(), project: project.into(), enabled, protected, variants: variants(v), rollout }).await?;
+    }
+    // a little history so revisions, diffs and recent changes have something to show
+    for (key, rollout) in [("new_onboarding", 5), ("new_onboarding", 10), ("new_onboarding", 25), ("one_click_checkout", 10)] {
+        sqlite::apply_update(&mut tx, key, &UpdateFlag { rollout: Some(rollout), ..Default::default() }).await?;
+    }
+    for (name, description, enabled, v, rollout) in TEMPLATES {
+        sqlx::query("INSERT INTO flag_templates (name, description, enabled, variants, rollout, created_at, updated_at) VALUES (?, ?, ?, ?, ?, datetime('now'), datetime('now'))")
+            .bind(name)
+            .bind(description)
+            .bind(enabled)
+            .bind(variants(v).map(|v| serde_json::to_string(&v)).transpose()?)
+            .bind(rollout.map(|r| r as i64))
+            .execute(&mut *tx)
+            .await?;
+    }
+    for (name, event, aggregation, description) in [("checkout_conversion", "purchase", "conversion", "Share of exposed users who purchase"), ("revenue", "purchase", "sum", "Purchase value per exposed user"), ("searches", "search", "count", "Searches per exposed user")] {
+        sqlx::query("INSERT INTO metric_definitions (name, event, aggregation, direction, description, created_at, updated_at) VALUES (?, ?, ?, 'increase', ?, datetime('now'), datetime('now'))")
+            .bind(name)
+            .bind(event)
+            .bind(aggregation)
+            .bind(description)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let mut secrets = Vec::with_capacity(TOKENS.len());
+    for (name, kind, project, role) in TOKENS {
+        let secret = format!("{name}-{}", uuid::Uuid::new_v4().simple());
+        let id = sqlx::query("INSERT INTO api_keys (name, key_hash, kind, created_at) VALUES (?, ?, ?, datetime('now'))")
+            .bind(name)
+            .bind(hash_secret(&secret))
+            .bind(kind)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+        if let Some(role) = role {
+            sqlx::query("INSERT INTO key_roles (key_id, project, environment, role) VALUES (?, ?, '*', ?)").bind(id).bind(project).bind(role).execute(&mut *tx).await?;
+        }
+        secrets.push(secret);
+    }
+    // a day of evaluation counts, so per-flag stats and /summary aren't empty
+    let now = chrono::Utc::now();
+    for (i, (key, _, _, _, v, _)) in FLAGS.iter().enumerate().filter(|(_, f)| f.2) {
+        for h in 0..24 {
+            let hour = (now - chrono::Duration::hours(h)).format("%Y-%m-%d %H:00:00").to_string();
+            let evaluations = (9 - i as i64) * 120 + (h * 37) % 100;
+            let names: Vec<&str> = if v.is_empty() { vec![""] } else { v.iter().map(|(n, _)| *n).collect() };
+            for name in &names {
+                let n = evaluations / names.len() as i64;
+                sqlx::query("INSERT INTO flag_eval_stats (flag_key, hour, variant, evaluations, matched) VALUES (?, ?, ?, ?, ?)")
+                    .bind(key)
+                    .bind(&hour)
+                    .bind(name)
+                    .bind(n)
+                    .bind(n * 9 / 10)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+    tx.commit().await?;
+    tracing::info!(flags = FLAGS.len(), templates = TEMPLATES.len(), "seeded demo data");
+    // printed rather than logged, so they show up whatever the log level
+    eprintln!("demo tokens:");
+    for ((name, kind, project, role), secret) in TOKENS.iter().zip(&secrets) {
+        eprintln!("  {name:<22} {kind:<7} {:<18} {secret}", role.map_or("sdk".to_string(), |r| format!("{r} on {project}")));
+    }
+    Ok(())
+}
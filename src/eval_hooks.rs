@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use feature_flags_core::{EvalContext, EvalHook, EvalResponse, HookRegistry, Hooks};
+
+use crate::config::EvaluationConfig;
+
+// logs every evaluation at info, with its result; `log=debug` logs at debug instead
+struct Log {
+    debug: bool,
+}
+
+impl EvalHook for Log {
+    fn name(&self) -> &str { "log" }
+
+    fn after(&self, ctx: &EvalContext, res: &mut EvalResponse) {
+        if self.debug {
+            tracing::debug!(key = %res.key, user_id = ?ctx.user_id, context = ?ctx.attributes, matched = res.matched, variant = ?res.variant, "flag evaluated");
+        } else {
+            tracing::info!(key = %res.key, user_id = ?ctx.user_id, matched = res.matched, variant = ?res.variant, "flag evaluated");
+        }
+    }
+}
+
+pub fn registry() -> HookRegistry {
+    let mut registry = HookRegistry::new();
+    registry.register("log", |arg| match arg {
+        "" | "info" => Ok(Arc::new(Log { debug: false })),
+        "debug" => Ok(Arc::new(Log { debug: true })),
+        other => Err(format!("expects info or debug, not {other}")),
+    });
+    registry
+}
+
+// EVAL_HOOKS, e.g. `log=debug; denylist=u-1,u-2`, in the order they run
+pub fn from_config(config: &EvaluationConfig) -> anyhow::Result<Hooks> {
+    let hooks = registry().build(config.hooks.as_deref().unwrap_or_default()).map_err(|e| anyhow::anyhow!("EVAL_HOOKS: {e}"))?;
+    if !hooks.is_empty() { tracing::info!(hooks = ?hooks.names(), "evaluation hooks enabled"); }
+    Ok(hooks)
+}
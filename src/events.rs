@@ -0,0 +1,144 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::Deserialize;
+use sqlx::{Pool, QueryBuilder, Sqlite};
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use tokio::sync::{mpsc, Notify};
+
+use crate::{auth::ApiKey, config::AnalyticsConfig, kafka::Exposure, AppState};
+
+const MAX_BATCH: usize = 1000;
+pub const INSERT_ROWS: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct TrackEvent {
+    event: String,
+    user_id: String,
+    value: Option<f64>,
+    experiment: Option<String>,
+    properties: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TrackInput {
+    One(TrackEvent),
+    Many(Vec<TrackEvent>),
+}
+
+enum Pending {
+    Exposure { flag_key: String, user_id: String, variant: String, at: String },
+    Conversion { event: TrackEvent, at: String },
+}
+
+pub struct Events {
+    pending: Mutex<Vec<Pending>>,
+    dedup_window: Duration,
+    seen: Mutex<HashMap<(String, String, String), Instant>>,
+    forward: Option<mpsc::Sender<Exposure>>,
+    max_buffer: usize,
+    full: Notify,
+}
+
+pub fn flush_interval(config: &AnalyticsConfig) -> Duration {
+    Duration::from_secs(config.flush_secs.filter(|s| *s > 0).unwrap_or(5))
+}
+
+fn now() -> String { chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string() }
+
+impl Events {
+    pub fn from_config(config: &AnalyticsConfig, forward: Option<mpsc::Sender<Exposure>>) -> Events {
+        let secs = config.exposure_dedup_secs.unwrap_or(3600);
+        let max_buffer = config.max_buffer.filter(|n| *n > 0).unwrap_or(10_000);
+        Events { pending: Mutex::new(Vec::new()), dedup_window: Duration::from_secs(secs), seen: Mutex::new(HashMap::new()), forward, max_buffer, full: Notify::new() }
+    }
+
+    fn push(&self, events: impl IntoIterator<Item = Pending>) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.extend(events);
+        if pending.len() >= self.max_buffer { self.full.notify_one(); }
+    }
+
+    pub fn record_exposure(&self, flag_key: &str, user_id: &str, variant: &str) {
+        if !self.dedup_window.is_zero() {
+            let key = (flag_key.to_string(), user_id.to_string(), variant.to_string());
+            let mut seen = self.seen.lock().unwrap();
+            if seen.get(&key).is_some_and(|at| at.elapsed() < self.dedup_window) { return; }
+            seen.insert(key, Instant::now());
+        }
+        if let Some(forward) = &self.forward {
+            let exposure = Exposure { flag_key: flag_key.to_string(), user_id: user_id.to_string(), variant: variant.to_string(), at: chrono::Utc::now() };
+            if forward.try_send(exposure).is_err() { tracing::warn!("exposure publishing queue is full, dropping event"); }
+        }
+        self.push([Pending::Exposure { flag_key: flag_key.to_string(), user_id: user_id.to_string(), variant: variant.to_string(), at: now() }]);
+    }
+
+    pub async fn flush(&self, db: &Pool<Sqlite>) {
+        if !self.dedup_window.is_zero() { self.seen.lock().unwrap().retain(|_, at| at.elapsed() < self.dedup_window); }
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() { return; }
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = db.begin().await?;
+            let exposures: Vec<_> = pending.iter().filter_map(|p| match p { Pending::Exposure { flag_key, user_id, variant, at } => Some((flag_key, user_id, variant, at)), _ => None }).collect();
+            for chunk in exposures.chunks(INSERT_ROWS) {
+                QueryBuilder::new("INSERT INTO exposures (flag_key, user_id, variant, at) ")
+                    .push_values(chunk, |mut b, (flag_key, user_id, variant, at)| { b.push_bind(*flag_key).push_bind(*user_id).push_bind(*variant).push_bind(*at); })
+                    .build()
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            let conversions: Vec<_> = pending.iter().filter_map(|p| match p { Pending::Conversion { event, at } => Some((event, at)), _ => None }).collect();
+            for chunk in conversions.chunks(INSERT_ROWS) {
+                QueryBuilder::new("INSERT INTO conversions (event, user_id, value, experiment, properties, at) ")
+                    .push_values(chunk, |mut b, (event, at)| {
+                        b.push_bind(&event.event).push_bind(&event.user_id).push_bind(event.value).push_bind(&event.experiment).push_bind(event.properties.as_ref().map(|p| p.to_string())).push_bind(*at);
+                    })
+                    .build()
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await
+        }.await;
+        if let Err(e) = res {
+            tracing::warn!(error = %e, events = pending.len(), "failed to flush events, keeping them for the next flush");
+            let mut current = self.pending.lock().unwrap();
+            current.splice(0..0, pending);
+            // bound memory while the database is unavailable
+            let cap = self.max_buffer * 10;
+            if current.len() > cap {
+                let dropped = current.len() - cap;
+                current.drain(..dropped);
+                tracing::error!(dropped, "event buffer is full, dropping the oldest events");
+            }
+        }
+    }
+
+    pub fn spawn_flusher(self: Arc<Self>, db: Pool<Sqlite>, every: Duration) {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(every);
+            loop {
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    _ = self.full.notified() => tick.reset(),
+                }
+                self.flush(&db).await;
+            }
+        });
+    }
+}
+
+pub async fn track(State(state): State<AppState>, Extension(key): Extension<ApiKey>, Json(input): Json<TrackInput>) -> StatusCode {
+    let events = match input { TrackInput::One(e) => vec![e], TrackInput::Many(events) => events };
+    if events.is_empty() || events.len() > MAX_BATCH { return StatusCode::BAD_REQUEST; }
+    if events.iter().any(|e| e.event.is_empty() || e.event.len() > 100 || e.user_id.is_empty() || e.value.is_some_and(|v| !v.is_finite())) { return StatusCode::BAD_REQUEST; }
+    // a conversion without an experiment counts toward every experiment, so a scoped key has to name one it can see
+    if events.iter().any(|e| e.experiment.as_deref().map_or(!key.flag_prefixes.is_empty(), |x| !key.sees(x))) { return StatusCode::FORBIDDEN; }
+    let at = now();
+    // recorded under the same id exposures are, so conversions after login still join the pre-signup assignment
+    let mut resolved = Vec::with_capacity(events.len());
+    for mut event in events {
+        if let Some(id) = state.aliases.bucketing_id(&state.db, Some(&event.user_id), None).await { event.user_id = id; }
+        resolved.push(Pending::Conversion { event, at: at.clone() });
+    }
+    state.events.push(resolved);
+    StatusCode::ACCEPTED
+}
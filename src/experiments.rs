@@ -0,0 +1,502 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqliteConnection};
+use std::collections::HashMap;
+
+use feature_flags_core::{sqlite, FlagStore, UpdateFlag};
+
+use crate::{auth::{Principal, Role}, check_protected, config::SrmConfig, jobs::Job, metric_definitions::{self, Aggregation, Direction}, store_status, AppState, MutationParams};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Draft,
+    Running,
+    Paused,
+    Concluded,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self { Status::Draft => "draft", Status::Running => "running", Status::Paused => "paused", Status::Concluded => "concluded" }
+    }
+
+    fn parse(s: &str) -> Status {
+        match s { "running" => Status::Running, "paused" => Status::Paused, "concluded" => Status::Concluded, _ => Status::Draft }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Experiment {
+    key: String,
+    status: Status,
+    description: Option<String>,
+    metric: Option<String>,
+    winner: Option<String>,
+    created_at: String,
+    started_at: Option<String>,
+    paused_at: Option<String>,
+    concluded_at: Option<String>,
+    srm: Option<Srm>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Srm {
+    detected: bool,
+    p_value: f64,
+    checked_at: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SrmAlert<'a> {
+    key: &'a str,
+    p_value: f64,
+    expected: HashMap<String, f64>,
+    observed: HashMap<String, i64>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct CreateExperiment {
+    description: Option<String>,
+    metric: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct Conclude {
+    winner: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResultsParams {
+    metric: Option<String>,
+    event: Option<String>,
+    control: Option<String>,
+    confidence: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetricUsed {
+    name: Option<String>,
+    event: String,
+    aggregation: Aggregation,
+    direction: Direction,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VariantResult {
+    variant: String,
+    exposed: i64,
+    converted: i64,
+    conversion_rate: f64,
+    value_total: f64,
+    value_per_exposed: f64,
+    estimate: f64,
+    ci: (f64, f64),
+    lift: Option<f64>,
+    diff_ci: Option<(f64, f64)>,
+    p_value: Option<f64>,
+    significant: Option<bool>,
+    improved: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExperimentResults {
+    key: String,
+    metric: MetricUsed,
+    control: Option<String>,
+    confidence: f64,
+    variants: Vec<VariantResult>,
+}
+
+struct Counts {
+    variant: String,
+    exposed: i64,
+    converted: i64,
+    value: f64,
+    total: f64,
+    total_sq: f64,
+}
+
+impl Counts {
+    fn mean(&self) -> f64 { self.total / self.exposed as f64 }
+
+    fn variance(&self) -> f64 {
+        let n = self.exposed as f64;
+        if n < 2.0 { return 0.0; }
+        ((self.total_sq - n * self.mean().powi(2)) / (n - 1.0)).max(0.0)
+    }
+}
+
+pub async fn results(State(state): State<AppState>, Path(key): Path<String>, Query(params): Query<ResultsParams>) -> Result<Json<ExperimentResults>, StatusCode> {
+    let confidence = params.confidence.unwrap_or(0.95);
+    if !(0.5..1.0).contains(&confidence) { return Err(StatusCode::BAD_REQUEST); }
+    state.store.get(&key).await.map_err(store_status)?;
+    let metric = match (params.metric, params.event) {
+        (Some(name), _) => resolve_metric(&state, &name).await?,
+        (None, Some(event)) => MetricUsed { name: None, event, aggregation: Aggregation::Conversion, direction: Direction::Increase },
+        (None, None) => {
+            let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            let name = fetch_experiment(&mut conn, &key).await.ok().and_then(|e| e.metric).ok_or(StatusCode::BAD_REQUEST)?;
+            resolve_metric(&state, &name).await?
+        }
+    };
+    state.events.flush(&state.db).await;
+    let window = exposure_window(&state.db, &key).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // the first exposure decides a user's variant; only events after it count
+    let rows = sqlx::query(
+        "SELECT variant, COUNT(*) AS exposed, SUM(hit) AS converted, SUM(value) AS value, SUM(x) AS total, SUM(x * x) AS total_sq FROM (
+            SELECT f.variant, COUNT(c.id) > 0 AS hit, COALESCE(SUM(c.value), 0.0) AS value,
+                CASE ? WHEN 'count' THEN COUNT(c.id) WHEN 'sum' THEN COALESCE(SUM(c.value), 0.0) ELSE COUNT(c.id) > 0 END * 1.0 AS x
+            FROM (SELECT user_id, variant, at, MIN(id) FROM exposures WHERE flag_key = ? AND (? IS NULL OR at >= ?) AND (? IS NULL OR at <= ?) GROUP BY user_id) f
+            LEFT JOIN conversions c ON c.user_id = f.user_id AND c.event = ? AND c.at >= f.at AND (c.experiment IS NULL OR c.experiment = ?)
+            GROUP BY f.user_id
+         ) GROUP BY variant ORDER BY variant")
+        .bind(metric.aggregation.as_str())
+        .bind(&key)
+        .bind(&window.0)
+        .bind(&window.0)
+        .bind(&window.1)
+        .bind(&window.1)
+        .bind(&metric.event)
+        .bind(&key)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let counts: Vec<Counts> = rows.iter().map(|r| Counts { variant: r.get("variant"), exposed: r.get("exposed"), converted: r.get("converted"), value: r.get("value"), total: r.get("total"), total_sq: r.get("total_sq") }).collect();
+    let control = params.control
+        .or_else(|| counts.iter().find(|c| c.variant == "control").map(|c| c.variant.clone()))
+        .or_else(|| counts.first().map(|c| c.variant.clone()));
+    let baseline = counts.iter().find(|c| Some(&c.variant) == control.as_ref());
+    let z = z_for(confidence);
+    let proportion = metric.aggregation == Aggregation::Conversion;
+    let variants = counts.iter().map(|c| {
+        let n = c.exposed as f64;
+        let estimate = c.mean();
+        let se = (c.variance() / n).sqrt();
+        let compare = baseline.filter(|b| Some(&c.variant) != control.as_ref() && b.exposed > 0).map(|b| {
+            if proportion { compare_proportions(n, c.total, b.exposed as f64, b.total, z) } else { compare_means(c, b, z) }
+        });
+        let ci = (estimate - z * se, estimate + z * se);
+        VariantResult {
+            variant: c.variant.clone(),
+            exposed: c.exposed,
+            converted: c.converted,
+            conversion_rate: c.converted as f64 / n,
+            value_total: c.value,
+            value_per_exposed: c.value / n,
+            estimate,
+            ci: if proportion { (ci.0.max(0.0), ci.1.min(1.0)) } else { ci },
+            lift: compare.and_then(|c| c.lift),
+            diff_ci: compare.map(|c| c.diff_ci),
+            p_value: compare.map(|c| c.p_value),
+            significant: compare.map(|c| c.p_value < 1.0 - confidence),
+            improved: baseline.filter(|_| compare.is_some()).map(|b| match metric.direction { Direction::Increase => estimate > b.mean(), Direction::Decrease => estimate < b.mean() }),
+        }
+    }).collect();
+    Ok(Json(ExperimentResults { key, metric, control, confidence, variants }))
+}
+
+async fn resolve_metric(state: &AppState, name: &str) -> Result<MetricUsed, StatusCode> {
+    let m = metric_definitions::fetch_metric(&state.db, name).await?;
+    Ok(MetricUsed { name: Some(m.name), event: m.event, aggregation: m.aggregation, direction: m.direction })
+}
+
+#[derive(Clone, Copy)]
+struct Comparison {
+    lift: Option<f64>,
+    diff_ci: (f64, f64),
+    p_value: f64,
+}
+
+fn compare_means(c: &Counts, b: &Counts, z: f64) -> Comparison {
+    let diff = c.mean() - b.mean();
+    let se = (c.variance() / c.exposed as f64 + b.variance() / b.exposed as f64).sqrt();
+    let p_value = if se > 0.0 { 2.0 * (1.0 - normal_cdf((diff / se).abs())) } else { 1.0 };
+    Comparison { lift: (b.mean() != 0.0).then(|| diff / b.mean()), diff_ci: (diff - z * se, diff + z * se), p_value }
+}
+
+fn compare_proportions(n: f64, x: f64, nc: f64, xc: f64, z: f64) -> Comparison {
+    let (p, pc) = (x / n, xc / nc);
+    let diff = p - pc;
+    let se_diff = (p * (1.0 - p) / n + pc * (1.0 - pc) / nc).sqrt();
+    let pooled = (x + xc) / (n + nc);
+    let se_pooled = (pooled * (1.0 - pooled) * (1.0 / n + 1.0 / nc)).sqrt();
+    let p_value = if se_pooled > 0.0 { 2.0 * (1.0 - normal_cdf((diff / se_pooled).abs())) } else { 1.0 };
+    Comparison { lift: (pc > 0.0).then(|| diff / pc), diff_ci: (diff - z * se_diff, diff + z * se_diff), p_value }
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+// Abramowitz & Stegun 7.1.26, accurate to about 1.5e-7
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let y = 1.0 - t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429)))) * (-x * x).exp();
+    y.copysign(x)
+}
+
+fn chi_square_sf(x: f64, df: f64) -> f64 {
+    if x <= 0.0 { return 1.0; }
+    upper_gamma_q(df / 2.0, x / 2.0)
+}
+
+// regularized upper incomplete gamma Q(a, x): series below a + 1, continued fraction above
+fn upper_gamma_q(a: f64, x: f64) -> f64 {
+    let ln_prefix = -x + a * x.ln() - ln_gamma(a);
+    if x < a + 1.0 {
+        let (mut sum, mut term, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..500 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 { break; }
+        }
+        return (1.0 - sum * ln_prefix.exp()).clamp(0.0, 1.0);
+    }
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..500 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny { d = tiny; }
+        c = b + an / c;
+        if c.abs() < tiny { c = tiny; }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-15 { break; }
+    }
+    (ln_prefix.exp() * h).clamp(0.0, 1.0)
+}
+
+// Lanczos approximation, g = 7
+fn ln_gamma(x: f64) -> f64 {
+    const C: [f64; 9] = [0.999_999_999_999_809_9, 676.5203681218851, -1259.1392167224028, 771.323_428_777_653_1, -176.615_029_162_140_6, 12.507343278686905, -0.13857109526572012, 9.984_369_578_019_572e-6, 1.5056327351493116e-7];
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let series = C.iter().enumerate().skip(1).fold(C[0], |acc, (i, c)| acc + c / (x + i as f64));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+fn z_for(confidence: f64) -> f64 {
+    let target = 1.0 - (1.0 - confidence) / 2.0;
+    let (mut lo, mut hi) = (0.0, 10.0);
+    for _ in 0..60 {
+        let mid = (lo + hi) / 2.0;
+        if normal_cdf(mid) < target { lo = mid } else { hi = mid }
+    }
+    (lo + hi) / 2.0
+}
+
+async fn exposure_window(db: &sqlx::Pool<sqlx::Sqlite>, key: &str) -> Result<(Option<String>, Option<String>), sqlx::Error> {
+    Ok(sqlx::query("SELECT started_at, concluded_at FROM experiments WHERE flag_key = ?")
+        .bind(key)
+        .fetch_optional(db)
+        .await?
+        .map(|r| (r.get("started_at"), r.get("concluded_at")))
+        .unwrap_or_default())
+}
+
+pub fn job(config: &SrmConfig) -> Job {
+    let every = config.check_interval_secs.filter(|s| *s > 0).unwrap_or(300);
+    let threshold = config.p_threshold.unwrap_or(0.001);
+    Job::new("srm-check", std::time::Duration::from_secs(every), move |state| async move { check_srm(&state, threshold).await })
+}
+
+async fn check_srm(state: &AppState, threshold: f64) -> anyhow::Result<()> {
+    state.events.flush(&state.db).await;
+    let running: Vec<(String, bool)> = sqlx::query("SELECT flag_key, srm_detected FROM experiments WHERE status = 'running'")
+        .fetch_all(&state.db)
+        .await?
+        .into_iter()
+        .map(|r| (r.get("flag_key"), r.get::<Option<i64>,_>("srm_detected").unwrap_or(0) != 0))
+        .collect();
+    for (key, already_detected) in running {
+        let Ok(flag) = state.store.get(&key).await else { continue };
+        let Some(weights) = flag.variants.filter(|v| v.len() >= 2) else { continue };
+        let window = exposure_window(&state.db, &key).await?;
+        let observed: HashMap<String, i64> = sqlx::query("SELECT variant, COUNT(*) AS exposed FROM (SELECT user_id, variant, at, MIN(id) FROM exposures WHERE flag_key = ? AND (? IS NULL OR at >= ?) GROUP BY user_id) GROUP BY variant")
+            .bind(&key)
+            .bind(&window.0)
+            .bind(&window.0)
+            .fetch_all(&state.db)
+            .await?
+            .into_iter()
+            .map(|r| (r.get("variant"), r.get("exposed")))
+            .collect();
+        let total: i64 = observed.iter().filter(|(v, _)| weights.contains_key(*v)).map(|(_, n)| n).sum();
+        if total < 100 { continue; }
+        let weight_sum: u32 = weights.values().sum();
+        let expected: HashMap<String, f64> = weights.iter().map(|(v, w)| (v.clone(), total as f64 * *w as f64 / weight_sum as f64)).collect();
+        let chi2: f64 = expected.iter().filter(|(_, e)| **e > 0.0).map(|(v, e)| { let o = *observed.get(v).unwrap_or(&0) as f64; (o - e).powi(2) / e }).sum();
+        let p_value = chi_square_sf(chi2, (expected.len() - 1) as f64);
+        let detected = p_value < threshold;
+        sqlx::query("UPDATE experiments SET srm_detected = ?, srm_p_value = ?, srm_checked_at = datetime('now') WHERE flag_key = ?")
+            .bind(detected as i64)
+            .bind(p_value)
+            .bind(&key)
+            .execute(&state.db)
+            .await?;
+        if detected && !already_detected {
+            tracing::warn!(key, p_value, "sample ratio mismatch detected");
+            state.webhooks.notify("experiment.srm_detected", SrmAlert { key: &key, p_value, expected, observed });
+        }
+    }
+    Ok(())
+}
+
+pub async fn list_experiments(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Result<Json<Vec<Experiment>>, StatusCode> {
+    let rows = sqlx::query("SELECT e.*, f.project FROM experiments e JOIN flags f ON f.key = e.flag_key ORDER BY e.flag_key")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(rows.into_iter().filter(|r| principal.has_role(&r.get::<String,_>("project"), Role::Viewer)).map(row_to_experiment).collect()))
+}
+
+pub async fn get_experiment(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<Experiment>, StatusCode> {
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(fetch_experiment(&mut conn, &key).await?))
+}
+
+pub async fn create_experiment(State(state): State<AppState>, Path(key): Path<String>, input: Option<Json<CreateExperiment>>) -> Result<Json<Experiment>, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    if flag.variants.as_ref().is_none_or(|v| v.len() < 2) { return Err(StatusCode::UNPROCESSABLE_ENTITY); }
+    let input = input.map(|Json(i)| i).unwrap_or_default();
+    if let Some(metric) = &input.metric { metric_definitions::fetch_metric(&state.db, metric).await.map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?; }
+    sqlx::query("INSERT INTO experiments (flag_key, status, description, metric, created_at) VALUES (?, 'draft', ?, ?, datetime('now'))")
+        .bind(&key)
+        .bind(&input.description)
+        .bind(&input.metric)
+        .execute(&state.db)
+        .await
+        .map_err(|e| match e { sqlx::Error::Database(d) if d.is_unique_violation() => StatusCode::CONFLICT, _ => StatusCode::INTERNAL_SERVER_ERROR })?;
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(fetch_experiment(&mut conn, &key).await?))
+}
+
+pub async fn start_experiment(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<Experiment>, StatusCode> {
+    transition(&state, &key, &[Status::Draft, Status::Paused], Status::Running, "started_at = COALESCE(started_at, datetime('now')), paused_at = NULL").await
+}
+
+pub async fn pause_experiment(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<Experiment>, StatusCode> {
+    transition(&state, &key, &[Status::Running], Status::Paused, "paused_at = datetime('now')").await
+}
+
+pub async fn conclude_experiment(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(key): Path<String>, Query(params): Query<MutationParams>, input: Option<Json<Conclude>>) -> Result<Json<Experiment>, StatusCode> {
+    let winner = input.and_then(|Json(i)| i.winner);
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let experiment = fetch_experiment(&mut tx, &key).await?;
+    if !matches!(experiment.status, Status::Running | Status::Paused) { return Err(StatusCode::CONFLICT); }
+    set_status(&mut tx, &key, Status::Concluded, "concluded_at = datetime('now')").await?;
+    sqlx::query("UPDATE experiments SET winner = ? WHERE flag_key = ?")
+        .bind(&winner)
+        .bind(&key)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(winner) = &winner {
+        if crate::break_glass::approval_required(&state, &principal) { return Err(StatusCode::FORBIDDEN); }
+        let flag = sqlite::fetch_flag(&mut tx, &key).await.map_err(store_status)?;
+        if !flag.variants.as_ref().is_some_and(|v| v.contains_key(winner)) { return Err(StatusCode::UNPROCESSABLE_ENTITY); }
+        check_protected(&flag, &principal, params.confirm.as_deref())?;
+        let rollout = UpdateFlag { enabled: Some(true), variants: Some(HashMap::from([(winner.clone(), 1)])), rollout: Some(100), ..Default::default() };
+        sqlite::apply_update(&mut tx, &key, &rollout).await.map_err(store_status)?;
+    }
+    let experiment = fetch_experiment(&mut tx, &key).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if winner.is_some() { state.flag_changes.publish(&key, "updated"); }
+    Ok(Json(experiment))
+}
+
+pub async fn check_unlocked(conn: &mut SqliteConnection, key: &str, update: Option<&UpdateFlag>) -> Result<(), StatusCode> {
+    if update.is_some_and(|u| u.variants.is_none()) { return Ok(()); }
+    let running = sqlx::query("SELECT 1 FROM experiments WHERE flag_key = ? AND status = 'running'")
+        .bind(key)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if running.is_some() { return Err(StatusCode::CONFLICT); }
+    Ok(())
+}
+
+async fn transition(state: &AppState, key: &str, from: &[Status], to: Status, set: &str) -> Result<Json<Experiment>, StatusCode> {
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let experiment = fetch_experiment(&mut tx, key).await?;
+    if !from.contains(&experiment.status) { return Err(StatusCode::CONFLICT); }
+    set_status(&mut tx, key, to, set).await?;
+    let experiment = fetch_experiment(&mut tx, key).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(experiment))
+}
+
+async fn set_status(conn: &mut SqliteConnection, key: &str, status: Status, set: &str) -> Result<(), StatusCode> {
+    sqlx::query(&format!("UPDATE experiments SET status = ?, {set} WHERE flag_key = ?"))
+        .bind(status.as_str())
+        .bind(key)
+        .execute(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(())
+}
+
+async fn fetch_experiment(conn: &mut SqliteConnection, key: &str) -> Result<Experiment, StatusCode> {
+    sqlx::query("SELECT * FROM experiments WHERE flag_key = ?")
+        .bind(key)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(row_to_experiment)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn row_to_experiment(r: sqlx::sqlite::SqliteRow) -> Experiment {
+    Experiment {
+        key: r.get("flag_key"),
+        status: Status::parse(&r.get::<String,_>("status")),
+        description: r.get("description"),
+        metric: r.get("metric"),
+        winner: r.get("winner"),
+        created_at: r.get("created_at"),
+        started_at: r.get("started_at"),
+        paused_at: r.get("paused_at"),
+        concluded_at: r.get("concluded_at"),
+        srm: r.get::<Option<String>,_>("srm_checked_at").map(|checked_at| Srm { detected: r.get::<i64,_>("srm_detected") != 0, p_value: r.get("srm_p_value"), checked_at }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(actual: f64, expected: f64, tolerance: f64) -> bool { (actual - expected).abs() < tolerance }
+
+    #[test]
+    fn erf_matches_reference_values() {
+        for (x, expected) in [(0.0, 0.0), (0.5, 0.520_499_877_8), (1.0, 0.842_700_792_9), (2.0, 0.995_322_265_0), (-1.0, -0.842_700_792_9), (4.0, 0.999_999_984_6)] {
+            assert!(close(erf(x), expected, 2e-7), "erf({x}) = {}, expected {expected}", erf(x));
+        }
+    }
+
+    #[test]
+    fn chi_square_sf_matches_critical_values() {
+        // both branches of the incomplete gamma: the continued fraction for x >= df / 2 + 1, the series below
+        for (x, df, expected) in [(3.841_458_821, 1.0, 0.05), (5.991_464_547, 2.0, 0.05), (7.814_727_903, 3.0, 0.05), (10.827_566_17, 1.0, 0.001), (2.0, 10.0, 0.996_340_153), (9.341_817_766, 10.0, 0.5)] {
+            let p = chi_square_sf(x, df);
+            assert!(close(p, expected, 1e-6), "chi_square_sf({x}, {df}) = {p}, expected {expected}");
+        }
+        assert_eq!(chi_square_sf(0.0, 4.0), 1.0);
+        assert!(chi_square_sf(1e4, 1.0) < 1e-12);
+    }
+
+    #[test]
+    fn z_for_matches_the_normal_quantiles() {
+        for (confidence, expected) in [(0.90, 1.644_853_627), (0.95, 1.959_963_985), (0.99, 2.575_829_304)] {
+            assert!(close(z_for(confidence), expected, 1e-5), "z_for({confidence}) = {}, expected {expected}", z_for(confidence));
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use arrow_array::{builder::{Float64Builder, Int64Builder, StringBuilder}, ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use axum::{body::Body, extract::{Path, Query, State}, http::{header, StatusCode}, response::{IntoResponse, Response}, Extension};
+use futures_util::TryStreamExt;
+use hmac::{Hmac, Mac};
+use parquet::arrow::ArrowWriter;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{sqlite::SqliteRow, Pool, Row, Sqlite};
+use std::{io::Write, sync::{Arc, Mutex}, time::Duration};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{auth::{Principal, Role}, config::ExportsConfig, jobs::Job, AppState};
+
+const BATCH_ROWS: usize = 5_000;
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Int,
+    Real,
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dataset {
+    Exposures,
+    Conversions,
+    Comments,
+}
+
+impl Dataset {
+    fn parse(s: &str) -> Option<Dataset> {
+        match s { "exposures" => Some(Dataset::Exposures), "conversions" => Some(Dataset::Conversions), "comments" => Some(Dataset::Comments), _ => None }
+    }
+
+    fn name(self) -> &'static str {
+        match self { Dataset::Exposures => "exposures", Dataset::Conversions => "conversions", Dataset::Comments => "comments" }
+    }
+
+    fn columns(self) -> &'static [(&'static str, Kind)] {
+        match self {
+            Dataset::Exposures => &[("id", Kind::Int), ("flag_key", Kind::Text), ("user_id", Kind::Text), ("variant", Kind::Text), ("at", Kind::Text)],
+            Dataset::Conversions => &[("id", Kind::Int), ("event", Kind::Text), ("user_id", Kind::Text), ("value", Kind::Real), ("experiment", Kind::Text), ("properties", Kind::Text), ("at", Kind::Text)],
+            Dataset::Comments => &[("id", Kind::Int), ("flag_key", Kind::Text), ("rev", Kind::Int), ("author", Kind::Text), ("body", Kind::Text), ("reply_to", Kind::Int), ("created_at", Kind::Text)],
+        }
+    }
+
+    fn flag_column(self) -> &'static str {
+        match self { Dataset::Exposures | Dataset::Comments => "flag_key", Dataset::Conversions => "experiment" }
+    }
+
+    fn table(self) -> &'static str {
+        match self { Dataset::Comments => "flag_comments", _ => self.name() }
+    }
+
+    fn time_column(self) -> &'static str {
+        match self { Dataset::Comments => "created_at", _ => "at" }
+    }
+
+    fn query(self) -> String {
+        let cols: Vec<&str> = self.columns().iter().map(|c| c.0).collect();
+        let at = self.time_column();
+        format!("SELECT {} FROM {} WHERE (? IS NULL OR {at} >= ?) AND (? IS NULL OR {at} < ?) AND (? IS NULL OR {} = ?) ORDER BY id", cols.join(", "), self.table(), self.flag_column())
+    }
+
+    fn schema(self) -> Arc<Schema> {
+        Arc::new(Schema::new(self.columns().iter().map(|(name, kind)| {
+            let ty = match kind { Kind::Int => DataType::Int64, Kind::Real => DataType::Float64, Kind::Text => DataType::Utf8 };
+            Field::new(*name, ty, *name != "id")
+        }).collect::<Vec<_>>()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Csv,
+    Parquet,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self { Format::Csv => "csv", Format::Parquet => "parquet" }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self { Format::Csv => "text/csv", Format::Parquet => "application/vnd.apache.parquet" }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    from: Option<String>,
+    to: Option<String>,
+    flag: Option<String>,
+    format: Option<Format>,
+}
+
+#[derive(Clone, Default)]
+struct Chunks(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Chunks {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.0.lock().unwrap().extend_from_slice(buf); Ok(buf.len()) }
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+impl Chunks {
+    fn take(&self) -> Vec<u8> { std::mem::take(&mut *self.0.lock().unwrap()) }
+}
+
+enum Encoder {
+    Csv { dataset: Dataset, header: bool },
+    Parquet { dataset: Dataset, writer: Box<ArrowWriter<Chunks>>, out: Chunks },
+}
+
+impl Encoder {
+    fn new(dataset: Dataset, format: Format) -> anyhow::Result<Encoder> {
+        Ok(match format {
+            Format::Csv => Encoder::Csv { dataset, header: false },
+            Format::Parquet => {
+                let out = Chunks::default();
+                let props = parquet::file::properties::WriterProperties::builder().set_compression(parquet::basic::Compression::SNAPPY).build();
+                Encoder::Parquet { dataset, writer: Box::new(ArrowWriter::try_new(out.clone(), dataset.schema(), Some(props))?), out }
+            }
+        })
+    }
+
+    fn encode(&mut self, rows: &[SqliteRow]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Encoder::Csv { dataset, header } => {
+                let mut out = Vec::new();
+                if !*header {
+                    *header = true;
+                    writeln!(out, "{}", dataset.columns().iter().map(|c| c.0).collect::<Vec<_>>().join(","))?;
+                }
+                for r in rows {
+                    let fields: Vec<String> = dataset.columns().iter().enumerate().map(|(i, (_, kind))| match kind {
+                        Kind::Int => r.get::<Option<i64>,_>(i).map(|v| v.to_string()).unwrap_or_default(),
+                        Kind::Real => r.get::<Option<f64>,_>(i).map(|v| v.to_string()).unwrap_or_default(),
+                        Kind::Text => csv_field(r.get::<Option<String>,_>(i).as_deref().unwrap_or_default()),
+                    }).collect();
+                    writeln!(out, "{}", fields.join(","))?;
+                }
+                Ok(out)
+            }
+            Encoder::Parquet { dataset, writer, out } => {
+                let arrays: Vec<ArrayRef> = dataset.columns().iter().enumerate().map(|(i, (_, kind))| -> ArrayRef {
+                    match kind {
+                        Kind::Int => { let mut b = Int64Builder::new(); for r in rows { b.append_option(r.get::<Option<i64>,_>(i)); } Arc::new(b.finish()) }
+                        Kind::Real => { let mut b = Float64Builder::new(); for r in rows { b.append_option(r.get::<Option<f64>,_>(i)); } Arc::new(b.finish()) }
+                        Kind::Text => { let mut b = StringBuilder::new(); for r in rows { b.append_option(r.get::<Option<String>,_>(i)); } Arc::new(b.finish()) }
+                    }
+                }).collect();
+                writer.write(&RecordBatch::try_new(dataset.schema(), arrays)?)?;
+                writer.flush()?;
+                Ok(out.take())
+            }
+        }
+    }
+
+    fn finish(self) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Encoder::Csv { dataset, header } => if header { Ok(Vec::new()) } else { Ok(format!("{}\n", dataset.columns().iter().map(|c| c.0).collect::<Vec<_>>().join(",")).into_bytes()) },
+            Encoder::Parquet { writer, out, .. } => { writer.close()?; Ok(out.take()) }
+        }
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_string() }
+}
+
+pub fn normalize_time(s: &str) -> Option<String> {
+    if let Ok(t) = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") { return Some(t.format("%Y-%m-%d %H:%M:%S").to_string()); }
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(s) { return Some(t.with_timezone(&chrono::Utc).format("%Y-%m-%d %H:%M:%S").to_string()); }
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().map(|d| d.format("%Y-%m-%d 00:00:00").to_string())
+}
+
+async fn export(db: Pool<Sqlite>, dataset: Dataset, format: Format, from: Option<String>, to: Option<String>, flag: Option<String>, tx: mpsc::Sender<Result<Vec<u8>, std::io::Error>>) -> anyhow::Result<()> {
+    let sql = dataset.query();
+    let mut rows = sqlx::query(&sql).bind(&from).bind(&from).bind(&to).bind(&to).bind(&flag).bind(&flag).fetch(&db);
+    let mut encoder = Encoder::new(dataset, format)?;
+    let mut batch = Vec::with_capacity(BATCH_ROWS);
+    loop {
+        let row = rows.try_next().await?;
+        let done = row.is_none();
+        batch.extend(row);
+        if batch.len() >= BATCH_ROWS || (done && !batch.is_empty()) {
+            let chunk = encoder.encode(&batch)?;
+            batch.clear();
+            if tx.send(Ok(chunk)).await.is_err() { return Ok(()); }
+        }
+        if done { break; }
+    }
+    let _ = tx.send(Ok(encoder.finish()?)).await;
+    Ok(())
+}
+
+pub async fn export_dataset(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(dataset): Path<String>, Query(params): Query<ExportParams>) -> Result<Response, StatusCode> {
+    principal.require("*", Role::Viewer)?;
+    let dataset = Dataset::parse(&dataset).ok_or(StatusCode::NOT_FOUND)?;
+    let format = params.format.unwrap_or(Format::Csv);
+    let from = params.from.map(|f| normalize_time(&f).ok_or(StatusCode::BAD_REQUEST)).transpose()?;
+    let to = params.to.map(|t| normalize_time(&t).ok_or(StatusCode::BAD_REQUEST)).transpose()?;
+    state.events.flush(&state.db).await;
+    let (tx, rx) = mpsc::channel(4);
+    let db = state.db.clone();
+    tokio::spawn(async move {
+        let errors = tx.clone();
+        if let Err(e) = export(db, dataset, format, from, to, params.flag, tx).await {
+            tracing::error!(error = %e, dataset = dataset.name(), "export failed");
+            let _ = errors.send(Err(std::io::Error::other(e.to_string()))).await;
+        }
+    });
+    let filename = format!("{}.{}", dataset.name(), format.extension());
+    Ok((
+        [(header::CONTENT_TYPE, format.content_type().to_string()), (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\""))],
+        Body::from_stream(ReceiverStream::new(rx)),
+    ).into_response())
+}
+
+pub struct S3 {
+    http: reqwest::Client,
+    bucket: String,
+    prefix: String,
+    region: String,
+    endpoint: Option<String>,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3 {
+    // credentials come from the usual AWS_* variables rather than the config file
+    pub fn from_config(config: &ExportsConfig) -> anyhow::Result<Option<S3>> {
+        let Some(bucket) = config.s3_bucket.clone() else { return Ok(None) };
+        let required = |name: &str| std::env::var(name).map_err(|_| anyhow::anyhow!("{name} is required when EXPORT_S3_BUCKET is set"));
+        Ok(Some(S3 {
+            http: reqwest::Client::builder().timeout(Duration::from_secs(300)).build()?,
+            bucket,
+            prefix: config.s3_prefix.clone().unwrap_or_default(),
+            region: config.s3_region.clone().or_else(|| std::env::var("AWS_REGION").ok()).unwrap_or_else(|| "us-east-1".into()),
+            endpoint: config.s3_endpoint.as_deref().map(|e| e.trim_end_matches('/').to_string()),
+            access_key: required("AWS_ACCESS_KEY_ID")?,
+            secret_key: required("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        }))
+    }
+
+    // AWS Signature Version 4, single-chunk payload
+    async fn put(&self, key: &str, body: Vec<u8>, content_type: &str) -> anyhow::Result<()> {
+        let path: String = key.split('/').map(uri_encode).collect::<Vec<_>>().join("/");
+        let url = match &self.endpoint {
+            Some(endpoint) => format!("{endpoint}/{}/{path}", self.bucket),
+            None => format!("https://{}.s3.{}.amazonaws.com/{path}", self.bucket, self.region),
+        };
+        let parsed = reqwest::Url::parse(&url)?;
+        let host = match parsed.port() { Some(p) => format!("{}:{p}", parsed.host_str().unwrap_or_default()), None => parsed.host_str().unwrap_or_default().to_string() };
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex(&Sha256::digest(&body));
+        let mut headers = vec![("host", host), ("x-amz-content-sha256", payload_hash.clone()), ("x-amz-date", amz_date.clone())];
+        if let Some(token) = &self.session_token { headers.push(("x-amz-security-token", token.clone())); }
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{k}:{}\n", v.trim())).collect();
+        let signed_headers = headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("PUT\n{}\n\n{canonical_headers}\n{signed_headers}\n{payload_hash}", parsed.path());
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}", hex(&Sha256::digest(canonical_request.as_bytes())));
+        let mut signing_key = hmac(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] { signing_key = hmac(&signing_key, part.as_bytes()); }
+        let signature = hex(&hmac(&signing_key, string_to_sign.as_bytes()));
+        let mut req = self.http.put(parsed)
+            .header("authorization", format!("AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}", self.access_key))
+            .header("content-type", content_type)
+            .body(body);
+        for (k, v) in headers.into_iter().skip(1) { req = req.header(k, v); }
+        let res = req.send().await?;
+        if !res.status().is_success() { anyhow::bail!("s3 put {key} returned {}: {}", res.status(), res.text().await.unwrap_or_default()); }
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn uri_encode(segment: &str) -> String {
+    segment.bytes().map(|b| if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) { (b as char).to_string() } else { format!("%{b:02X}") }).collect()
+}
+
+// a dataset picks up from where its last successful export ended, so retrying after a failure only redoes what's missing
+pub fn job(config: &ExportsConfig, s3: S3) -> Job {
+    let every = config.interval_secs.filter(|s| *s > 0).unwrap_or(86_400);
+    let format = match config.format.as_deref() { Some("csv") => Format::Csv, _ => Format::Parquet };
+    tracing::info!(bucket = %s3.bucket, every_secs = every, "scheduled exports to s3 enabled");
+    let s3 = Arc::new(s3);
+    Job::new("s3-export", Duration::from_secs(every), move |state| {
+        let s3 = s3.clone();
+        async move {
+            state.events.flush(&state.db).await;
+            let mut failed = Vec::new();
+            for dataset in [Dataset::Exposures, Dataset::Conversions, Dataset::Comments] {
+                if let Err(e) = export_to_s3(&s3, &state.db, dataset, format).await { failed.push(format!("{}: {e:#}", dataset.name())); }
+            }
+            if !failed.is_empty() { anyhow::bail!("{}", failed.join("; ")); }
+            Ok(())
+        }
+    }).timeout(Duration::from_secs(3600))
+}
+
+async fn export_to_s3(s3: &S3, db: &Pool<Sqlite>, dataset: Dataset, format: Format) -> anyhow::Result<()> {
+    let from: Option<String> = sqlx::query("SELECT until FROM export_runs WHERE dataset = ?").bind(dataset.name()).fetch_optional(db).await?.map(|r| r.get("until"));
+    let until = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let (tx, mut rx) = mpsc::channel(4);
+    let exporting = export(db.clone(), dataset, format, from.clone(), Some(until.clone()), None, tx);
+    let collecting = async { let mut body = Vec::new(); while let Some(chunk) = rx.recv().await { body.extend(chunk?); } Ok::<_, std::io::Error>(body) };
+    let (exported, body) = tokio::join!(exporting, collecting);
+    exported?;
+    let body = body?;
+    let key = format!("{}{}/{}.{}", s3.prefix, dataset.name(), until.replace([' ', ':'], "-"), format.extension());
+    let size = body.len();
+    s3.put(&key, body, format.content_type()).await?;
+    sqlx::query("INSERT INTO export_runs (dataset, until) VALUES (?, ?) ON CONFLICT (dataset) DO UPDATE SET until = excluded.until")
+        .bind(dataset.name())
+        .bind(&until)
+        .execute(db)
+        .await?;
+    tracing::info!(dataset = dataset.name(), key, bytes = size, "exported to s3");
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use feature_flags_core::{EvalResponse, FlagStore};
+use serde::Serialize;
+use sqlx::{Pool, QueryBuilder, Row, Sqlite};
+use std::{collections::{BTreeMap, HashMap}, sync::{Arc, Mutex}, time::Duration};
+
+use crate::{events::INSERT_ROWS, store_status, AppState};
+
+const RETENTION_HOURS: i64 = 30 * 24;
+const WINDOWS: [(&str, i64); 4] = [("1h", 1), ("24h", 24), ("7d", 7 * 24), ("30d", 30 * 24)];
+
+type Bucket = (String, String, String);
+
+pub struct FlagStats {
+    pending: Mutex<HashMap<Bucket, (i64, i64)>>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct Window {
+    evaluations: i64,
+    matched: i64,
+    match_rate: Option<f64>,
+    variants: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsReport {
+    key: String,
+    last_evaluated_hour: Option<String>,
+    windows: BTreeMap<&'static str, Window>,
+}
+
+fn hour(at: chrono::DateTime<chrono::Utc>) -> String { at.format("%Y-%m-%d %H:00:00").to_string() }
+
+impl FlagStats {
+    pub fn new() -> FlagStats {
+        FlagStats { pending: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn record(&self, results: &[EvalResponse]) {
+        let hour = hour(chrono::Utc::now());
+        let mut pending = self.pending.lock().unwrap();
+        for res in results {
+            let entry = pending.entry((res.key.clone(), hour.clone(), res.variant.clone().unwrap_or_default())).or_default();
+            entry.0 += 1;
+            if res.matched { entry.1 += 1; }
+        }
+    }
+
+    // evaluations per flag counted since `since` (an hour as stored) that haven't been flushed yet
+    pub fn unflushed(&self, since: &str) -> HashMap<String, i64> {
+        let mut out = HashMap::new();
+        for ((key, hour, _), (evaluations, _)) in self.pending.lock().unwrap().iter() {
+            if hour.as_str() >= since { *out.entry(key.clone()).or_default() += evaluations; }
+        }
+        out
+    }
+
+    pub async fn flush(&self, db: &Pool<Sqlite>) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() { return; }
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = db.begin().await?;
+            let rows: Vec<_> = pending.iter().collect();
+            for chunk in rows.chunks(INSERT_ROWS) {
+                QueryBuilder::new("INSERT INTO flag_eval_stats (flag_key, hour, variant, evaluations, matched) ")
+                    .push_values(chunk, |mut b, ((flag_key, hour, variant), (evaluations, matched))| { b.push_bind(flag_key).push_bind(hour).push_bind(variant).push_bind(evaluations).push_bind(matched); })
+                    .push(" ON CONFLICT (flag_key, hour, variant) DO UPDATE SET evaluations = evaluations + excluded.evaluations, matched = matched + excluded.matched")
+                    .build()
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query("DELETE FROM flag_eval_stats WHERE hour < ?")
+                .bind(hour(chrono::Utc::now() - chrono::Duration::hours(RETENTION_HOURS)))
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await
+        }.await;
+        if let Err(e) = res {
+            tracing::warn!(error = %e, "failed to flush evaluation stats, keeping counts for the next flush");
+            let mut current = self.pending.lock().unwrap();
+            for (k, (evaluations, matched)) in pending {
+                let entry = current.entry(k).or_default();
+                entry.0 += evaluations;
+                entry.1 += matched;
+            }
+        }
+    }
+
+    pub fn spawn_flusher(self: Arc<Self>, db: Pool<Sqlite>, every: Duration) {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(every);
+            loop {
+                tick.tick().await;
+                self.flush(&db).await;
+            }
+        });
+    }
+}
+
+pub async fn flag_stats(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<StatsReport>, StatusCode> {
+    state.store.get(&key).await.map_err(store_status)?;
+    state.flag_stats.flush(&state.db).await;
+    let now = chrono::Utc::now();
+    let rows = sqlx::query("SELECT hour, variant, evaluations, matched FROM flag_eval_stats WHERE flag_key = ? AND hour >= ?")
+        .bind(&key)
+        .bind(hour(now - chrono::Duration::hours(RETENTION_HOURS - 1)))
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut windows: BTreeMap<&'static str, Window> = WINDOWS.iter().map(|(name, _)| (*name, Window::default())).collect();
+    let mut last_evaluated_hour: Option<String> = None;
+    for r in rows {
+        let at: String = r.get("hour");
+        let variant: String = r.get("variant");
+        let (evaluations, matched): (i64, i64) = (r.get("evaluations"), r.get("matched"));
+        for (name, hours) in WINDOWS {
+            if at < hour(now - chrono::Duration::hours(hours - 1)) { continue; }
+            let w = windows.get_mut(name).unwrap();
+            w.evaluations += evaluations;
+            w.matched += matched;
+            if !variant.is_empty() { *w.variants.entry(variant.clone()).or_default() += evaluations; }
+        }
+        if last_evaluated_hour.as_ref().is_none_or(|l| *l < at) { last_evaluated_hour = Some(at); }
+    }
+    for w in windows.values_mut() {
+        w.match_rate = (w.evaluations > 0).then(|| w.matched as f64 / w.evaluations as f64);
+    }
+    Ok(Json(StatsReport { key, last_evaluated_hour, windows }))
+}
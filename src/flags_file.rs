@@ -0,0 +1,172 @@
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use notify::{RecursiveMode, Watcher};
+use std::{collections::HashSet, path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+
+use feature_flags_core::{sqlite, CreateFlag, Flag, StoreError};
+
+use crate::{config::FlagsFileConfig, stream::Changes};
+
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    CreateMissing,
+    Enforce,
+}
+
+#[derive(Debug, Deserialize)]
+struct FlagFile {
+    flags: Vec<CreateFlag>,
+}
+
+pub struct FlagsFile {
+    pub path: PathBuf,
+    pub mode: Mode,
+    pub prune: bool,
+}
+
+impl FlagsFile {
+    pub fn from_config(config: &FlagsFileConfig) -> anyhow::Result<Option<FlagsFile>> {
+        let Some(path) = &config.path else { return Ok(None) };
+        let mode = match config.mode.as_deref() {
+            None | Some("create") => Mode::CreateMissing,
+            Some("enforce") => Mode::Enforce,
+            Some(other) => bail!("FLAGS_FILE_MODE must be create or enforce, got {other}"),
+        };
+        Ok(Some(FlagsFile { path: path.into(), mode, prune: false }))
+    }
+
+    pub fn load(&self) -> anyhow::Result<Vec<CreateFlag>> {
+        let files = if self.path.is_dir() {
+            let mut files: Vec<PathBuf> = std::fs::read_dir(&self.path).with_context(|| format!("reading {}", self.path.display()))?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "yaml" || ext == "yml"))
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![self.path.clone()]
+        };
+        let mut flags = Vec::new();
+        let mut seen = HashSet::new();
+        for path in files {
+            let raw = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+            let file: FlagFile = serde_yaml::from_str(&raw).with_context(|| format!("parsing {}", path.display()))?;
+            for f in file.flags {
+                if !seen.insert(f.key.clone()) { bail!("{}: flag {} is listed twice", path.display(), f.key); }
+                f.validate().with_context(|| format!("{}: flag {}", path.display(), f.key))?;
+                flags.push(f);
+            }
+        }
+        Ok(flags)
+    }
+
+    pub async fn apply(&self, db: &Pool<Sqlite>, flags: &[CreateFlag]) -> anyhow::Result<Vec<(String, &'static str)>> {
+        let mut tx = db.begin().await?;
+        let changed = self.reconcile(&mut tx, flags).await?;
+        tx.commit().await?;
+        Ok(changed)
+    }
+
+    // project quotas apply as they do to /apply: a file that would go over one isn't applied at all
+    pub async fn reconcile(&self, tx: &mut SqliteConnection, flags: &[CreateFlag]) -> anyhow::Result<Vec<(String, &'static str)>> {
+        let mut changed = Vec::new();
+        for want in flags {
+            let current = match sqlite::fetch_flag(tx, &want.key).await {
+                Err(StoreError::NotFound) => {
+                    let after = sqlite::insert_flag(tx, want).await?;
+                    if let Err(e) = crate::quotas::check(tx, &after, true, false).await? { bail!("flag {}: {}", want.key, e.message); }
+                    changed.push((want.key.clone(), "created"));
+                    continue;
+                }
+                other => other?,
+            };
+            if self.mode != Mode::Enforce { continue; }
+            if current.project != want.project { tracing::warn!(key = %want.key, stored = %current.project, file = %want.project, "flags file cannot move a flag between projects"); }
+            if want.uid.as_ref().is_some_and(|u| *u != current.uid) { tracing::warn!(key = %want.key, stored = %current.uid, file = want.uid.as_deref().unwrap_or_default(), "flags file has a different uid for this flag, keeping the stored one"); }
+            let mut want = want.clone();
+            if drift(&current, &want).variants && crate::experiments::check_unlocked(tx, &want.key, None).await.is_err() {
+                tracing::warn!(key = %want.key, "experiment is running, leaving variants as they are");
+                want.variants = current.variants.clone();
+            }
+            let drift = drift(&current, &want);
+            if drift.is_noop() { continue; }
+            let (_, after) = sqlite::replace_flag(tx, &want.key, &want).await?;
+            if let Err(e) = crate::quotas::check(tx, &after, false, drift.variants).await? { bail!("flag {}: {}", want.key, e.message); }
+            changed.push((want.key.clone(), "updated"));
+        }
+        if self.prune {
+            let keys: HashSet<&str> = flags.iter().map(|f| f.key.as_str()).collect();
+            for extra in sqlite::list_flags(tx).await? {
+                if keys.contains(extra.key.as_str()) { continue; }
+                if crate::experiments::check_unlocked(tx, &extra.key, None).await.is_err() {
+                    tracing::warn!(key = %extra.key, "experiment is running, not pruning flag");
+                    continue;
+                }
+                sqlite::remove_flag(tx, &extra.key).await?;
+                changed.push((extra.key, "deleted"));
+            }
+        }
+        Ok(changed)
+    }
+}
+
+// which fields applying a manifest entry changes. variants or rollout it leaves out are cleared, so they count as changed too
+pub struct Drift {
+    pub enabled: bool,
+    pub protected: bool,
+    pub variants: bool,
+    pub rollout: bool,
+}
+
+impl Drift {
+    pub fn is_noop(&self) -> bool { !(self.enabled || self.protected || self.variants || self.rollout) }
+}
+
+pub fn drift(current: &Flag, want: &CreateFlag) -> Drift {
+    Drift { enabled: want.enabled != current.enabled, protected: want.protected != current.protected, variants: want.variants != current.variants, rollout: want.rollout != current.rollout }
+}
+
+pub async fn seed(config: &FlagsFileConfig, db: &Pool<Sqlite>) -> anyhow::Result<Option<FlagsFile>> {
+    let Some(file) = FlagsFile::from_config(config)? else { return Ok(None) };
+    let flags = file.load()?;
+    let changed = file.apply(db, &flags).await?;
+    tracing::info!(path = %file.path.display(), mode = ?file.mode, flags = flags.len(), changed = changed.len(), "applied flags file");
+    Ok(Some(file))
+}
+
+pub fn watch(file: FlagsFile, db: Pool<Sqlite>, changes: Arc<Changes>) -> anyhow::Result<()> {
+    let path = std::path::absolute(&file.path)?;
+    let dir = if path.is_dir() { path.clone() } else { path.parent().context("flags file has no parent directory")?.to_path_buf() };
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher({
+        let path = path.clone();
+        move |res: notify::Result<notify::Event>| {
+            if res.is_ok_and(|e| !e.kind.is_access() && e.paths.iter().any(|p| p.starts_with(&path))) { let _ = tx.send(()); }
+        }
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    tracing::info!(path = %path.display(), "watching flags file");
+    tokio::spawn(async move {
+        let _watcher = watcher;
+        while rx.recv().await.is_some() {
+            tokio::time::sleep(DEBOUNCE).await;
+            while rx.try_recv().is_ok() {}
+            let flags = match file.load() {
+                Ok(flags) => flags,
+                Err(e) => { tracing::error!(error = format!("{e:#}"), "flags file rejected, keeping current flags"); continue; }
+            };
+            match file.apply(&db, &flags).await {
+                Ok(changed) => {
+                    for (key, action) in &changed { changes.publish(key, action); }
+                    tracing::info!(path = %file.path.display(), changed = changed.len(), "reloaded flags file");
+                }
+                Err(e) => tracing::error!(error = format!("{e:#}"), "failed to apply flags file"),
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use anyhow::{bail, Context};
+use axum::{body::Bytes, extract::State, http::{HeaderMap, StatusCode}, Extension, Json};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::{path::PathBuf, sync::{Arc, Mutex}, time::Duration};
+use tokio::{process::Command, sync::Notify};
+
+// with GIT_SYNC_INTERVAL_SECS=0 the job only runs when the webhook or POST /git-sync asks for it
+const WEBHOOK_ONLY: Duration = Duration::from_secs(365 * 86_400);
+
+use crate::{auth::{Principal, Role}, config::GitSyncConfig, flags_file::{FlagsFile, Mode}, jobs::{self, Job}, stream::Changes, webhooks::Webhooks, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    Enforce,
+    Report,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncedChange {
+    key: String,
+    action: &'static str,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncStatus {
+    commit: Option<String>,
+    synced_at: Option<String>,
+    last_error: Option<String>,
+    applied: Vec<SyncedChange>,
+    drift: Vec<SyncedChange>,
+}
+
+#[derive(Serialize)]
+pub struct StatusResponse {
+    branch: String,
+    path: String,
+    mode: SyncMode,
+    prune: bool,
+    #[serde(flatten)]
+    status: SyncStatus,
+}
+
+pub struct GitSync {
+    repo: String,
+    branch: String,
+    path: String,
+    dir: PathBuf,
+    mode: SyncMode,
+    prune: bool,
+    interval: Duration,
+    webhook_secret: Option<String>,
+    trigger: Arc<Notify>,
+    status: Mutex<SyncStatus>,
+}
+
+impl GitSync {
+    pub fn from_config(config: &GitSyncConfig) -> anyhow::Result<Option<GitSync>> {
+        let Some(repo) = config.repo.clone() else { return Ok(None) };
+        let mode = match config.mode.as_deref() {
+            None | Some("enforce") => SyncMode::Enforce,
+            Some("report") => SyncMode::Report,
+            Some(other) => bail!("GIT_SYNC_MODE must be enforce or report, got {other}"),
+        };
+        Ok(Some(GitSync {
+            repo,
+            branch: config.branch.clone().unwrap_or_else(|| "main".into()),
+            path: config.path.clone().unwrap_or_else(|| ".".into()),
+            dir: config.dir.as_ref().map(PathBuf::from).unwrap_or_else(|| std::env::temp_dir().join("flags-git-sync")),
+            mode,
+            prune: config.prune,
+            interval: Duration::from_secs(config.interval_secs.unwrap_or(60)),
+            webhook_secret: config.webhook_secret.clone().filter(|s| !s.is_empty()),
+            trigger: Arc::new(Notify::new()),
+            status: Mutex::new(SyncStatus::default()),
+        }))
+    }
+
+    async fn git(&self, args: &[&str]) -> anyhow::Result<String> {
+        let out = Command::new("git").args(args).env("GIT_TERMINAL_PROMPT", "0").output().await.context("running git")?;
+        if !out.status.success() { bail!("git {} failed: {}", args.first().unwrap_or(&""), String::from_utf8_lossy(&out.stderr).trim()); }
+        Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+    }
+
+    async fn checkout(&self) -> anyhow::Result<String> {
+        let dir = self.dir.to_string_lossy();
+        if self.dir.join(".git").is_dir() {
+            self.git(&["-C", &dir, "fetch", "--depth", "1", "origin", &self.branch]).await?;
+            self.git(&["-C", &dir, "reset", "--hard", "FETCH_HEAD"]).await?;
+        } else {
+            self.git(&["clone", "--depth", "1", "--single-branch", "--branch", &self.branch, &self.repo, &dir]).await?;
+        }
+        self.git(&["-C", &dir, "rev-parse", "HEAD"]).await
+    }
+
+    async fn run(&self, db: &sqlx::Pool<sqlx::Sqlite>, changes: &Changes, webhooks: &Webhooks) -> anyhow::Result<()> {
+        let commit = self.checkout().await?;
+        let file = FlagsFile { path: self.dir.join(&self.path), mode: Mode::Enforce, prune: self.prune };
+        let flags = file.load()?;
+        let mut tx = db.begin().await?;
+        let changed: Vec<SyncedChange> = file.reconcile(&mut tx, &flags).await?.into_iter().map(|(key, action)| SyncedChange { key, action }).collect();
+        let previous = self.status.lock().unwrap().clone();
+        let (applied, drift) = match self.mode {
+            SyncMode::Report => { tx.rollback().await?; (Vec::new(), changed) }
+            SyncMode::Enforce => {
+                tx.commit().await?;
+                for c in &changed { changes.publish(&c.key, c.action); }
+                let drift = if previous.commit.as_ref() == Some(&commit) { changed.clone() } else { Vec::new() };
+                (changed, drift)
+            }
+        };
+        if !drift.is_empty() {
+            tracing::warn!(commit = %commit, flags = drift.len(), mode = ?self.mode, "flags have drifted from the git repository");
+            let keys: Vec<&str> = drift.iter().map(|c| c.key.as_str()).collect();
+            let previous_keys: Vec<&str> = previous.drift.iter().map(|c| c.key.as_str()).collect();
+            if keys != previous_keys { webhooks.notify("git_sync.drift", serde_json::json!({ "commit": commit, "mode": self.mode, "drift": drift })); }
+        }
+        if previous.commit.as_ref() != Some(&commit) { tracing::info!(commit = %commit, flags = flags.len(), changed = applied.len(), "synced flags from git"); }
+        *self.status.lock().unwrap() = SyncStatus { commit: Some(commit), synced_at: Some(chrono::Utc::now().to_rfc3339()), last_error: None, applied, drift };
+        Ok(())
+    }
+
+    async fn sync(&self, state: &AppState) -> anyhow::Result<()> {
+        let result = self.run(&state.db, &state.flag_changes, &state.webhooks).await;
+        if let Err(e) = &result {
+            tracing::error!(error = format!("{e:#}"), "git sync failed, keeping current flags");
+            self.status.lock().unwrap().last_error = Some(format!("{e:#}"));
+        }
+        result
+    }
+
+    fn report(&self) -> StatusResponse {
+        StatusResponse { branch: self.branch.clone(), path: self.path.clone(), mode: self.mode, prune: self.prune, status: self.status.lock().unwrap().clone() }
+    }
+}
+
+// a job like any other writer of the shared database, so with several instances each sync runs on one of them
+pub fn job(state: &AppState) -> Option<Job> {
+    let sync = state.git_sync.clone()?;
+    tracing::info!(branch = %sync.branch, path = %sync.path, mode = ?sync.mode, "git sync enabled");
+    let every = if sync.interval.is_zero() { WEBHOOK_ONLY } else { sync.interval };
+    Some(Job::new("git-sync", every, |state| async move {
+        let Some(sync) = state.git_sync.clone() else { return Ok(()) };
+        sync.sync(&state).await
+    }).wake_on(sync.trigger.clone()))
+}
+
+// asks for a run on whichever instance claims the job first, waking this one's runner right away. a push that lands
+// during a run gets another run right after it, since that run may have fetched before the push
+async fn request(state: &AppState, sync: &GitSync) -> Result<StatusCode, StatusCode> {
+    let status = jobs::request(&state.db, "git-sync", true).await?;
+    sync.trigger.notify_one();
+    Ok(status)
+}
+
+pub async fn status(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Result<Json<StatusResponse>, StatusCode> {
+    principal.require("*", Role::Viewer)?;
+    let sync = state.git_sync.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(sync.report()))
+}
+
+pub async fn sync_now(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Result<StatusCode, StatusCode> {
+    principal.require("*", Role::Editor)?;
+    let sync = state.git_sync.clone().ok_or(StatusCode::NOT_FOUND)?;
+    request(&state, &sync).await
+}
+
+pub async fn webhook(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let Some(sync) = &state.git_sync else { return StatusCode::NOT_FOUND };
+    let Some(secret) = &sync.webhook_secret else { return StatusCode::NOT_FOUND };
+    let github = headers.get("x-hub-signature-256").and_then(|v| v.to_str().ok()).and_then(|v| v.strip_prefix("sha256=")).and_then(hex_decode).is_some_and(|sig| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+        mac.update(&body);
+        mac.verify_slice(&sig).is_ok()
+    });
+    let gitlab = headers.get("x-gitlab-token").is_some_and(|v| blake3::hash(v.as_bytes()) == blake3::hash(secret.as_bytes()));
+    if !github && !gitlab { return StatusCode::UNAUTHORIZED; }
+    request(&state, sync).await.unwrap_or_else(|status| status)
+}
+
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) { return None; }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
@@ -0,0 +1,192 @@
+use axum::{extract::{Path, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::time::Duration;
+
+use feature_flags_core::{sqlite, Flag, FlagStore};
+
+use crate::{audit, auth::{Principal, Role}, config::GuardConfig, jobs::Job, store_status, AppState};
+
+pub struct Prometheus {
+    http: reqwest::Client,
+    url: String,
+    every: Duration,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArmInput {
+    query: Option<String>,
+    threshold: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TripInput {
+    reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Guard {
+    flag_key: String,
+    safe_rev: i64,
+    safe_state: Flag,
+    query: Option<String>,
+    threshold: Option<f64>,
+    status: String,
+    reason: Option<String>,
+    armed_by: String,
+    armed_at: String,
+    tripped_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Tripped<'a> {
+    key: &'a str,
+    reason: &'a str,
+    tripped_by: &'a str,
+    restored_rev: i64,
+    flag: &'a Flag,
+}
+
+impl Prometheus {
+    pub fn from_config(config: &GuardConfig) -> anyhow::Result<Option<Prometheus>> {
+        let Some(url) = &config.prometheus_url else { return Ok(None) };
+        let every = config.poll_secs.filter(|s| *s > 0).unwrap_or(30);
+        Ok(Some(Prometheus { http: reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?, url: url.trim_end_matches('/').to_string(), every: Duration::from_secs(every) }))
+    }
+
+    // the largest sample of an instant query, so a per-instance error rate trips on the worst instance
+    async fn query(&self, query: &str) -> anyhow::Result<Option<f64>> {
+        let body: serde_json::Value = self.http.get(format!("{}/api/v1/query", self.url)).query(&[("query", query)]).send().await?.error_for_status()?.json().await?;
+        let data = &body["data"];
+        let samples: Vec<&serde_json::Value> = match data["resultType"].as_str() {
+            Some("vector") => data["result"].as_array().map(|r| r.iter().map(|s| &s["value"]).collect()).unwrap_or_default(),
+            Some("scalar") => vec![&data["result"]],
+            other => anyhow::bail!("unsupported result type {other:?}"),
+        };
+        Ok(samples.iter().filter_map(|v| v[1].as_str()?.parse::<f64>().ok()).filter(|v| !v.is_nan()).reduce(f64::max))
+    }
+}
+
+pub async fn arm(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(key): Path<String>, input: Option<Json<ArmInput>>) -> Result<Json<Guard>, StatusCode> {
+    let ArmInput { query, threshold } = input.map(|Json(i)| i).unwrap_or(ArmInput { query: None, threshold: None });
+    if query.is_some() != threshold.is_some() { return Err(StatusCode::BAD_REQUEST); }
+    if query.is_some() && state.prometheus.is_none() { return Err(StatusCode::BAD_REQUEST); }
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let flag = sqlite::resolve_flag(&mut tx, &key).await.map_err(store_status)?;
+    // tripping writes without a caller to check, so a protected flag can only be guarded by an admin
+    if flag.protected { principal.require(&flag.project, Role::Admin)?; }
+    // flags from before revisions were kept have none yet, so their current state becomes the first
+    let revisions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM flag_revisions WHERE flag_key = ?").bind(&flag.key).fetch_one(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if revisions == 0 { sqlite::record_revision(&mut tx, &flag).await.map_err(store_status)?; }
+    sqlx::query("INSERT INTO flag_guards (flag_key, safe_rev, query, threshold, status, armed_by, armed_at) SELECT ?, MAX(rev), ?, ?, 'armed', ?, datetime('now') FROM flag_revisions WHERE flag_key = ? ON CONFLICT (flag_key) DO UPDATE SET safe_rev = excluded.safe_rev, query = excluded.query, threshold = excluded.threshold, status = 'armed', reason = NULL, armed_by = excluded.armed_by, armed_at = excluded.armed_at, tripped_at = NULL")
+        .bind(&flag.key)
+        .bind(&query)
+        .bind(threshold)
+        .bind(&principal.subject)
+        .bind(&flag.key)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    fetch_guard(&state, &flag.key).await.map(Json)
+}
+
+pub async fn get_guard(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<Guard>, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    fetch_guard(&state, &flag.key).await.map(Json)
+}
+
+pub async fn disarm(State(state): State<AppState>, Path(key): Path<String>) -> Result<StatusCode, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    let rows = sqlx::query("DELETE FROM flag_guards WHERE flag_key = ?")
+        .bind(&flag.key)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn trip_guard(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(key): Path<String>, input: Option<Json<TripInput>>) -> Result<Json<Guard>, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    let reason = input.and_then(|Json(i)| i.reason).unwrap_or_else(|| "tripped by an external monitor".into());
+    trip(&state, &flag.key, &principal.subject, &reason).await?;
+    fetch_guard(&state, &flag.key).await.map(Json)
+}
+
+async fn trip(state: &AppState, key: &str, tripped_by: &str, reason: &str) -> Result<(), StatusCode> {
+    let guard = fetch_guard(state, key).await?;
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let rows = sqlx::query("UPDATE flag_guards SET status = 'tripped', reason = ?, tripped_at = datetime('now') WHERE flag_key = ? AND status = 'armed'")
+        .bind(reason)
+        .bind(key)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::CONFLICT); }
+    let (_, flag) = sqlite::restore_flag(&mut tx, key, &guard.safe_state).await.map_err(store_status)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.flag_changes.publish(key, "updated");
+    tracing::warn!(key, reason, restored_rev = guard.safe_rev, "guard tripped, flag rolled back");
+    state.webhooks.notify("flag.guard_tripped", Tripped { key, reason, tripped_by, restored_rev: guard.safe_rev, flag: &flag });
+    Ok(())
+}
+
+async fn fetch_guard(state: &AppState, key: &str) -> Result<Guard, StatusCode> {
+    let r = sqlx::query("SELECT g.*, r.data FROM flag_guards g JOIN flag_revisions r ON r.flag_key = g.flag_key AND r.rev = g.safe_rev WHERE g.flag_key = ?")
+        .bind(key)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Guard {
+        flag_key: r.get("flag_key"),
+        safe_rev: r.get("safe_rev"),
+        safe_state: serde_json::from_str(&r.get::<String,_>("data")).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        query: r.get("query"),
+        threshold: r.get("threshold"),
+        status: r.get("status"),
+        reason: r.get("reason"),
+        armed_by: r.get("armed_by"),
+        armed_at: r.get("armed_at"),
+        tripped_at: r.get("tripped_at"),
+    })
+}
+
+async fn check(prometheus: &Prometheus, state: &AppState) -> Result<(), sqlx::Error> {
+    let guards: Vec<(String, String, f64)> = sqlx::query("SELECT flag_key, query, threshold FROM flag_guards WHERE status = 'armed' AND query IS NOT NULL")
+        .fetch_all(&state.db)
+        .await?
+        .iter()
+        .map(|r| (r.get("flag_key"), r.get("query"), r.get("threshold")))
+        .collect();
+    for (key, query, threshold) in guards {
+        let value = match prometheus.query(&query).await {
+            Ok(Some(v)) => v,
+            Ok(None) => continue,
+            Err(e) => { tracing::warn!(error = %e, key, "guard query failed"); continue; }
+        };
+        if value <= threshold { continue; }
+        let reason = format!("{query} = {value} exceeded {threshold}");
+        let outcome = match trip(state, &key, "guard", &reason).await { Ok(()) => "ok", Err(StatusCode::CONFLICT) => continue, Err(_) => "failed" };
+        state.audit.record(audit::NewEntry {
+            actor: Some("guard"),
+            action: "guard.tripped",
+            target: Some(&format!("/flags/{key}")),
+            outcome,
+            detail: Some(serde_json::json!({ "query": query, "value": value, "threshold": threshold })),
+            ..Default::default()
+        }).await;
+    }
+    Ok(())
+}
+
+pub fn job(state: &AppState) -> Option<Job> {
+    let every = state.prometheus.as_ref()?.every;
+    Some(Job::new("flag-guards", every, |state| async move {
+        let Some(prometheus) = state.prometheus.clone() else { return Ok(()) };
+        Ok(check(&prometheus, &state).await?)
+    }))
+}
@@ -0,0 +1,101 @@
+use axum::{http::{header, HeaderMap, HeaderName, HeaderValue}, response::Response};
+
+use feature_flags_core::Attributes;
+
+use crate::config::EvaluationConfig;
+
+const MAX_VALUE: usize = 256;
+
+// request headers merged into the evaluation context, so clients that only send a user id still get os, device and locale
+pub struct HeaderContext {
+    user_agent: bool,
+    accept_language: bool,
+    custom: Vec<(HeaderName, String)>,
+}
+
+struct UserAgent {
+    os: Option<&'static str>,
+    browser: Option<&'static str>,
+    device: &'static str,
+}
+
+// good enough for targeting by platform, not a full user agent database; order matters, e.g. Edge and Chrome both say Chrome
+fn parse_user_agent(ua: &str) -> UserAgent {
+    let has = |s: &str| ua.contains(s);
+    let lower = ua.to_ascii_lowercase();
+    let os = if has("iPhone") || has("iPad") || has("iPod") { Some("iOS") }
+        else if has("Android") { Some("Android") }
+        else if has("Windows") { Some("Windows") }
+        else if has("CrOS") { Some("ChromeOS") }
+        else if has("Mac OS X") || has("Macintosh") { Some("macOS") }
+        else if has("Linux") { Some("Linux") }
+        else { None };
+    let browser = if has("Edg/") || has("EdgA/") || has("EdgiOS/") { Some("Edge") }
+        else if has("OPR/") || has("Opera") { Some("Opera") }
+        else if has("SamsungBrowser/") { Some("Samsung Internet") }
+        else if has("Firefox/") || has("FxiOS/") { Some("Firefox") }
+        else if has("Chrome/") || has("CriOS/") { Some("Chrome") }
+        else if has("Safari/") { Some("Safari") }
+        else { None };
+    let device = if lower.contains("bot") || lower.contains("spider") || lower.contains("crawl") { "bot" }
+        else if has("iPad") || has("Tablet") || (has("Android") && !has("Mobile")) { "tablet" }
+        else if has("Mobi") || has("iPhone") || has("iPod") { "mobile" }
+        else { "desktop" };
+    UserAgent { os, browser, device }
+}
+
+impl HeaderContext {
+    // CONTEXT_HEADERS, e.g. `user-agent, accept-language, x-app-version=app_version`; a custom header without a name
+    // becomes an attribute named like the header with - replaced by _
+    pub fn from_config(config: &EvaluationConfig) -> anyhow::Result<Option<HeaderContext>> {
+        let Some(spec) = &config.context_headers else { return Ok(None) };
+        let mut ctx = HeaderContext { user_agent: false, accept_language: false, custom: Vec::new() };
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, attribute) = entry.split_once('=').map_or((entry, None), |(n, a)| (n.trim(), Some(a.trim())));
+            let name = HeaderName::try_from(name).map_err(|_| anyhow::anyhow!("CONTEXT_HEADERS: invalid header name {name:?}"))?;
+            match (name, attribute) {
+                (n, None) if n == header::USER_AGENT => ctx.user_agent = true,
+                (n, None) if n == header::ACCEPT_LANGUAGE => ctx.accept_language = true,
+                (n, attribute) => {
+                    let attribute = attribute.map(str::to_string).unwrap_or_else(|| n.as_str().replace('-', "_"));
+                    if attribute.is_empty() { anyhow::bail!("CONTEXT_HEADERS: empty attribute name for {n}"); }
+                    ctx.custom.push((n, attribute));
+                }
+            }
+        }
+        if ctx.user_agent || ctx.accept_language || !ctx.custom.is_empty() { Ok(Some(ctx)) } else { Ok(None) }
+    }
+
+    // what the client sent itself always wins over what the headers say
+    pub fn enrich(&self, headers: &HeaderMap, context: &mut Attributes) {
+        let value = |name: &HeaderName| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim).filter(|v| !v.is_empty() && v.len() <= MAX_VALUE);
+        if self.user_agent {
+            if let Some(ua) = value(&header::USER_AGENT) {
+                let ua = parse_user_agent(ua);
+                if let Some(os) = ua.os { context.entry("os".into()).or_insert_with(|| os.into()); }
+                if let Some(browser) = ua.browser { context.entry("browser".into()).or_insert_with(|| browser.into()); }
+                context.entry("device".into()).or_insert_with(|| ua.device.into());
+            }
+        }
+        if self.accept_language {
+            // browsers list languages in order of preference, so the first is the one to use
+            if let Some(locale) = value(&header::ACCEPT_LANGUAGE).and_then(|v| v.split(',').next()).map(|l| l.split(';').next().unwrap_or(l).trim()).filter(|l| !l.is_empty() && *l != "*") {
+                let language = locale.split(['-', '_']).next().unwrap_or(locale).to_ascii_lowercase();
+                context.entry("locale".into()).or_insert_with(|| locale.into());
+                context.entry("language".into()).or_insert_with(|| language.into());
+            }
+        }
+        for (name, attribute) in &self.custom {
+            if let Some(v) = value(name) { context.entry(attribute.clone()).or_insert_with(|| v.into()); }
+        }
+    }
+
+    // snapshots differ by these headers now, so shared caches have to key on them too
+    pub fn vary(&self, res: &mut Response) {
+        let mut names: Vec<String> = res.headers().get(header::VARY).and_then(|v| v.to_str().ok()).map(|v| v.split(',').map(|n| n.trim().to_string()).collect()).unwrap_or_default();
+        if self.user_agent { names.push("User-Agent".into()); }
+        if self.accept_language { names.push("Accept-Language".into()); }
+        names.extend(self.custom.iter().map(|(n, _)| n.to_string()));
+        if let Ok(v) = HeaderValue::from_str(&names.join(", ")) { res.headers_mut().insert(header::VARY, v); }
+    }
+}
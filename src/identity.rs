@@ -0,0 +1,149 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::{collections::HashMap, future::Future, sync::Mutex, time::{Duration, Instant}};
+
+use crate::{auth::{ApiKey, KeyKind}, config::IdentityConfig, AppState};
+
+const MAX_CACHED: usize = 100_000;
+const LOOKUP_TIMEOUT: Duration = Duration::from_millis(250);
+const BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+pub struct Identify {
+    anonymous_id: String,
+    user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AliasParams {
+    user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Relink {
+    anonymous_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Alias {
+    user_id: String,
+    anonymous_id: String,
+    linked_at: String,
+}
+
+// user id -> the anonymous id it's linked to. a lookup is trusted for ALIAS_CACHE_SECS, since another instance may link
+// the user (or an admin re-link them) meanwhile. with CACHE_ONLY_EVAL the database is never asked, so only links made
+// through this instance apply. relays keep one too, looking links up from the upstream
+pub struct Aliases {
+    cache: Mutex<HashMap<String, (Option<String>, Instant)>>,
+    ttl: Duration,
+    cache_only: bool,
+    // a failed lookup skips the database for a while, so evaluations don't each wait on a pool that's down
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl Aliases {
+    pub fn from_config(config: &IdentityConfig, cache_only: bool) -> Aliases {
+        let secs = config.alias_cache_secs.unwrap_or(60);
+        Aliases { cache: Mutex::new(HashMap::new()), ttl: Duration::from_secs(secs), cache_only, down_until: Mutex::new(None) }
+    }
+
+    fn remember(&self, user_id: &str, anonymous_id: Option<String>) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED { cache.clear(); }
+        cache.insert(user_id.to_string(), (anonymous_id, Instant::now()));
+    }
+
+    async fn linked(&self, user_id: &str, lookup: impl Future<Output = Result<Option<String>, String>>) -> Option<String> {
+        let cached = self.cache.lock().unwrap().get(user_id).cloned();
+        if let Some((linked, at)) = &cached {
+            if self.cache_only || at.elapsed() < self.ttl { return linked.clone(); }
+        }
+        if self.cache_only || self.down_until.lock().unwrap().is_some_and(|until| Instant::now() < until) { return cached.and_then(|(linked, _)| linked); }
+        match tokio::time::timeout(LOOKUP_TIMEOUT, lookup).await {
+            Ok(Ok(linked)) => {
+                self.remember(user_id, linked.clone());
+                linked
+            }
+            failed => {
+                let error = match failed { Ok(Err(e)) => e, _ => "timed out".to_string() };
+                tracing::warn!(error, "failed to look up identity alias, bucketing by user id");
+                *self.down_until.lock().unwrap() = Some(Instant::now() + BACKOFF);
+                cached.and_then(|(linked, _)| linked)
+            }
+        }
+    }
+
+    // the id rollouts and variants are hashed on: a known user keeps the bucket of the anonymous id they signed up with
+    pub async fn bucketing_id(&self, db: &Pool<Sqlite>, user_id: Option<&str>, anonymous_id: Option<&str>) -> Option<String> {
+        self.resolve(user_id, anonymous_id, |user_id| async move {
+            let row = sqlx::query("SELECT anonymous_id FROM identity_aliases WHERE user_id = ?").bind(user_id).fetch_optional(db).await.map_err(|e| e.to_string())?;
+            Ok(row.map(|r| r.get("anonymous_id")))
+        }).await
+    }
+
+    // bucketing_id with the links coming from somewhere other than the database
+    pub async fn resolve<F, Fut>(&self, user_id: Option<&str>, anonymous_id: Option<&str>, lookup: F) -> Option<String>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = Result<Option<String>, String>>,
+    {
+        match user_id {
+            Some(user_id) => Some(self.linked(user_id, lookup(user_id.to_string())).await.unwrap_or_else(|| user_id.to_string())),
+            None => anonymous_id.map(str::to_string),
+        }
+    }
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 256
+}
+
+// the first link wins, so logging in from a second device doesn't move the user to another bucket. server keys only:
+// the backend has authenticated the user, a browser could link anyone to an id of its choosing
+pub async fn identify(State(state): State<AppState>, Extension(key): Extension<ApiKey>, Json(input): Json<Identify>) -> Result<Json<Alias>, StatusCode> {
+    // a link changes bucketing for every flag, not just the ones a scoped key can see
+    if key.kind != KeyKind::Server || !key.flag_prefixes.is_empty() { return Err(StatusCode::FORBIDDEN); }
+    if !valid_id(&input.anonymous_id) || !valid_id(&input.user_id) || input.anonymous_id == input.user_id { return Err(StatusCode::BAD_REQUEST); }
+    sqlx::query("INSERT INTO identity_aliases (user_id, anonymous_id, linked_at) VALUES (?, ?, datetime('now')) ON CONFLICT (user_id) DO NOTHING")
+        .bind(&input.user_id)
+        .bind(&input.anonymous_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let r = sqlx::query("SELECT * FROM identity_aliases WHERE user_id = ?")
+        .bind(&input.user_id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let alias = Alias { user_id: r.get("user_id"), anonymous_id: r.get("anonymous_id"), linked_at: r.get("linked_at") };
+    state.aliases.remember(&alias.user_id, Some(alias.anonymous_id.clone()));
+    Ok(Json(alias))
+}
+
+// for relays, which bucket like the primary but have no database. `404` when the user isn't linked
+pub async fn get_alias(State(state): State<AppState>, Extension(key): Extension<ApiKey>, Query(params): Query<AliasParams>) -> Result<Json<Alias>, StatusCode> {
+    if key.kind != KeyKind::Server || !key.flag_prefixes.is_empty() { return Err(StatusCode::FORBIDDEN); }
+    let r = sqlx::query("SELECT * FROM identity_aliases WHERE user_id = ?")
+        .bind(&params.user_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(Alias { user_id: r.get("user_id"), anonymous_id: r.get("anonymous_id"), linked_at: r.get("linked_at") }))
+}
+
+// admins can move a user to another anonymous id, e.g. to undo a wrong link
+pub async fn relink(State(state): State<AppState>, Path(user_id): Path<String>, Json(input): Json<Relink>) -> Result<Json<Alias>, StatusCode> {
+    if !valid_id(&input.anonymous_id) || !valid_id(&user_id) || input.anonymous_id == user_id { return Err(StatusCode::BAD_REQUEST); }
+    let r = sqlx::query("INSERT INTO identity_aliases (user_id, anonymous_id, linked_at) VALUES (?, ?, datetime('now')) ON CONFLICT (user_id) DO UPDATE SET anonymous_id = excluded.anonymous_id, linked_at = excluded.linked_at RETURNING *")
+        .bind(&user_id)
+        .bind(&input.anonymous_id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let alias = Alias { user_id: r.get("user_id"), anonymous_id: r.get("anonymous_id"), linked_at: r.get("linked_at") };
+    state.aliases.remember(&alias.user_id, Some(alias.anonymous_id.clone()));
+    Ok(Json(alias))
+}
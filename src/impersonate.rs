@@ -0,0 +1,73 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use feature_flags_core::{eval_pinned, rollout_bucket, Attributes};
+
+use crate::{auth::Principal, cache, store_status, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct EvaluateAs {
+    user_id: Option<String>,
+    anonymous_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    Overridden,
+    Hook,
+    Pinned,
+    Disabled,
+    NoIdentity,
+    OutsideRollout,
+    InRollout,
+    On,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Evaluation {
+    key: String,
+    project: String,
+    matched: bool,
+    variant: Option<String>,
+    reason: Reason,
+    rollout: Option<u8>,
+    bucket: Option<u8>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EvaluatedAs {
+    user_id: Option<String>,
+    anonymous_id: Option<String>,
+    bucketing_id: Option<String>,
+    environment: String,
+    flags: Vec<Evaluation>,
+}
+
+// what /snapshot would serve this user right now, with why; nothing is recorded as an evaluation or exposure,
+// but the request itself lands in the audit log like any other management POST
+pub async fn evaluate_as(State(state): State<AppState>, Extension(principal): Extension<Principal>, Json(input): Json<EvaluateAs>) -> Result<Json<EvaluatedAs>, StatusCode> {
+    if input.user_id.is_none() && input.anonymous_id.is_none() { return Err(StatusCode::BAD_REQUEST); }
+    let identity = state.aliases.bucketing_id(&state.db, input.user_id.as_deref(), input.anonymous_id.as_deref()).await;
+    let evaluated_as = crate::cdn::evaluated_as(identity.as_deref());
+    let flags = state.overrides.apply_all(cache::all(&state).await.map_err(store_status)?);
+    let flags = flags.into_iter().map(|flag| {
+        let plain = eval_pinned(&flag, input.user_id.as_deref(), evaluated_as.as_deref());
+        let res = state.hooks.evaluate_for(&flag, input.user_id.as_deref(), evaluated_as.as_deref(), &Attributes::new());
+        let bucket = flag.rollout.and(evaluated_as.as_deref()).map(|id| rollout_bucket(&flag.key, id));
+        let pinned = [input.user_id.as_deref(), evaluated_as.as_deref()].into_iter().flatten().any(|id| flag.pins.contains_key(id));
+        let reason = match (state.overrides.is_overridden(&flag.key), flag.enabled, flag.rollout, bucket) {
+            (true, _, _, _) => Reason::Overridden,
+            _ if res != plain => Reason::Hook,
+            _ if pinned => Reason::Pinned,
+            (_, false, _, _) => Reason::Disabled,
+            (_, _, None, _) => Reason::On,
+            (_, _, Some(_), None) => Reason::NoIdentity,
+            _ if res.matched => Reason::InRollout,
+            _ => Reason::OutsideRollout,
+        };
+        Evaluation { key: res.key, project: flag.project, matched: res.matched, variant: res.variant, reason, rollout: flag.rollout, bucket }
+    }).collect();
+    tracing::info!(actor = %principal.subject, user_id = ?input.user_id, anonymous_id = ?input.anonymous_id, "evaluated flags as another user");
+    Ok(Json(EvaluatedAs { user_id: input.user_id, anonymous_id: input.anonymous_id, bucketing_id: identity, environment: state.environment.to_string(), flags }))
+}
@@ -0,0 +1,291 @@
+use axum::{extract::{Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use feature_flags_core::{default_project, CreateFlag};
+
+use crate::{apply::{self, ApplyParams, ApplyResponse}, auth::Principal, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct ImportParams {
+    #[serde(default)]
+    dry_run: bool,
+    project: Option<String>,
+    environment: Option<String>,
+    #[serde(default)]
+    confirm_protected: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Unsupported {
+    key: String,
+    feature: &'static str,
+    detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    #[serde(flatten)]
+    result: ApplyResponse,
+    unsupported: Vec<Unsupported>,
+}
+
+// the SDK data format: what /sdk/latest-all, the relay proxy and ldcli's file data source all use
+#[derive(Debug, Deserialize)]
+pub struct LdExport {
+    #[serde(default)]
+    flags: BTreeMap<String, LdFlag>,
+    #[serde(default)]
+    segments: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LdFlag {
+    #[serde(default)]
+    on: bool,
+    #[serde(default)]
+    variations: Vec<Value>,
+    fallthrough: Option<LdFallthrough>,
+    off_variation: Option<usize>,
+    #[serde(default)]
+    targets: Vec<Value>,
+    #[serde(default)]
+    context_targets: Vec<Value>,
+    #[serde(default)]
+    rules: Vec<Value>,
+    #[serde(default)]
+    prerequisites: Vec<Value>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct LdFallthrough {
+    variation: Option<usize>,
+    rollout: Option<LdRollout>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LdRollout {
+    #[serde(default)]
+    variations: Vec<LdWeighted>,
+    bucket_by: Option<String>,
+    context_kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LdWeighted {
+    variation: usize,
+    weight: u32,
+}
+
+// both the legacy export (strategies inline on each feature) and the v4+ one (per-environment feature_strategies)
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnleashExport {
+    #[serde(default)]
+    features: Vec<UnleashFeature>,
+    #[serde(default)]
+    feature_strategies: Vec<UnleashStrategy>,
+    #[serde(default)]
+    feature_environments: Vec<UnleashEnvironment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnleashFeature {
+    name: String,
+    project: Option<String>,
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    strategies: Vec<UnleashStrategy>,
+    #[serde(default)]
+    variants: Vec<UnleashVariant>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UnleashStrategy {
+    #[serde(alias = "strategyName")]
+    name: String,
+    feature_name: Option<String>,
+    environment: Option<String>,
+    #[serde(default)]
+    parameters: HashMap<String, Value>,
+    #[serde(default)]
+    constraints: Vec<Value>,
+    #[serde(default)]
+    variants: Vec<UnleashVariant>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UnleashEnvironment {
+    feature_name: String,
+    environment: String,
+    enabled: bool,
+    #[serde(default)]
+    variants: Vec<UnleashVariant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnleashVariant {
+    name: String,
+    weight: u32,
+    #[serde(default)]
+    overrides: Vec<Value>,
+}
+
+struct Report<'a> {
+    key: &'a str,
+    out: &'a mut Vec<Unsupported>,
+}
+
+impl Report<'_> {
+    fn add(&mut self, feature: &'static str, detail: impl Into<String>) {
+        self.out.push(Unsupported { key: self.key.to_string(), feature, detail: detail.into() });
+    }
+}
+
+// per-variation weights of the fallthrough, in LaunchDarkly's thousandths of a percent
+fn ld_weights(flag: &LdFlag, report: &mut Report) -> Option<Vec<u32>> {
+    let mut weights = vec![0; flag.variations.len()];
+    match &flag.fallthrough {
+        Some(LdFallthrough { variation: Some(i), .. }) if *i < weights.len() => weights[*i] = 100_000,
+        Some(LdFallthrough { rollout: Some(rollout), .. }) => {
+            if let Some(by) = rollout.bucket_by.as_deref().filter(|b| *b != "key") { report.add("bucketBy", format!("rollouts bucket by user id here, not by {by}")); }
+            if let Some(kind) = rollout.context_kind.as_deref().filter(|k| *k != "user") { report.add("contextKind", format!("rollouts bucket by user id here, not by {kind} contexts")); }
+            for w in &rollout.variations {
+                let Some(slot) = weights.get_mut(w.variation) else { report.add("fallthrough", format!("rollout refers to missing variation {}", w.variation)); return None };
+                *slot += w.weight;
+            }
+        }
+        _ => { report.add("fallthrough", "no usable fallthrough; imported as serving nobody"); return None; }
+    }
+    if weights.iter().sum::<u32>() == 0 { report.add("fallthrough", "rollout weights are all zero; imported as serving nobody"); return None; }
+    Some(weights)
+}
+
+fn convert_ld(key: &str, flag: &LdFlag, project: &str, unsupported: &mut Vec<Unsupported>) -> CreateFlag {
+    let mut report = Report { key, out: unsupported };
+    for (feature, items) in [("targets", &flag.targets), ("contextTargets", &flag.context_targets), ("rules", &flag.rules), ("prerequisites", &flag.prerequisites)] {
+        if !items.is_empty() { report.add(feature, format!("{} skipped; only the fallthrough is imported", items.len())); }
+    }
+    let weights = ld_weights(flag, &mut report);
+    let boolean = !flag.variations.is_empty() && flag.variations.iter().all(Value::is_boolean);
+    let (variants, rollout) = if boolean {
+        let on = flag.variations.iter().position(|v| *v == Value::Bool(true));
+        if flag.off_variation.is_some_and(|i| Some(i) == on) { report.add("offVariation", "serves true while off; disabled flags serve false here"); }
+        let rollout = match (weights, on) {
+            (Some(w), Some(on)) => {
+                let share = w[on] as f64 * 100.0 / w.iter().sum::<u32>() as f64;
+                if share.fract() != 0.0 { report.add("fallthrough", format!("{share:.3}% rounded to whole percent")); }
+                share.round() as u8
+            }
+            _ => 0,
+        };
+        (None, if rollout == 100 { None } else { Some(rollout) })
+    } else {
+        if flag.off_variation.is_some() { report.add("offVariation", "disabled flags serve no variant here"); }
+        let names: Vec<String> = flag.variations.iter().enumerate().map(|(i, v)| v.as_str().map_or_else(|| format!("variation_{i}"), str::to_string)).collect();
+        if flag.variations.iter().any(|v| !v.is_string()) { report.add("variations", "non-string values are imported as variants named variation_<index>"); }
+        let weights = weights.unwrap_or_else(|| vec![0; names.len()]);
+        let serves_nobody = weights.iter().all(|w| *w == 0);
+        let variants: HashMap<String, u32> = names.into_iter().zip(weights).collect();
+        (Some(variants), if serves_nobody { Some(0) } else { None })
+    };
+    CreateFlag { uid: None, key: key.to_string(), project: project.to_string(), enabled: flag.on, protected: false, variants, rollout }
+}
+
+pub async fn launchdarkly(State(state): State<AppState>, Extension(principal): Extension<Principal>, Query(params): Query<ImportParams>, Json(input): Json<LdExport>) -> Result<Json<ImportResponse>, StatusCode> {
+    let project = params.project.clone().unwrap_or_else(default_project);
+    let mut unsupported = Vec::new();
+    let flags: Vec<CreateFlag> = input.flags.iter().filter(|(_, f)| !f.deleted).map(|(key, f)| convert_ld(key, f, &project, &mut unsupported)).collect();
+    for key in input.segments.keys() { unsupported.push(Unsupported { key: key.clone(), feature: "segments", detail: "segments aren't supported; rules using it were skipped".into() }); }
+    let result = apply::apply_flags(&state, &principal, &ApplyParams { dry_run: params.dry_run, prune: false, project: None, confirm_protected: params.confirm_protected }, &flags).await?;
+    Ok(Json(ImportResponse { result, unsupported }))
+}
+
+// unleash sends numbers as strings in strategy parameters
+fn percentage(v: Option<&Value>) -> Option<u8> {
+    let n = match v? { Value::String(s) => s.trim().parse::<f64>().ok()?, v => v.as_f64()? };
+    (0.0..=100.0).contains(&n).then(|| n.round() as u8)
+}
+
+// the rollout a single strategy enables; None when it only targets something this server can't express
+fn strategy_rollout(s: &UnleashStrategy, report: &mut Report) -> Option<u8> {
+    if !s.constraints.is_empty() { report.add("constraints", format!("{} on {} skipped; the strategy applies to everyone", s.constraints.len(), s.name)); }
+    match s.name.as_str() {
+        "default" => Some(100),
+        "flexibleRollout" => {
+            let stickiness = s.parameters.get("stickiness").and_then(Value::as_str).unwrap_or("default");
+            if !matches!(stickiness, "default" | "userId") { report.add("stickiness", format!("rollouts bucket by user id here, not by {stickiness}")); }
+            let rollout = percentage(s.parameters.get("rollout"));
+            if rollout.is_none() { report.add("flexibleRollout", "missing or invalid rollout parameter"); }
+            rollout
+        }
+        "gradualRolloutUserId" => percentage(s.parameters.get("percentage")),
+        "gradualRolloutRandom" | "gradualRolloutSessionId" => {
+            report.add("stickiness", format!("{} imported as a rollout by user id", s.name));
+            percentage(s.parameters.get("percentage"))
+        }
+        "userWithId" => {
+            let ids = s.parameters.get("userIds").and_then(Value::as_str).unwrap_or_default();
+            report.add("userWithId", format!("targeting users {ids} skipped; individual users can't be targeted here"));
+            None
+        }
+        _ => { report.add("strategies", format!("{} skipped", s.name)); None }
+    }
+}
+
+fn convert_unleash(feature: &UnleashFeature, enabled: bool, strategies: &[&UnleashStrategy], variants: &[UnleashVariant], project: &str, unsupported: &mut Vec<Unsupported>) -> CreateFlag {
+    let mut report = Report { key: &feature.name, out: unsupported };
+    // strategies are ORed, so the widest supported rollout wins; no strategies at all means on for everyone
+    let rollout = if strategies.is_empty() { Some(100) } else { strategies.iter().filter_map(|s| strategy_rollout(s, &mut report)).max() };
+    if rollout.is_none() { report.add("strategies", "no supported strategy; imported as serving nobody"); }
+    let variants = if !variants.is_empty() { variants } else { strategies.iter().map(|s| s.variants.as_slice()).find(|v| !v.is_empty()).unwrap_or_default() };
+    if strategies.iter().filter(|s| !s.variants.is_empty()).count() > 1 { report.add("variants", "per-strategy variants merged into the first strategy's"); }
+    for v in variants.iter().filter(|v| !v.overrides.is_empty()) { report.add("overrides", format!("overrides for variant {} skipped", v.name)); }
+    let variants = (!variants.is_empty()).then(|| variants.iter().map(|v| (v.name.clone(), v.weight)).collect());
+    let rollout = rollout.unwrap_or(0);
+    CreateFlag { uid: None, key: feature.name.clone(), project: project.to_string(), enabled, protected: false, variants, rollout: if rollout == 100 { None } else { Some(rollout) } }
+}
+
+pub async fn unleash(State(state): State<AppState>, Extension(principal): Extension<Principal>, Query(params): Query<ImportParams>, Json(input): Json<UnleashExport>) -> Result<Json<ImportResponse>, StatusCode> {
+    let per_environment = !input.feature_environments.is_empty() || !input.feature_strategies.is_empty();
+    // a v4 export covers every environment; which one to take is only optional when there's just one
+    let environment = if per_environment {
+        let mut all: Vec<&str> = input.feature_environments.iter().map(|e| e.environment.as_str()).chain(input.feature_strategies.iter().filter_map(|s| s.environment.as_deref())).collect();
+        all.sort_unstable();
+        all.dedup();
+        match (&params.environment, all.as_slice()) {
+            (Some(env), _) if all.contains(&env.as_str()) => Some(env.clone()),
+            (Some(_), _) => return Err(StatusCode::NOT_FOUND),
+            (None, [only]) => Some(only.to_string()),
+            (None, _) => return Err(StatusCode::BAD_REQUEST),
+        }
+    } else { None };
+
+    let mut unsupported = Vec::new();
+    let mut flags = Vec::new();
+    for feature in input.features.iter().filter(|f| !f.archived) {
+        let project = params.project.clone().or_else(|| feature.project.clone()).unwrap_or_else(default_project);
+        let flag = match &environment {
+            Some(env) => {
+                let setting = input.feature_environments.iter().find(|e| e.feature_name == feature.name && e.environment == *env);
+                let strategies: Vec<&UnleashStrategy> = input.feature_strategies.iter().filter(|s| s.feature_name.as_deref() == Some(&feature.name) && s.environment.as_deref() == Some(env)).collect();
+                let variants = setting.map(|e| e.variants.as_slice()).filter(|v| !v.is_empty()).unwrap_or(&feature.variants);
+                convert_unleash(feature, setting.is_some_and(|e| e.enabled), &strategies, variants, &project, &mut unsupported)
+            }
+            None => convert_unleash(feature, feature.enabled, &feature.strategies.iter().collect::<Vec<_>>(), &feature.variants, &project, &mut unsupported),
+        };
+        flags.push(flag);
+    }
+    let result = apply::apply_flags(&state, &principal, &ApplyParams { dry_run: params.dry_run, prune: false, project: None, confirm_protected: params.confirm_protected }, &flags).await?;
+    Ok(Json(ImportResponse { result, unsupported }))
+}
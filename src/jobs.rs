@@ -0,0 +1,303 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+use tokio::sync::Notify;
+
+use crate::{auth::{Principal, Role}, AppState};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+const MAX_POLL: Duration = Duration::from_secs(60);
+// the lease outlasts the timeout by this much, so a run cut off right at its timeout is recorded before anyone else can claim the job
+const LEASE_SLACK: Duration = Duration::from_secs(60);
+const HISTORY: i64 = 200;
+
+type Run = Box<dyn Fn(AppState) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync>;
+
+pub struct Job {
+    name: &'static str,
+    every: Duration,
+    timeout: Duration,
+    retries: u32,
+    run: Run,
+    wake: Option<Arc<Notify>>,
+}
+
+impl Job {
+    pub fn new<F, Fut>(name: &'static str, every: Duration, run: F) -> Job
+    where
+        F: Fn(AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        Job { name, every, timeout: DEFAULT_TIMEOUT, retries: 3, run: Box::new(move |state| Box::pin(run(state))), wake: None }
+    }
+
+    // a run that outlives it is abandoned, and an instance that dies mid-run only holds the job a little longer
+    pub fn timeout(mut self, timeout: Duration) -> Job {
+        self.timeout = timeout;
+        self
+    }
+
+    // look again as soon as this is notified instead of at the next poll, for jobs something outside asks to run now
+    pub fn wake_on(mut self, wake: Arc<Notify>) -> Job {
+        self.wake = Some(wake);
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobStatus {
+    name: String,
+    interval_secs: i64,
+    next_run_at: String,
+    running: bool,
+    failures: i64,
+    last_run: Option<JobRun>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobRun {
+    id: i64,
+    attempt: i64,
+    instance: String,
+    status: String,
+    error: Option<String>,
+    started_at: String,
+    finished_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobHistory {
+    #[serde(flatten)]
+    job: JobStatus,
+    runs: Vec<JobRun>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryParams {
+    status: Option<String>,
+    limit: Option<i64>,
+}
+
+fn instance() -> String {
+    let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "instance".into());
+    format!("{host}-{}", std::process::id())
+}
+
+fn secs(d: Duration) -> String {
+    format!("+{} seconds", d.as_secs().max(1))
+}
+
+// 30s, 1m, 2m, ... but never later than the next regular run would have been
+fn backoff(failures: u32, every: Duration) -> Duration {
+    (Duration::from_secs(30) * 2u32.saturating_pow(failures.saturating_sub(1).min(16))).min(every)
+}
+
+// the schedule lives in the database, so a run that was due while every instance was down happens on the next start
+pub async fn spawn(state: &AppState, jobs: Vec<Job>) -> anyhow::Result<()> {
+    let instance: Arc<str> = instance().into();
+    for job in jobs {
+        // a shortened interval pulls the next run in instead of waiting out the old one
+        sqlx::query("INSERT INTO jobs (name, interval_secs, next_run_at) VALUES (?, ?, datetime('now')) ON CONFLICT (name) DO UPDATE SET interval_secs = excluded.interval_secs, next_run_at = MIN(next_run_at, datetime('now', ?))")
+            .bind(job.name)
+            .bind(job.every.as_secs() as i64)
+            .bind(secs(job.every))
+            .execute(&state.db)
+            .await?;
+        tracing::debug!(job = job.name, every_secs = job.every.as_secs(), "job registered");
+        tokio::spawn(drive(state.clone(), job, instance.clone()));
+    }
+    Ok(())
+}
+
+async fn drive(state: AppState, job: Job, instance: Arc<str>) {
+    loop {
+        match claim(&state.db, &job).await {
+            Ok(Some(failures)) => run(&state, &job, failures, &instance).await,
+            Ok(None) => {}
+            Err(e) => tracing::warn!(job = job.name, error = %e, "failed to claim job"),
+        }
+        // another instance may have run it or someone asked for a run now, so never sleep long without looking again
+        let wait = until_due(&state.db, job.name).await.unwrap_or(job.every);
+        let sleep = tokio::time::sleep(wait.clamp(Duration::from_secs(1), job.every.min(MAX_POLL)));
+        match &job.wake {
+            Some(wake) => tokio::select! { _ = sleep => {}, _ = wake.notified() => {} },
+            None => sleep.await,
+        }
+    }
+}
+
+// claimed with a conditional update so with several instances on one database each run happens once
+async fn claim(db: &Pool<Sqlite>, job: &Job) -> Result<Option<u32>, sqlx::Error> {
+    let failures = sqlx::query("UPDATE jobs SET lease_until = datetime('now', ?) WHERE name = ? AND next_run_at <= datetime('now') AND (lease_until IS NULL OR lease_until <= datetime('now')) RETURNING failures")
+        .bind(secs(job.timeout + LEASE_SLACK))
+        .bind(job.name)
+        .fetch_optional(db)
+        .await?
+        .map(|r| r.get::<i64,_>("failures") as u32);
+    if failures.is_some() {
+        // still marked running with the lease expired: whoever ran it stopped before finishing
+        sqlx::query("UPDATE job_runs SET status = 'interrupted', finished_at = datetime('now') WHERE job = ? AND status = 'running'")
+            .bind(job.name)
+            .execute(db)
+            .await?;
+    }
+    Ok(failures)
+}
+
+async fn until_due(db: &Pool<Sqlite>, name: &str) -> Result<Duration, sqlx::Error> {
+    let secs: f64 = sqlx::query("SELECT (MAX(julianday(next_run_at), COALESCE(julianday(lease_until), 0)) - julianday('now')) * 86400 FROM jobs WHERE name = ?")
+        .bind(name)
+        .fetch_one(db)
+        .await?
+        .get(0);
+    Ok(Duration::from_secs_f64(secs.max(0.0)))
+}
+
+async fn run(state: &AppState, job: &Job, failures: u32, instance: &str) {
+    let attempt = failures % (job.retries + 1) + 1;
+    let id = sqlx::query("INSERT INTO job_runs (job, attempt, instance, status, started_at) VALUES (?, ?, ?, 'running', datetime('now'))")
+        .bind(job.name)
+        .bind(attempt)
+        .bind(instance)
+        .execute(&state.db)
+        .await
+        .map(|r| r.last_insert_rowid());
+    let result = match tokio::time::timeout(job.timeout, (job.run)(state.clone())).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("timed out after {}s", job.timeout.as_secs())),
+    };
+    let (status, error, failures, next) = match &result {
+        Ok(()) => ("ok", None, 0, job.every),
+        Err(e) => {
+            let failures = failures + 1;
+            // after the last retry it waits for its regular interval, still counting failures
+            let next = if attempt <= job.retries { backoff(attempt, job.every) } else { job.every };
+            tracing::warn!(job = job.name, attempt, error = %e, retry_in_secs = next.as_secs(), "job failed");
+            ("failed", Some(format!("{e:#}")), failures, next)
+        }
+    };
+    if let Err(e) = finish(&state.db, job.name, id, status, error, failures, next).await { tracing::warn!(job = job.name, error = %e, "failed to record job run"); }
+}
+
+async fn finish(db: &Pool<Sqlite>, name: &str, id: Result<i64, sqlx::Error>, status: &str, error: Option<String>, failures: u32, next: Duration) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query("UPDATE jobs SET next_run_at = CASE WHEN rerun THEN datetime('now') ELSE datetime('now', ?) END, rerun = 0, lease_until = NULL, failures = ? WHERE name = ?")
+        .bind(secs(next))
+        .bind(failures)
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+    // without its row the run still moves the schedule on
+    if let Ok(id) = id {
+        sqlx::query("UPDATE job_runs SET status = ?, error = ?, finished_at = datetime('now') WHERE id = ?")
+            .bind(status)
+            .bind(error)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query("DELETE FROM job_runs WHERE job = ? AND id < (SELECT id FROM job_runs WHERE job = ? ORDER BY id DESC LIMIT 1 OFFSET ?)")
+        .bind(name)
+        .bind(name)
+        .bind(HISTORY - 1)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+fn row_to_run(r: &sqlx::sqlite::SqliteRow) -> JobRun {
+    JobRun {
+        id: r.get("id"),
+        attempt: r.get("attempt"),
+        instance: r.get("instance"),
+        status: r.get("status"),
+        error: r.get("error"),
+        started_at: r.get("started_at"),
+        finished_at: r.get("finished_at"),
+    }
+}
+
+fn row_to_status(r: &sqlx::sqlite::SqliteRow, last_run: Option<JobRun>) -> JobStatus {
+    JobStatus {
+        name: r.get("name"),
+        interval_secs: r.get("interval_secs"),
+        next_run_at: r.get("next_run_at"),
+        running: r.get("running"),
+        failures: r.get("failures"),
+        last_run,
+    }
+}
+
+const SELECT_JOBS: &str = "SELECT *, COALESCE(lease_until > datetime('now'), 0) AS running FROM jobs";
+
+pub async fn list_jobs(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Result<Json<Vec<JobStatus>>, StatusCode> {
+    principal.require("*", Role::Viewer)?;
+    let mut last: std::collections::HashMap<String, JobRun> = sqlx::query("SELECT * FROM job_runs WHERE id IN (SELECT MAX(id) FROM job_runs GROUP BY job)")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .iter()
+        .map(|r| (r.get("job"), row_to_run(r)))
+        .collect();
+    let jobs = sqlx::query(&format!("{SELECT_JOBS} ORDER BY name"))
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(jobs.iter().map(|r| row_to_status(r, last.remove(&r.get::<String,_>("name")))).collect()))
+}
+
+// newest first, e.g. ?status=failed&limit=20
+pub async fn get_job(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>, Query(params): Query<HistoryParams>) -> Result<Json<JobHistory>, StatusCode> {
+    principal.require("*", Role::Viewer)?;
+    let job = sqlx::query(&format!("{SELECT_JOBS} WHERE name = ?"))
+        .bind(&name)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let runs: Vec<JobRun> = sqlx::query("SELECT * FROM job_runs WHERE job = ? AND (? IS NULL OR status = ?) ORDER BY id DESC LIMIT ?")
+        .bind(&name)
+        .bind(&params.status)
+        .bind(&params.status)
+        .bind(params.limit.unwrap_or(50).clamp(1, HISTORY))
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .iter()
+        .map(row_to_run)
+        .collect();
+    let last_run = sqlx::query("SELECT * FROM job_runs WHERE job = ? ORDER BY id DESC LIMIT 1")
+        .bind(&name)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .as_ref()
+        .map(row_to_run);
+    Ok(Json(JobHistory { job: row_to_status(&job, last_run), runs }))
+}
+
+// due now rather than at its next interval; whichever instance looks first runs it, within a minute
+pub async fn run_now(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>) -> Result<StatusCode, StatusCode> {
+    principal.require("*", Role::Admin)?;
+    request(&state.db, &name, false).await
+}
+
+// with queue, a request while the job runs makes it due again as soon as that run finishes instead of being refused
+pub async fn request(db: &Pool<Sqlite>, name: &str, queue: bool) -> Result<StatusCode, StatusCode> {
+    let running = sqlx::query(&format!("{SELECT_JOBS} WHERE name = ?"))
+        .bind(name)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?
+        .get::<bool,_>("running");
+    if running && !queue { return Err(StatusCode::CONFLICT); }
+    sqlx::query("UPDATE jobs SET next_run_at = datetime('now'), rerun = ? WHERE name = ?")
+        .bind(running)
+        .bind(name)
+        .execute(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::ACCEPTED)
+}
@@ -0,0 +1,231 @@
+use rskafka::{client::{partition::{Compression, PartitionClient, UnknownTopicHandling}, Client, ClientBuilder}, record::Record};
+use serde_json::json;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{config::KafkaConfig, stream::{Changes, FlagChange}};
+
+const QUEUE_SIZE: usize = 10_000;
+const MAX_BATCH: usize = 500;
+const MAX_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone)]
+pub struct Exposure {
+    pub flag_key: String,
+    pub user_id: String,
+    pub variant: String,
+    pub at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Text(&'static str),
+    TimestampMillis(&'static str),
+}
+
+const CHANGE_FIELDS: &[Field] = &[Field::Text("key"), Field::Text("action"), Field::Text("environment"), Field::TimestampMillis("at")];
+const EXPOSURE_FIELDS: &[Field] = &[Field::Text("flag_key"), Field::Text("user_id"), Field::Text("variant"), Field::Text("environment"), Field::TimestampMillis("at")];
+
+enum Encoding {
+    Json,
+    Avro { change_schema_id: u32, exposure_schema_id: u32 },
+}
+
+// one client per partition; records go where Kafka's default partitioner would put their key
+struct Topic {
+    partitions: Vec<PartitionClient>,
+}
+
+impl Topic {
+    async fn connect(client: &Client, name: &str) -> anyhow::Result<Topic> {
+        let ids = client.list_topics().await?.into_iter().find(|t| t.name == name).map(|t| t.partitions.into_iter().collect()).unwrap_or_else(|| vec![0]);
+        let mut partitions = Vec::new();
+        for id in ids { partitions.push(client.partition_client(name.to_string(), id, UnknownTopicHandling::Retry).await?); }
+        Ok(Topic { partitions })
+    }
+
+    fn partition(&self, key: &[u8]) -> usize {
+        (murmur2(key) & 0x7fff_ffff) as usize % self.partitions.len()
+    }
+}
+
+pub struct Kafka {
+    changes: Topic,
+    exposures: Topic,
+    encoding: Encoding,
+    environment: Arc<str>,
+}
+
+impl Kafka {
+    pub async fn from_config(config: &KafkaConfig, environment: Arc<str>) -> anyhow::Result<Option<Kafka>> {
+        let Some(brokers) = config.brokers.clone().filter(|b| !b.is_empty()) else { return Ok(None) };
+        let changes_topic = config.flag_changes_topic.clone().unwrap_or_else(|| "flag-changes".into());
+        let exposures_topic = config.exposures_topic.clone().unwrap_or_else(|| "flag-exposures".into());
+        let client = ClientBuilder::new(brokers).build().await?;
+        let changes = Topic::connect(&client, &changes_topic).await?;
+        let exposures = Topic::connect(&client, &exposures_topic).await?;
+        let encoding = match config.format.as_deref() {
+            None | Some("json") => Encoding::Json,
+            Some("avro") => {
+                let registry = config.schema_registry_url.as_deref().ok_or_else(|| anyhow::anyhow!("KAFKA_SCHEMA_REGISTRY_URL is required when KAFKA_FORMAT=avro"))?;
+                let http = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+                Encoding::Avro {
+                    change_schema_id: register(&http, registry, &changes_topic, "FlagChange", CHANGE_FIELDS).await?,
+                    exposure_schema_id: register(&http, registry, &exposures_topic, "Exposure", EXPOSURE_FIELDS).await?,
+                }
+            }
+            Some(other) => anyhow::bail!("unknown KAFKA_FORMAT {other:?}, expected json or avro"),
+        };
+        tracing::info!(changes = %changes_topic, change_partitions = changes.partitions.len(), exposures = %exposures_topic, exposure_partitions = exposures.partitions.len(), "kafka publishing enabled");
+        Ok(Some(Kafka { changes, exposures, encoding, environment }))
+    }
+
+    fn change_record(&self, change: &FlagChange) -> Record {
+        let now = chrono::Utc::now();
+        let values = [Value::Text(&change.key), Value::Text(change.action), Value::Text(&self.environment), Value::Millis(now.timestamp_millis())];
+        let value = match self.encoding {
+            Encoding::Json => connect_json("FlagChange", CHANGE_FIELDS, &values),
+            Encoding::Avro { change_schema_id, .. } => avro_datum(change_schema_id, &values),
+        };
+        Record { key: Some(change.key.clone().into_bytes()), value: Some(value), headers: BTreeMap::new(), timestamp: now }
+    }
+
+    fn exposure_record(&self, e: &Exposure) -> Record {
+        let values = [Value::Text(&e.flag_key), Value::Text(&e.user_id), Value::Text(&e.variant), Value::Text(&self.environment), Value::Millis(e.at.timestamp_millis())];
+        let value = match self.encoding {
+            Encoding::Json => connect_json("Exposure", EXPOSURE_FIELDS, &values),
+            Encoding::Avro { exposure_schema_id, .. } => avro_datum(exposure_schema_id, &values),
+        };
+        Record { key: Some(e.user_id.clone().into_bytes()), value: Some(value), headers: BTreeMap::new(), timestamp: e.at }
+    }
+}
+
+// the Java client's murmur2, so consumers see the same partitioning as from any other producer
+fn murmur2(data: &[u8]) -> u32 {
+    const M: u32 = 0x5bd1_e995;
+    let mut h = 0x9747_b28c_u32 ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for c in &mut chunks {
+        let mut k = u32::from_le_bytes([c[0], c[1], c[2], c[3]]).wrapping_mul(M);
+        k ^= k >> 24;
+        h = h.wrapping_mul(M) ^ k.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, b) in tail.iter().enumerate() { h ^= (*b as u32) << (8 * i); }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^ (h >> 15)
+}
+
+enum Value<'a> {
+    Text(&'a str),
+    Millis(i64),
+}
+
+fn avro_schema(name: &str, fields: &[Field]) -> serde_json::Value {
+    let fields: Vec<_> = fields.iter().map(|f| match f {
+        Field::Text(n) => json!({ "name": n, "type": "string" }),
+        Field::TimestampMillis(n) => json!({ "name": n, "type": { "type": "long", "logicalType": "timestamp-millis" } }),
+    }).collect();
+    json!({ "type": "record", "name": name, "namespace": "flags", "fields": fields })
+}
+
+async fn register(http: &reqwest::Client, registry: &str, topic: &str, name: &str, fields: &[Field]) -> anyhow::Result<u32> {
+    #[derive(serde::Deserialize)]
+    struct Registered { id: u32 }
+    let url = format!("{}/subjects/{topic}-value/versions", registry.trim_end_matches('/'));
+    let body = json!({ "schema": avro_schema(name, fields).to_string() });
+    let res: Registered = http.post(url).header("content-type", "application/vnd.schemaregistry.v1+json").json(&body).send().await?.error_for_status()?.json().await?;
+    Ok(res.id)
+}
+
+// Kafka Connect JsonConverter envelope (schemas.enable=true)
+fn connect_json(name: &str, fields: &[Field], values: &[Value]) -> Vec<u8> {
+    let schema_fields: Vec<_> = fields.iter().map(|f| match f {
+        Field::Text(n) => json!({ "field": n, "type": "string", "optional": false }),
+        Field::TimestampMillis(n) => json!({ "field": n, "type": "int64", "optional": false, "name": "org.apache.kafka.connect.data.Timestamp", "version": 1 }),
+    }).collect();
+    let payload: serde_json::Map<_, _> = fields.iter().zip(values).map(|(f, v)| {
+        let name = match f { Field::Text(n) | Field::TimestampMillis(n) => n.to_string() };
+        let value = match v { Value::Text(s) => json!(s), Value::Millis(ms) => json!(ms) };
+        (name, value)
+    }).collect();
+    serde_json::to_vec(&json!({ "schema": { "type": "struct", "name": format!("flags.{name}"), "optional": false, "fields": schema_fields }, "payload": payload })).unwrap_or_default()
+}
+
+// Confluent wire format: magic byte 0, big-endian schema id, Avro binary datum
+fn avro_datum(schema_id: u32, values: &[Value]) -> Vec<u8> {
+    let mut out = vec![0];
+    out.extend_from_slice(&schema_id.to_be_bytes());
+    for v in values {
+        match v {
+            Value::Text(s) => avro_string(&mut out, s),
+            Value::Millis(ms) => avro_long(&mut out, *ms),
+        }
+    }
+    out
+}
+
+fn avro_long(out: &mut Vec<u8>, v: i64) {
+    let mut n = ((v << 1) ^ (v >> 63)) as u64;
+    while n >= 0x80 {
+        out.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn avro_string(out: &mut Vec<u8>, s: &str) {
+    avro_long(out, s.len() as i64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+async fn produce(client: &PartitionClient, records: Vec<Record>, what: &str) {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match client.produce(records.clone(), Compression::NoCompression).await {
+            Ok(_) => return,
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                tracing::debug!(error = %e, attempt, what, "kafka produce failed, retrying");
+                tokio::time::sleep(Duration::from_millis(200 * 2u64.pow(attempt))).await;
+            }
+            Err(e) => { tracing::error!(error = %e, what, records = records.len(), "giving up publishing to kafka"); return; }
+        }
+    }
+}
+
+// changes and exposures run separately, so a slow exposure batch never holds flag changes back until they lag out
+pub fn spawn(kafka: Kafka, changes: &Changes) -> mpsc::Sender<Exposure> {
+    let kafka = Arc::new(kafka);
+    let (tx, mut exposures) = mpsc::channel::<Exposure>(QUEUE_SIZE);
+    let mut flag_changes = changes.subscribe();
+    let k = kafka.clone();
+    tokio::spawn(async move {
+        loop {
+            match flag_changes.recv().await {
+                Ok(change) if change.remote => {}
+                Ok(change) => produce(&k.changes.partitions[k.changes.partition(change.key.as_bytes())], vec![k.change_record(&change)], "flag change").await,
+                Err(broadcast::error::RecvError::Lagged(n)) => tracing::warn!(skipped = n, "kafka publisher fell behind on flag changes"),
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    });
+    tokio::spawn(async move {
+        while let Some(first) = exposures.recv().await {
+            let mut batches: BTreeMap<usize, Vec<Record>> = BTreeMap::new();
+            let mut next = Some(first);
+            let mut n = 0;
+            while let Some(e) = next.take() {
+                batches.entry(kafka.exposures.partition(e.user_id.as_bytes())).or_default().push(kafka.exposure_record(&e));
+                n += 1;
+                if n < MAX_BATCH { next = exposures.try_recv().ok(); }
+            }
+            for (partition, batch) in batches { produce(&kafka.exposures.partitions[partition], batch, "exposures").await; }
+        }
+    });
+    tx
+}
@@ -0,0 +1,84 @@
+use axum::{extract::{Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::time::Duration;
+
+use crate::{auth::{Principal, Role}, code_refs, config::CleanupConfig, jobs::Job, teams, AppState};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CleanupDue {
+    key: String,
+    project: String,
+    team: Option<String>,
+    launched_at: String,
+    days_launched: i64,
+    last_nudged_at: Option<String>,
+    code_refs: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportParams {
+    team: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Nudge<'a> {
+    #[serde(flatten)]
+    flag: &'a CleanupDue,
+    members: Vec<String>,
+}
+
+fn cleanup_after_days(config: &CleanupConfig) -> i64 {
+    config.after_days.filter(|d| *d > 0).unwrap_or(30)
+}
+
+// launched, on for everyone and left alone: the code path behind the flag is ready to be made permanent
+async fn cleanup_due(db: &Pool<Sqlite>, after_days: i64) -> Result<Vec<CleanupDue>, sqlx::Error> {
+    let rows = sqlx::query("SELECT key, project, o.team, lifecycle_changed_at, CAST(julianday('now') - julianday(lifecycle_changed_at) AS INTEGER) AS days, cleanup_nudged_at FROM flags LEFT JOIN flag_owners o ON o.flag_key = flags.key WHERE lifecycle = 'launched' AND enabled = 1 AND (rollout IS NULL OR rollout = 100) AND lifecycle_changed_at < datetime('now', ?) ORDER BY lifecycle_changed_at, key")
+        .bind(format!("-{after_days} days"))
+        .fetch_all(db)
+        .await?;
+    let refs = code_refs::counts(db).await?;
+    Ok(rows.iter().map(|r| {
+        let key: String = r.get("key");
+        let code_refs = refs.as_ref().map(|c| c.get(&key).copied().unwrap_or(0));
+        CleanupDue { key, project: r.get("project"), team: r.get("team"), launched_at: r.get("lifecycle_changed_at"), days_launched: r.get("days"), last_nudged_at: r.get("cleanup_nudged_at"), code_refs }
+    }).collect())
+}
+
+pub async fn cleanup_report(State(state): State<AppState>, Extension(principal): Extension<Principal>, Query(params): Query<ReportParams>) -> Result<Json<Vec<CleanupDue>>, StatusCode> {
+    let mut due = cleanup_due(&state.db, cleanup_after_days(&state.config.current().cleanup)).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    due.retain(|d| principal.has_role(&d.project, Role::Viewer) && params.team.as_ref().is_none_or(|t| d.team.as_ref() == Some(t)));
+    Ok(Json(due))
+}
+
+async fn nudge(state: &AppState, after_days: i64) -> Result<(), sqlx::Error> {
+    for flag in cleanup_due(&state.db, after_days).await? {
+        // claimed with a conditional update so several instances don't all send the same reminder
+        let claimed = sqlx::query("UPDATE flags SET cleanup_nudged_at = datetime('now') WHERE key = ? AND (cleanup_nudged_at IS NULL OR cleanup_nudged_at < datetime('now', ?))")
+            .bind(&flag.key)
+            .bind(format!("-{after_days} days"))
+            .execute(&state.db)
+            .await?
+            .rows_affected();
+        if claimed == 0 { continue; }
+        tracing::info!(key = %flag.key, days_launched = flag.days_launched, "flag is due for cleanup");
+        // owned flags nag their team, on the team's webhook when it has one; the rest go to WEBHOOK_URLS
+        let contact = match &flag.team { Some(team) => teams::contact(&state.db, team).await?, None => None };
+        let (url, members) = contact.map(|c| (c.webhook_url, c.members)).unwrap_or_default();
+        let nudge = Nudge { flag: &flag, members };
+        match url {
+            Some(url) => state.webhooks.notify_url(&url, "flag.cleanup_due", nudge),
+            None => state.webhooks.notify("flag.cleanup_due", nudge),
+        }
+    }
+    Ok(())
+}
+
+pub fn job(config: &CleanupConfig) -> Job {
+    let every = config.check_interval_secs.filter(|s| *s > 0).unwrap_or(3600);
+    Job::new("cleanup-nudges", Duration::from_secs(every), |state| async move {
+        let after_days = cleanup_after_days(&state.config.current().cleanup);
+        Ok(nudge(&state, after_days).await?)
+    })
+}
@@ -0,0 +1,93 @@
+use axum::{http::StatusCode, response::{IntoResponse, Response}, Json};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, str::FromStr, sync::Mutex, time::Duration};
+use tracing::level_filters::LevelFilter;
+
+use crate::telemetry;
+
+const MAX_REVERT: Duration = Duration::from_secs(24 * 3600);
+
+#[derive(Debug, Deserialize)]
+pub struct SetLogLevel {
+    filter: Option<String>,
+    #[serde(default)]
+    modules: BTreeMap<String, Option<String>>,
+    revert_after_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogLevel {
+    filter: String,
+    revert_at: Option<String>,
+    revert_to: Option<String>,
+}
+
+struct Revert {
+    generation: u64,
+    at: chrono::DateTime<chrono::Utc>,
+    to: String,
+}
+
+// at most one pending revert; it goes back to the filter from before the first temporary change
+static REVERT: Mutex<Option<Revert>> = Mutex::new(None);
+
+fn current() -> LogLevel {
+    let (filter, generation) = telemetry::filter();
+    let revert = REVERT.lock().unwrap();
+    let pending = revert.as_ref().filter(|r| r.generation == generation);
+    LogLevel { filter, revert_at: pending.map(|r| r.at.to_rfc3339()), revert_to: pending.map(|r| r.to.clone()) }
+}
+
+fn directive_target(directive: &str) -> Option<&str> {
+    directive.split_once('=').map(|(target, _)| target.trim())
+}
+
+// a module's level replaces whatever directive named that module before; null drops it back to the default
+fn merge(base: &str, modules: &BTreeMap<String, Option<String>>) -> Result<String, String> {
+    let mut directives: Vec<String> = base.split(',').map(str::trim).filter(|d| !d.is_empty() && directive_target(d).is_none_or(|t| !modules.contains_key(t))).map(str::to_string).collect();
+    for (module, level) in modules {
+        if module.is_empty() || module.contains([',', '=', '[', ']']) || module.contains(char::is_whitespace) { return Err(format!("invalid module {module:?}")); }
+        let Some(level) = level else { continue };
+        LevelFilter::from_str(level).map_err(|_| format!("invalid level {level:?} for {module}, use off, error, warn, info, debug or trace"))?;
+        directives.push(format!("{module}={level}"));
+    }
+    Ok(directives.join(","))
+}
+
+fn rejected(message: String) -> Response {
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+pub async fn get_log_level() -> Json<LogLevel> {
+    Json(current())
+}
+
+// e.g. {"modules": {"feature_flags_core": "debug"}, "revert_after_secs": 900} while chasing an evaluation bug
+pub async fn set_log_level(Json(input): Json<SetLogLevel>) -> Result<Json<LogLevel>, Response> {
+    if input.filter.is_none() && input.modules.is_empty() { return Err(StatusCode::BAD_REQUEST.into_response()); }
+    let revert_after = input.revert_after_secs.map(Duration::from_secs);
+    if revert_after.is_some_and(|d| d.is_zero() || d > MAX_REVERT) { return Err(StatusCode::BAD_REQUEST.into_response()); }
+    let (before, generation) = telemetry::filter();
+    let directives = merge(input.filter.as_deref().unwrap_or(&before), &input.modules).map_err(rejected)?;
+    let mut revert = REVERT.lock().unwrap();
+    // stacking temporary changes still ends at the filter from before the first of them
+    let to = revert.as_ref().filter(|r| r.generation == generation).map_or(before, |r| r.to.clone());
+    let generation = telemetry::set_filter(&directives).map_err(|e| rejected(format!("{e:#}")))?;
+    tracing::warn!(filter = %directives, revert_after_secs = input.revert_after_secs, "log level changed");
+    *revert = revert_after.map(|after| {
+        tokio::spawn(async move {
+            tokio::time::sleep(after).await;
+            let pending = REVERT.lock().unwrap().take_if(|r| r.generation == generation);
+            // a later change, through this endpoint or a config reload, wins over the revert
+            if let Some(r) = pending {
+                match telemetry::set_filter(&r.to) {
+                    Ok(_) => tracing::warn!(filter = %r.to, "log level reverted"),
+                    Err(e) => tracing::error!(error = %e, "failed to revert log level"),
+                }
+            }
+        });
+        Revert { generation, at: chrono::Utc::now() + after, to }
+    });
+    drop(revert);
+    Ok(Json(current()))
+}
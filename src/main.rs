@@ -1,206 +1,743 @@
-﻿use axum::{extract::{Path, State}, routing::{get, post, patch, delete}, Json, Router};
+﻿use axum::{extract::{Path, Query, State}, Extension, response::{IntoResponse, Response}, routing::{get, post}, Json, Router};
+use futures_util::TryStreamExt;
 use serde::{Deserialize, Serialize};
 use sqlx::{sqlite::SqlitePoolOptions, Pool, Row, Sqlite};
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use tokio::sync::RwLock;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use std::{collections::HashMap, sync::Arc};
+
+use feature_flags_core::{diff_flags, is_uuid, Attributes, lint_flag, sqlite, Bundle, CreateFlag, FieldChange, Flag, Hooks, Lifecycle, Lint, SqliteStore, StoreError, UpdateFlag};
+
+mod access_log;
+mod allowlist;
+mod annotations;
+mod apply;
+mod audit;
+mod audit_sink;
+mod auth;
+mod backup;
+mod break_glass;
+mod bucketing;
+mod bulk;
+mod cache;
+mod cdn;
+mod changes;
+mod check;
+mod cluster;
+mod code_refs;
+mod comments;
+mod config;
+mod context_schema;
+mod demo;
+mod eval_hooks;
+mod events;
+mod experiments;
+mod exports;
+mod flag_stats;
+mod flags_file;
+mod git_sync;
+mod guard;
+mod header_context;
+mod identity;
+mod impersonate;
+mod import;
+mod jobs;
+mod kafka;
+mod lifecycle;
+mod log_level;
+mod metrics;
+mod metric_definitions;
+mod oidc;
+mod overrides;
+mod pins;
+mod preview;
+mod pubsub;
+mod quotas;
+mod relay;
+mod release_groups;
+mod replication;
+mod schedule;
+mod server;
+mod shadow;
+mod signing;
+mod simulate;
+mod stream;
+mod summary;
+mod telemetry;
+mod tenants;
+mod teams;
+mod templates;
+mod usage;
+mod webhooks;
+
+const STREAM_CHUNK: usize = 64 * 1024;
 
 #[derive(Clone)]
 struct AppState {
     db: Pool<Sqlite>,
-    cache: Arc<RwLock<HashMap<String, Flag>>>,
+    store: metrics::Timed<SqliteStore>,
+    environment: Arc<str>,
+    // empty without TENANTS
+    tenant: Arc<str>,
+    oidc: Option<Arc<oidc::Oidc>>,
+    webhooks: Arc<webhooks::Webhooks>,
+    require_approval: bool,
+    allowlist: Arc<allowlist::Allowlist>,
+    usage: Arc<usage::Usage>,
+    audit: Arc<audit::Audit>,
+    flag_changes: Arc<stream::Changes>,
+    overrides: Arc<overrides::Overrides>,
+    events: Arc<events::Events>,
+    flag_stats: Arc<flag_stats::FlagStats>,
+    git_sync: Option<Arc<git_sync::GitSync>>,
+    cache: Arc<cache::FlagCache>,
+    cache_only: Option<std::time::Duration>,
+    cluster: Option<Arc<cluster::Cluster>>,
+    prometheus: Option<Arc<guard::Prometheus>>,
+    context_schemas: Arc<context_schema::ContextSchemas>,
+    shadows: Arc<shadow::Shadows>,
+    hooks: Arc<Hooks>,
+    header_context: Option<Arc<header_context::HeaderContext>>,
+    signer: Option<Arc<feature_flags_core::BundleSigner>>,
+    config: Arc<config::Reloadable>,
+    aliases: Arc<identity::Aliases>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Flag {
-    id: i64,
-    key: String,
-    enabled: bool,
-    variants: Option<HashMap<String, u32>>,
-    rollout: Option<u8>,
-    updated_at: String,
+#[derive(Debug, Deserialize, Default)]
+struct MutationParams {
+    #[serde(default)]
+    dry_run: bool,
+    confirm: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
-struct CreateFlag {
+struct DeleteParams {
+    confirm: Option<String>,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Dependents {
+    release_groups: Vec<String>,
+    // the status of the flag's experiment, unless it's concluded
+    experiment: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DryRunResponse {
+    dry_run: bool,
+    flag: Flag,
+    diff: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateResponse {
     key: String,
-    enabled: bool,
-    variants: Option<HashMap<String, u32>>,
-    rollout: Option<u8>,
+    valid: bool,
+    #[serde(flatten)]
+    lint: Lint,
+}
+
+#[derive(Debug, Serialize)]
+struct Revision {
+    rev: i64,
+    flag: Flag,
+    created_at: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    comments: Vec<comments::Comment>,
 }
 
 #[derive(Debug, Deserialize)]
-struct UpdateFlag {
-    enabled: Option<bool>,
-    variants: Option<HashMap<String, u32>>,
-    rollout: Option<u8>,
+struct ListParams {
+    format: Option<String>,
+    lifecycle: Option<Lifecycle>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiffParams {
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct DiffResponse {
+    key: String,
+    from: i64,
+    to: i64,
+    changes: Vec<FieldChange>,
 }
 
 #[derive(Debug, Deserialize)]
 struct EvalRequest {
     key: String,
     user_id: Option<String>,
+    anonymous_id: Option<String>,
+    #[serde(default)]
+    context: Attributes,
 }
 
-#[derive(Debug, Serialize)]
-struct EvalResponse {
-    key: String,
-    matched: bool,
-    variant: Option<String>,
+#[derive(Debug, Deserialize)]
+struct SnapshotParams {
+    user_id: Option<String>,
+    anonymous_id: Option<String>,
+    bucket: Option<u32>,
+    prefix: Option<String>,
+    #[serde(default)]
+    signed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct BootstrapParams {
+    env: Option<String>,
+    #[serde(default)]
+    signed: bool,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let env_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "info,tower_http=info".into());
-    tracing_subscriber::registry().with(tracing_subscriber::EnvFilter::new(env_filter)).with(tracing_subscriber::fmt::layer()).init();
+    let loaded = config::Config::load();
+    telemetry::init(loaded.as_ref().ok());
+
+    if check::requested() { std::process::exit(check::run().await); }
+    let config = loaded?;
+    // RUST_LOG was already applied when logging started; this is for a level that only the file sets
+    if std::env::var("RUST_LOG").is_err() { telemetry::set_filter(&config.log.level)?; }
+    metrics::configure(&config.metrics);
+    quotas::configure(&config.quotas);
+    cdn::configure(&config.snapshot);
+    if let Some(relay) = relay::Relay::from_config(&config)? { return relay::serve(relay, &config).await; }
+
+    let demo = demo::requested();
+    let live = config::Reloadable::new(config.clone());
+    let mut states = Vec::new();
+    let demo_db = demo.then(demo::database_path);
+    let app = match tenants::Tenants::from_config(&config)? {
+        None => {
+            let database_url = demo_db.as_deref().map_or_else(|| config.database.url.clone(), demo::database_url);
+            if demo { tracing::info!(%database_url, "demo mode, using a temporary database"); }
+            let (state, app) = start(&live, None, &database_url, demo).await?;
+            states.push(state);
+            app
+        }
+        Some(tenants) => {
+            if demo { anyhow::bail!("--demo can't be combined with TENANTS"); }
+            let mut apps = HashMap::new();
+            for name in tenants.names() {
+                tracing::info!(tenant = %name, "starting tenant");
+                let (state, app) = start(&live, Some(name), &tenants.database_url(name), false).await?;
+                states.push(state);
+                apps.insert(name.clone(), app);
+            }
+            tenants::router(tenants, apps, live.clone()).route("/health", get(health))
+        }
+    };
+    let app = telemetry::layer(app, &config.access_log)?;
 
-    let database_url = std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite://flags.db".into());
-    let pool = SqlitePoolOptions::new().max_connections(5).connect(&database_url).await?;
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS flags (\n            id INTEGER PRIMARY KEY AUTOINCREMENT,\n            key TEXT UNIQUE NOT NULL,\n            enabled INTEGER NOT NULL,\n            variants TEXT NULL,\n            rollout INTEGER NULL,\n            updated_at TEXT NOT NULL\n        )",
-    )
-    .execute(&pool)
-    .await?;
+    let mut server = config.server.clone();
+    if demo { server.bind = demo::loopback(&server.bind); tracing::info!(bind = %server.bind, "demo mode, listening on loopback only"); }
+    let changes: Vec<_> = states.iter().map(|t| t.flag_changes.clone()).collect();
+    server::Server::from_config(&server)?.serve(app, async move { shutdown_signal().await; for c in changes { c.close(); } }).await?;
+    for state in &states {
+        state.usage.flush(&state.db).await;
+        state.events.flush(&state.db).await;
+        state.flag_stats.flush(&state.db).await;
+        state.shadows.flush(&state.db).await;
+        if let Some(cluster) = &state.cluster { cluster.leave(&state.db).await; }
+    }
+    if let Some(path) = demo_db {
+        for state in &states { state.db.close().await; }
+        demo::remove_database(&path);
+    }
+    Ok(())
+}
+
+// one database with its state, background workers and routes; a multi-tenant server runs one of these per tenant
+async fn start(live: &Arc<config::Reloadable>, tenant: Option<&str>, database_url: &str, demo: bool) -> anyhow::Result<(AppState, Router)> {
+    let config = live.current();
+    let pool = SqlitePoolOptions::new().max_connections(5).connect_with(sqlite::connect_options(database_url)?).await?;
+    feature_flags_core::migrations::run(&pool).await?;
+    if demo { demo::seed(&pool).await?; }
+    let admin_api_key = match tenant { Some(t) => tenants::admin_api_key(t), None => config.auth.admin_api_key.clone() };
+    auth::init(&pool, admin_api_key.as_deref()).await?;
+    let flags_file = flags_file::seed(&config.flags_file, &pool).await?;
+
+    let environment: Arc<str> = config.environment.as_str().into();
+    tracing::info!(%environment, "serving environment");
+
+    let oidc = oidc::Oidc::from_config(&config.auth.oidc).await?;
+
+    let webhooks = Arc::new(webhooks::Webhooks::from_config(&config.webhooks)?);
+    let require_approval = config.auth.require_approval;
+
+    let allowlist = Arc::new(allowlist::Allowlist::from_config(&config.auth)?);
 
-    let state = AppState { db: pool, cache: Arc::new(RwLock::new(HashMap::new())) };
+    let usage = Arc::new(usage::Usage::new(live.clone()));
+    usage.clone().spawn_flusher(pool.clone());
+
+    let audit = Arc::new(audit::Audit::from_config(&config.audit_sink, pool.clone()).await?);
+
+    let flag_changes = Arc::new(stream::Changes::new());
+    let exposure_forward = kafka::Kafka::from_config(&config.kafka, environment.clone()).await?.map(|k| kafka::spawn(k, &flag_changes));
+    if let Some(broadcaster) = pubsub::Broadcaster::from_config(&config.pubsub).await? { broadcaster.spawn(&config.pubsub, &flag_changes, environment.clone()); }
+    let flush_every = events::flush_interval(&config.analytics);
+    let events = Arc::new(events::Events::from_config(&config.analytics, exposure_forward));
+    events.clone().spawn_flusher(pool.clone(), flush_every);
+    let flag_stats = Arc::new(flag_stats::FlagStats::new());
+    flag_stats.clone().spawn_flusher(pool.clone(), flush_every);
+    let shadows = Arc::new(shadow::Shadows::load(&pool).await?);
+    shadows.clone().spawn_flusher(pool.clone(), flush_every);
+
+    let context_schemas = Arc::new(context_schema::ContextSchemas::load(&pool).await?);
+
+    let state = AppState { store: metrics::Timed::new(SqliteStore::new(pool.clone()), "sqlite"), db: pool, environment, tenant: tenant.unwrap_or_default().into(), oidc, webhooks, require_approval, allowlist, usage, audit, flag_changes, overrides: Arc::new(overrides::Overrides::from_env()), events, flag_stats, git_sync: git_sync::GitSync::from_config(&config.git_sync)?.map(Arc::new), cache: Arc::new(cache::from_config(&config.cache)?), cache_only: cache::cache_only(&config.cache), cluster: cluster::Cluster::from_config(&config.cluster, &config.server.bind).map(Arc::new), prometheus: guard::Prometheus::from_config(&config.guard)?.map(Arc::new), context_schemas, aliases: Arc::new(identity::Aliases::from_config(&config.identity, cache::cache_only(&config.cache).is_some())), shadows, hooks: Arc::new(eval_hooks::from_config(&config.evaluation)?), header_context: header_context::HeaderContext::from_config(&config.evaluation)?.map(Arc::new), signer: signing::from_config(&config.signing)?.map(Arc::new), config: live.clone() };
+
+    if let Some(cluster) = &state.cluster { cluster.mark(&state.db).await?; }
+    let cached = cache::warm(&state).await?;
+    tracing::info!(flags = cached, "flag cache warmed");
+    cache::spawn_refresher(state.clone());
+    if let Some(cluster) = state.cluster.clone() { cluster::spawn(cluster, state.clone()).await?; }
+    // work that touches the shared database goes through the job runner; caches and flushers stay per instance
+    let mut jobs = vec![schedule::job(&config.schedule), lifecycle::job(&config.cleanup), experiments::job(&config.srm), replication::job(&config.replication), pins::job(), break_glass::job()];
+    jobs.extend(guard::job(&state));
+    jobs.extend(git_sync::job(&state));
+    if let Some(s3) = exports::S3::from_config(&config.exports)? { jobs.push(exports::job(&config.exports, s3)); }
+    jobs.extend(backup::job(&config.backup, database_url)?);
+    jobs::spawn(&state, jobs).await?;
+    state.context_schemas.clone().spawn_reloader(state.db.clone(), live.clone());
+    if let Some(file) = flags_file { flags_file::watch(file, state.db.clone(), state.flag_changes.clone())?; }
+
+    let sdk = Router::new()
+        .route("/evaluate", post(evaluate))
+        .route("/snapshot", get(snapshot))
+        .route("/rules", get(rules))
+        .route("/bootstrap", get(bootstrap))
+        .route("/stream", get(stream::stream))
+        .route("/events/track", post(events::track))
+        .route("/identify", get(identity::get_alias).post(identity::identify))
+        .route("/sdk-key", get(auth::sdk_key))
+        .route("/replication/snapshot", get(replication::snapshot))
+        .route("/replication/changes", get(replication::changes))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), auth::require_sdk_key));
+
+    let flag_routes = Router::new()
+        .route("/flags/:key", get(get_flag).patch(update_flag).delete(delete_flag))
+        .route("/flags/:key/revisions", get(list_revisions))
+        .route("/flags/:key/diff", get(diff_revisions))
+        .route("/flags/:key/stats", get(flag_stats::flag_stats))
+        .route("/flags/:key/preview", get(preview::preview_user))
+        .route("/flags/:key/validate", post(validate_flag))
+        .route("/flags/:key/schedule", get(schedule::list_actions).post(schedule::schedule_action))
+        .route("/flags/:key/schedule/:id", axum::routing::delete(schedule::cancel_action))
+        .route("/flags/:key/ramp", post(schedule::ramp))
+        .route("/flags/:key/guard", get(guard::get_guard).put(guard::arm).delete(guard::disarm))
+        .route("/flags/:key/guard/trip", post(guard::trip_guard))
+        .route("/flags/:key/comments", get(comments::list_comments).post(comments::add_comment))
+        .route("/flags/:key/comments/:id", axum::routing::delete(comments::delete_comment))
+        .route("/flags/:key/shadow", get(shadow::get_shadow).put(shadow::set_shadow).delete(shadow::clear_shadow))
+        .route("/flags/:key/overrides", get(pins::list_flag_pins))
+        .route("/flags/:key/overrides/:user_id", axum::routing::put(pins::set_pin).delete(pins::clear_pin))
+        .route("/flags/:key/code-refs", get(code_refs::flag_refs))
+        .route("/flags/:key/owner", get(teams::get_owner).put(teams::assign_owner).delete(teams::unassign_owner))
+        .route("/experiments/:key", get(experiments::get_experiment).post(experiments::create_experiment))
+        .route("/experiments/:key/start", post(experiments::start_experiment))
+        .route("/experiments/:key/pause", post(experiments::pause_experiment))
+        .route("/experiments/:key/conclude", post(experiments::conclude_experiment))
+        .route("/experiments/:key/results", get(experiments::results))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), auth::authorize_flag));
+
+    let admin_routes = Router::new()
+        .route("/tokens", get(auth::list_tokens).post(auth::create_token))
+        .route("/tokens/:id", get(auth::get_token).patch(auth::update_token).delete(auth::revoke_token))
+        .route("/tokens/:id/usage", get(usage::token_usage))
+        .route("/tokens/:id/rotate", post(auth::rotate_token))
+        .route("/tokens/:id/roles", axum::routing::put(auth::assign_role))
+        .route("/audit", get(audit::list_audit))
+        .route("/audit/verify", get(audit::verify_audit))
+        .route("/evaluate/as", post(impersonate::evaluate_as))
+        .route("/identify/:user_id", axum::routing::put(identity::relink));
+    // with TENANTS these change the whole process, so they're the operator's (see tenants::router)
+    let admin_routes = match tenant {
+        Some(_) => admin_routes,
+        None => admin_routes
+            .route("/admin/config/reload", post(config::reload).with_state(state.config.clone()))
+            .route("/admin/log-level", get(log_level::get_log_level).put(log_level::set_log_level)),
+    }.route_layer(axum::middleware::from_fn(auth::require_admin));
+
+    let management = Router::new()
+        .route("/flags", get(list_flags).post(create_flag))
+        .route("/flags/from-template/:template", post(templates::create_from_template))
+        .route("/flags/rollout/bulk", post(bulk::bulk_rollout))
+        .route("/simulate", post(simulate::simulate))
+        .route("/overrides", get(pins::list_pins))
+        .route("/break-glass", get(break_glass::list_sessions).post(break_glass::activate))
+        .route("/break-glass/:id", axum::routing::delete(break_glass::end_session))
+        .route("/release-groups", get(release_groups::list_groups).post(release_groups::create_group))
+        .route("/release-groups/:name", get(release_groups::get_group).put(release_groups::update_group).delete(release_groups::delete_group))
+        .route("/release-groups/:name/enable", post(release_groups::enable_group))
+        .route("/release-groups/:name/disable", post(release_groups::disable_group))
+        .route("/flags/:key/preview/batch", post(preview::preview_batch))
+        .route("/quotas", get(quotas::list_quotas))
+        .route("/quotas/:project", get(quotas::get_quota).put(quotas::set_quota).delete(quotas::delete_quota))
+        .route("/context-schema", get(context_schema::list_schemas))
+        .route("/context-schema/:project", get(context_schema::get_schema).put(context_schema::put_schema).delete(context_schema::delete_schema))
+        .route("/teams", get(teams::list_teams).post(teams::create_team))
+        .route("/teams/:name", get(teams::get_team).put(teams::update_team).delete(teams::delete_team))
+        .route("/teams/:name/members/:member", axum::routing::put(teams::add_member).delete(teams::remove_member))
+        .route("/templates", get(templates::list_templates).post(templates::create_template))
+        .route("/templates/:name", get(templates::get_template).put(templates::update_template).delete(templates::delete_template))
+        .route("/apply", post(apply::apply))
+        .route("/import/launchdarkly", post(import::launchdarkly))
+        .route("/import/unleash", post(import::unleash))
+        .route("/drift-check", post(apply::drift_check))
+        .route("/git-sync", get(git_sync::status).post(git_sync::sync_now))
+        .route("/cluster/status", get(cluster::status))
+        .route("/summary", get(summary::summary))
+        .route("/annotations", get(annotations::annotations))
+        .route("/reports/cleanup", get(lifecycle::cleanup_report))
+        .route("/code-refs", post(code_refs::upload))
+        .route("/cache/stats", get(cache::stats))
+        .route("/jobs", get(jobs::list_jobs))
+        .route("/jobs/:name", get(jobs::get_job))
+        .route("/jobs/:name/run", post(jobs::run_now))
+        .route("/metrics", get(metrics::tenant_metrics))
+        .route("/experiments", get(experiments::list_experiments))
+        .route("/exports/:dataset", get(exports::export_dataset))
+        .route("/metrics-definitions", get(metric_definitions::list_metrics).post(metric_definitions::create_metric))
+        .route("/metrics-definitions/:name", get(metric_definitions::get_metric).put(metric_definitions::update_metric).delete(metric_definitions::delete_metric))
+        .route("/change-requests", get(changes::list_change_requests).post(changes::create_change_request))
+        .route("/change-requests/:id", get(changes::get_change_request))
+        .route("/change-requests/:id/approve", post(changes::approve_change_request))
+        .route("/change-requests/:id/reject", post(changes::reject_change_request))
+        .merge(flag_routes)
+        .merge(admin_routes)
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), auth::require_server_key))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), audit::audit_management))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), allowlist::enforce));
 
     let app = Router::new()
         .route("/health", get(health))
-        .route("/flags", get(list_flags).post(create_flag))
-        .route("/flags/:key", get(get_flag).patch(update_flag).delete(delete_flag))
-        .route("/evaluate", post(evaluate))
-        .with_state(state)
-        .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http());
+        .route("/bucketing/test-vectors", get(bucketing::test_vectors))
+        .route("/signing-key", get(signing::signing_key))
+        .route("/git-sync/webhook", post(git_sync::webhook))
+        .merge(sdk)
+        .merge(management)
+        .with_state(state.clone());
+    let app = Router::new()
+        .fallback_service(app)
+        .layer(axum::middleware::from_fn_with_state(state.clone(), resolve_flag_uid))
+        .layer(config.cors.layer()?);
+    Ok((state, app))
+}
 
-    let addr: SocketAddr = std::env::var("BIND").unwrap_or_else(|_| "0.0.0.0:8080".into()).parse()?;
-    tracing::info!(%addr, "listening");
-    axum::Server::bind(&addr).serve(app.into_make_service()).await?;
-    Ok(())
+async fn shutdown_signal() {
+    let ctrl_c = async { let _ = tokio::signal::ctrl_c().await; };
+    #[cfg(unix)]
+    let terminate = async {
+        if let Ok(mut s) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) { s.recv().await; }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! { _ = ctrl_c => {}, _ = terminate => {} }
+    tracing::info!("shutting down");
 }
 
 async fn health() -> &'static str { "ok" }
 
-async fn list_flags(State(state): State<AppState>) -> Result<Json<Vec<Flag>>, axum::http::StatusCode> {
-    let rows = sqlx::query("SELECT id, key, enabled, variants, rollout, updated_at FROM flags")
-        .fetch_all(&state.db)
-        .await
-        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    let out = rows.into_iter().map(row_to_flag).collect::<Result<Vec<_>, _>>()
-        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    Ok(Json(out))
+async fn list_flags(State(state): State<AppState>, Extension(principal): Extension<auth::Principal>, Query(params): Query<ListParams>, headers: axum::http::HeaderMap) -> Response {
+    let ndjson = params.format.as_deref() == Some("ndjson") || headers.get(axum::http::header::ACCEPT).is_some_and(|a| a.as_bytes().starts_with(b"application/x-ndjson"));
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    tokio::spawn(async move {
+        let errors = tx.clone();
+        if let Err(e) = stream_flags(state.db, principal, params.lifecycle, ndjson, tx).await {
+            tracing::error!(error = %e, "listing flags failed");
+            let _ = errors.send(Err(std::io::Error::other(e.to_string()))).await;
+        }
+    });
+    let content_type = if ndjson { "application/x-ndjson" } else { "application/json" };
+    ([(axum::http::header::CONTENT_TYPE, content_type)], axum::body::Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx))).into_response()
+}
+
+async fn stream_flags(db: Pool<Sqlite>, principal: auth::Principal, lifecycle: Option<Lifecycle>, ndjson: bool, tx: tokio::sync::mpsc::Sender<Result<Vec<u8>, std::io::Error>>) -> anyhow::Result<()> {
+    let mut rows = sqlx::query(sqlite::LIST_FLAGS).fetch(&db);
+    let mut chunk = if ndjson { Vec::new() } else { b"[".to_vec() };
+    let mut first = true;
+    while let Some(row) = rows.try_next().await? {
+        let flag = sqlite::row_to_flag(row)?;
+        if !principal.has_role(&flag.project, auth::Role::Viewer) || lifecycle.is_some_and(|l| l != flag.lifecycle) { continue; }
+        if !ndjson && !first { chunk.push(b','); }
+        first = false;
+        serde_json::to_writer(&mut chunk, &flag)?;
+        if ndjson { chunk.push(b'\n'); }
+        if chunk.len() >= STREAM_CHUNK && tx.send(Ok(std::mem::take(&mut chunk))).await.is_err() { return Ok(()); }
+    }
+    if !ndjson { chunk.push(b']'); }
+    let _ = tx.send(Ok(chunk)).await;
+    Ok(())
 }
 
 async fn get_flag(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<Flag>, axum::http::StatusCode> {
-    let r = sqlx::query("SELECT id, key, enabled, variants, rollout, updated_at FROM flags WHERE key = ?")
-        .bind(&key)
-        .fetch_optional(&state.db)
-        .await
-        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(axum::http::StatusCode::NOT_FOUND)?;
-    let f = row_to_flag(r).map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = state.db.acquire().await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let f = metrics::store_op("sqlite", "get", sqlite::resolve_flag(&mut conn, &key)).await.map_err(store_status)?;
     Ok(Json(f))
 }
 
-async fn create_flag(State(state): State<AppState>, Json(input): Json<CreateFlag>) -> Result<Json<Flag>, axum::http::StatusCode> {
-    if input.rollout.is_some() && input.rollout.unwrap() > 100 { return Err(axum::http::StatusCode::BAD_REQUEST); }
-    let variants_str = input.variants.as_ref().map(|v| serde_json::to_string(v).unwrap());
-    sqlx::query("INSERT INTO flags (key, enabled, variants, rollout, updated_at) VALUES (?, ?, ?, ?, datetime('now'))")
-        .bind(&input.key)
-        .bind(if input.enabled { 1 } else { 0 })
-        .bind(variants_str)
-        .bind(input.rollout.map(|x| x as i64))
-        .execute(&state.db)
-        .await
-        .map_err(|_| axum::http::StatusCode::CONFLICT)?;
-    let r = sqlx::query("SELECT id, key, enabled, variants, rollout, updated_at FROM flags WHERE key = ?")
-        .bind(&input.key)
-        .fetch_one(&state.db)
-        .await
-        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    let f = row_to_flag(r).map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    Ok(Json(f))
+// every /flags/:key route also takes the flag's uid; it's swapped for the key before routing, so handlers only ever see keys
+async fn resolve_flag_uid(State(state): State<AppState>, mut req: axum::extract::Request, next: axum::middleware::Next) -> Response {
+    let Some((uid, rest)) = req.uri().path().strip_prefix("/flags/").map(|p| p.split_once('/').map_or((p.to_string(), String::new()), |(uid, rest)| (uid.to_string(), format!("/{rest}")))) else { return next.run(req).await };
+    if !is_uuid(&uid) { return next.run(req).await; }
+    let found = match state.db.acquire().await { Ok(mut conn) => sqlite::resolve_flag(&mut conn, &uid).await.ok(), Err(_) => None };
+    let Some(flag) = found.filter(|f| f.key != uid) else { return next.run(req).await };
+    let uri = match req.uri().query() { Some(q) => format!("/flags/{}{rest}?{q}", flag.key), None => format!("/flags/{}{rest}", flag.key) };
+    if let Ok(uri) = uri.parse() { *req.uri_mut() = uri; }
+    next.run(req).await
+}
+
+async fn create_flag(State(state): State<AppState>, Extension(principal): Extension<auth::Principal>, Query(params): Query<MutationParams>, Json(input): Json<CreateFlag>) -> Result<Response, axum::http::StatusCode> {
+    principal.require(&input.project, if input.protected { auth::Role::Admin } else { auth::Role::Editor })?;
+    if break_glass::approval_required(&state, &principal) && !params.dry_run { return Err(axum::http::StatusCode::FORBIDDEN); }
+    let mut tx = state.db.begin().await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let f = metrics::store_op("sqlite", "create", sqlite::insert_flag(&mut tx, &input)).await.map_err(store_status)?;
+    if let Err(e) = quotas::check(&mut tx, &f, true, false).await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)? { return Ok(e.into_response()); }
+    let res = finish_mutation(tx, params.dry_run, None, f).await?;
+    if !params.dry_run { state.flag_changes.publish(&input.key, "created"); }
+    Ok(res)
+}
+
+async fn update_flag(State(state): State<AppState>, Extension(principal): Extension<auth::Principal>, Path(key): Path<String>, Query(params): Query<MutationParams>, Json(input): Json<UpdateFlag>) -> Result<Response, axum::http::StatusCode> {
+    if break_glass::approval_required(&state, &principal) && !params.dry_run { return Err(axum::http::StatusCode::FORBIDDEN); }
+    let mut tx = state.db.begin().await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let current = sqlite::fetch_flag(&mut tx, &key).await.map_err(store_status)?;
+    check_protected(&current, &principal, params.confirm.as_deref())?;
+    experiments::check_unlocked(&mut tx, &key, Some(&input)).await?;
+    if input.protected.is_some_and(|p| p != current.protected) { principal.require(&current.project, auth::Role::Admin)?; }
+    let (existing, f) = metrics::store_op("sqlite", "update", sqlite::apply_update(&mut tx, &key, &input)).await.map_err(store_status)?;
+    if let Err(e) = quotas::check(&mut tx, &f, false, input.variants.is_some()).await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)? { return Ok(e.into_response()); }
+    let res = finish_mutation(tx, params.dry_run, Some(&existing), f).await?;
+    if !params.dry_run { state.flag_changes.publish(&key, "updated"); }
+    Ok(res)
 }
 
-async fn update_flag(State(state): State<AppState>, Path(key): Path<String>, Json(input): Json<UpdateFlag>) -> Result<Json<Flag>, axum::http::StatusCode> {
-    if let Some(r) = input.rollout { if r > 100 { return Err(axum::http::StatusCode::BAD_REQUEST); } }
-    let existing_row = sqlx::query("SELECT id, key, enabled, variants, rollout, updated_at FROM flags WHERE key = ?")
+fn store_status(e: StoreError) -> axum::http::StatusCode {
+    match e {
+        StoreError::NotFound => axum::http::StatusCode::NOT_FOUND,
+        StoreError::Conflict => axum::http::StatusCode::CONFLICT,
+        StoreError::Invalid(_) => axum::http::StatusCode::BAD_REQUEST,
+        StoreError::Malformed(_) | StoreError::Backend(_) => { tracing::error!(error = %e, "flag store failure"); axum::http::StatusCode::INTERNAL_SERVER_ERROR }
+    }
+}
+
+// names of metrics, templates, teams and context attributes
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+fn check_protected(flag: &Flag, principal: &auth::Principal, confirm: Option<&str>) -> Result<(), axum::http::StatusCode> {
+    if !flag.protected { return Ok(()); }
+    principal.require(&flag.project, auth::Role::Admin)?;
+    if confirm != Some(flag.key.as_str()) { return Err(axum::http::StatusCode::PRECONDITION_REQUIRED); }
+    Ok(())
+}
+
+// lint errors undo the change; warnings are listed by code in x-flag-warnings
+async fn finish_mutation(tx: sqlx::Transaction<'_, Sqlite>, dry_run: bool, before: Option<&Flag>, after: Flag) -> Result<Response, axum::http::StatusCode> {
+    let lint = lint_flag(&after);
+    if !lint.is_valid() {
+        tx.rollback().await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        return Ok((axum::http::StatusCode::UNPROCESSABLE_ENTITY, Json(lint)).into_response());
+    }
+    let mut res = if dry_run {
+        tx.rollback().await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        let diff = diff_flags(before, &after);
+        Json(DryRunResponse { dry_run: true, flag: after, diff }).into_response()
+    } else {
+        tx.commit().await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        Json(after).into_response()
+    };
+    let codes: Vec<&str> = lint.warnings.iter().map(|w| w.code.as_str()).collect();
+    if !codes.is_empty() { res.headers_mut().insert("x-flag-warnings", axum::http::HeaderValue::from_str(&codes.join(", ")).expect("ascii header value")); }
+    Ok(res)
+}
+
+// checks the flag as it is, or as it would be with the given changes, without saving anything
+async fn validate_flag(State(state): State<AppState>, Path(key): Path<String>, input: Option<Json<UpdateFlag>>) -> Result<Json<ValidateResponse>, axum::http::StatusCode> {
+    let mut tx = state.db.begin().await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let flag = match input {
+        Some(Json(changes)) => sqlite::apply_update(&mut tx, &key, &changes).await.map_err(store_status)?.1,
+        None => sqlite::fetch_flag(&mut tx, &key).await.map_err(store_status)?,
+    };
+    tx.rollback().await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let lint = lint_flag(&flag);
+    Ok(Json(ValidateResponse { key, valid: lint.is_valid(), lint }))
+}
+async fn list_revisions(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<Vec<Revision>>, axum::http::StatusCode> {
+    let rows = sqlx::query("SELECT rev, data, created_at FROM flag_revisions WHERE flag_key = ? ORDER BY rev")
         .bind(&key)
-        .fetch_optional(&state.db)
-        .await
-        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(axum::http::StatusCode::NOT_FOUND)?;
-    let existing = row_to_flag(existing_row).map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    let enabled = input.enabled.unwrap_or(existing.enabled);
-    let variants = match (input.variants, existing.variants) { (Some(v), _) => Some(serde_json::to_string(&v).unwrap()), (None, v) => v.map(|vv| serde_json::to_string(&vv).unwrap()) };
-    let rollout = input.rollout.map(|x| x as i64).or(existing.rollout.map(|x| x as i64));
-    sqlx::query("UPDATE flags SET enabled = ?, variants = ?, rollout = ?, updated_at = datetime('now') WHERE key = ?")
-        .bind(if enabled { 1 } else { 0 })
-        .bind(variants)
-        .bind(rollout)
-        .bind(&existing.key)
-        .execute(&state.db)
+        .fetch_all(&state.db)
         .await
         .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    let r = sqlx::query("SELECT id, key, enabled, variants, rollout, updated_at FROM flags WHERE key = ?")
-        .bind(&existing.key)
-        .fetch_one(&state.db)
-        .await
+    if rows.is_empty() { return Err(axum::http::StatusCode::NOT_FOUND); }
+    let mut out = rows.into_iter().map(row_to_revision).collect::<Result<Vec<_>, _>>()
         .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    let f = row_to_flag(r).map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    Ok(Json(f))
+    let mut comments = comments::by_rev(&state.db, &key).await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    for r in &mut out { r.comments = comments.remove(&r.rev).unwrap_or_default(); }
+    Ok(Json(out))
 }
 
-async fn delete_flag(State(state): State<AppState>, Path(key): Path<String>) -> Result<(), axum::http::StatusCode> {
-    let rows = sqlx::query("DELETE FROM flags WHERE key = ?")
+async fn diff_revisions(State(state): State<AppState>, Path(key): Path<String>, Query(params): Query<DiffParams>) -> Result<Json<DiffResponse>, axum::http::StatusCode> {
+    let latest = sqlx::query("SELECT MAX(rev) AS rev FROM flag_revisions WHERE flag_key = ?")
         .bind(&key)
-        .execute(&state.db)
+        .fetch_one(&state.db)
         .await
         .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
-        .rows_affected();
-    if rows == 0 { return Err(axum::http::StatusCode::NOT_FOUND); }
-    Ok(())
+        .get::<Option<i64>,_>("rev")
+        .ok_or(axum::http::StatusCode::NOT_FOUND)?;
+    let to = params.to.unwrap_or(latest);
+    let from = params.from.unwrap_or(to - 1);
+    let before = if from == 0 { None } else { Some(fetch_revision(&state.db, &key, from).await?.flag) };
+    let after = fetch_revision(&state.db, &key, to).await?.flag;
+    let changes = diff_flags(before.as_ref(), &after);
+    Ok(Json(DiffResponse { key, from, to, changes }))
 }
 
-async fn evaluate(State(state): State<AppState>, Json(req): Json<EvalRequest>) -> Result<Json<EvalResponse>, axum::http::StatusCode> {
-    let r = sqlx::query("SELECT id, key, enabled, variants, rollout, updated_at FROM flags WHERE key = ?")
-        .bind(&req.key)
-        .fetch_optional(&state.db)
+async fn fetch_revision(db: &Pool<Sqlite>, key: &str, rev: i64) -> Result<Revision, axum::http::StatusCode> {
+    let r = sqlx::query("SELECT rev, data, created_at FROM flag_revisions WHERE flag_key = ? AND rev = ?")
+        .bind(key)
+        .bind(rev)
+        .fetch_optional(db)
         .await
         .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(axum::http::StatusCode::NOT_FOUND)?;
-    let flag = row_to_flag(r).map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    let res = eval_flag(&flag, req.user_id.as_deref());
-    Ok(Json(res))
-}
-
-fn row_to_flag(r: sqlx::sqlite::SqliteRow) -> Result<Flag, anyhow::Error> {
-    let id = r.get::<i64,_>("id");
-    let key = r.get::<String,_>("key");
-    let enabled = r.get::<i64,_>("enabled") != 0;
-    let variants_str = r.get::<Option<String>,_>("variants");
-    let variants = match variants_str { Some(s) => Some(serde_json::from_str::<HashMap<String, u32>>(&s)?), None => None };
-    let rollout = r.get::<Option<i64>,_>("rollout").map(|x| x as u8);
-    let updated_at = r.get::<String,_>("updated_at");
-    Ok(Flag { id, key, enabled, variants, rollout, updated_at })
-}
-
-fn eval_flag(flag: &Flag, user_id: Option<&str>) -> EvalResponse {
-    let gate = match flag.rollout {
-        None => true,
-        Some(p) => match user_id { None => false, Some(uid) => { let mut hasher = blake3::Hasher::new(); hasher.update(flag.key.as_bytes()); hasher.update(b":"); hasher.update(uid.as_bytes()); let h = hasher.finalize(); (h.as_bytes()[0] % 100) < p } },
+    row_to_revision(r).map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn dependents(conn: &mut sqlx::SqliteConnection, key: &str) -> Result<Dependents, sqlx::Error> {
+    let release_groups = sqlx::query("SELECT group_name FROM release_group_flags WHERE flag_key = ? ORDER BY group_name").bind(key).fetch_all(&mut *conn).await?
+        .into_iter().map(|r| r.get("group_name")).collect();
+    let experiment = sqlx::query("SELECT status FROM experiments WHERE flag_key = ? AND status != 'concluded'").bind(key).fetch_optional(&mut *conn).await?
+        .map(|r| r.get("status"));
+    Ok(Dependents { release_groups, experiment })
+}
+
+// release groups and an unfinished experiment would silently lose the flag, so deleting one they use is a 409 listing them.
+// an admin can pass ?force=true to delete anyway, which drops the memberships and the experiment with it
+async fn delete_flag(State(state): State<AppState>, Extension(principal): Extension<auth::Principal>, Path(key): Path<String>, Query(params): Query<DeleteParams>) -> Result<Response, axum::http::StatusCode> {
+    if break_glass::approval_required(&state, &principal) { return Err(axum::http::StatusCode::FORBIDDEN); }
+    let mut tx = state.db.begin().await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let current = sqlite::fetch_flag(&mut tx, &key).await.map_err(store_status)?;
+    check_protected(&current, &principal, params.confirm.as_deref())?;
+    let dependents = dependents(&mut tx, &key).await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !dependents.release_groups.is_empty() || dependents.experiment.is_some() {
+        if !params.force { return Ok((axum::http::StatusCode::CONFLICT, Json(serde_json::json!({ "dependents": dependents }))).into_response()); }
+        principal.require(&current.project, auth::Role::Admin)?;
+        sqlx::query("DELETE FROM experiments WHERE flag_key = ? AND status != 'concluded'").bind(&key).execute(&mut *tx).await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    metrics::store_op("sqlite", "delete", sqlite::remove_flag(&mut tx, &key)).await.map_err(store_status)?;
+    tx.commit().await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.flag_changes.publish(&key, "deleted");
+    Ok(().into_response())
+}
+
+async fn evaluate(State(state): State<AppState>, Extension(key): Extension<auth::ApiKey>, headers: axum::http::HeaderMap, Json(req): Json<EvalRequest>) -> Result<Response, axum::http::StatusCode> {
+    let age = cache::age(&state);
+    if state.cache_only.is_some_and(|max| age > max) {
+        tracing::warn!(age_secs = age.as_secs(), "flag snapshot is older than CACHE_MAX_STALENESS_SECS, refusing to evaluate");
+        return Err(axum::http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+    telemetry::record_flag(&req.key);
+    if !key.sees(&req.key) { return Err(axum::http::StatusCode::NOT_FOUND); }
+    let flag = state.overrides.apply(cache::get(&state, &req.key).await.map_err(store_status)?);
+    let mut context = req.context;
+    if let Some(user_id) = &req.user_id { context.entry("user_id".into()).or_insert_with(|| user_id.clone().into()); }
+    if let Some(anonymous_id) = &req.anonymous_id { context.entry("anonymous_id".into()).or_insert_with(|| anonymous_id.clone().into()); }
+    let warnings = match state.context_schemas.validate(&flag.project, &context) {
+        context_schema::Verdict::Valid => None,
+        context_schema::Verdict::Warn(problems) => Some(problems.join("; ")),
+        context_schema::Verdict::Reject(problems) => return Ok((axum::http::StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({ "errors": problems }))).into_response()),
     };
-    if !flag.enabled || !gate { return EvalResponse { key: flag.key.clone(), matched: false, variant: None }; }
-    if let Some(vs) = &flag.variants {
-        let total: u32 = vs.values().copied().sum();
-        if total == 0 { return EvalResponse { key: flag.key.clone(), matched: true, variant: None }; }
-        let pick = match user_id { None => 0, Some(uid) => { let mut hasher = blake3::Hasher::new(); hasher.update(flag.key.as_bytes()); hasher.update(b"/"); hasher.update(uid.as_bytes()); let hh = hasher.finalize(); let n = u32::from_le_bytes(hk(hh.as_bytes())); n % total } };
-        let mut acc = 0u32;
-        for (name, weight) in vs.iter() { acc += *weight; if pick < acc { return EvalResponse { key: flag.key.clone(), matched: true, variant: Some(name.clone()) }; } }
-        return EvalResponse { key: flag.key.clone(), matched: true, variant: None };
+    // header attributes come from the server, so they're added after the client's context has been checked against the schema
+    if let Some(h) = &state.header_context { h.enrich(&headers, &mut context); }
+    let identity = state.aliases.bucketing_id(&state.db, req.user_id.as_deref(), req.anonymous_id.as_deref()).await;
+    let evaluated_as = cdn::evaluated_as(identity.as_deref());
+    let res = metrics::evaluate(&state.tenant, &state.hooks, &flag, req.user_id.as_deref(), evaluated_as.as_deref(), &context);
+    state.flag_stats.record(std::slice::from_ref(&res));
+    state.shadows.observe(std::slice::from_ref(&flag), evaluated_as.as_deref(), std::slice::from_ref(&res));
+    if let (Some(identity), Some(variant)) = (&identity, &res.variant) { state.events.record_exposure(&flag.key, identity, variant); }
+    let mut res = ([("x-flags-snapshot-age", age.as_secs().to_string())], Json(res)).into_response();
+    if let Some(w) = warnings.and_then(|w| axum::http::HeaderValue::from_str(&w).ok()) { res.headers_mut().insert("x-context-warnings", w); }
+    Ok(res)
+}
+
+// a key scoped to prefixes gets only its flags, and ?prefix= narrows any snapshot further, e.g. for mobile clients
+async fn snapshot(State(state): State<AppState>, Extension(key): Extension<auth::ApiKey>, Query(params): Query<SnapshotParams>, headers: axum::http::HeaderMap) -> Result<Response, axum::http::StatusCode> {
+    let bucket = cdn::bucket(&params)?;
+    let user_id = params.user_id.as_deref().filter(|_| bucket.is_none());
+    let subject = bucket.clone().or_else(|| params.user_id.clone()).or_else(|| params.anonymous_id.clone());
+    let identity = match &bucket {
+        Some(bucket) => Some(bucket.clone()),
+        None => cdn::evaluated_as(state.aliases.bucketing_id(&state.db, params.user_id.as_deref(), params.anonymous_id.as_deref()).await.as_deref()),
+    };
+    let mut flags = state.overrides.apply_all(cache::all(&state).await.map_err(store_status)?);
+    flags.retain(|f| key.sees(&f.key) && params.prefix.as_deref().is_none_or(|p| f.key.starts_with(p)));
+    let mut context = Attributes::new();
+    if let Some(h) = &state.header_context { h.enrich(&headers, &mut context); }
+    let out = metrics::evaluate_all(&state.tenant, &state.hooks, &flags, user_id, identity.as_deref(), &context);
+    state.flag_stats.record(&out);
+    // a bucket is no user, and a CDN only asks for it on a miss
+    if bucket.is_none() { state.shadows.observe(&flags, identity.as_deref(), &out); }
+    let mut res = cdn::cache_headers(&params, with_etag(&headers, &signing::wrap(state.signer.as_deref(), params.signed, &state.environment, subject.as_deref(), &out)?)?);
+    if let Some(h) = &state.header_context { h.vary(&mut res); }
+    Ok(res)
+}
+
+async fn rules(State(state): State<AppState>, Extension(key): Extension<auth::ApiKey>, Query(params): Query<signing::SignParams>, headers: axum::http::HeaderMap) -> Result<Response, axum::http::StatusCode> {
+    if key.kind != auth::KeyKind::Server { return Err(axum::http::StatusCode::FORBIDDEN); }
+    let mut flags = state.overrides.apply_all(cache::all(&state).await.map_err(store_status)?);
+    flags.retain(|f| key.sees(&f.key));
+    with_etag(&headers, &signing::wrap(state.signer.as_deref(), params.signed, &state.environment, None, &flags)?)
+}
+
+async fn bootstrap(State(state): State<AppState>, Extension(key): Extension<auth::ApiKey>, Query(params): Query<BootstrapParams>, headers: axum::http::HeaderMap) -> Result<Response, axum::http::StatusCode> {
+    if key.kind != auth::KeyKind::Server { return Err(axum::http::StatusCode::FORBIDDEN); }
+    if params.env.as_deref().is_some_and(|e| e != &*state.environment) { return Err(axum::http::StatusCode::NOT_FOUND); }
+    let mut flags = state.overrides.apply_all(cache::all(&state).await.map_err(store_status)?);
+    flags.retain(|f| key.sees(&f.key));
+    let bundle = Bundle { environment: state.environment.to_string(), generated_at: chrono::Utc::now().to_rfc3339(), flags };
+    encoded(&headers, &signing::wrap(state.signer.as_deref(), params.signed, &state.environment, None, &bundle)?)
+}
+
+// MessagePack when the client's Accept asks for it, JSON otherwise. Maps keep their field names, so both decode into the same types
+fn encode<T: Serialize>(headers: &axum::http::HeaderMap, value: &T) -> Result<(Vec<u8>, &'static str), axum::http::StatusCode> {
+    let accept = headers.get(axum::http::header::ACCEPT).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let msgpack = accept.split(',').any(|t| matches!(t.split(';').next().unwrap_or_default().trim(), "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack"));
+    if msgpack {
+        rmp_serde::to_vec_named(value).map(|b| (b, "application/msgpack")).map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+    } else {
+        serde_json::to_vec(value).map(|b| (b, "application/json")).map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+fn encoded<T: Serialize>(headers: &axum::http::HeaderMap, value: &T) -> Result<Response, axum::http::StatusCode> {
+    let (body, content_type) = encode(headers, value)?;
+    Ok(([(axum::http::header::CONTENT_TYPE, content_type), (axum::http::header::VARY, "Accept")], body).into_response())
+}
+
+// the ETag is of the encoded body, so JSON and MessagePack copies never validate each other. a signed copy's signature and
+// issue time change on every request, so it's tagged by what it signs instead
+fn with_etag<T: Serialize>(headers: &axum::http::HeaderMap, value: &signing::MaybeSigned<T>) -> Result<Response, axum::http::StatusCode> {
+    let (body, content_type) = encode(headers, value)?;
+    let hash = match value {
+        signing::MaybeSigned::Plain(_) => blake3::hash(&body),
+        signing::MaybeSigned::Signed { value, .. } => blake3::Hasher::new().update(b"signed:").update(&encode(headers, value)?.0).finalize(),
+    };
+    let etag = format!("\"{}\"", &hash.to_hex()[..16]);
+    if headers.get(axum::http::header::IF_NONE_MATCH).is_some_and(|v| v.as_bytes() == etag.as_bytes()) {
+        return Ok((axum::http::StatusCode::NOT_MODIFIED, [(axum::http::header::ETAG, etag), (axum::http::header::VARY, "Accept".to_string())]).into_response());
     }
-    EvalResponse { key: flag.key.clone(), matched: true, variant: None }
+    Ok(([(axum::http::header::ETAG, etag), (axum::http::header::CONTENT_TYPE, content_type.to_string()), (axum::http::header::VARY, "Accept".to_string())], body).into_response())
 }
 
-fn hk(b: &[u8]) -> [u8; 4] { [b[0], b[1], b[2], b[3]] }
+fn row_to_revision(r: sqlx::sqlite::SqliteRow) -> Result<Revision, anyhow::Error> {
+    let rev = r.get::<i64,_>("rev");
+    let flag = serde_json::from_str::<Flag>(&r.get::<String,_>("data"))?;
+    let created_at = r.get::<String,_>("created_at");
+    Ok(Revision { rev, flag, created_at, comments: Vec::new() })
+}
@@ -0,0 +1,151 @@
+use axum::{extract::{Path, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::{auth::Principal, valid_name, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    Conversion,
+    Count,
+    Sum,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Increase,
+    Decrease,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricDefinition {
+    pub name: String,
+    pub event: String,
+    pub aggregation: Aggregation,
+    pub direction: Direction,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateMetric {
+    name: String,
+    #[serde(flatten)]
+    fields: MetricFields,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetricFields {
+    event: String,
+    #[serde(default = "default_aggregation")]
+    aggregation: Aggregation,
+    #[serde(default = "default_direction")]
+    direction: Direction,
+    description: Option<String>,
+}
+
+fn default_aggregation() -> Aggregation { Aggregation::Conversion }
+fn default_direction() -> Direction { Direction::Increase }
+
+impl Aggregation {
+    pub fn as_str(self) -> &'static str {
+        match self { Aggregation::Conversion => "conversion", Aggregation::Count => "count", Aggregation::Sum => "sum" }
+    }
+
+    fn parse(s: &str) -> Aggregation {
+        match s { "count" => Aggregation::Count, "sum" => Aggregation::Sum, _ => Aggregation::Conversion }
+    }
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self { Direction::Increase => "increase", Direction::Decrease => "decrease" }
+    }
+}
+
+pub async fn list_metrics(State(state): State<AppState>) -> Result<Json<Vec<MetricDefinition>>, StatusCode> {
+    let rows = sqlx::query("SELECT * FROM metric_definitions ORDER BY name")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(rows.into_iter().map(row_to_metric).collect()))
+}
+
+pub async fn get_metric(State(state): State<AppState>, Path(name): Path<String>) -> Result<Json<MetricDefinition>, StatusCode> {
+    Ok(Json(fetch_metric(&state.db, &name).await?))
+}
+
+pub async fn create_metric(State(state): State<AppState>, Extension(principal): Extension<Principal>, Json(input): Json<CreateMetric>) -> Result<Json<MetricDefinition>, StatusCode> {
+    principal.require_editor()?;
+    if !valid_name(&input.name) || input.fields.event.is_empty() { return Err(StatusCode::BAD_REQUEST); }
+    sqlx::query("INSERT INTO metric_definitions (name, event, aggregation, direction, description, created_at, updated_at) VALUES (?, ?, ?, ?, ?, datetime('now'), datetime('now'))")
+        .bind(&input.name)
+        .bind(&input.fields.event)
+        .bind(input.fields.aggregation.as_str())
+        .bind(input.fields.direction.as_str())
+        .bind(&input.fields.description)
+        .execute(&state.db)
+        .await
+        .map_err(|e| match e { sqlx::Error::Database(d) if d.is_unique_violation() => StatusCode::CONFLICT, _ => StatusCode::INTERNAL_SERVER_ERROR })?;
+    Ok(Json(fetch_metric(&state.db, &input.name).await?))
+}
+
+pub async fn update_metric(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>, Json(input): Json<MetricFields>) -> Result<Json<MetricDefinition>, StatusCode> {
+    principal.require_editor()?;
+    if input.event.is_empty() { return Err(StatusCode::BAD_REQUEST); }
+    let rows = sqlx::query("UPDATE metric_definitions SET event = ?, aggregation = ?, direction = ?, description = ?, updated_at = datetime('now') WHERE name = ?")
+        .bind(&input.event)
+        .bind(input.aggregation.as_str())
+        .bind(input.direction.as_str())
+        .bind(&input.description)
+        .bind(&name)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    Ok(Json(fetch_metric(&state.db, &name).await?))
+}
+
+pub async fn delete_metric(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>) -> Result<(), StatusCode> {
+    principal.require_editor()?;
+    let in_use = sqlx::query("SELECT 1 FROM experiments WHERE metric = ? AND status != 'concluded'")
+        .bind(&name)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if in_use.is_some() { return Err(StatusCode::CONFLICT); }
+    let rows = sqlx::query("DELETE FROM metric_definitions WHERE name = ?")
+        .bind(&name)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    Ok(())
+}
+
+pub async fn fetch_metric(db: &Pool<Sqlite>, name: &str) -> Result<MetricDefinition, StatusCode> {
+    sqlx::query("SELECT * FROM metric_definitions WHERE name = ?")
+        .bind(name)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(row_to_metric)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn row_to_metric(r: sqlx::sqlite::SqliteRow) -> MetricDefinition {
+    MetricDefinition {
+        name: r.get("name"),
+        event: r.get("event"),
+        aggregation: Aggregation::parse(&r.get::<String,_>("aggregation")),
+        direction: if r.get::<String,_>("direction") == "decrease" { Direction::Decrease } else { Direction::Increase },
+        description: r.get("description"),
+        created_at: r.get("created_at"),
+        updated_at: r.get("updated_at"),
+    }
+}
@@ -0,0 +1,162 @@
+use async_trait::async_trait;
+use axum::{extract::{MatchedPath, Request, State}, http::header::CONTENT_TYPE, middleware::Next, response::{IntoResponse, Response}};
+use std::{collections::HashMap, fmt::Write, future::Future, sync::{atomic::{AtomicUsize, Ordering}, LazyLock, Mutex}, time::{Duration, Instant}};
+
+use feature_flags_core::{Attributes, CreateFlag, EvalResponse, Flag, FlagStore, Hooks, StoreError, UpdateFlag};
+
+use crate::{config::MetricsConfig, AppState};
+
+const BUCKETS: [f64; 16] = [0.000_001, 0.000_005, 0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0, 5.0];
+const OTHER_FLAGS: &str = "__other__";
+
+#[derive(Default, Clone)]
+struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, d: Duration) {
+        let secs = d.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|b| secs <= *b) { self.counts[i] += 1; }
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+struct Registry {
+    http: Mutex<HashMap<(String, String), Histogram>>,
+    eval: Mutex<HashMap<(String, String), Histogram>>,
+    store: Mutex<HashMap<(&'static str, &'static str), Histogram>>,
+    gauges: Mutex<HashMap<&'static str, (&'static str, f64)>>,
+    max_flags: AtomicUsize,
+}
+
+static METRICS: LazyLock<Registry> = LazyLock::new(|| Registry {
+    http: Mutex::new(HashMap::new()),
+    eval: Mutex::new(HashMap::new()),
+    store: Mutex::new(HashMap::new()),
+    gauges: Mutex::new(HashMap::new()),
+    max_flags: AtomicUsize::new(1000),
+});
+
+// set once at startup, before anything is evaluated
+pub fn configure(config: &MetricsConfig) {
+    METRICS.max_flags.store(config.max_flags, Ordering::Relaxed);
+}
+
+// per-flag series are capped so an installation with a huge flag set can't blow up the scrape; they're kept by tenant
+// ("" without TENANTS) so one tenant's /metrics never names another's flags
+fn observe_evals<'a>(tenant: &str, samples: impl IntoIterator<Item = (&'a str, Duration)>) {
+    let max_flags = METRICS.max_flags.load(Ordering::Relaxed);
+    let mut eval = METRICS.eval.lock().unwrap();
+    let mut series = eval.keys().filter(|(t, _)| t == tenant).count();
+    for (key, d) in samples {
+        let known = eval.contains_key(&(tenant.to_string(), key.to_string()));
+        let key = if known || series < max_flags { key } else { OTHER_FLAGS };
+        if !known && key != OTHER_FLAGS { series += 1; }
+        eval.entry((tenant.to_string(), key.to_string())).or_default().observe(d);
+    }
+}
+
+pub fn evaluate(tenant: &str, hooks: &Hooks, flag: &Flag, user_id: Option<&str>, bucketing_id: Option<&str>, attributes: &Attributes) -> EvalResponse {
+    let started = Instant::now();
+    let res = hooks.evaluate_for(flag, user_id, bucketing_id, attributes);
+    observe_evals(tenant, [(flag.key.as_str(), started.elapsed())]);
+    res
+}
+
+pub fn evaluate_all(tenant: &str, hooks: &Hooks, flags: &[Flag], user_id: Option<&str>, bucketing_id: Option<&str>, attributes: &Attributes) -> Vec<EvalResponse> {
+    let mut samples = Vec::with_capacity(flags.len());
+    let out = flags.iter().map(|f| {
+        let started = Instant::now();
+        let res = hooks.evaluate_for(f, user_id, bucketing_id, attributes);
+        samples.push((f.key.as_str(), started.elapsed()));
+        res
+    }).collect();
+    observe_evals(tenant, samples);
+    out
+}
+
+pub async fn store_op<T>(backend: &'static str, op: &'static str, fut: impl Future<Output = T>) -> T {
+    let started = Instant::now();
+    let out = fut.await;
+    METRICS.store.lock().unwrap().entry((backend, op)).or_default().observe(started.elapsed());
+    out
+}
+
+pub fn set_gauge(name: &'static str, help: &'static str, value: f64) {
+    METRICS.gauges.lock().unwrap().insert(name, (help, value));
+}
+
+pub async fn track(req: Request, next: Next) -> Response {
+    let route = req.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string()).unwrap_or_else(|| "unmatched".into());
+    let method = req.method().to_string();
+    let started = Instant::now();
+    let res = next.run(req).await;
+    METRICS.http.lock().unwrap().entry((method, route)).or_default().observe(started.elapsed());
+    res
+}
+
+#[derive(Clone)]
+pub struct Timed<S> {
+    inner: S,
+    backend: &'static str,
+}
+
+impl<S> Timed<S> {
+    pub fn new(inner: S, backend: &'static str) -> Timed<S> { Timed { inner, backend } }
+}
+
+#[async_trait]
+impl<S: FlagStore> FlagStore for Timed<S> {
+    async fn list(&self) -> Result<Vec<Flag>, StoreError> { store_op(self.backend, "list", self.inner.list()).await }
+    async fn get(&self, key: &str) -> Result<Flag, StoreError> { store_op(self.backend, "get", self.inner.get(key)).await }
+    async fn create(&self, input: &CreateFlag) -> Result<Flag, StoreError> { store_op(self.backend, "create", self.inner.create(input)).await }
+    async fn update(&self, key: &str, input: &UpdateFlag) -> Result<Flag, StoreError> { store_op(self.backend, "update", self.inner.update(key, input)).await }
+    async fn delete(&self, key: &str) -> Result<Flag, StoreError> { store_op(self.backend, "delete", self.inner.delete(key)).await }
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn render_family<K>(out: &mut String, name: &str, help: &str, series: &HashMap<K, Histogram>, labels: impl Fn(&K) -> String) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+    let mut rows: Vec<(String, &Histogram)> = series.iter().map(|(k, h)| (labels(k), h)).collect();
+    rows.sort_by(|a, b| a.0.cmp(&b.0));
+    for (labels, h) in rows {
+        let mut cumulative = 0;
+        for (bound, n) in BUCKETS.iter().zip(h.counts) {
+            cumulative += n;
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", h.count);
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", h.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", h.count);
+    }
+}
+
+pub async fn metrics() -> Response {
+    render("")
+}
+
+pub async fn tenant_metrics(State(state): State<AppState>) -> Response {
+    render(&state.tenant)
+}
+
+// request and store timings are by route and operation only, so they are the process's and shared by every tenant
+fn render(tenant: &str) -> Response {
+    let mut out = String::new();
+    let http = METRICS.http.lock().unwrap().clone();
+    render_family(&mut out, "flags_http_request_duration_seconds", "Time to handle a request, by route.", &http, |(method, route)| format!("method=\"{method}\",route=\"{}\"", escape(route)));
+    let eval: HashMap<String, Histogram> = METRICS.eval.lock().unwrap().iter().filter(|((t, _), _)| t == tenant).map(|((_, key), h)| (key.clone(), h.clone())).collect();
+    render_family(&mut out, "flags_evaluation_duration_seconds", "Time to evaluate a flag's rules, by flag.", &eval, |key| format!("flag=\"{}\"", escape(key)));
+    let store = METRICS.store.lock().unwrap().clone();
+    render_family(&mut out, "flags_store_operation_duration_seconds", "Time spent in the flag store, by backend and operation.", &store, |(backend, op)| format!("backend=\"{backend}\",op=\"{op}\""));
+    let mut gauges: Vec<_> = METRICS.gauges.lock().unwrap().iter().map(|(name, g)| (*name, *g)).collect();
+    gauges.sort_by_key(|(name, _)| *name);
+    for (name, (help, value)) in gauges { let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}"); }
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], out).into_response()
+}
@@ -0,0 +1,119 @@
+use axum::http::StatusCode;
+use jsonwebtoken::{jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet}, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::{Duration, Instant}};
+use tokio::sync::RwLock;
+
+use crate::{auth::Role, config::OidcConfig};
+
+const JWKS_TTL: Duration = Duration::from_secs(600);
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+pub struct Oidc {
+    issuer: String,
+    audience: String,
+    scope: Option<String>,
+    roles_claim: String,
+    jwks_uri: String,
+    http: reqwest::Client,
+    jwks: RwLock<(Instant, JwkSet)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    jwks_uri: String,
+}
+
+pub struct Identity {
+    pub subject: String,
+    pub roles: HashMap<String, Role>,
+}
+
+impl Oidc {
+    pub async fn from_config(config: &OidcConfig) -> anyhow::Result<Option<Arc<Oidc>>> {
+        let Some(issuer) = config.issuer.clone() else { return Ok(None) };
+        let audience = config.audience.clone().ok_or_else(|| anyhow::anyhow!("OIDC_AUDIENCE is required when OIDC_ISSUER is set"))?;
+        let scope = config.scope.clone().filter(|s| !s.is_empty());
+        let roles_claim = config.roles_claim.clone();
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+        let discovery_url = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+        let discovery: Discovery = http.get(&discovery_url).send().await?.error_for_status()?.json().await?;
+        let jwks: JwkSet = http.get(&discovery.jwks_uri).send().await?.error_for_status()?.json().await?;
+        tracing::info!(%issuer, jwks_uri = %discovery.jwks_uri, keys = jwks.keys.len(), "oidc enabled");
+        Ok(Some(Arc::new(Oidc { issuer, audience, scope, roles_claim, jwks_uri: discovery.jwks_uri, http, jwks: RwLock::new((Instant::now(), jwks)) })))
+    }
+
+    pub fn looks_like_jwt(token: &str) -> bool {
+        token.split('.').count() == 3
+    }
+
+    // roles are `role`, `role:project` or `role:project:environment`; one for another environment is ignored
+    pub async fn verify(&self, token: &str, environment: &str) -> Result<Identity, StatusCode> {
+        let header = jsonwebtoken::decode_header(token).map_err(|_| StatusCode::UNAUTHORIZED)?;
+        let kid = header.kid.ok_or(StatusCode::UNAUTHORIZED)?;
+        let (key, alg) = self.decoding_key(&kid).await?;
+        if header.alg != alg { return Err(StatusCode::UNAUTHORIZED); }
+        let mut validation = Validation::new(alg);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        let claims = jsonwebtoken::decode::<serde_json::Map<String, serde_json::Value>>(token, &key, &validation)
+            .map_err(|e| { tracing::debug!(error = %e, "rejected jwt"); StatusCode::UNAUTHORIZED })?
+            .claims;
+        if let Some(required) = &self.scope {
+            if !claim_values(claims.get("scope").or(claims.get("scp"))).iter().any(|s| s == required) {
+                return Err(StatusCode::FORBIDDEN);
+            }
+        }
+        let subject = claims.get("sub").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        let mut roles: HashMap<String, Role> = HashMap::new();
+        for value in claim_values(claims.get(&self.roles_claim)) {
+            let mut parts = value.splitn(3, ':');
+            let (role, project, env) = (parts.next().unwrap_or_default(), parts.next().unwrap_or("*"), parts.next().unwrap_or("*"));
+            if env != "*" && env != environment { continue; }
+            let Some(role) = Role::parse(role) else { continue };
+            let entry = roles.entry(project.to_string()).or_insert(role);
+            *entry = (*entry).max(role);
+        }
+        Ok(Identity { subject, roles })
+    }
+
+    async fn decoding_key(&self, kid: &str) -> Result<(DecodingKey, Algorithm), StatusCode> {
+        {
+            let (fetched_at, jwks) = &*self.jwks.read().await;
+            if fetched_at.elapsed() < JWKS_TTL {
+                if let Some(jwk) = jwks.find(kid) { return key_for(jwk); }
+                if fetched_at.elapsed() < JWKS_MIN_REFRESH { return Err(StatusCode::UNAUTHORIZED); }
+            }
+        }
+        let jwks: JwkSet = self.http.get(&self.jwks_uri).send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?
+            .json().await
+            .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+        let key = jwks.find(kid).map(key_for);
+        *self.jwks.write().await = (Instant::now(), jwks);
+        key.ok_or(StatusCode::UNAUTHORIZED)?
+    }
+}
+
+// the algorithm comes from the published key, never from the token's header: the JWK's `alg`, or the usual one for its
+// key type. shared-secret keys are refused, since a JWKS is public
+fn key_for(jwk: &Jwk) -> Result<(DecodingKey, Algorithm), StatusCode> {
+    let alg = match (jwk.common.key_algorithm, &jwk.algorithm) {
+        (_, AlgorithmParameters::OctetKey(_)) => None,
+        (Some(alg), _) => Algorithm::from_str(&alg.to_string()).ok().filter(|a| !matches!(a, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)),
+        (None, AlgorithmParameters::RSA(_)) => Some(Algorithm::RS256),
+        (None, AlgorithmParameters::EllipticCurve(p)) => match p.curve { EllipticCurve::P256 => Some(Algorithm::ES256), EllipticCurve::P384 => Some(Algorithm::ES384), _ => None },
+        (None, AlgorithmParameters::OctetKeyPair(_)) => Some(Algorithm::EdDSA),
+    };
+    let alg = alg.ok_or(StatusCode::UNAUTHORIZED)?;
+    Ok((DecodingKey::from_jwk(jwk).map_err(|_| StatusCode::UNAUTHORIZED)?, alg))
+}
+
+fn claim_values(v: Option<&serde_json::Value>) -> Vec<String> {
+    match v {
+        Some(serde_json::Value::String(s)) => s.split_whitespace().map(str::to_string).collect(),
+        Some(serde_json::Value::Array(items)) => items.iter().filter_map(|i| i.as_str().map(str::to_string)).collect(),
+        _ => Vec::new(),
+    }
+}
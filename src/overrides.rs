@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use feature_flags_core::Flag;
+
+const PREFIX: &str = "FLAG_OVERRIDE_";
+
+#[derive(Debug, Clone)]
+enum Override {
+    Enabled(bool),
+    Variant(String),
+}
+
+#[derive(Debug, Default)]
+pub struct Overrides {
+    by_key: HashMap<String, Override>,
+}
+
+impl Overrides {
+    pub fn from_env() -> Overrides {
+        let mut by_key = HashMap::new();
+        for (name, value) in std::env::vars() {
+            let Some(key) = name.strip_prefix(PREFIX).filter(|k| !k.is_empty()) else { continue };
+            let value = match value.trim() {
+                "true" | "on" | "1" => Override::Enabled(true),
+                "false" | "off" | "0" => Override::Enabled(false),
+                "" => continue,
+                variant => Override::Variant(variant.to_string()),
+            };
+            tracing::warn!(key, ?value, "flag overridden by environment");
+            by_key.insert(key.to_string(), value);
+        }
+        Overrides { by_key }
+    }
+
+    fn find(&self, key: &str) -> Option<&Override> {
+        if self.by_key.is_empty() { return None; }
+        self.by_key.get(key).or_else(|| self.by_key.get(&key.replace(['-', '.'], "_")))
+    }
+
+    pub fn is_overridden(&self, key: &str) -> bool { self.find(key).is_some() }
+
+    pub fn apply(&self, mut flag: Flag) -> Flag {
+        match self.find(&flag.key) {
+            None => {}
+            Some(Override::Enabled(enabled)) => { flag.enabled = *enabled; flag.rollout = None; flag.pins.clear(); }
+            Some(Override::Variant(variant)) => { flag.enabled = true; flag.rollout = None; flag.variants = Some(HashMap::from([(variant.clone(), 1)])); flag.pins.clear(); }
+        }
+        flag
+    }
+
+    pub fn apply_all(&self, flags: Vec<Flag>) -> Vec<Flag> {
+        flags.into_iter().map(|f| self.apply(f)).collect()
+    }
+}
@@ -0,0 +1,137 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, response::{IntoResponse, Response}, Extension, Json};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+
+use feature_flags_core::{sqlite, Flag};
+
+use crate::{auth::{Principal, Role}, jobs::Job, quotas, store_status, AppState};
+
+const DEFAULT_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Deserialize)]
+pub struct SetPin {
+    enabled: Option<bool>,
+    variant: Option<String>,
+    expires_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PinParams {
+    user_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PinEntry {
+    flag_key: String,
+    project: String,
+    user_id: String,
+    enabled: bool,
+    variant: Option<String>,
+    expires_at: Option<String>,
+    pinned_by: String,
+    pinned_at: String,
+}
+
+fn row_to_pin(r: sqlx::sqlite::SqliteRow) -> PinEntry {
+    PinEntry {
+        flag_key: r.get("flag_key"),
+        project: r.get("project"),
+        user_id: r.get("user_id"),
+        enabled: r.get::<i64, _>("enabled") != 0,
+        variant: r.get("variant"),
+        expires_at: r.get("expires_at"),
+        pinned_by: r.get("pinned_by"),
+        pinned_at: r.get("pinned_at"),
+    }
+}
+
+async fn fetch_pins(state: &AppState, key: Option<&str>, user_id: Option<&str>) -> Result<Vec<PinEntry>, StatusCode> {
+    let rows = sqlx::query("SELECT p.*, f.project FROM flag_pins p JOIN flags f ON f.key = p.flag_key WHERE (?1 IS NULL OR p.flag_key = ?1) AND (?2 IS NULL OR p.user_id = ?2) AND (p.expires_at IS NULL OR p.expires_at > datetime('now')) ORDER BY p.flag_key, p.user_id")
+        .bind(key)
+        .bind(user_id)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(rows.into_iter().map(row_to_pin).collect())
+}
+
+// pins live next to the flag, so its updated_at moves with them and caches, ETags, streams and relays pick the change up
+async fn touch(state: &AppState, key: &str) -> Result<(), StatusCode> {
+    sqlx::query("UPDATE flags SET updated_at = datetime('now') WHERE key = ?").bind(key).execute(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.flag_changes.publish(key, "updated");
+    Ok(())
+}
+
+async fn resolve(state: &AppState, key: &str) -> Result<Flag, StatusCode> {
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlite::resolve_flag(&mut conn, key).await.map_err(store_status)
+}
+
+pub async fn list_flag_pins(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<Vec<PinEntry>>, StatusCode> {
+    let flag = resolve(&state, &key).await?;
+    fetch_pins(&state, Some(&flag.key), None).await.map(Json)
+}
+
+// every active pin, e.g. all the flags a QA account is currently forced into
+pub async fn list_pins(State(state): State<AppState>, Extension(principal): Extension<Principal>, Query(params): Query<PinParams>) -> Result<Json<Vec<PinEntry>>, StatusCode> {
+    let pins = fetch_pins(&state, None, params.user_id.as_deref()).await?;
+    Ok(Json(pins.into_iter().filter(|p| principal.has_role(&p.project, Role::Viewer)).collect()))
+}
+
+// a variant implies enabled; without expires_at the pin lasts a day so forgotten QA pins don't stick around
+pub async fn set_pin(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path((key, user_id)): Path<(String, String)>, Json(input): Json<SetPin>) -> Result<Response, StatusCode> {
+    let flag = resolve(&state, &key).await?;
+    let enabled = input.enabled.unwrap_or(true);
+    if user_id.trim().is_empty() || (!enabled && input.variant.is_some()) { return Err(StatusCode::BAD_REQUEST); }
+    if let Some(v) = &input.variant {
+        if !flag.variants.as_ref().is_some_and(|vs| vs.contains_key(v)) { return Err(StatusCode::UNPROCESSABLE_ENTITY); }
+    }
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Err(e) = quotas::check_pins(&mut conn, &flag.project, &flag.key, &user_id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? { return Ok(e.into_response()); }
+    drop(conn);
+    let expires_at = match &input.expires_at {
+        Some(s) => DateTime::parse_from_rfc3339(s).map(|t| t.with_timezone(&Utc)).ok().filter(|t| *t > Utc::now()).ok_or(StatusCode::BAD_REQUEST)?,
+        None => Utc::now() + Duration::hours(DEFAULT_TTL_HOURS),
+    };
+    sqlx::query("INSERT INTO flag_pins (flag_key, user_id, enabled, variant, expires_at, pinned_by, pinned_at) VALUES (?, ?, ?, ?, ?, ?, datetime('now')) ON CONFLICT (flag_key, user_id) DO UPDATE SET enabled = excluded.enabled, variant = excluded.variant, expires_at = excluded.expires_at, pinned_by = excluded.pinned_by, pinned_at = excluded.pinned_at")
+        .bind(&flag.key)
+        .bind(&user_id)
+        .bind(enabled)
+        .bind(&input.variant)
+        .bind(expires_at.format("%Y-%m-%d %H:%M:%S").to_string())
+        .bind(&principal.subject)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    touch(&state, &flag.key).await?;
+    fetch_pins(&state, Some(&flag.key), Some(&user_id)).await?.pop().map(|p| Json(p).into_response()).ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+pub async fn clear_pin(State(state): State<AppState>, Path((key, user_id)): Path<(String, String)>) -> Result<StatusCode, StatusCode> {
+    let flag = resolve(&state, &key).await?;
+    let rows = sqlx::query("DELETE FROM flag_pins WHERE flag_key = ? AND user_id = ?")
+        .bind(&flag.key)
+        .bind(&user_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    touch(&state, &flag.key).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// stores already skip expired pins, but cached copies of the flag keep them until something tells the caches to reload
+pub fn job() -> Job {
+    Job::new("pin-expiry", std::time::Duration::from_secs(60), |state| async move {
+        let expired = sqlx::query("DELETE FROM flag_pins WHERE expires_at <= datetime('now') RETURNING flag_key").fetch_all(&state.db).await?;
+        let mut keys: Vec<String> = expired.into_iter().map(|r| r.get("flag_key")).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            if let Err(status) = touch(&state, &key).await { tracing::warn!(key, %status, "failed to publish expired flag pins"); }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,85 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use feature_flags_core::{eval_flag, rollout_bucket, Flag};
+
+use crate::{auth::{Principal, Role}, cache, store_status, AppState};
+
+const DEFAULT_PERCENTAGES: [u8; 7] = [1, 5, 10, 25, 50, 75, 100];
+const MAX_USERS: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewParams {
+    user_id: String,
+    percentages: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchPreview {
+    user_ids: Vec<String>,
+    percentages: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AtRollout {
+    rollout: u8,
+    included: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserPreview {
+    user_id: String,
+    bucket: u8,
+    included_from: u8,
+    included_now: bool,
+    variant: Option<String>,
+    at: Vec<AtRollout>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Preview {
+    key: String,
+    enabled: bool,
+    rollout: Option<u8>,
+    users: Vec<UserPreview>,
+}
+
+fn preview(flag: &Flag, user_ids: Vec<String>, percentages: &[u8]) -> Preview {
+    // the variant a user gets once included doesn't depend on the rollout
+    let everyone = Flag { enabled: true, rollout: None, ..flag.clone() };
+    let users = user_ids.into_iter().map(|user_id| {
+        let bucket = rollout_bucket(&flag.key, &user_id);
+        UserPreview {
+            bucket,
+            included_from: bucket + 1,
+            included_now: eval_flag(flag, Some(&user_id)).matched,
+            variant: eval_flag(&everyone, Some(&user_id)).variant,
+            at: percentages.iter().map(|&rollout| AtRollout { rollout, included: bucket < rollout }).collect(),
+            user_id,
+        }
+    }).collect();
+    Preview { key: flag.key.clone(), enabled: flag.enabled, rollout: flag.rollout, users }
+}
+
+fn valid_percentages(p: &[u8]) -> bool {
+    !p.is_empty() && p.iter().all(|r| *r <= 100)
+}
+
+pub async fn preview_user(State(state): State<AppState>, Path(key): Path<String>, Query(params): Query<PreviewParams>) -> Result<Json<Preview>, StatusCode> {
+    let percentages = match params.percentages {
+        Some(p) => p.split(',').map(|r| r.trim().parse::<u8>()).collect::<Result<Vec<_>, _>>().map_err(|_| StatusCode::BAD_REQUEST)?,
+        None => DEFAULT_PERCENTAGES.to_vec(),
+    };
+    if !valid_percentages(&percentages) { return Err(StatusCode::BAD_REQUEST); }
+    let flag = state.overrides.apply(cache::get(&state, &key).await.map_err(store_status)?);
+    Ok(Json(preview(&flag, vec![params.user_id], &percentages)))
+}
+
+// read-only, so it only needs viewer even though it's a POST
+pub async fn preview_batch(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(key): Path<String>, Json(input): Json<BatchPreview>) -> Result<Json<Preview>, StatusCode> {
+    let percentages = input.percentages.unwrap_or_else(|| DEFAULT_PERCENTAGES.to_vec());
+    if input.user_ids.is_empty() || input.user_ids.len() > MAX_USERS || !valid_percentages(&percentages) { return Err(StatusCode::BAD_REQUEST); }
+    let flag = state.overrides.apply(cache::get(&state, &key).await.map_err(store_status)?);
+    principal.require(&flag.project, Role::Viewer)?;
+    Ok(Json(preview(&flag, input.user_ids, &percentages)))
+}
@@ -0,0 +1,59 @@
+use redis::aio::ConnectionManager;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+use crate::{config::PubsubConfig, stream::Changes};
+
+pub enum Broadcaster {
+    Nats { client: async_nats::Client },
+    Redis { conn: ConnectionManager },
+}
+
+#[derive(Serialize)]
+struct Notification<'a> {
+    key: &'a str,
+    action: &'a str,
+    environment: &'a str,
+    at: String,
+}
+
+impl Broadcaster {
+    pub async fn from_config(config: &PubsubConfig) -> anyhow::Result<Option<Broadcaster>> {
+        let Some(url) = &config.url else { return Ok(None) };
+        let broadcaster = match url.split_once("://").map(|(scheme, _)| scheme) {
+            Some("nats" | "tls") => Broadcaster::Nats { client: async_nats::connect(url.as_str()).await? },
+            Some("redis" | "rediss") => Broadcaster::Redis { conn: ConnectionManager::new(redis::Client::open(url.as_str())?).await? },
+            _ => anyhow::bail!("unsupported PUBSUB_URL {url:?}, expected nats:// or redis://"),
+        };
+        Ok(Some(broadcaster))
+    }
+
+    async fn publish(&mut self, channel: &str, payload: Vec<u8>) -> anyhow::Result<()> {
+        match self {
+            Broadcaster::Nats { client } => client.publish(channel.to_string(), payload.into()).await?,
+            Broadcaster::Redis { conn } => redis::cmd("PUBLISH").arg(channel).arg(payload).query_async::<()>(conn).await?,
+        }
+        Ok(())
+    }
+
+    pub fn spawn(mut self, config: &PubsubConfig, changes: &Changes, environment: Arc<str>) {
+        let channel = config.channel.clone().unwrap_or_else(|| "flags.changes".into());
+        tracing::info!(channel = %channel, "change broadcasting enabled");
+        let mut rx = changes.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let change = match rx.recv().await {
+                    Ok(change) if change.remote => continue,
+                    Ok(change) => change,
+                    Err(broadcast::error::RecvError::Lagged(n)) => { tracing::warn!(skipped = n, "change broadcaster fell behind"); continue; }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                let payload = serde_json::to_vec(&Notification { key: &change.key, action: change.action, environment: &environment, at: chrono::Utc::now().to_rfc3339() }).unwrap_or_default();
+                if let Err(e) = self.publish(&channel, payload).await {
+                    tracing::warn!(error = %e, key = %change.key, "failed to broadcast flag change");
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,256 @@
+use axum::{extract::{Path, State}, http::StatusCode, response::{IntoResponse, Response}, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqliteConnection};
+use std::sync::RwLock;
+
+use feature_flags_core::Flag;
+
+use crate::{auth::{Principal, Role}, config::QuotasConfig, AppState};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Limits {
+    max_flags: i64,
+    max_variants: i64,
+    max_pins: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetQuota {
+    max_flags: Option<i64>,
+    max_variants: Option<i64>,
+    max_pins: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    flags: i64,
+    most_variants: i64,
+    most_pins: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Quota {
+    project: String,
+    limits: Limits,
+    usage: Usage,
+    custom: bool,
+    updated_by: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuotaList {
+    defaults: Limits,
+    projects: Vec<Quota>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Exceeded {
+    error: &'static str,
+    project: String,
+    quota: &'static str,
+    limit: i64,
+    requested: i64,
+    pub message: String,
+}
+
+impl IntoResponse for Exceeded {
+    fn into_response(self) -> Response { (StatusCode::UNPROCESSABLE_ENTITY, Json(self)).into_response() }
+}
+
+static DEFAULTS: RwLock<QuotasConfig> = RwLock::new(QuotasConfig { max_flags: None, max_variants: None, max_pins: None });
+
+// set at startup and again on a config reload
+pub fn configure(config: &QuotasConfig) {
+    *DEFAULTS.write().unwrap() = config.clone();
+}
+
+fn defaults() -> Limits {
+    let config = DEFAULTS.read().unwrap();
+    let limit = |value: Option<i64>, default: i64| value.filter(|n| *n > 0).unwrap_or(default);
+    Limits { max_flags: limit(config.max_flags, 5000), max_variants: limit(config.max_variants, 50), max_pins: limit(config.max_pins, 10_000) }
+}
+
+// a project without its own row, or a column left NULL, gets the QUOTA_* default
+async fn limits(conn: &mut SqliteConnection, project: &str) -> Result<(Limits, Option<sqlx::sqlite::SqliteRow>), sqlx::Error> {
+    let d = defaults();
+    let row = sqlx::query("SELECT * FROM project_quotas WHERE project = ?").bind(project).fetch_optional(&mut *conn).await?;
+    let limits = match &row {
+        Some(r) => Limits {
+            max_flags: r.get::<Option<i64>, _>("max_flags").unwrap_or(d.max_flags),
+            max_variants: r.get::<Option<i64>, _>("max_variants").unwrap_or(d.max_variants),
+            max_pins: r.get::<Option<i64>, _>("max_pins").unwrap_or(d.max_pins),
+        },
+        None => d,
+    };
+    Ok((limits, row))
+}
+
+fn exceeded(project: &str, quota: &'static str, limit: i64, requested: i64, what: &str) -> Exceeded {
+    Exceeded { error: "quota_exceeded", project: project.to_string(), quota, limit, requested, message: format!("project {project} allows at most {limit} {what}, this would make {requested}") }
+}
+
+// run inside the transaction that wrote the flag, so the count includes it. only what the write changed is checked:
+// a flag already over a lowered limit can still be toggled, it just can't gain variants
+pub async fn check(conn: &mut SqliteConnection, after: &Flag, created: bool, variants_set: bool) -> Result<Result<(), Exceeded>, sqlx::Error> {
+    let (limits, _) = limits(conn, &after.project).await?;
+    let variants = after.variants.as_ref().map_or(0, |v| v.len()) as i64;
+    if (created || variants_set) && variants > limits.max_variants { return Ok(Err(exceeded(&after.project, "max_variants", limits.max_variants, variants, "variants per flag"))); }
+    if !created { return Ok(Ok(())); }
+    let flags: i64 = sqlx::query("SELECT COUNT(*) FROM flags WHERE project = ?").bind(&after.project).fetch_one(&mut *conn).await?.get(0);
+    Ok(if flags > limits.max_flags { Err(exceeded(&after.project, "max_flags", limits.max_flags, flags, "flags")) } else { Ok(()) })
+}
+
+// for callers that can only answer with a status, like apply and change requests; the reason goes to the log
+pub async fn enforce(conn: &mut SqliteConnection, after: &Flag, created: bool, variants_set: bool) -> Result<(), StatusCode> {
+    match check(conn, after, created, variants_set).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+        Ok(()) => Ok(()),
+        Err(e) => { tracing::info!(key = %after.key, quota = e.quota, "{}", e.message); Err(StatusCode::UNPROCESSABLE_ENTITY) }
+    }
+}
+
+// before a pin is added; replacing a user's existing pin doesn't count
+pub async fn check_pins(conn: &mut SqliteConnection, project: &str, key: &str, user_id: &str) -> Result<Result<(), Exceeded>, sqlx::Error> {
+    let (limits, _) = limits(conn, project).await?;
+    let others: i64 = sqlx::query("SELECT COUNT(*) FROM flag_pins WHERE flag_key = ? AND user_id != ? AND (expires_at IS NULL OR expires_at > datetime('now'))")
+        .bind(key)
+        .bind(user_id)
+        .fetch_one(&mut *conn)
+        .await?
+        .get(0);
+    Ok(if others >= limits.max_pins { Err(exceeded(project, "max_pins", limits.max_pins, others + 1, "overrides per flag")) } else { Ok(()) })
+}
+
+async fn quota(conn: &mut SqliteConnection, project: &str) -> Result<Quota, sqlx::Error> {
+    let (limits, row) = limits(conn, project).await?;
+    let flags: i64 = sqlx::query("SELECT COUNT(*) FROM flags WHERE project = ?").bind(project).fetch_one(&mut *conn).await?.get(0);
+    let most_variants: i64 = sqlx::query("SELECT COALESCE(MAX((SELECT COUNT(*) FROM json_each(variants))), 0) FROM flags WHERE project = ? AND variants IS NOT NULL")
+        .bind(project)
+        .fetch_one(&mut *conn)
+        .await?
+        .get(0);
+    let most_pins: i64 = sqlx::query("SELECT COALESCE(MAX(n), 0) FROM (SELECT COUNT(*) AS n FROM flag_pins p JOIN flags f ON f.key = p.flag_key WHERE f.project = ? AND (p.expires_at IS NULL OR p.expires_at > datetime('now')) GROUP BY p.flag_key)")
+        .bind(project)
+        .fetch_one(&mut *conn)
+        .await?
+        .get(0);
+    Ok(Quota {
+        project: project.to_string(),
+        limits,
+        usage: Usage { flags, most_variants, most_pins },
+        custom: row.is_some(),
+        updated_by: row.as_ref().map(|r| r.get("updated_by")),
+        updated_at: row.as_ref().map(|r| r.get("updated_at")),
+    })
+}
+
+// every project that has flags or its own limits
+pub async fn list_quotas(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Result<Json<QuotaList>, StatusCode> {
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let projects: Vec<String> = sqlx::query("SELECT project FROM flags UNION SELECT project FROM project_quotas ORDER BY project")
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|r| r.get::<String, _>("project"))
+        .filter(|p| principal.has_role(p, Role::Viewer))
+        .collect();
+    let mut out = Vec::with_capacity(projects.len());
+    for p in projects { out.push(quota(&mut conn, &p).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?); }
+    Ok(Json(QuotaList { defaults: defaults(), projects: out }))
+}
+
+pub async fn get_quota(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(project): Path<String>) -> Result<Json<Quota>, StatusCode> {
+    principal.require(&project, Role::Viewer)?;
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    quota(&mut conn, &project).await.map(Json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+// limits protect the whole deployment, so only admins on every project set them; lowering one never touches what already exists
+pub async fn set_quota(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(project): Path<String>, Json(input): Json<SetQuota>) -> Result<Json<Quota>, StatusCode> {
+    principal.require("*", Role::Admin)?;
+    if project.trim().is_empty() || [input.max_flags, input.max_variants, input.max_pins].iter().flatten().any(|n| *n < 1) { return Err(StatusCode::BAD_REQUEST); }
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("INSERT INTO project_quotas (project, max_flags, max_variants, max_pins, updated_by, updated_at) VALUES (?, ?, ?, ?, ?, datetime('now')) ON CONFLICT (project) DO UPDATE SET max_flags = excluded.max_flags, max_variants = excluded.max_variants, max_pins = excluded.max_pins, updated_by = excluded.updated_by, updated_at = excluded.updated_at")
+        .bind(&project)
+        .bind(input.max_flags)
+        .bind(input.max_variants)
+        .bind(input.max_pins)
+        .bind(&principal.subject)
+        .execute(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    quota(&mut conn, &project).await.map(Json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+pub async fn delete_quota(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(project): Path<String>) -> Result<StatusCode, StatusCode> {
+    principal.require("*", Role::Admin)?;
+    let rows = sqlx::query("DELETE FROM project_quotas WHERE project = ?").bind(&project).execute(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.rows_affected();
+    if rows == 0 { Err(StatusCode::NOT_FOUND) } else { Ok(StatusCode::NO_CONTENT) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{pool::PoolConnection, sqlite::SqlitePoolOptions, Sqlite};
+    use std::collections::HashMap;
+
+    async fn db() -> PoolConnection<Sqlite> {
+        let db = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        feature_flags_core::migrations::run(&db).await.unwrap();
+        db.acquire().await.unwrap()
+    }
+
+    async fn insert(conn: &mut SqliteConnection, key: &str, project: &str) {
+        sqlx::query("INSERT INTO flags (uid, key, project, enabled, updated_at) VALUES (?, ?, ?, 1, datetime('now'))").bind(key).bind(key).bind(project).execute(conn).await.unwrap();
+    }
+
+    fn flag(key: &str, project: &str, variants: usize) -> Flag {
+        Flag {
+            id: 0,
+            uid: key.into(),
+            key: key.into(),
+            project: project.into(),
+            enabled: true,
+            protected: false,
+            variants: (variants > 0).then(|| (0..variants).map(|i| (format!("v{i}"), 1)).collect()),
+            rollout: None,
+            lifecycle: Default::default(),
+            updated_at: String::new(),
+            pins: HashMap::new(),
+        }
+    }
+
+    async fn set(conn: &mut SqliteConnection, project: &str, max_flags: Option<i64>, max_variants: Option<i64>) {
+        sqlx::query("INSERT INTO project_quotas (project, max_flags, max_variants, updated_by, updated_at) VALUES (?, ?, ?, 'test', datetime('now'))").bind(project).bind(max_flags).bind(max_variants).execute(conn).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn counts_the_flag_being_created() {
+        let mut conn = db().await;
+        set(&mut conn, "web", Some(2), None).await;
+        insert(&mut conn, "a", "web").await;
+        insert(&mut conn, "b", "web").await;
+        assert!(check(&mut conn, &flag("b", "web", 0), true, false).await.unwrap().is_ok());
+        insert(&mut conn, "c", "web").await;
+        let e = check(&mut conn, &flag("c", "web", 0), true, false).await.unwrap().unwrap_err();
+        assert_eq!((e.quota, e.limit, e.requested), ("max_flags", 2, 3));
+        // updates don't count flags, and other projects keep the default
+        assert!(check(&mut conn, &flag("c", "web", 0), false, false).await.unwrap().is_ok());
+        insert(&mut conn, "d", "mobile").await;
+        assert!(check(&mut conn, &flag("d", "mobile", 0), true, false).await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn variants_only_checked_when_set() {
+        let mut conn = db().await;
+        set(&mut conn, "web", None, Some(3)).await;
+        insert(&mut conn, "a", "web").await;
+        assert!(check(&mut conn, &flag("a", "web", 3), false, true).await.unwrap().is_ok());
+        let e = check(&mut conn, &flag("a", "web", 4), false, true).await.unwrap().unwrap_err();
+        assert_eq!((e.quota, e.limit, e.requested), ("max_variants", 3, 4));
+        assert_eq!(check(&mut conn, &flag("a", "web", 4), true, false).await.unwrap().unwrap_err().quota, "max_variants");
+        // a toggle of a flag already over a lowered limit still goes through
+        assert!(check(&mut conn, &flag("a", "web", 4), false, false).await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,363 @@
+use axum::{extract::{Query, Request, State}, http::StatusCode, middleware::Next, response::{IntoResponse, Response}, routing::{get, post}, Extension, Json, Router};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, num::NonZeroUsize, sync::{atomic::{AtomicBool, AtomicI64, Ordering}, Arc, Mutex, RwLock}, time::{Duration, Instant}};
+
+use feature_flags_core::{Attributes, Bundle, BundleSigner, EvalResponse, Flag, Hooks};
+
+use crate::{auth::{self, ApiKey, KeyKind}, config::Config, encoded, header_context::HeaderContext, with_etag, overrides::Overrides, replication::{Change, Changes, Snapshot}, signing::{self, PublicKey, SignParams}, stream, BootstrapParams, EvalRequest, SnapshotParams};
+
+const KEY_TTL: Duration = Duration::from_secs(60);
+const MAX_KEYS: usize = 10_000;
+const RESYNC_INTERVAL: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(45);
+const STREAM_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+struct Replication {
+    syncing: tokio::sync::Mutex<()>,
+    applied: AtomicI64,
+    head: AtomicI64,
+    last_sync: RwLock<Option<Instant>>,
+    apply_lag: RwLock<Option<Duration>>,
+    max_lag: Option<Duration>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplicationStatus {
+    upstream: String,
+    synced: bool,
+    applied_seq: i64,
+    primary_seq: i64,
+    lag_changes: i64,
+    last_sync_secs_ago: Option<f64>,
+    apply_lag_secs: Option<f64>,
+    flags: usize,
+}
+
+pub struct Relay {
+    upstream: String,
+    token: String,
+    http: reqwest::Client,
+    environment: RwLock<String>,
+    flags: RwLock<Vec<Flag>>,
+    synced: AtomicBool,
+    replication: Replication,
+    keys: Mutex<LruCache<String, (Instant, ApiKey)>>,
+    changes: Arc<stream::Changes>,
+    overrides: Overrides,
+    hooks: Hooks,
+    header_context: Option<HeaderContext>,
+    signer: Option<BundleSigner>,
+    aliases: crate::identity::Aliases,
+}
+
+#[derive(Deserialize)]
+struct Linked {
+    anonymous_id: String,
+}
+
+impl Relay {
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Arc<Relay>>> {
+        let Some(upstream) = &config.relay.upstream else { return Ok(None) };
+        let token = config.relay.token.clone().ok_or_else(|| anyhow::anyhow!("RELAY_TOKEN (a server token on the upstream) is required when RELAY_UPSTREAM is set"))?;
+        let http = reqwest::Client::builder().connect_timeout(REQUEST_TIMEOUT).build()?;
+        Ok(Some(Arc::new(Relay {
+            upstream: upstream.trim_end_matches('/').to_string(),
+            token,
+            http,
+            environment: RwLock::new(String::new()),
+            flags: RwLock::new(Vec::new()),
+            synced: AtomicBool::new(false),
+            replication: Replication {
+                syncing: tokio::sync::Mutex::new(()),
+                applied: AtomicI64::new(0),
+                head: AtomicI64::new(0),
+                last_sync: RwLock::new(None),
+                apply_lag: RwLock::new(None),
+                max_lag: config.relay.max_lag_secs.map(Duration::from_secs),
+            },
+            keys: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_KEYS).unwrap())),
+            changes: Arc::new(stream::Changes::new()),
+            overrides: Overrides::from_env(),
+            hooks: crate::eval_hooks::from_config(&config.evaluation)?,
+            header_context: HeaderContext::from_config(&config.evaluation)?,
+            signer: crate::signing::from_config(&config.signing)?,
+            aliases: crate::identity::Aliases::from_config(&config.identity, false),
+        })))
+    }
+
+    pub fn upstream(&self) -> &str { &self.upstream }
+
+    // a snapshot on first sync, then only what changed since the last applied sequence number
+    async fn sync(&self) -> anyhow::Result<()> {
+        let _syncing = self.replication.syncing.lock().await;
+        if !self.synced.load(Ordering::Relaxed) {
+            let bundle: Bundle = self.http.get(format!("{}/bootstrap", self.upstream)).bearer_auth(&self.token).timeout(REQUEST_TIMEOUT)
+                .send().await?.error_for_status()?.json().await?;
+            *self.environment.write().unwrap() = bundle.environment;
+            self.resnapshot().await?;
+        }
+        loop {
+            let since = self.replication.applied.load(Ordering::Relaxed);
+            let res = self.http.get(format!("{}/replication/changes", self.upstream)).query(&[("since", since)]).bearer_auth(&self.token).timeout(REQUEST_TIMEOUT).send().await?;
+            // the primary pruned past our position (or was rebuilt), so catch up from a fresh snapshot
+            if res.status() == StatusCode::GONE {
+                tracing::warn!(since, "relay fell behind the primary's replication log, taking a new snapshot");
+                self.resnapshot().await?;
+                continue;
+            }
+            let page: Changes = res.error_for_status()?.json().await?;
+            let done = page.changes.is_empty() || page.seq >= page.head;
+            self.apply(page.changes);
+            self.replication.applied.store(page.seq, Ordering::Relaxed);
+            self.replication.head.store(page.head, Ordering::Relaxed);
+            if done { break; }
+        }
+        *self.replication.last_sync.write().unwrap() = Some(Instant::now());
+        if !self.synced.swap(true, Ordering::Relaxed) { tracing::info!(upstream = %self.upstream, flags = self.flags.read().unwrap().len(), seq = self.replication.applied.load(Ordering::Relaxed), "relay synced"); }
+        Ok(())
+    }
+
+    async fn resnapshot(&self) -> anyhow::Result<()> {
+        let snapshot: Snapshot = self.http.get(format!("{}/replication/snapshot", self.upstream)).bearer_auth(&self.token).timeout(REQUEST_TIMEOUT)
+            .send().await?.error_for_status()?.json().await?;
+        self.replace(snapshot.flags);
+        self.replication.applied.store(snapshot.seq, Ordering::Relaxed);
+        self.replication.head.store(snapshot.seq, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn replace(&self, flags: Vec<Flag>) {
+        let mut current = self.flags.write().unwrap();
+        let old: HashMap<&str, &Flag> = current.iter().map(|f| (f.key.as_str(), f)).collect();
+        for f in &flags {
+            match old.get(f.key.as_str()) {
+                None => self.changes.publish(&f.key, "created"),
+                Some(prev) if *prev != f => self.changes.publish(&f.key, "updated"),
+                Some(_) => {}
+            }
+        }
+        for key in old.keys().filter(|k| !flags.iter().any(|f| f.key == **k)) { self.changes.publish(key, "deleted"); }
+        *current = flags;
+    }
+
+    fn apply(&self, changes: Vec<Change>) {
+        let Some(last) = changes.last() else { return };
+        // how long the newest change took to get here from the primary
+        if let Ok(at) = chrono::NaiveDateTime::parse_from_str(&last.at, "%Y-%m-%d %H:%M:%S") {
+            *self.replication.apply_lag.write().unwrap() = Some((chrono::Utc::now().naive_utc() - at).to_std().unwrap_or_default());
+        }
+        let mut current = self.flags.write().unwrap();
+        for change in changes {
+            let existing = current.iter().position(|f| f.key == change.key);
+            match (existing, change.flag) {
+                (Some(i), Some(flag)) => if current[i] != flag { current[i] = flag; self.changes.publish(&change.key, "updated"); },
+                (None, Some(flag)) => { current.push(flag); self.changes.publish(&change.key, "created"); }
+                (Some(i), None) => { current.remove(i); self.changes.publish(&change.key, "deleted"); }
+                (None, None) => {}
+            }
+        }
+    }
+
+    fn status(&self) -> ReplicationStatus {
+        let applied = self.replication.applied.load(Ordering::Relaxed);
+        let head = self.replication.head.load(Ordering::Relaxed);
+        ReplicationStatus {
+            upstream: self.upstream.clone(),
+            synced: self.synced.load(Ordering::Relaxed),
+            applied_seq: applied,
+            primary_seq: head,
+            lag_changes: (head - applied).max(0),
+            last_sync_secs_ago: self.replication.last_sync.read().unwrap().map(|at| at.elapsed().as_secs_f64()),
+            apply_lag_secs: self.replication.apply_lag.read().unwrap().map(|d| d.as_secs_f64()),
+            flags: self.flags.read().unwrap().len(),
+        }
+    }
+
+    fn stale(&self) -> bool {
+        let Some(max_lag) = self.replication.max_lag else { return false };
+        self.replication.last_sync.read().unwrap().is_none_or(|at| at.elapsed() > max_lag)
+    }
+
+    async fn follow(&self) -> anyhow::Result<()> {
+        let mut res = self.http.get(format!("{}/stream", self.upstream)).bearer_auth(&self.token).send().await?.error_for_status()?;
+        let mut buf = String::new();
+        loop {
+            let chunk = tokio::time::timeout(STREAM_IDLE_TIMEOUT, res.chunk()).await.map_err(|_| anyhow::anyhow!("upstream stream went idle"))??
+                .ok_or_else(|| anyhow::anyhow!("upstream closed the stream"))?;
+            buf.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(end) = buf.find("\n\n") {
+                let block: String = buf.drain(..end + 2).collect();
+                let event = block.lines().find_map(|l| l.strip_prefix("event:")).map(str::trim);
+                if matches!(event, Some("ready" | "flag" | "resync")) {
+                    if let Err(e) = self.sync().await { tracing::warn!(error = %e, "relay sync failed"); }
+                }
+            }
+        }
+    }
+
+    // users linked through /identify are bucketed as their anonymous id, as on the primary
+    async fn bucketing_id(&self, user_id: Option<&str>, anonymous_id: Option<&str>) -> Option<String> {
+        self.aliases.resolve(user_id, anonymous_id, |user_id| async move {
+            let res = self.http.get(format!("{}/identify", self.upstream)).query(&[("user_id", user_id)]).bearer_auth(&self.token).send().await.map_err(|e| e.to_string())?;
+            if res.status() == StatusCode::NOT_FOUND { return Ok(None); }
+            let linked: Linked = res.error_for_status().map_err(|e| e.to_string())?.json().await.map_err(|e| e.to_string())?;
+            Ok(Some(linked.anonymous_id))
+        }).await
+    }
+
+    // the upstream says what the key is and which prefixes it's scoped to, so the relay filters exactly as the primary would.
+    // only valid keys are kept, and only the most recently used MAX_KEYS of them, so made-up tokens can't grow the cache
+    async fn authorize(&self, secret: &str) -> Result<ApiKey, StatusCode> {
+        let hash = auth::hash_secret(secret);
+        let cached = self.keys.lock().unwrap().get(&hash).cloned();
+        if let Some((at, key)) = &cached {
+            if at.elapsed() < KEY_TTL { return Ok(key.clone()); }
+        }
+        let res = self.http.get(format!("{}/sdk-key", self.upstream)).bearer_auth(secret).timeout(REQUEST_TIMEOUT).send().await;
+        match res {
+            Ok(r) if r.status().is_success() => {
+                let key: ApiKey = r.json().await.map_err(|_| StatusCode::BAD_GATEWAY)?;
+                self.keys.lock().unwrap().put(hash, (Instant::now(), key.clone()));
+                Ok(key)
+            }
+            Ok(r) if r.status() == StatusCode::UNAUTHORIZED => { self.keys.lock().unwrap().pop(&hash); Err(StatusCode::UNAUTHORIZED) }
+            Ok(r) if r.status() == StatusCode::TOO_MANY_REQUESTS => Err(StatusCode::TOO_MANY_REQUESTS),
+            _ => cached.map(|(_, key)| key).ok_or(StatusCode::SERVICE_UNAVAILABLE),
+        }
+    }
+}
+
+pub async fn serve(relay: Arc<Relay>, config: &Config) -> anyhow::Result<()> {
+    tracing::info!(upstream = %relay.upstream, "running as a read-only relay");
+    tokio::spawn(resync(Arc::downgrade(&relay)));
+    tokio::spawn(follow(Arc::downgrade(&relay)));
+
+    let app = crate::telemetry::layer(Router::new()
+        .route("/evaluate", post(evaluate))
+        .route("/snapshot", get(snapshot))
+        .route("/rules", get(rules))
+        .route("/bootstrap", get(bootstrap))
+        .route("/stream", get(relay_stream))
+        .route("/sdk-key", get(auth::sdk_key))
+        .route("/metrics", get(metrics))
+        .route_layer(axum::middleware::from_fn_with_state(relay.clone(), require_key))
+        .route("/health", get(health))
+        .route("/bucketing/test-vectors", get(crate::bucketing::test_vectors))
+        .route("/signing-key", get(signing_key))
+        .route("/replication/status", get(replication_status))
+        .with_state(relay.clone())
+        .layer(config.cors.layer()?), &config.access_log)?;
+
+    let changes = relay.changes.clone();
+    crate::server::Server::from_config(&config.server)?.serve(app, async move { crate::shutdown_signal().await; changes.close(); }).await?;
+    Ok(())
+}
+
+// polling more often than the lag budget keeps an idle but healthy relay from looking stale
+async fn resync(relay: std::sync::Weak<Relay>) {
+    let every = relay.upgrade().and_then(|r| r.replication.max_lag).map_or(RESYNC_INTERVAL, |lag| (lag / 2).clamp(Duration::from_secs(1), RESYNC_INTERVAL));
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        ticker.tick().await;
+        let Some(relay) = relay.upgrade() else { return };
+        if let Err(e) = relay.sync().await { tracing::warn!(error = %e, "relay sync failed"); }
+    }
+}
+
+async fn follow(relay: std::sync::Weak<Relay>) {
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        let Some(strong) = relay.upgrade() else { return };
+        let started = Instant::now();
+        let err = strong.follow().await.err();
+        drop(strong);
+        if started.elapsed() > STREAM_IDLE_TIMEOUT { backoff = Duration::from_secs(1); }
+        if let Some(e) = err { tracing::warn!(error = %e, retry_in = ?backoff, "upstream stream unavailable, relying on periodic resync"); }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(STREAM_MAX_BACKOFF);
+    }
+}
+
+async fn require_key(State(relay): State<Arc<Relay>>, mut req: Request, next: Next) -> Result<Response, StatusCode> {
+    let key = relay.authorize(auth::bearer_token(req.headers())?).await?;
+    if !relay.synced.load(Ordering::Relaxed) { return Err(StatusCode::SERVICE_UNAVAILABLE); }
+    req.extensions_mut().insert(key);
+    Ok(next.run(req).await)
+}
+
+// with RELAY_MAX_LAG_SECS set, a relay that can't reach the primary drops out of the load balancer instead of serving stale flags
+async fn health(State(relay): State<Arc<Relay>>) -> Result<&'static str, StatusCode> {
+    if relay.synced.load(Ordering::Relaxed) && !relay.stale() { Ok("ok") } else { Err(StatusCode::SERVICE_UNAVAILABLE) }
+}
+
+async fn replication_status(State(relay): State<Arc<Relay>>) -> Json<ReplicationStatus> {
+    Json(relay.status())
+}
+
+async fn metrics(State(relay): State<Arc<Relay>>) -> Response {
+    let status = relay.status();
+    crate::metrics::set_gauge("flags_replication_applied_seq", "Last primary change sequence number applied by this relay.", status.applied_seq as f64);
+    crate::metrics::set_gauge("flags_replication_primary_seq", "Latest change sequence number seen on the primary.", status.primary_seq as f64);
+    crate::metrics::set_gauge("flags_replication_lag_changes", "Changes on the primary this relay hasn't applied yet.", status.lag_changes as f64);
+    crate::metrics::set_gauge("flags_replication_last_sync_age_seconds", "Seconds since this relay last caught up with the primary.", status.last_sync_secs_ago.unwrap_or(f64::INFINITY));
+    crate::metrics::set_gauge("flags_replication_apply_lag_seconds", "Delay between the newest applied change on the primary and it reaching this relay.", status.apply_lag_secs.unwrap_or(0.0));
+    crate::metrics::metrics().await
+}
+
+async fn evaluate(State(relay): State<Arc<Relay>>, Extension(key): Extension<ApiKey>, headers: axum::http::HeaderMap, Json(req): Json<EvalRequest>) -> Result<Json<EvalResponse>, StatusCode> {
+    crate::telemetry::record_flag(&req.key);
+    if !key.sees(&req.key) { return Err(StatusCode::NOT_FOUND); }
+    let flag = relay.flags.read().unwrap().iter().find(|f| f.key == req.key).cloned().ok_or(StatusCode::NOT_FOUND)?;
+    let mut context = req.context;
+    if let Some(h) = &relay.header_context { h.enrich(&headers, &mut context); }
+    let identity = crate::cdn::evaluated_as(relay.bucketing_id(req.user_id.as_deref(), req.anonymous_id.as_deref()).await.as_deref());
+    Ok(Json(crate::metrics::evaluate("", &relay.hooks, &relay.overrides.apply(flag), req.user_id.as_deref(), identity.as_deref(), &context)))
+}
+
+async fn snapshot(State(relay): State<Arc<Relay>>, Extension(key): Extension<ApiKey>, Query(params): Query<SnapshotParams>, headers: axum::http::HeaderMap) -> Result<Response, StatusCode> {
+    let bucket = crate::cdn::bucket(&params)?;
+    let user_id = params.user_id.as_deref().filter(|_| bucket.is_none());
+    let subject = bucket.clone().or_else(|| params.user_id.clone()).or_else(|| params.anonymous_id.clone());
+    let identity = match bucket {
+        Some(bucket) => Some(bucket),
+        None => crate::cdn::evaluated_as(relay.bucketing_id(params.user_id.as_deref(), params.anonymous_id.as_deref()).await.as_deref()),
+    };
+    let environment = relay.environment.read().unwrap().clone();
+    let mut flags = relay.overrides.apply_all(relay.flags.read().unwrap().clone());
+    flags.retain(|f| key.sees(&f.key) && params.prefix.as_deref().is_none_or(|p| f.key.starts_with(p)));
+    let mut context = Attributes::new();
+    if let Some(h) = &relay.header_context { h.enrich(&headers, &mut context); }
+    let out = crate::metrics::evaluate_all("", &relay.hooks, &flags, user_id, identity.as_deref(), &context);
+    let mut res = crate::cdn::cache_headers(&params, with_etag(&headers, &signing::wrap(relay.signer.as_ref(), params.signed, &environment, subject.as_deref(), &out)?)?);
+    if let Some(h) = &relay.header_context { h.vary(&mut res); }
+    Ok(res)
+}
+
+async fn rules(State(relay): State<Arc<Relay>>, Extension(key): Extension<ApiKey>, Query(params): Query<SignParams>, headers: axum::http::HeaderMap) -> Result<Response, StatusCode> {
+    if key.kind != KeyKind::Server { return Err(StatusCode::FORBIDDEN); }
+    let mut flags = relay.overrides.apply_all(relay.flags.read().unwrap().clone());
+    flags.retain(|f| key.sees(&f.key));
+    let environment = relay.environment.read().unwrap().clone();
+    with_etag(&headers, &signing::wrap(relay.signer.as_ref(), params.signed, &environment, None, &flags)?)
+}
+
+async fn bootstrap(State(relay): State<Arc<Relay>>, Extension(key): Extension<ApiKey>, Query(params): Query<BootstrapParams>, headers: axum::http::HeaderMap) -> Result<Response, StatusCode> {
+    if key.kind != KeyKind::Server { return Err(StatusCode::FORBIDDEN); }
+    let environment = relay.environment.read().unwrap().clone();
+    if params.env.as_deref().is_some_and(|e| e != environment) { return Err(StatusCode::NOT_FOUND); }
+    let mut flags = relay.overrides.apply_all(relay.flags.read().unwrap().clone());
+    flags.retain(|f| key.sees(&f.key));
+    let bundle = Bundle { environment, generated_at: chrono::Utc::now().to_rfc3339(), flags };
+    encoded(&headers, &signing::wrap(relay.signer.as_ref(), params.signed, &bundle.environment, None, &bundle)?)
+}
+
+// the relay signs with its own SIGNING_KEY, so edge consumers trust the relay's key rather than the primary's
+async fn signing_key(State(relay): State<Arc<Relay>>) -> Result<Json<PublicKey>, StatusCode> {
+    signing::public_key(relay.signer.as_ref())
+}
+
+async fn relay_stream(State(relay): State<Arc<Relay>>, Extension(key): Extension<ApiKey>) -> impl IntoResponse {
+    stream::sse(&relay.changes, key.flag_prefixes)
+}
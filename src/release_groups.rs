@@ -0,0 +1,212 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqliteConnection};
+use std::collections::HashSet;
+
+use feature_flags_core::{diff_flags, sqlite, FieldChange, Flag, UpdateFlag};
+
+use crate::{auth::{Principal, Role}, experiments, store_status, AppState};
+
+const MAX_FLAGS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateGroup {
+    name: String,
+    description: Option<String>,
+    flags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateGroup {
+    description: Option<String>,
+    flags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ToggleParams {
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    confirm_protected: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Member {
+    key: String,
+    project: String,
+    enabled: bool,
+}
+
+// `state` is on or off when every flag agrees, mixed otherwise
+#[derive(Debug, Serialize)]
+pub struct ReleaseGroup {
+    name: String,
+    description: Option<String>,
+    state: &'static str,
+    flags: Vec<Member>,
+    created_by: String,
+    created_at: String,
+    updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Toggled {
+    key: String,
+    project: String,
+    diff: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToggleResponse {
+    group: String,
+    enabled: bool,
+    dry_run: bool,
+    changes: Vec<Toggled>,
+    unchanged: usize,
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+// every flag must exist and the caller must be able to change it, so nobody can put a flag they can't toggle into a group
+async fn resolve_flags(conn: &mut SqliteConnection, principal: &Principal, keys: &[String]) -> Result<Vec<Flag>, StatusCode> {
+    let unique: HashSet<&String> = keys.iter().collect();
+    if keys.is_empty() || keys.len() > MAX_FLAGS || unique.len() != keys.len() { return Err(StatusCode::BAD_REQUEST); }
+    let mut flags = Vec::with_capacity(keys.len());
+    for key in keys {
+        let flag = sqlite::fetch_flag(conn, key).await.map_err(store_status)?;
+        principal.require(&flag.project, Role::Editor)?;
+        flags.push(flag);
+    }
+    Ok(flags)
+}
+
+async fn set_members(conn: &mut SqliteConnection, name: &str, flags: &[Flag]) -> Result<(), StatusCode> {
+    sqlx::query("DELETE FROM release_group_flags WHERE group_name = ?").bind(name).execute(&mut *conn).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for f in flags {
+        sqlx::query("INSERT INTO release_group_flags (group_name, flag_key) VALUES (?, ?)").bind(name).bind(&f.key).execute(&mut *conn).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    Ok(())
+}
+
+async fn fetch_group(conn: &mut SqliteConnection, name: &str) -> Result<ReleaseGroup, StatusCode> {
+    let r = sqlx::query("SELECT * FROM release_groups WHERE name = ?")
+        .bind(name)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let flags: Vec<Member> = sqlx::query("SELECT f.key, f.project, f.enabled FROM release_group_flags g JOIN flags f ON f.key = g.flag_key WHERE g.group_name = ? ORDER BY f.key")
+        .bind(name)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|r| Member { key: r.get("key"), project: r.get("project"), enabled: r.get::<i64, _>("enabled") != 0 })
+        .collect();
+    let state = if flags.iter().all(|m| m.enabled) { "on" } else if flags.iter().all(|m| !m.enabled) { "off" } else { "mixed" };
+    Ok(ReleaseGroup { name: r.get("name"), description: r.get("description"), state, flags, created_by: r.get("created_by"), created_at: r.get("created_at"), updated_at: r.get("updated_at") })
+}
+
+fn visible(principal: &Principal, group: &ReleaseGroup) -> bool {
+    group.flags.iter().all(|m| principal.has_role(&m.project, Role::Viewer))
+}
+
+pub async fn list_groups(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Result<Json<Vec<ReleaseGroup>>, StatusCode> {
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let names: Vec<String> = sqlx::query("SELECT name FROM release_groups ORDER BY name").fetch_all(&mut *conn).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.into_iter().map(|r| r.get("name")).collect();
+    let mut out = Vec::with_capacity(names.len());
+    for name in names {
+        let group = fetch_group(&mut conn, &name).await?;
+        if visible(&principal, &group) { out.push(group); }
+    }
+    Ok(Json(out))
+}
+
+pub async fn get_group(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>) -> Result<Json<ReleaseGroup>, StatusCode> {
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let group = fetch_group(&mut conn, &name).await?;
+    if !visible(&principal, &group) { return Err(StatusCode::FORBIDDEN); }
+    Ok(Json(group))
+}
+
+pub async fn create_group(State(state): State<AppState>, Extension(principal): Extension<Principal>, Json(input): Json<CreateGroup>) -> Result<Json<ReleaseGroup>, StatusCode> {
+    if !valid_name(&input.name) { return Err(StatusCode::BAD_REQUEST); }
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let flags = resolve_flags(&mut tx, &principal, &input.flags).await?;
+    let rows = sqlx::query("INSERT OR IGNORE INTO release_groups (name, description, created_by, created_at, updated_at) VALUES (?, ?, ?, datetime('now'), datetime('now'))")
+        .bind(&input.name)
+        .bind(&input.description)
+        .bind(&principal.subject)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::CONFLICT); }
+    set_members(&mut tx, &input.name, &flags).await?;
+    let group = fetch_group(&mut tx, &input.name).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(group))
+}
+
+// replaces the description and the member list; the caller needs editor on the flags before and after
+pub async fn update_group(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>, Json(input): Json<UpdateGroup>) -> Result<Json<ReleaseGroup>, StatusCode> {
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let current = fetch_group(&mut tx, &name).await?;
+    for m in &current.flags { principal.require(&m.project, Role::Editor)?; }
+    let flags = resolve_flags(&mut tx, &principal, &input.flags).await?;
+    sqlx::query("UPDATE release_groups SET description = ?, updated_at = datetime('now') WHERE name = ?").bind(&input.description).bind(&name).execute(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    set_members(&mut tx, &name, &flags).await?;
+    let group = fetch_group(&mut tx, &name).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(group))
+}
+
+pub async fn delete_group(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>) -> Result<StatusCode, StatusCode> {
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let current = fetch_group(&mut tx, &name).await?;
+    for m in &current.flags { principal.require(&m.project, Role::Editor)?; }
+    sqlx::query("DELETE FROM release_group_flags WHERE group_name = ?").bind(&name).execute(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("DELETE FROM release_groups WHERE name = ?").bind(&name).execute(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn enable_group(state: State<AppState>, principal: Extension<Principal>, name: Path<String>, params: Query<ToggleParams>) -> Result<Json<ToggleResponse>, StatusCode> {
+    toggle(state, principal, name, params, true).await
+}
+
+pub async fn disable_group(state: State<AppState>, principal: Extension<Principal>, name: Path<String>, params: Query<ToggleParams>) -> Result<Json<ToggleResponse>, StatusCode> {
+    toggle(state, principal, name, params, false).await
+}
+
+// all or nothing: every flag is changed in one transaction, and any flag the caller may not change fails the whole call
+async fn toggle(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>, Query(params): Query<ToggleParams>, enabled: bool) -> Result<Json<ToggleResponse>, StatusCode> {
+    if crate::break_glass::approval_required(&state, &principal) && !params.dry_run { return Err(StatusCode::FORBIDDEN); }
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let group = fetch_group(&mut tx, &name).await?;
+    let mut changes = Vec::new();
+    let mut unchanged = 0;
+    for member in &group.flags {
+        principal.require(&member.project, Role::Editor)?;
+        if member.enabled == enabled { unchanged += 1; continue; }
+        let current = sqlite::fetch_flag(&mut tx, &member.key).await.map_err(store_status)?;
+        if current.protected {
+            principal.require(&current.project, Role::Admin)?;
+            if !params.confirm_protected { return Err(StatusCode::PRECONDITION_REQUIRED); }
+        }
+        let update = UpdateFlag { enabled: Some(enabled), ..Default::default() };
+        experiments::check_unlocked(&mut tx, &current.key, Some(&update)).await?;
+        let (before, after) = sqlite::apply_update(&mut tx, &current.key, &update).await.map_err(store_status)?;
+        changes.push(Toggled { key: current.key, project: current.project, diff: diff_flags(Some(&before), &after) });
+    }
+    if params.dry_run {
+        tx.rollback().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    } else {
+        tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        for c in &changes { state.flag_changes.publish(&c.key, "updated"); }
+        tracing::info!(group = %name, enabled, changed = changes.len(), actor = %principal.subject, "release group toggled");
+    }
+    Ok(Json(ToggleResponse { group: name, enabled, dry_run: params.dry_run, changes, unchanged }))
+}
@@ -0,0 +1,99 @@
+use axum::{extract::{Query, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::time::Duration;
+
+use feature_flags_core::{sqlite, Flag, StoreError};
+
+use crate::{auth::{ApiKey, KeyKind}, config::ReplicationConfig, jobs::Job, store_status, AppState};
+
+const MAX_PAGE: i64 = 1000;
+
+// every write to the flags table lands in replication_log through triggers, so followers see changes
+// from the API, /apply, Git sync and FLAGS_FILE alike
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub seq: i64,
+    pub at: String,
+    pub flags: Vec<Flag>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Change {
+    pub seq: i64,
+    pub key: String,
+    pub at: String,
+    pub flag: Option<Flag>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Changes {
+    pub seq: i64,
+    pub head: i64,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesParams {
+    since: i64,
+    limit: Option<i64>,
+}
+
+// a replica needs every flag, so keys scoped to prefixes can't replicate
+fn require_server(key: &ApiKey) -> Result<(), StatusCode> {
+    if key.kind == KeyKind::Server && key.flag_prefixes.is_empty() { Ok(()) } else { Err(StatusCode::FORBIDDEN) }
+}
+
+fn timestamp() -> String { chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string() }
+
+pub async fn snapshot(State(state): State<AppState>, Extension(key): Extension<ApiKey>) -> Result<Json<Snapshot>, StatusCode> {
+    require_server(&key)?;
+    // one read transaction, so the flags are exactly the state as of seq
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let seq = sqlx::query("SELECT COALESCE(MAX(seq), 0) AS seq FROM replication_log").fetch_one(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.get("seq");
+    let flags = sqlite::list_flags(&mut tx).await.map_err(store_status)?;
+    tx.rollback().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(Snapshot { seq, at: timestamp(), flags }))
+}
+
+// several changes to one flag collapse into its latest state; 410 when the log no longer reaches back to since
+pub async fn changes(State(state): State<AppState>, Extension(key): Extension<ApiKey>, Query(params): Query<ChangesParams>) -> Result<Json<Changes>, StatusCode> {
+    require_server(&key)?;
+    let limit = params.limit.unwrap_or(MAX_PAGE).clamp(1, MAX_PAGE);
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let bounds = sqlx::query("SELECT MIN(seq) AS first, COALESCE(MAX(seq), 0) AS head FROM replication_log").fetch_one(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let head: i64 = bounds.get("head");
+    if params.since < 0 || params.since > head || bounds.get::<Option<i64>,_>("first").is_some_and(|first| params.since < first - 1) { return Err(StatusCode::GONE); }
+    let rows = sqlx::query("SELECT flag_key, MAX(seq) AS seq, MAX(at) AS at FROM replication_log WHERE seq > ? GROUP BY flag_key ORDER BY seq LIMIT ?")
+        .bind(params.since)
+        .bind(limit + 1)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let more = rows.len() as i64 > limit;
+    let mut changes = Vec::with_capacity(rows.len());
+    for r in rows.into_iter().take(limit as usize) {
+        let key: String = r.get("flag_key");
+        let flag = match sqlite::fetch_flag(&mut tx, &key).await {
+            Ok(f) => Some(f),
+            Err(StoreError::NotFound) => None,
+            Err(e) => return Err(store_status(e)),
+        };
+        changes.push(Change { seq: r.get("seq"), key, at: r.get("at"), flag });
+    }
+    tx.rollback().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let seq = if more { changes.last().map_or(params.since, |c| c.seq) } else { head };
+    Ok(Json(Changes { seq, head, changes }))
+}
+
+// the newest entry is always kept so the sequence survives a quiet period
+pub fn job(config: &ReplicationConfig) -> Job {
+    let days = config.retention_days.filter(|d| *d > 0).unwrap_or(7);
+    Job::new("replication-prune", Duration::from_secs(3600), move |state| async move {
+        sqlx::query("DELETE FROM replication_log WHERE at < datetime('now', ?) AND seq < (SELECT MAX(seq) FROM replication_log)")
+            .bind(format!("-{days} days"))
+            .execute(&state.db)
+            .await?;
+        Ok(())
+    })
+}
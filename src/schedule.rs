@@ -0,0 +1,208 @@
+use axum::{extract::{Path, State}, http::StatusCode, Extension, Json};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::time::Duration;
+
+use feature_flags_core::{sqlite, UpdateFlag};
+
+use crate::{audit, auth::{Principal, Role}, config::ScheduleConfig, jobs::Job, store_status, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleInput {
+    run_at: String,
+    #[serde(flatten)]
+    changes: UpdateFlag,
+}
+
+// one scheduled rollout change per step, `every_secs` apart, starting at `start_at` or straight away
+#[derive(Debug, Deserialize)]
+pub struct RampInput {
+    steps: Vec<u8>,
+    every_secs: u64,
+    start_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduledAction {
+    id: i64,
+    flag_key: String,
+    changes: UpdateFlag,
+    run_at: String,
+    status: String,
+    error: Option<String>,
+    created_by: String,
+    created_at: String,
+    executed_at: Option<String>,
+}
+
+const MAX_RAMP_STEPS: usize = 20;
+
+// RFC 3339, or minute precision like `2024-06-01T09:00Z`
+fn parse_run_at(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s).map(|t| t.with_timezone(&Utc)).ok()
+        .or_else(|| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%MZ").ok().map(|t| t.and_utc()))
+}
+
+pub async fn schedule_action(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(key): Path<String>, Json(input): Json<ScheduleInput>) -> Result<Json<ScheduledAction>, StatusCode> {
+    if crate::break_glass::approval_required(&state, &principal) { return Err(StatusCode::FORBIDDEN); }
+    let run_at = parse_run_at(&input.run_at).filter(|t| *t > Utc::now()).ok_or(StatusCode::BAD_REQUEST)?;
+    input.changes.validate().map_err(store_status)?;
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let flag = sqlite::resolve_flag(&mut conn, &key).await.map_err(store_status)?;
+    drop(conn);
+    // the worker has no caller to check, so whether the change may touch a protected flag is decided now
+    let admin = principal.has_role(&flag.project, Role::Admin);
+    if (flag.protected || input.changes.protected.is_some()) && !admin { return Err(StatusCode::FORBIDDEN); }
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let id = insert_action(&mut conn, &flag.key, &input.changes, run_at, admin, &principal.subject).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    drop(conn);
+    fetch_action(&state.db, id).await.map(Json)
+}
+
+async fn insert_action(conn: &mut sqlx::SqliteConnection, key: &str, changes: &UpdateFlag, run_at: DateTime<Utc>, protected_ok: bool, created_by: &str) -> Result<i64, sqlx::Error> {
+    Ok(sqlx::query("INSERT INTO scheduled_actions (flag_key, changes, run_at, status, protected_ok, created_by, created_at) VALUES (?, ?, ?, 'pending', ?, ?, datetime('now'))")
+        .bind(key)
+        .bind(serde_json::to_string(changes).unwrap())
+        .bind(run_at.format("%Y-%m-%d %H:%M:%S").to_string())
+        .bind(protected_ok)
+        .bind(created_by)
+        .execute(conn)
+        .await?
+        .last_insert_rowid())
+}
+
+// e.g. {"steps": [5, 25, 50, 100], "every_secs": 3600}; each step only sets the rollout, so a flag a guard switched off stays off
+pub async fn ramp(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(key): Path<String>, Json(input): Json<RampInput>) -> Result<Json<Vec<ScheduledAction>>, StatusCode> {
+    if crate::break_glass::approval_required(&state, &principal) { return Err(StatusCode::FORBIDDEN); }
+    if input.steps.is_empty() || input.steps.len() > MAX_RAMP_STEPS || input.steps.iter().any(|s| *s > 100) || !(60..=30 * 86_400).contains(&input.every_secs) { return Err(StatusCode::BAD_REQUEST); }
+    let start = match &input.start_at {
+        Some(at) => parse_run_at(at).filter(|t| *t > Utc::now()).ok_or(StatusCode::BAD_REQUEST)?,
+        None => Utc::now(),
+    };
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let flag = sqlite::resolve_flag(&mut tx, &key).await.map_err(store_status)?;
+    let admin = principal.has_role(&flag.project, Role::Admin);
+    if flag.protected && !admin { return Err(StatusCode::FORBIDDEN); }
+    let mut ids = Vec::new();
+    for (i, step) in input.steps.iter().enumerate() {
+        let changes = UpdateFlag { rollout: Some(*step), ..Default::default() };
+        let run_at = start + chrono::Duration::seconds((input.every_secs * i as u64) as i64);
+        ids.push(insert_action(&mut tx, &flag.key, &changes, run_at, admin, &principal.subject).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?);
+    }
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut out = Vec::new();
+    for id in ids { out.push(fetch_action(&state.db, id).await?); }
+    Ok(Json(out))
+}
+
+pub async fn list_actions(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<Vec<ScheduledAction>>, StatusCode> {
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let flag = sqlite::resolve_flag(&mut conn, &key).await.map_err(store_status)?;
+    let rows = sqlx::query("SELECT * FROM scheduled_actions WHERE flag_key = ? ORDER BY run_at, id")
+        .bind(&flag.key)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let out = rows.into_iter().map(row_to_action).collect::<Result<Vec<_>, _>>().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(out))
+}
+
+pub async fn cancel_action(State(state): State<AppState>, Path((key, id)): Path<(String, i64)>) -> Result<Json<ScheduledAction>, StatusCode> {
+    let action = fetch_action(&state.db, id).await?;
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if sqlite::resolve_flag(&mut conn, &key).await.map_err(store_status)?.key != action.flag_key { return Err(StatusCode::NOT_FOUND); }
+    let rows = sqlx::query("UPDATE scheduled_actions SET status = 'cancelled' WHERE id = ? AND status = 'pending'")
+        .bind(id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::CONFLICT); }
+    drop(conn);
+    fetch_action(&state.db, id).await.map(Json)
+}
+
+async fn fetch_action(db: &sqlx::Pool<sqlx::Sqlite>, id: i64) -> Result<ScheduledAction, StatusCode> {
+    let r = sqlx::query("SELECT * FROM scheduled_actions WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    row_to_action(r).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn row_to_action(r: sqlx::sqlite::SqliteRow) -> Result<ScheduledAction, anyhow::Error> {
+    Ok(ScheduledAction {
+        id: r.get("id"),
+        flag_key: r.get("flag_key"),
+        changes: serde_json::from_str(&r.get::<String,_>("changes"))?,
+        run_at: r.get("run_at"),
+        status: r.get("status"),
+        error: r.get("error"),
+        created_by: r.get("created_by"),
+        created_at: r.get("created_at"),
+        executed_at: r.get("executed_at"),
+    })
+}
+
+// claiming and applying happen in one transaction, so with several instances each action runs exactly once
+async fn execute(state: &AppState, action: &ScheduledAction, protected_ok: bool) -> Result<bool, String> {
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    let claimed = sqlx::query("UPDATE scheduled_actions SET status = 'done', executed_at = datetime('now') WHERE id = ? AND status = 'pending'")
+        .bind(action.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .rows_affected();
+    if claimed == 0 { return Ok(false); }
+    let current = sqlite::fetch_flag(&mut tx, &action.flag_key).await.map_err(|e| e.to_string())?;
+    if current.protected && !protected_ok { return Err("flag was protected after the action was scheduled".into()); }
+    crate::experiments::check_unlocked(&mut tx, &action.flag_key, Some(&action.changes)).await.map_err(|s| format!("rejected with {s}"))?;
+    let (_, after) = sqlite::apply_update(&mut tx, &action.flag_key, &action.changes).await.map_err(|e| e.to_string())?;
+    if let Err(e) = crate::quotas::check(&mut tx, &after, false, action.changes.variants.is_some()).await.map_err(|e| e.to_string())? { return Err(e.message); }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+async fn run_due(state: &AppState) -> Result<(), sqlx::Error> {
+    let rows = sqlx::query("SELECT * FROM scheduled_actions WHERE status = 'pending' AND run_at <= datetime('now') ORDER BY run_at, id")
+        .fetch_all(&state.db)
+        .await?;
+    for r in rows {
+        let protected_ok = r.get::<bool,_>("protected_ok");
+        let action = match row_to_action(r) {
+            Ok(a) => a,
+            Err(e) => { tracing::warn!(error = %e, "skipping unreadable scheduled action"); continue; }
+        };
+        let target = format!("/flags/{}", action.flag_key);
+        let (outcome, error) = match execute(state, &action, protected_ok).await {
+            Ok(false) => continue,
+            Ok(true) => { state.flag_changes.publish(&action.flag_key, "updated"); ("ok", None) }
+            Err(e) => {
+                sqlx::query("UPDATE scheduled_actions SET status = 'failed', error = ?, executed_at = datetime('now') WHERE id = ? AND status = 'pending'")
+                    .bind(&e)
+                    .bind(action.id)
+                    .execute(&state.db)
+                    .await?;
+                tracing::warn!(id = action.id, key = %action.flag_key, error = %e, "scheduled action failed");
+                ("failed", Some(e))
+            }
+        };
+        state.audit.record(audit::NewEntry {
+            actor: Some(&action.created_by),
+            action: "schedule.executed",
+            target: Some(&target),
+            outcome,
+            detail: Some(serde_json::json!({ "id": action.id, "run_at": action.run_at, "changes": action.changes, "error": error })),
+            ..Default::default()
+        }).await;
+    }
+    Ok(())
+}
+
+pub fn job(config: &ScheduleConfig) -> Job {
+    let every = config.poll_secs.filter(|s| *s > 0).unwrap_or(10);
+    Job::new("scheduled-actions", Duration::from_secs(every), |state| async move { Ok(run_due(&state).await?) })
+}
@@ -0,0 +1,113 @@
+use axum::{extract::{ConnectInfo, Request}, Router};
+use hyper::body::Incoming;
+use hyper_util::{rt::{TokioExecutor, TokioIo, TokioTimer}, server::{conn::auto, graceful::{GracefulShutdown, Watcher}}, service::TowerToHyperService};
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{io::{AsyncRead, AsyncWrite}, net::TcpListener};
+use tokio_rustls::{rustls, TlsAcceptor};
+use tower::ServiceExt;
+
+use crate::config::ServerConfig;
+
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Server {
+    addr: SocketAddr,
+    tls: Option<TlsAcceptor>,
+    http2: bool,
+    keep_alive: bool,
+    header_read_timeout: Duration,
+    h2_keep_alive_interval: Option<Duration>,
+    h2_keep_alive_timeout: Duration,
+    h2_max_concurrent_streams: u32,
+    shutdown_timeout: Duration,
+}
+
+fn tls(config: &ServerConfig) -> anyhow::Result<Option<TlsAcceptor>> {
+    let (cert, key) = match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) => return Ok(None),
+        // serving plain HTTP when HTTPS was asked for would go unnoticed
+        (Some(_), None) => anyhow::bail!("TLS_CERT_FILE is set without TLS_KEY_FILE"),
+        (None, Some(_)) => anyhow::bail!("TLS_KEY_FILE is set without TLS_CERT_FILE"),
+    };
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(std::fs::File::open(cert)?)).collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(&mut std::io::BufReader::new(std::fs::File::open(key)?))?.ok_or_else(|| anyhow::anyhow!("no private key found in {key}"))?;
+    let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    tls.alpn_protocols = if config.http2 { vec![b"h2".to_vec(), b"http/1.1".to_vec()] } else { vec![b"http/1.1".to_vec()] };
+    Ok(Some(TlsAcceptor::from(Arc::new(tls))))
+}
+
+impl Server {
+    pub fn from_config(config: &ServerConfig) -> anyhow::Result<Server> {
+        Ok(Server {
+            addr: config.bind.parse().map_err(|e| anyhow::anyhow!("invalid bind address {:?}: {e}", config.bind))?,
+            tls: tls(config)?,
+            http2: config.http2,
+            keep_alive: config.keep_alive,
+            header_read_timeout: Duration::from_secs(config.header_read_timeout_secs),
+            h2_keep_alive_interval: config.http2_keepalive_interval_secs.filter(|s| *s > 0).map(Duration::from_secs),
+            h2_keep_alive_timeout: Duration::from_secs(config.http2_keepalive_timeout_secs),
+            h2_max_concurrent_streams: config.http2_max_concurrent_streams,
+            shutdown_timeout: Duration::from_secs(config.shutdown_timeout_secs),
+        })
+    }
+
+    fn builder(&self) -> auto::Builder<TokioExecutor> {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder.http1().timer(TokioTimer::new()).keep_alive(self.keep_alive).header_read_timeout(self.header_read_timeout);
+        builder.http2()
+            .timer(TokioTimer::new())
+            .keep_alive_interval(self.h2_keep_alive_interval)
+            .keep_alive_timeout(self.h2_keep_alive_timeout)
+            .max_concurrent_streams(self.h2_max_concurrent_streams)
+            .adaptive_window(true);
+        if self.http2 { builder } else { builder.http1_only() }
+    }
+
+    pub async fn serve(self, app: Router, shutdown: impl Future<Output = ()>) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(self.addr).await?;
+        tracing::info!(addr = %self.addr, tls = self.tls.is_some(), http2 = self.http2, "listening");
+        let builder = self.builder();
+        let graceful = GracefulShutdown::new();
+        tokio::pin!(shutdown);
+        loop {
+            let (stream, remote) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => { tracing::warn!(error = %e, "failed to accept connection"); tokio::time::sleep(Duration::from_millis(50)).await; continue; }
+                },
+                _ = &mut shutdown => break,
+            };
+            let _ = stream.set_nodelay(true);
+            let svc = TowerToHyperService::new(app.clone().map_request(move |mut req: Request<Incoming>| { req.extensions_mut().insert(ConnectInfo(remote)); req }));
+            let (tls, builder, watcher) = (self.tls.clone(), builder.clone(), graceful.watcher());
+            tokio::spawn(async move {
+                let Some(tls) = tls else { return serve_connection(builder, watcher, stream, svc).await };
+                match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, tls.accept(stream)).await {
+                    Ok(Ok(stream)) => serve_connection(builder, watcher, stream, svc).await,
+                    Ok(Err(e)) => tracing::debug!(error = %e, %remote, "tls handshake failed"),
+                    Err(_) => tracing::debug!(%remote, "tls handshake timed out"),
+                }
+            });
+        }
+        drop(listener);
+        if tokio::time::timeout(self.shutdown_timeout, graceful.shutdown()).await.is_err() {
+            tracing::warn!(timeout_secs = self.shutdown_timeout.as_secs(), "connections still open after the shutdown timeout, closing them");
+        }
+        Ok(())
+    }
+}
+
+async fn serve_connection<I, S>(builder: auto::Builder<TokioExecutor>, watcher: Watcher, io: I, svc: TowerToHyperService<S>)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: tower::Service<Request<Incoming>, Response = axum::response::Response, Error = std::convert::Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    if let Err(e) = watcher.watch(builder.serve_connection(TokioIo::new(io), svc)).await {
+        tracing::debug!(error = %e, "connection closed with an error");
+    }
+}
@@ -0,0 +1,190 @@
+use axum::{extract::{Path, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, QueryBuilder, Row, Sqlite};
+use std::{collections::HashMap, sync::{Arc, Mutex, RwLock}, time::Duration};
+
+use feature_flags_core::{eval_flag, EvalResponse, Flag, FlagStore, UpdateFlag};
+
+use crate::{auth::Principal, events::INSERT_ROWS, store_status, AppState};
+
+type Pair = (String, String, String);
+
+#[derive(Debug, Deserialize)]
+pub struct SetShadow {
+    enabled: bool,
+    variants: Option<HashMap<String, u32>>,
+    rollout: Option<u8>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShadowConfig {
+    enabled: bool,
+    variants: Option<HashMap<String, u32>>,
+    rollout: Option<u8>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PairCount {
+    live: String,
+    shadow: String,
+    evaluations: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShadowReport {
+    key: String,
+    shadow: ShadowConfig,
+    set_by: String,
+    set_at: String,
+    evaluations: i64,
+    agreement: Option<f64>,
+    results: Vec<PairCount>,
+}
+
+// shadow configurations by flag key, evaluated next to the live flag and counted but never served
+pub struct Shadows {
+    configs: RwLock<HashMap<String, Flag>>,
+    pending: Mutex<HashMap<Pair, i64>>,
+}
+
+// off, on, or on:<variant>
+fn label(res: &EvalResponse) -> String {
+    match (&res.variant, res.matched) {
+        (_, false) => "off".into(),
+        (None, true) => "on".into(),
+        (Some(v), true) => format!("on:{v}"),
+    }
+}
+
+fn shadow_flag(key: &str, enabled: bool, variants: Option<HashMap<String, u32>>, rollout: Option<u8>) -> Flag {
+    Flag { id: 0, uid: String::new(), key: key.to_string(), project: String::new(), enabled, protected: false, variants, rollout, lifecycle: Default::default(), updated_at: String::new(), pins: Default::default() }
+}
+
+async fn load(db: &Pool<Sqlite>) -> Result<HashMap<String, Flag>, sqlx::Error> {
+    let rows = sqlx::query("SELECT flag_key, enabled, variants, rollout FROM flag_shadows").fetch_all(db).await?;
+    Ok(rows.into_iter().map(|r| {
+        let key: String = r.get("flag_key");
+        let variants = r.get::<Option<String>, _>("variants").and_then(|v| serde_json::from_str(&v).ok());
+        let flag = shadow_flag(&key, r.get::<i64, _>("enabled") != 0, variants, r.get::<Option<i64>, _>("rollout").map(|r| r as u8));
+        (key, flag)
+    }).collect())
+}
+
+impl Shadows {
+    pub async fn load(db: &Pool<Sqlite>) -> anyhow::Result<Shadows> {
+        Ok(Shadows { configs: RwLock::new(load(db).await?), pending: Mutex::new(HashMap::new()) })
+    }
+
+    // results are aligned with flags, as evaluate_all returns them
+    pub fn observe(&self, flags: &[Flag], user_id: Option<&str>, results: &[EvalResponse]) {
+        let configs = self.configs.read().unwrap();
+        if configs.is_empty() { return; }
+        let mut pairs = Vec::new();
+        for (flag, live) in flags.iter().zip(results) {
+            let Some(shadow) = configs.get(&flag.key) else { continue };
+            pairs.push((flag.key.clone(), label(live), label(&eval_flag(shadow, user_id))));
+        }
+        drop(configs);
+        if pairs.is_empty() { return; }
+        let mut pending = self.pending.lock().unwrap();
+        for pair in pairs { *pending.entry(pair).or_default() += 1; }
+    }
+
+    pub async fn flush(&self, db: &Pool<Sqlite>) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() { return; }
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = db.begin().await?;
+            let rows: Vec<_> = pending.iter().collect();
+            // counts for a shadow removed since they were taken are dropped by the join
+            for chunk in rows.chunks(INSERT_ROWS) {
+                QueryBuilder::new("INSERT INTO shadow_results (flag_key, live, shadow, evaluations) SELECT v.column1, v.column2, v.column3, v.column4 FROM (")
+                    .push_values(chunk, |mut b, ((key, live, shadow), n)| { b.push_bind(key).push_bind(live).push_bind(shadow).push_bind(n); })
+                    .push(") AS v JOIN flag_shadows s ON s.flag_key = v.column1 WHERE true ON CONFLICT (flag_key, live, shadow) DO UPDATE SET evaluations = evaluations + excluded.evaluations")
+                    .build()
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await
+        }.await;
+        if let Err(e) = res {
+            tracing::warn!(error = %e, "failed to flush shadow results, keeping counts for the next flush");
+            let mut current = self.pending.lock().unwrap();
+            for (k, n) in pending { *current.entry(k).or_default() += n; }
+        }
+    }
+
+    // other instances may set or clear shadows, so the configs are reloaded along with every flush
+    pub fn spawn_flusher(self: Arc<Self>, db: Pool<Sqlite>, every: Duration) {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(every);
+            loop {
+                tick.tick().await;
+                self.flush(&db).await;
+                match load(&db).await {
+                    Ok(configs) => *self.configs.write().unwrap() = configs,
+                    Err(e) => tracing::warn!(error = %e, "failed to reload shadow configurations"),
+                }
+            }
+        });
+    }
+}
+
+async fn report(state: &AppState, key: &str) -> Result<ShadowReport, StatusCode> {
+    let r = sqlx::query("SELECT * FROM flag_shadows WHERE flag_key = ?")
+        .bind(key)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let shadow = ShadowConfig { enabled: r.get::<i64, _>("enabled") != 0, variants: r.get::<Option<String>, _>("variants").and_then(|v| serde_json::from_str(&v).ok()), rollout: r.get::<Option<i64>, _>("rollout").map(|r| r as u8) };
+    let results: Vec<PairCount> = sqlx::query("SELECT live, shadow, evaluations FROM shadow_results WHERE flag_key = ? ORDER BY evaluations DESC, live, shadow")
+        .bind(key)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|r| PairCount { live: r.get("live"), shadow: r.get("shadow"), evaluations: r.get("evaluations") })
+        .collect();
+    let evaluations: i64 = results.iter().map(|p| p.evaluations).sum();
+    let agreed: i64 = results.iter().filter(|p| p.live == p.shadow).map(|p| p.evaluations).sum();
+    Ok(ShadowReport { key: key.to_string(), shadow, set_by: r.get("set_by"), set_at: r.get("set_at"), evaluations, agreement: (evaluations > 0).then(|| agreed as f64 / evaluations as f64), results })
+}
+
+pub async fn get_shadow(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<ShadowReport>, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    state.shadows.flush(&state.db).await;
+    report(&state, &flag.key).await.map(Json)
+}
+
+// replacing a shadow starts its comparison over
+pub async fn set_shadow(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(key): Path<String>, Json(input): Json<SetShadow>) -> Result<Json<ShadowReport>, StatusCode> {
+    UpdateFlag { rollout: input.rollout, ..Default::default() }.validate().map_err(store_status)?;
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    state.shadows.flush(&state.db).await;
+    let variants = input.variants.as_ref().map(serde_json::to_string).transpose().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("INSERT INTO flag_shadows (flag_key, enabled, variants, rollout, set_by, set_at) VALUES (?, ?, ?, ?, ?, datetime('now')) ON CONFLICT (flag_key) DO UPDATE SET enabled = excluded.enabled, variants = excluded.variants, rollout = excluded.rollout, set_by = excluded.set_by, set_at = excluded.set_at")
+        .bind(&flag.key)
+        .bind(input.enabled)
+        .bind(variants)
+        .bind(input.rollout.map(i64::from))
+        .bind(&principal.subject)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("DELETE FROM shadow_results WHERE flag_key = ?").bind(&flag.key).execute(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.shadows.configs.write().unwrap().insert(flag.key.clone(), shadow_flag(&flag.key, input.enabled, input.variants, input.rollout));
+    report(&state, &flag.key).await.map(Json)
+}
+
+pub async fn clear_shadow(State(state): State<AppState>, Path(key): Path<String>) -> Result<StatusCode, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let rows = sqlx::query("DELETE FROM flag_shadows WHERE flag_key = ?").bind(&flag.key).execute(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.rows_affected();
+    sqlx::query("DELETE FROM shadow_results WHERE flag_key = ?").bind(&flag.key).execute(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.shadows.configs.write().unwrap().remove(&flag.key);
+    if rows == 0 { Err(StatusCode::NOT_FOUND) } else { Ok(StatusCode::NO_CONTENT) }
+}
@@ -0,0 +1,60 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+
+use feature_flags_core::{BundleSigner, Signed};
+
+use crate::{config::SigningConfig, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct SignParams {
+    #[serde(default)]
+    pub signed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublicKey {
+    algorithm: &'static str,
+    key_id: String,
+    public_key: String,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum MaybeSigned<'a, T> {
+    Plain(&'a T),
+    // keeps what was signed, since that's what an ETag has to be of
+    Signed {
+        #[serde(flatten)]
+        signed: Signed,
+        #[serde(skip)]
+        value: &'a T,
+    },
+}
+
+// SIGNING_KEY is a base64 32-byte Ed25519 seed, SIGNING_KEY_FILE a file holding one; unset means nothing is signed
+pub fn from_config(config: &SigningConfig) -> anyhow::Result<Option<BundleSigner>> {
+    let seed = match (&config.key, &config.key_file) {
+        (Some(seed), _) => seed.clone(),
+        (_, Some(path)) => std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("SIGNING_KEY_FILE {path}: {e}"))?,
+        _ => return Ok(None),
+    };
+    BundleSigner::from_base64(&seed).map(Some).map_err(|e| anyhow::anyhow!("SIGNING_KEY: {e}"))
+}
+
+// ?signed=true wraps the body in an envelope the SDKs verify; asking for it without a key configured is a 404 rather than a silently unsigned body.
+// the signature covers the environment, the time and, for a snapshot, who it was evaluated for
+pub fn wrap<'a, T: Serialize>(signer: Option<&BundleSigner>, signed: bool, environment: &str, subject: Option<&str>, value: &'a T) -> Result<MaybeSigned<'a, T>, StatusCode> {
+    if !signed { return Ok(MaybeSigned::Plain(value)); }
+    signer.ok_or(StatusCode::NOT_FOUND)?.sign_for(environment, subject, value).map(|signed| MaybeSigned::Signed { signed, value }).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+
+pub fn public_key(signer: Option<&BundleSigner>) -> Result<Json<PublicKey>, StatusCode> {
+    let signer = signer.ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(PublicKey { algorithm: "ed25519", key_id: signer.key_id().to_string(), public_key: signer.public_key() }))
+}
+
+// public, like the test vectors: it's what edge and offline consumers pin to check bundles against
+pub async fn signing_key(State(state): State<AppState>) -> Result<Json<PublicKey>, StatusCode> {
+    public_key(state.signer.as_deref())
+}
@@ -0,0 +1,110 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::collections::BTreeMap;
+
+use feature_flags_core::{eval_pinned, sqlite, CreateFlag, EvalResponse, Flag, StoreError, UpdateFlag};
+
+use crate::{auth::{Principal, Role}, store_status, AppState};
+
+const MAX_CONTEXTS: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct SimContext {
+    user_id: Option<String>,
+    anonymous_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SimulateInput {
+    key: String,
+    changes: Option<UpdateFlag>,
+    flag: Option<CreateFlag>,
+    contexts: Option<Vec<SimContext>>,
+    hours: Option<u32>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Outcome {
+    matched: usize,
+    match_rate: f64,
+    variants: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimulateResponse {
+    key: String,
+    source: &'static str,
+    sampled: usize,
+    current: Option<Outcome>,
+    proposed: Outcome,
+    changed: usize,
+}
+
+fn outcome(results: &[EvalResponse]) -> Outcome {
+    let mut out = Outcome::default();
+    for r in results.iter().filter(|r| r.matched) {
+        out.matched += 1;
+        if let Some(v) = &r.variant { *out.variants.entry(v.clone()).or_default() += 1; }
+    }
+    out.match_rate = if results.is_empty() { 0.0 } else { out.matched as f64 / results.len() as f64 };
+    out
+}
+
+// recent traffic is everyone who was assigned a variant of any flag in the window, as recorded for experiments
+async fn recent_users(state: &AppState, hours: u32, limit: usize) -> Result<Vec<String>, StatusCode> {
+    let rows = sqlx::query("SELECT user_id, MAX(at) AS last FROM exposures WHERE at >= datetime('now', ?) GROUP BY user_id ORDER BY last DESC LIMIT ?")
+        .bind(format!("-{hours} hours"))
+        .bind(limit as i64)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(rows.into_iter().map(|r| r.get("user_id")).collect())
+}
+
+pub async fn simulate(State(state): State<AppState>, Extension(principal): Extension<Principal>, Json(input): Json<SimulateInput>) -> Result<Json<SimulateResponse>, StatusCode> {
+    let limit = input.limit.unwrap_or(1000);
+    if input.changes.is_some() == input.flag.is_some() || limit == 0 || limit > MAX_CONTEXTS || input.contexts.as_ref().is_some_and(|c| c.is_empty() || c.len() > MAX_CONTEXTS) { return Err(StatusCode::BAD_REQUEST); }
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let current = match sqlite::fetch_flag(&mut conn, &input.key).await {
+        Ok(f) => Some(f),
+        Err(StoreError::NotFound) => None,
+        Err(e) => return Err(store_status(e)),
+    };
+    drop(conn);
+
+    // changes apply on top of the stored flag the way PATCH would; a full flag stands on its own, e.g. for one not created yet
+    let proposed = match (&current, input.changes, input.flag) {
+        (Some(c), Some(ch), None) => {
+            ch.validate().map_err(store_status)?;
+            Flag { enabled: ch.enabled.unwrap_or(c.enabled), variants: ch.variants.or_else(|| c.variants.clone()), rollout: ch.rollout.or(c.rollout), ..c.clone() }
+        }
+        (None, Some(_), None) => return Err(StatusCode::NOT_FOUND),
+        (_, None, Some(f)) => {
+            f.validate().map_err(store_status)?;
+            if f.key != input.key { return Err(StatusCode::BAD_REQUEST); }
+            Flag { id: 0, uid: String::new(), key: f.key, project: f.project, enabled: f.enabled, protected: f.protected, variants: f.variants, rollout: f.rollout, lifecycle: Default::default(), updated_at: String::new(), pins: Default::default() }
+        }
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    principal.require(&proposed.project, Role::Viewer)?;
+    if let Some(c) = &current { principal.require(&c.project, Role::Viewer)?; }
+
+    let (source, ids) = match input.contexts {
+        Some(contexts) => {
+            let mut ids = Vec::with_capacity(contexts.len());
+            for c in contexts {
+                let bucketing_id = state.aliases.bucketing_id(&state.db, c.user_id.as_deref(), c.anonymous_id.as_deref()).await;
+                ids.push((c.user_id, crate::cdn::evaluated_as(bucketing_id.as_deref())));
+            }
+            ("contexts", ids)
+        }
+        // exposures are recorded by bucketing id, so that's all there is to go on for recent users
+        None => ("recent", recent_users(&state, input.hours.unwrap_or(24).clamp(1, 24 * 30), limit).await?.into_iter().map(|id| (None, crate::cdn::evaluated_as(Some(&id)))).collect()),
+    };
+    let after: Vec<EvalResponse> = ids.iter().map(|(user_id, id)| eval_pinned(&proposed, user_id.as_deref(), id.as_deref())).collect();
+    let before: Option<Vec<EvalResponse>> = current.as_ref().map(|c| ids.iter().map(|(user_id, id)| eval_pinned(c, user_id.as_deref(), id.as_deref())).collect());
+    let changed = before.as_ref().map_or(after.iter().filter(|r| r.matched).count(), |b| b.iter().zip(&after).filter(|(b, a)| b.matched != a.matched || b.variant != a.variant).count());
+    Ok(Json(SimulateResponse { key: input.key, source, sampled: ids.len(), current: before.as_deref().map(outcome), proposed: outcome(&after), changed }))
+}
@@ -0,0 +1,66 @@
+use axum::{extract::State, response::sse::{Event, KeepAlive, Sse}, Extension};
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use std::{convert::Infallible, time::Duration};
+use tokio::sync::{broadcast, watch};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+
+use crate::{auth::ApiKey, AppState};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FlagChange {
+    pub key: String,
+    pub action: &'static str,
+    #[serde(skip)]
+    pub remote: bool,
+}
+
+pub struct Changes {
+    tx: broadcast::Sender<FlagChange>,
+    closed: watch::Sender<bool>,
+}
+
+impl Changes {
+    pub fn new() -> Changes {
+        let (tx, _) = broadcast::channel(1024);
+        let (closed, _) = watch::channel(false);
+        Changes { tx, closed }
+    }
+
+    pub fn publish(&self, key: &str, action: &'static str) {
+        let _ = self.tx.send(FlagChange { key: key.to_string(), action, remote: false });
+    }
+
+    // a change another instance already announced; local caches and streams react, forwarders skip it
+    pub fn publish_remote(&self, key: &str, action: &'static str) {
+        let _ = self.tx.send(FlagChange { key: key.to_string(), action, remote: true });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<FlagChange> {
+        self.tx.subscribe()
+    }
+
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+}
+
+pub async fn stream(State(state): State<AppState>, Extension(key): Extension<ApiKey>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    sse(&state.flag_changes, key.flag_prefixes)
+}
+
+// changes to flags outside the prefixes (when there are any) aren't sent
+pub fn sse(changes: &Changes, prefixes: Vec<String>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events = BroadcastStream::new(changes.tx.subscribe()).filter_map(move |change| {
+        let event = match change {
+            Ok(change) if !prefixes.is_empty() && !prefixes.iter().any(|p| change.key.starts_with(p.as_str())) => None,
+            Ok(change) => Some(Ok(Event::default().event("flag").json_data(&change).unwrap_or_default())),
+            Err(BroadcastStreamRecvError::Lagged(_)) => Some(Ok(Event::default().event("resync").data("{}"))),
+        };
+        std::future::ready(event)
+    });
+    let ready = futures_util::stream::once(async { Ok(Event::default().event("ready").data("{}")) });
+    let mut closed = changes.closed.subscribe();
+    let shutdown = async move { let _ = closed.wait_for(|c| *c).await; };
+    Sse::new(ready.chain(events).take_until(shutdown)).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
@@ -0,0 +1,117 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::Serialize;
+use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+
+use feature_flags_core::Lifecycle;
+
+use crate::{auth::{Principal, Role}, cache, code_refs, config::CleanupConfig, store_status, AppState};
+
+const RECENT_CHANGES: usize = 10;
+const TOP_EVALUATED: usize = 10;
+// evaluation stats are only kept for 30 days, so staleness can't look further back than that
+const MAX_STALE_DAYS: i64 = 30;
+
+#[derive(Debug, Serialize, Default)]
+pub struct Counts {
+    total: usize,
+    enabled: usize,
+    disabled: usize,
+    rolling_out: usize,
+    stale: usize,
+    archived: usize,
+    no_code_refs: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecentChange {
+    key: String,
+    rev: i64,
+    at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopFlag {
+    key: String,
+    evaluations: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    flags: Counts,
+    stale_after_days: i64,
+    // stale and not referenced from any scanned repository: most likely safe to delete
+    dead: Vec<String>,
+    recent_changes: Vec<RecentChange>,
+    top_evaluated_24h: Vec<TopFlag>,
+}
+
+fn stale_days(config: &CleanupConfig) -> i64 {
+    config.stale_flag_days.filter(|d| *d > 0).unwrap_or(MAX_STALE_DAYS).min(MAX_STALE_DAYS)
+}
+
+fn timestamp(at: chrono::DateTime<chrono::Utc>) -> String { at.format("%Y-%m-%d %H:%M:%S").to_string() }
+
+pub async fn summary(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> Result<Json<Summary>, StatusCode> {
+    let flags: Vec<_> = cache::all(&state).await.map_err(store_status)?.into_iter().filter(|f| principal.has_role(&f.project, Role::Viewer)).collect();
+    let visible: HashSet<&str> = flags.iter().map(|f| f.key.as_str()).collect();
+
+    let now = chrono::Utc::now();
+    let stale_after_days = stale_days(&state.config.current().cleanup);
+    let since = timestamp(now - chrono::Duration::days(stale_after_days));
+    // counts not flushed yet are read from memory rather than forcing a write from a read endpoint
+    let mut evaluated: HashSet<String> = sqlx::query("SELECT DISTINCT flag_key FROM flag_eval_stats WHERE hour >= ?")
+        .bind(&since)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .iter()
+        .map(|r| r.get("flag_key"))
+        .collect();
+    evaluated.extend(state.flag_stats.unflushed(&since).into_keys());
+
+    let refs = code_refs::counts(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let unreferenced = |key: &str| refs.as_ref().is_some_and(|r| !r.contains_key(key));
+
+    let mut counts = Counts { total: flags.len(), no_code_refs: refs.as_ref().map(|_| 0), ..Counts::default() };
+    let mut dead = Vec::new();
+    for f in &flags {
+        if f.enabled { counts.enabled += 1 } else { counts.disabled += 1 }
+        if f.enabled && f.rollout.is_some_and(|r| r > 0 && r < 100) { counts.rolling_out += 1; }
+        if f.lifecycle == Lifecycle::Archived { counts.archived += 1; continue; }
+        if unreferenced(&f.key) { counts.no_code_refs = counts.no_code_refs.map(|n| n + 1); }
+        if f.updated_at < since && !evaluated.contains(&f.key) {
+            counts.stale += 1;
+            if unreferenced(&f.key) { dead.push(f.key.clone()); }
+        }
+    }
+    dead.sort();
+
+    // over-fetch so flags outside the caller's projects don't leave the list short
+    let recent_changes = sqlx::query("SELECT flag_key, rev, created_at FROM flag_revisions ORDER BY id DESC LIMIT ?")
+        .bind((RECENT_CHANGES * 10) as i64)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .iter()
+        .filter(|r| visible.contains(r.get::<&str,_>("flag_key")))
+        .take(RECENT_CHANGES)
+        .map(|r| RecentChange { key: r.get("flag_key"), rev: r.get("rev"), at: r.get("created_at") })
+        .collect();
+
+    let day = (now - chrono::Duration::hours(23)).format("%Y-%m-%d %H:00:00").to_string();
+    let mut totals: HashMap<String, i64> = sqlx::query("SELECT flag_key, SUM(evaluations) AS evaluations FROM flag_eval_stats WHERE hour >= ? GROUP BY flag_key")
+        .bind(&day)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .iter()
+        .map(|r| (r.get("flag_key"), r.get("evaluations")))
+        .collect();
+    for (key, n) in state.flag_stats.unflushed(&day) { *totals.entry(key).or_default() += n; }
+    let mut top_evaluated_24h: Vec<TopFlag> = totals.into_iter().filter(|(k, _)| visible.contains(k.as_str())).map(|(key, evaluations)| TopFlag { key, evaluations }).collect();
+    top_evaluated_24h.sort_by(|a, b| b.evaluations.cmp(&a.evaluations).then_with(|| a.key.cmp(&b.key)));
+    top_evaluated_24h.truncate(TOP_EVALUATED);
+
+    Ok(Json(Summary { flags: counts, stale_after_days, dead, recent_changes, top_evaluated_24h }))
+}
@@ -0,0 +1,227 @@
+use axum::{extract::{Path, State}, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::collections::HashMap;
+
+use feature_flags_core::FlagStore;
+
+use crate::{auth::Principal, store_status, valid_name, AppState};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Team {
+    name: String,
+    description: Option<String>,
+    webhook_url: Option<String>,
+    members: Vec<String>,
+    flags: Vec<String>,
+    created_at: String,
+    updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTeam {
+    name: String,
+    #[serde(flatten)]
+    fields: TeamFields,
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TeamFields {
+    description: Option<String>,
+    webhook_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignOwner {
+    team: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Owner {
+    flag_key: String,
+    team: String,
+    assigned_by: String,
+    assigned_at: String,
+}
+
+// who to tell about a flag: the team's own webhook if it has one, and the members to mention
+pub struct Contact {
+    pub webhook_url: Option<String>,
+    pub members: Vec<String>,
+}
+
+impl TeamFields {
+    fn valid(&self) -> bool {
+        self.webhook_url.as_deref().is_none_or(|u| u.starts_with("http://") || u.starts_with("https://"))
+    }
+}
+
+fn valid_member(member: &str) -> bool {
+    !member.trim().is_empty() && member.len() <= 256
+}
+
+pub async fn list_teams(State(state): State<AppState>) -> Result<Json<Vec<Team>>, StatusCode> {
+    let rows = sqlx::query("SELECT * FROM teams ORDER BY name").fetch_all(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut members = group(&state.db, "SELECT team, member AS value FROM team_members ORDER BY member").await?;
+    let mut flags = group(&state.db, "SELECT o.team, o.flag_key AS value FROM flag_owners o JOIN flags f ON f.key = o.flag_key ORDER BY o.flag_key").await?;
+    Ok(Json(rows.into_iter().map(|r| {
+        let name: String = r.get("name");
+        Team { members: members.remove(&name).unwrap_or_default(), flags: flags.remove(&name).unwrap_or_default(), description: r.get("description"), webhook_url: r.get("webhook_url"), created_at: r.get("created_at"), updated_at: r.get("updated_at"), name }
+    }).collect()))
+}
+
+pub async fn get_team(State(state): State<AppState>, Path(name): Path<String>) -> Result<Json<Team>, StatusCode> {
+    Ok(Json(fetch_team(&state.db, &name).await?))
+}
+
+pub async fn create_team(State(state): State<AppState>, Extension(principal): Extension<Principal>, Json(input): Json<CreateTeam>) -> Result<Json<Team>, StatusCode> {
+    principal.require_editor()?;
+    if !valid_name(&input.name) || !input.fields.valid() || !input.members.iter().all(|m| valid_member(m)) { return Err(StatusCode::BAD_REQUEST); }
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("INSERT INTO teams (name, description, webhook_url, created_at, updated_at) VALUES (?, ?, ?, datetime('now'), datetime('now'))")
+        .bind(&input.name)
+        .bind(&input.fields.description)
+        .bind(&input.fields.webhook_url)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e { sqlx::Error::Database(d) if d.is_unique_violation() => StatusCode::CONFLICT, _ => StatusCode::INTERNAL_SERVER_ERROR })?;
+    for member in &input.members {
+        sqlx::query("INSERT OR IGNORE INTO team_members (team, member, added_at) VALUES (?, ?, datetime('now'))").bind(&input.name).bind(member.trim()).execute(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(fetch_team(&state.db, &input.name).await?))
+}
+
+pub async fn update_team(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>, Json(input): Json<TeamFields>) -> Result<Json<Team>, StatusCode> {
+    principal.require_editor()?;
+    if !input.valid() { return Err(StatusCode::BAD_REQUEST); }
+    let rows = sqlx::query("UPDATE teams SET description = ?, webhook_url = ?, updated_at = datetime('now') WHERE name = ?")
+        .bind(&input.description)
+        .bind(&input.webhook_url)
+        .bind(&name)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    Ok(Json(fetch_team(&state.db, &name).await?))
+}
+
+// the team's flags become unowned rather than deleted
+pub async fn delete_team(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>) -> Result<StatusCode, StatusCode> {
+    principal.require_editor()?;
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let rows = sqlx::query("DELETE FROM teams WHERE name = ?").bind(&name).execute(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    for table in ["team_members", "flag_owners"] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE team = ?")).bind(&name).execute(&mut *tx).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn add_member(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path((name, member)): Path<(String, String)>) -> Result<Json<Team>, StatusCode> {
+    principal.require_editor()?;
+    if !valid_member(&member) { return Err(StatusCode::BAD_REQUEST); }
+    fetch_team(&state.db, &name).await?;
+    sqlx::query("INSERT OR IGNORE INTO team_members (team, member, added_at) VALUES (?, ?, datetime('now'))")
+        .bind(&name)
+        .bind(member.trim())
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(fetch_team(&state.db, &name).await?))
+}
+
+pub async fn remove_member(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path((name, member)): Path<(String, String)>) -> Result<StatusCode, StatusCode> {
+    principal.require_editor()?;
+    let rows = sqlx::query("DELETE FROM team_members WHERE team = ? AND member = ?")
+        .bind(&name)
+        .bind(member.trim())
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_owner(State(state): State<AppState>, Path(key): Path<String>) -> Result<Json<Owner>, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    fetch_owner(&state.db, &flag.key).await.map(Json)
+}
+
+pub async fn assign_owner(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(key): Path<String>, Json(input): Json<AssignOwner>) -> Result<Json<Owner>, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    fetch_team(&state.db, &input.team).await.map_err(|s| if s == StatusCode::NOT_FOUND { StatusCode::BAD_REQUEST } else { s })?;
+    sqlx::query("INSERT INTO flag_owners (flag_key, team, assigned_by, assigned_at) VALUES (?, ?, ?, datetime('now')) ON CONFLICT (flag_key) DO UPDATE SET team = excluded.team, assigned_by = excluded.assigned_by, assigned_at = excluded.assigned_at")
+        .bind(&flag.key)
+        .bind(&input.team)
+        .bind(&principal.subject)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    fetch_owner(&state.db, &flag.key).await.map(Json)
+}
+
+pub async fn unassign_owner(State(state): State<AppState>, Path(key): Path<String>) -> Result<StatusCode, StatusCode> {
+    let flag = state.store.get(&key).await.map_err(store_status)?;
+    let rows = sqlx::query("DELETE FROM flag_owners WHERE flag_key = ?")
+        .bind(&flag.key)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn owned_flags(db: &Pool<Sqlite>, team: &str) -> Result<Vec<String>, StatusCode> {
+    fetch_team(db, team).await.map(|t| t.flags)
+}
+
+pub async fn contact(db: &Pool<Sqlite>, team: &str) -> Result<Option<Contact>, sqlx::Error> {
+    let Some(webhook_url) = sqlx::query("SELECT webhook_url FROM teams WHERE name = ?").bind(team).fetch_optional(db).await?.map(|r| r.get("webhook_url")) else { return Ok(None) };
+    let members = sqlx::query("SELECT member FROM team_members WHERE team = ? ORDER BY member").bind(team).fetch_all(db).await?.iter().map(|r| r.get("member")).collect();
+    Ok(Some(Contact { webhook_url, members }))
+}
+
+async fn group(db: &Pool<Sqlite>, sql: &str) -> Result<HashMap<String, Vec<String>>, StatusCode> {
+    let mut out: HashMap<String, Vec<String>> = HashMap::new();
+    for r in sqlx::query(sql).fetch_all(db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+        out.entry(r.get("team")).or_default().push(r.get("value"));
+    }
+    Ok(out)
+}
+
+async fn fetch_team(db: &Pool<Sqlite>, name: &str) -> Result<Team, StatusCode> {
+    let r = sqlx::query("SELECT * FROM teams WHERE name = ?")
+        .bind(name)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let members = sqlx::query("SELECT member FROM team_members WHERE team = ? ORDER BY member").bind(name).fetch_all(db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let flags = sqlx::query("SELECT o.flag_key FROM flag_owners o JOIN flags f ON f.key = o.flag_key WHERE o.team = ? ORDER BY o.flag_key").bind(name).fetch_all(db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Team {
+        name: r.get("name"),
+        description: r.get("description"),
+        webhook_url: r.get("webhook_url"),
+        members: members.iter().map(|m| m.get("member")).collect(),
+        flags: flags.iter().map(|f| f.get("flag_key")).collect(),
+        created_at: r.get("created_at"),
+        updated_at: r.get("updated_at"),
+    })
+}
+
+async fn fetch_owner(db: &Pool<Sqlite>, key: &str) -> Result<Owner, StatusCode> {
+    let r = sqlx::query("SELECT * FROM flag_owners WHERE flag_key = ?")
+        .bind(key)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Owner { flag_key: r.get("flag_key"), team: r.get("team"), assigned_by: r.get("assigned_by"), assigned_at: r.get("assigned_at") })
+}
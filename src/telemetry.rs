@@ -0,0 +1,75 @@
+use axum::{extract::{MatchedPath, Request}, response::Response, Router};
+use std::{sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Mutex, OnceLock}, time::Duration};
+use tower_http::{request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer}, trace::TraceLayer};
+use tracing::{field::Empty, Span};
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+
+use crate::config::{AccessLogConfig, Config};
+
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+// the directives in effect, and a count of changes so a delayed revert can tell whether something else changed them since
+static DIRECTIVES: Mutex<String> = Mutex::new(String::new());
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static ACCESS_LOG: AtomicBool = AtomicBool::new(false);
+
+// turning the access log on shouldn't also require lowering RUST_LOG
+fn with_access_log(filter: EnvFilter) -> EnvFilter {
+    if ACCESS_LOG.load(Ordering::Relaxed) { filter.add_directive("access_log=info".parse().expect("static directive")) } else { filter }
+}
+
+// a configuration that doesn't load still gets logging, in the default format, so the error can be reported
+pub fn init(config: Option<&Config>) {
+    ACCESS_LOG.store(config.is_some_and(|c| c.access_log.enabled), Ordering::Relaxed);
+    let directives = std::env::var("RUST_LOG").unwrap_or_else(|_| "info,tower_http=info".into());
+    let (env_filter, handle) = reload::Layer::new(with_access_log(EnvFilter::new(&directives)));
+    let _ = FILTER.set(handle);
+    *DIRECTIVES.lock().unwrap() = directives;
+    let registry = tracing_subscriber::registry().with(env_filter);
+    match config.and_then(|c| c.log.format.as_deref()) {
+        Some("json") => registry.with(tracing_subscriber::fmt::layer().json().flatten_event(true).with_current_span(true).with_span_list(false)).init(),
+        _ => registry.with(tracing_subscriber::fmt::layer()).init(),
+    }
+}
+
+pub fn parse_filter(directives: &str) -> anyhow::Result<EnvFilter> {
+    EnvFilter::try_new(directives).map_err(|e| anyhow::anyhow!("invalid log level {directives:?}: {e}"))
+}
+
+// swaps the filter of the running subscriber; unlike at startup, directives that don't parse are an error
+pub fn set_filter(directives: &str) -> anyhow::Result<u64> {
+    let filter = parse_filter(directives)?;
+    let handle = FILTER.get().ok_or_else(|| anyhow::anyhow!("logging isn't initialised"))?;
+    let mut current = DIRECTIVES.lock().unwrap();
+    handle.reload(with_access_log(filter))?;
+    *current = directives.to_string();
+    Ok(GENERATION.fetch_add(1, Ordering::Relaxed) + 1)
+}
+
+pub fn filter() -> (String, u64) {
+    let current = DIRECTIVES.lock().unwrap();
+    (current.clone(), GENERATION.load(Ordering::Relaxed))
+}
+
+fn make_span(req: &Request) -> Span {
+    let request_id = req.headers().get("x-request-id").and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let route = req.extensions().get::<MatchedPath>().map(|p| p.as_str()).unwrap_or_else(|| req.uri().path());
+    tracing::info_span!("request", request_id, method = %req.method(), route, actor = Empty, flag_key = Empty)
+}
+
+fn on_response(res: &Response, latency: Duration, _: &Span) {
+    tracing::info!(status = res.status().as_u16(), latency_ms = latency.as_secs_f64() * 1000.0, "finished processing request");
+}
+
+// fields filled in once auth and the handler know them; every later log line of the request carries them
+pub fn record_actor(actor: &str) { Span::current().record("actor", actor); }
+
+pub fn record_flag(key: &str) { Span::current().record("flag_key", key); }
+
+pub fn layer<S: Clone + Send + Sync + 'static>(mut router: Router<S>, access_log: &AccessLogConfig) -> anyhow::Result<Router<S>> {
+    if let Some(log) = crate::access_log::AccessLog::from_config(access_log)? { router = router.layer(axum::middleware::from_fn_with_state(log, crate::access_log::log)); }
+    Ok(router
+        .layer(axum::middleware::from_fn(crate::metrics::track))
+        .layer(TraceLayer::new_for_http().make_span_with(make_span).on_response(on_response))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid)))
+}
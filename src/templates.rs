@@ -0,0 +1,135 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, response::Response, Extension, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::collections::HashMap;
+
+use feature_flags_core::{default_project, CreateFlag};
+
+use crate::{auth::Principal, valid_name, AppState, MutationParams};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FlagTemplate {
+    name: String,
+    description: Option<String>,
+    enabled: bool,
+    variants: Option<HashMap<String, u32>>,
+    rollout: Option<u8>,
+    created_at: String,
+    updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTemplate {
+    name: String,
+    #[serde(flatten)]
+    fields: TemplateFields,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateFields {
+    description: Option<String>,
+    #[serde(default)]
+    enabled: bool,
+    variants: Option<HashMap<String, u32>>,
+    rollout: Option<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FromTemplate {
+    key: String,
+    #[serde(default = "default_project")]
+    project: String,
+    uid: Option<String>,
+}
+
+impl TemplateFields {
+    fn valid(&self) -> bool {
+        self.rollout.is_none_or(|r| r <= 100) && self.variants.as_ref().is_none_or(|v| !v.is_empty())
+    }
+}
+
+pub async fn list_templates(State(state): State<AppState>) -> Result<Json<Vec<FlagTemplate>>, StatusCode> {
+    let rows = sqlx::query("SELECT * FROM flag_templates ORDER BY name")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let out = rows.into_iter().map(row_to_template).collect::<Result<Vec<_>, _>>().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(out))
+}
+
+pub async fn get_template(State(state): State<AppState>, Path(name): Path<String>) -> Result<Json<FlagTemplate>, StatusCode> {
+    Ok(Json(fetch_template(&state.db, &name).await?))
+}
+
+pub async fn create_template(State(state): State<AppState>, Extension(principal): Extension<Principal>, Json(input): Json<CreateTemplate>) -> Result<Json<FlagTemplate>, StatusCode> {
+    principal.require_editor()?;
+    if !valid_name(&input.name) || !input.fields.valid() { return Err(StatusCode::BAD_REQUEST); }
+    sqlx::query("INSERT INTO flag_templates (name, description, enabled, variants, rollout, created_at, updated_at) VALUES (?, ?, ?, ?, ?, datetime('now'), datetime('now'))")
+        .bind(&input.name)
+        .bind(&input.fields.description)
+        .bind(input.fields.enabled)
+        .bind(input.fields.variants.as_ref().map(|v| serde_json::to_string(v).unwrap()))
+        .bind(input.fields.rollout.map(|r| r as i64))
+        .execute(&state.db)
+        .await
+        .map_err(|e| match e { sqlx::Error::Database(d) if d.is_unique_violation() => StatusCode::CONFLICT, _ => StatusCode::INTERNAL_SERVER_ERROR })?;
+    Ok(Json(fetch_template(&state.db, &input.name).await?))
+}
+
+pub async fn update_template(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>, Json(input): Json<TemplateFields>) -> Result<Json<FlagTemplate>, StatusCode> {
+    principal.require_editor()?;
+    if !input.valid() { return Err(StatusCode::BAD_REQUEST); }
+    let rows = sqlx::query("UPDATE flag_templates SET description = ?, enabled = ?, variants = ?, rollout = ?, updated_at = datetime('now') WHERE name = ?")
+        .bind(&input.description)
+        .bind(input.enabled)
+        .bind(input.variants.as_ref().map(|v| serde_json::to_string(v).unwrap()))
+        .bind(input.rollout.map(|r| r as i64))
+        .bind(&name)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    Ok(Json(fetch_template(&state.db, &name).await?))
+}
+
+pub async fn delete_template(State(state): State<AppState>, Extension(principal): Extension<Principal>, Path(name): Path<String>) -> Result<(), StatusCode> {
+    principal.require_editor()?;
+    let rows = sqlx::query("DELETE FROM flag_templates WHERE name = ?")
+        .bind(&name)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if rows == 0 { return Err(StatusCode::NOT_FOUND); }
+    Ok(())
+}
+
+// goes through the regular create path, so permissions, approval and dry runs behave exactly as for POST /flags
+pub async fn create_from_template(state: State<AppState>, principal: Extension<Principal>, Path(name): Path<String>, params: Query<MutationParams>, Json(input): Json<FromTemplate>) -> Result<Response, StatusCode> {
+    let template = fetch_template(&state.db, &name).await?;
+    let flag = CreateFlag { uid: input.uid, key: input.key, project: input.project, enabled: template.enabled, protected: false, variants: template.variants, rollout: template.rollout };
+    crate::create_flag(state, principal, params, Json(flag)).await
+}
+
+async fn fetch_template(db: &Pool<Sqlite>, name: &str) -> Result<FlagTemplate, StatusCode> {
+    let r = sqlx::query("SELECT * FROM flag_templates WHERE name = ?")
+        .bind(name)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    row_to_template(r).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn row_to_template(r: sqlx::sqlite::SqliteRow) -> Result<FlagTemplate, serde_json::Error> {
+    Ok(FlagTemplate {
+        name: r.get("name"),
+        description: r.get("description"),
+        enabled: r.get("enabled"),
+        variants: r.get::<Option<String>,_>("variants").map(|v| serde_json::from_str(&v)).transpose()?,
+        rollout: r.get::<Option<i64>,_>("rollout").map(|r| r as u8),
+        created_at: r.get("created_at"),
+        updated_at: r.get("updated_at"),
+    })
+}
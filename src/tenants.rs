@@ -0,0 +1,95 @@
+use axum::{extract::{Request, State}, http::{header::HOST, HeaderMap, HeaderName, StatusCode}, middleware::Next, response::{IntoResponse, Response}, routing::{get, post}, Router};
+use std::{collections::HashMap, sync::Arc};
+use tower::ServiceExt;
+
+use crate::{auth::{bearer_token, hash_secret}, config::{self, Config, Reloadable}, log_level};
+
+pub struct Tenants {
+    names: Vec<String>,
+    database_url: String,
+    header: HeaderName,
+    domain: Option<String>,
+    operator_key: Option<String>,
+}
+
+struct Routed {
+    tenants: Tenants,
+    apps: HashMap<String, Router>,
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 63 && name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+impl Tenants {
+    // unset TENANTS keeps the single-database server
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Tenants>> {
+        let Some(mut names) = config.tenants.names.clone() else { return Ok(None) };
+        names.sort();
+        names.dedup();
+        if names.is_empty() { anyhow::bail!("TENANTS is set but lists no tenants"); }
+        if let Some(bad) = names.iter().find(|n| !valid_name(n)) { anyhow::bail!("invalid tenant name {bad:?}, use lowercase letters, digits, - and _"); }
+        let database_url = config.tenants.database_url.clone().unwrap_or_else(|| "sqlite://flags-{tenant}.db".into());
+        if !database_url.contains("{tenant}") { anyhow::bail!("TENANT_DATABASE_URL must contain {{tenant}}, or every tenant would share one database"); }
+        // the first two would write the same flags into every tenant, the others send every tenant's data to one destination
+        let shared = [
+            ("FLAGS_FILE", config.flags_file.path.is_some()),
+            ("GIT_SYNC_REPO", config.git_sync.repo.is_some()),
+            ("KAFKA_BROKERS", config.kafka.brokers.is_some()),
+            ("PUBSUB_URL", config.pubsub.url.is_some()),
+            ("EXPORT_S3_BUCKET", config.exports.s3_bucket.is_some()),
+            ("AUDIT_SINK", config.audit_sink.kind.is_some()),
+        ];
+        if let Some((name, _)) = shared.iter().find(|(_, set)| *set) { anyhow::bail!("{name} can't be used with TENANTS"); }
+        if !config.webhooks.urls.is_empty() { anyhow::bail!("WEBHOOK_URLS can't be used with TENANTS, every tenant's flag events would reach the same receivers"); }
+        // roles without a project are admin everywhere, so one identity provider's token would be admin in every tenant
+        if config.auth.oidc.issuer.is_some() { anyhow::bail!("OIDC_ISSUER can't be used with TENANTS"); }
+        let auth = &config.auth;
+        if auth.admin_api_key.as_deref().is_some_and(|k| !k.is_empty()) { anyhow::bail!("ADMIN_API_KEY can't be used with TENANTS, it would be admin in every tenant; set ADMIN_API_KEY_<TENANT> for each instead"); }
+        let header = HeaderName::try_from(config.tenants.header.clone().unwrap_or_else(|| "x-tenant".into()))?;
+        let domain = config.tenants.domain.as_deref().map(|d| d.trim_start_matches('.').to_ascii_lowercase()).filter(|d| !d.is_empty());
+        let operator_key = config.tenants.operator_api_key.as_deref().filter(|k| !k.is_empty()).map(hash_secret);
+        Ok(Some(Tenants { names, database_url, header, domain, operator_key }))
+    }
+
+    pub fn names(&self) -> &[String] { &self.names }
+
+    pub fn database_url(&self, tenant: &str) -> String { self.database_url.replace("{tenant}", tenant) }
+
+    // the header wins over the subdomain, e.g. acme.flags.example.com with TENANT_DOMAIN=flags.example.com
+    fn resolve(&self, headers: &HeaderMap) -> Option<String> {
+        if let Some(tenant) = headers.get(&self.header).and_then(|v| v.to_str().ok()) { return Some(tenant.trim().to_string()); }
+        let domain = self.domain.as_ref()?;
+        let host = headers.get(HOST)?.to_str().ok()?.to_ascii_lowercase();
+        let host = host.split(':').next()?;
+        host.strip_suffix(domain.as_str())?.strip_suffix('.').filter(|t| !t.contains('.')).map(str::to_string)
+    }
+}
+
+// e.g. ADMIN_API_KEY_ACME_EU for tenant acme-eu
+pub fn admin_api_key(tenant: &str) -> Option<String> {
+    std::env::var(format!("ADMIN_API_KEY_{}", tenant.to_ascii_uppercase().replace('-', "_"))).ok()
+}
+
+// every tenant has its own router, state, database and caches; this only picks which one handles the request.
+// settings and the log filter are the process's, so only the operator can change them, and only without a tenant
+pub fn router(tenants: Tenants, apps: HashMap<String, Router>, live: Arc<Reloadable>) -> Router {
+    let operator = tenants.operator_key.clone().map(|key| Router::new()
+        .route("/admin/config/reload", post(config::reload))
+        .route("/admin/log-level", get(log_level::get_log_level).put(log_level::set_log_level))
+        .route_layer(axum::middleware::from_fn_with_state(Arc::new(key), require_operator))
+        .with_state(live));
+    let routed = Router::new().fallback(dispatch).with_state(Arc::new(Routed { tenants, apps }));
+    match operator { Some(operator) => operator.merge(routed), None => routed }
+}
+
+async fn require_operator(State(key): State<Arc<String>>, req: Request, next: Next) -> Result<Response, StatusCode> {
+    if hash_secret(bearer_token(req.headers())?) != *key { return Err(StatusCode::UNAUTHORIZED); }
+    Ok(next.run(req).await)
+}
+
+async fn dispatch(State(routed): State<Arc<Routed>>, req: Request) -> Response {
+    let Some(tenant) = routed.tenants.resolve(req.headers()) else { return (StatusCode::BAD_REQUEST, "no tenant in the request").into_response() };
+    let Some(app) = routed.apps.get(&tenant) else { return StatusCode::NOT_FOUND.into_response() };
+    app.clone().oneshot(req).await.into_response()
+}
@@ -0,0 +1,119 @@
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use serde::Serialize;
+use sqlx::{Pool, Row, Sqlite};
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use crate::{config::Reloadable, AppState};
+
+const WINDOW_SECS: u64 = 60;
+
+pub struct Usage {
+    // TOKEN_RATE_LIMIT, which a config reload can change
+    config: Arc<Reloadable>,
+    windows: Mutex<HashMap<i64, (u64, u32)>>,
+    pending: Mutex<HashMap<(i64, String, String), i64>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageRow {
+    day: String,
+    route: String,
+    count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    token_id: i64,
+    rate_limit: Option<u32>,
+    current_window: u32,
+    window_secs: u64,
+    daily: Vec<UsageRow>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+impl Usage {
+    pub fn new(config: Arc<Reloadable>) -> Usage {
+        Usage { config, windows: Mutex::new(HashMap::new()), pending: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn limit_for(&self, token_limit: Option<u32>) -> Option<u32> {
+        token_limit.or(self.config.token_rate_limit())
+    }
+
+    pub fn check(&self, key_id: i64, token_limit: Option<u32>) -> Result<(), u64> {
+        let now = now_secs();
+        let window = now / WINDOW_SECS;
+        let mut windows = self.windows.lock().unwrap();
+        let entry = windows.entry(key_id).or_insert((window, 0));
+        if entry.0 != window { *entry = (window, 0); }
+        if let Some(limit) = self.limit_for(token_limit) {
+            if entry.1 >= limit { return Err((window + 1) * WINDOW_SECS - now); }
+        }
+        entry.1 += 1;
+        Ok(())
+    }
+
+    pub fn record(&self, key_id: i64, route: &str) {
+        let day = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        *self.pending.lock().unwrap().entry((key_id, day, route.to_string())).or_default() += 1;
+    }
+
+    fn current_window(&self, key_id: i64) -> u32 {
+        let window = now_secs() / WINDOW_SECS;
+        self.windows.lock().unwrap().get(&key_id).filter(|w| w.0 == window).map(|w| w.1).unwrap_or(0)
+    }
+
+    pub async fn flush(&self, db: &Pool<Sqlite>) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() { return; }
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = db.begin().await?;
+            for ((key_id, day, route), count) in &pending {
+                sqlx::query("INSERT INTO token_usage (key_id, day, route, count) VALUES (?, ?, ?, ?) ON CONFLICT (key_id, day, route) DO UPDATE SET count = count + excluded.count")
+                    .bind(key_id)
+                    .bind(day)
+                    .bind(route)
+                    .bind(count)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await
+        }.await;
+        if let Err(e) = res {
+            tracing::warn!(error = %e, "failed to flush token usage, keeping counts for the next flush");
+            let mut current = self.pending.lock().unwrap();
+            for (k, v) in pending { *current.entry(k).or_default() += v; }
+        }
+    }
+
+    pub fn spawn_flusher(self: Arc<Self>, db: Pool<Sqlite>) {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(Duration::from_secs(10));
+            loop {
+                tick.tick().await;
+                self.flush(&db).await;
+            }
+        });
+    }
+}
+
+pub async fn token_usage(State(state): State<AppState>, Path(id): Path<i64>) -> Result<Json<UsageReport>, StatusCode> {
+    let r = sqlx::query("SELECT rate_limit FROM api_keys WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let rate_limit = state.usage.limit_for(r.get::<Option<i64>,_>("rate_limit").map(|l| l as u32));
+    state.usage.flush(&state.db).await;
+    let rows = sqlx::query("SELECT day, route, count FROM token_usage WHERE key_id = ? ORDER BY day DESC, route LIMIT 500")
+        .bind(id)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let daily = rows.into_iter().map(|r| UsageRow { day: r.get("day"), route: r.get("route"), count: r.get("count") }).collect();
+    Ok(Json(UsageReport { token_id: id, rate_limit, current_window: state.usage.current_window(id), window_secs: WINDOW_SECS, daily }))
+}
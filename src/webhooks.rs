@@ -0,0 +1,59 @@
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::config::WebhooksConfig;
+
+pub struct Webhooks {
+    urls: Vec<String>,
+    http: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct Event<'a, T: Serialize> {
+    event: &'a str,
+    at: String,
+    data: T,
+}
+
+impl Webhooks {
+    pub fn from_config(config: &WebhooksConfig) -> anyhow::Result<Webhooks> {
+        let urls = config.urls.clone();
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(5)).build()?;
+        Ok(Webhooks { urls, http })
+    }
+
+    pub fn urls(&self) -> &[String] { &self.urls }
+
+    // any HTTP response counts as reachable; only connection failures and timeouts don't
+    pub async fn probe(&self, url: &str) -> Result<reqwest::StatusCode, reqwest::Error> {
+        Ok(self.http.head(url).send().await?.status())
+    }
+
+    pub fn notify<T: Serialize>(&self, event: &str, data: T) {
+        self.deliver(&self.urls, event, data);
+    }
+
+    // for events with their own recipient, e.g. a team's webhook, instead of WEBHOOK_URLS
+    pub fn notify_url<T: Serialize>(&self, url: &str, event: &str, data: T) {
+        self.deliver(&[url.to_string()], event, data);
+    }
+
+    fn deliver<T: Serialize>(&self, urls: &[String], event: &str, data: T) {
+        if urls.is_empty() { return; }
+        let body = match serde_json::to_value(Event { event, at: chrono::Utc::now().to_rfc3339(), data }) {
+            Ok(b) => b,
+            Err(e) => { tracing::warn!(error = %e, event, "failed to serialize webhook payload"); return; }
+        };
+        for url in urls {
+            let req = self.http.post(url).json(&body);
+            let url = url.clone();
+            let event = event.to_string();
+            tokio::spawn(async move {
+                match req.send().await.and_then(|r| r.error_for_status()) {
+                    Ok(_) => tracing::debug!(%url, %event, "webhook delivered"),
+                    Err(e) => tracing::warn!(%url, %event, error = %e, "webhook delivery failed"),
+                }
+            });
+        }
+    }
+}